@@ -0,0 +1,168 @@
+//! The `#[llm_tool]` attribute of `llm-sdk` (feature `macros`), use it through `llm_sdk::llm_tool`.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, Expr, FnArg, GenericArgument, ItemFn, Lit, LitStr, Meta, PathArguments,
+    ReturnType, Type,
+};
+
+/// Turn an async fn into a tool of a `ToolRegistry`. The fn takes one argument struct, whose
+/// JSON schema is the parameters of the tool, and returns an `anyhow::Result` of a serializable
+/// output. The doc comment of the fn is the description of the tool, and its name the name of
+/// the tool unless set with `#[llm_tool(name = "...")]`.
+///
+/// The fn is kept as is, and a unit struct named after it (`get_weather` gives `GetWeatherTool`)
+/// implements `LlmTool`, to be registered with `ToolRegistry::tool(GetWeatherTool)`.
+#[proc_macro_attribute]
+pub fn llm_tool(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut name = None;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("name") {
+            name = Some(meta.value()?.parse::<LitStr>()?);
+            Ok(())
+        } else {
+            Err(meta.error("unsupported llm_tool attribute, expected `name`"))
+        }
+    });
+    parse_macro_input!(attr with parser);
+    let func = parse_macro_input!(item as ItemFn);
+    expand(name, func)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+fn expand(name: Option<LitStr>, func: ItemFn) -> syn::Result<TokenStream2> {
+    let sig = &func.sig;
+    if sig.asyncness.is_none() {
+        return Err(syn::Error::new_spanned(
+            sig,
+            "#[llm_tool] requires an async fn",
+        ));
+    }
+    let args_ty = match sig.inputs.iter().collect::<Vec<_>>().as_slice() {
+        [FnArg::Typed(arg)] => &arg.ty,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &sig.inputs,
+                "#[llm_tool] fn must take exactly one argument struct",
+            ))
+        }
+    };
+    let output_ty = result_type(&sig.output).ok_or_else(|| {
+        syn::Error::new_spanned(&sig.output, "#[llm_tool] fn must return a Result<T>")
+    })?;
+
+    let ident = &sig.ident;
+    let vis = &func.vis;
+    let tool = format_ident!("{}Tool", camel_case(&ident.to_string()));
+    let name = name.map_or_else(|| ident.to_string(), |name| name.value());
+    let description = description(&func);
+    Ok(quote! {
+        #func
+
+        #[doc = concat!("The `", #name, "` tool, see [`", stringify!(#ident), "`].")]
+        #[derive(Debug, Clone, Copy, Default)]
+        #vis struct #tool;
+
+        impl ::llm_sdk::LlmTool for #tool {
+            type Args = #args_ty;
+            type Output = #output_ty;
+            const NAME: &'static str = #name;
+            const DESCRIPTION: &'static str = #description;
+
+            fn call(args: Self::Args) -> ::llm_sdk::ToolFuture<Self::Output> {
+                ::std::boxed::Box::pin(#ident(args))
+            }
+        }
+    })
+}
+
+/// The `T` of a `Result<T>` (or `Result<T, E>`) return type.
+fn result_type(output: &ReturnType) -> Option<&Type> {
+    let ReturnType::Type(_, ty) = output else {
+        return None;
+    };
+    let Type::Path(path) = ty.as_ref() else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Result" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match args.args.first()? {
+        GenericArgument::Type(ty) => Some(ty),
+        _ => None,
+    }
+}
+
+/// The doc comment of the fn, one line per line of the comment.
+fn description(func: &ItemFn) -> String {
+    func.attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(nv) => match &nv.value {
+                Expr::Lit(expr) => match &expr.lit {
+                    Lit::Str(s) => Some(s.value().trim().to_string()),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+fn camel_case(name: &str) -> String {
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|c| c.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_should_generate_the_tool() -> syn::Result<()> {
+        let func: ItemFn = syn::parse_quote! {
+            /// Get the weather of a city.
+            pub async fn get_weather(args: GetWeatherArgs) -> anyhow::Result<Weather> {
+                todo!()
+            }
+        };
+        let output = expand(None, func)?.to_string();
+        assert!(output.contains("pub struct GetWeatherTool"));
+        assert!(output.contains("type Output = Weather"));
+        assert!(output.contains("\"Get the weather of a city.\""));
+
+        let func: ItemFn = syn::parse_quote! {
+            fn sync_tool(args: Args) -> anyhow::Result<String> {
+                todo!()
+            }
+        };
+        assert!(expand(None, func).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn camel_case_should_work() {
+        assert_eq!(camel_case("get_weather"), "GetWeather");
+        assert_eq!(camel_case("search"), "Search");
+    }
+}
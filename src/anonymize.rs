@@ -0,0 +1,291 @@
+//! Anonymized exports of stored conversations, e.g. to share a `DatasetLogger` corpus with a
+//! vendor or annotators. `PiiMasker` replaces the emails, phone numbers and card numbers of the
+//! texts with placeholders like `<EMAIL_1>`, the same value always getting the same placeholder.
+//! The `PiiMapping` from placeholders to the original values can be saved to restore the texts
+//! later; keep it away from the exported corpus. The masker is also a `DatasetHook`, to mask the
+//! pairs as they are logged.
+
+use crate::{dataset::sanitize_texts, DatasetHook};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{BufRead, BufReader, BufWriter, Write},
+    ops::Range,
+    path::Path,
+    sync::Mutex,
+};
+use strum::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum PiiKind {
+    Email,
+    Phone,
+    CardNumber,
+}
+
+#[derive(Debug, Default)]
+pub struct PiiMasker {
+    mapping: Mutex<PiiMapping>,
+}
+
+/// The original values of the placeholders.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PiiMapping {
+    /// Placeholder to original value.
+    pub entries: BTreeMap<String, String>,
+    #[serde(skip)]
+    placeholders: HashMap<String, String>,
+}
+
+impl PiiMasker {
+    /// Continue the placeholders of an existing mapping, e.g. to export a corpus in several runs.
+    pub fn with_mapping(mapping: PiiMapping) -> Self {
+        Self {
+            mapping: Mutex::new(mapping),
+        }
+    }
+
+    pub fn mask(&self, text: &str) -> String {
+        let spans = detect(text);
+        if spans.is_empty() {
+            return text.to_string();
+        }
+        let mut mapping = self.mapping.lock().unwrap_or_else(|e| e.into_inner());
+        let mut ret = String::with_capacity(text.len());
+        let mut last = 0;
+        for (range, kind) in spans {
+            ret.push_str(&text[last..range.start]);
+            ret.push_str(&mapping.placeholder(&text[range.clone()], kind));
+            last = range.end;
+        }
+        ret.push_str(&text[last..]);
+        ret
+    }
+
+    pub fn mapping(&self) -> PiiMapping {
+        self.mapping
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+impl DatasetHook for PiiMasker {
+    fn sanitize(&self, text: &str) -> String {
+        self.mask(text)
+    }
+}
+
+impl PiiMapping {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let mut mapping: Self = serde_json::from_slice(&fs::read(path)?)?;
+        mapping.placeholders = mapping
+            .entries
+            .iter()
+            .map(|(placeholder, value)| (value.clone(), placeholder.clone()))
+            .collect();
+        Ok(mapping)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Replace the placeholders of `text` by their original values.
+    pub fn restore(&self, text: &str) -> String {
+        self.entries
+            .iter()
+            .fold(text.to_string(), |text, (placeholder, value)| {
+                text.replace(placeholder, value)
+            })
+    }
+
+    fn placeholder(&mut self, value: &str, kind: PiiKind) -> String {
+        if let Some(placeholder) = self.placeholders.get(value) {
+            return placeholder.clone();
+        }
+        let prefix = format!("<{}_", kind);
+        let n = self
+            .entries
+            .keys()
+            .filter(|p| p.starts_with(&prefix))
+            .count()
+            + 1;
+        let placeholder = format!("{}{}>", prefix, n);
+        self.entries.insert(placeholder.clone(), value.to_string());
+        self.placeholders
+            .insert(value.to_string(), placeholder.clone());
+        placeholder
+    }
+}
+
+/// Mask the message texts of a JSONL corpus (in the format of `DatasetLogger`) into `output`,
+/// returning the number of lines written. Save `masker.mapping()` afterwards to keep the export
+/// reversible.
+pub fn export_anonymized(
+    input: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    masker: &PiiMasker,
+) -> Result<usize> {
+    let reader = BufReader::new(fs::File::open(input)?);
+    let mut writer = BufWriter::new(fs::File::create(output)?);
+    let mut count = 0;
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut value: Value = serde_json::from_str(&line)?;
+        sanitize_texts(&mut value, &|s| masker.mask(s));
+        serde_json::to_writer(&mut writer, &value)?;
+        writer.write_all(b"\n")?;
+        count += 1;
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+/// The PII in `text`, ordered and non overlapping.
+fn detect(text: &str) -> Vec<(Range<usize>, PiiKind)> {
+    let mut spans = emails(text);
+    for (range, kind) in numbers(text) {
+        if !spans
+            .iter()
+            .any(|(r, _)| r.start < range.end && range.start < r.end)
+        {
+            spans.push((range, kind));
+        }
+    }
+    spans.sort_by_key(|(range, _)| range.start);
+    spans
+}
+
+fn emails(text: &str) -> Vec<(Range<usize>, PiiKind)> {
+    let is_email_char = |c: char| c.is_alphanumeric() || "._%+-@".contains(c);
+    let mut ret = Vec::new();
+    let mut offset = 0;
+    for word in text.split_inclusive(char::is_whitespace) {
+        let start = offset;
+        offset += word.len();
+        let trimmed = word.trim_start_matches(|c: char| !c.is_alphanumeric());
+        let lead = word.len() - trimmed.len();
+        let trimmed = trimmed.trim_end_matches(|c: char| !c.is_alphanumeric());
+        let Some((local, domain)) = trimmed.split_once('@') else {
+            continue;
+        };
+        let valid = !local.is_empty()
+            && domain.contains('.')
+            && !domain.contains('@')
+            && !domain.starts_with('.')
+            && trimmed.chars().all(is_email_char);
+        if valid {
+            let start = start + lead;
+            ret.push((start..start + trimmed.len(), PiiKind::Email));
+        }
+    }
+    ret
+}
+
+/// Runs of digits (with the usual separators) long enough to be a phone or card number.
+fn numbers(text: &str) -> Vec<(Range<usize>, PiiKind)> {
+    let mut ret = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if !(c.is_ascii_digit() || c == '+' || c == '(') {
+            continue;
+        }
+        let mut end = start + c.len_utf8();
+        let mut digits: Vec<u32> = c.to_digit(10).into_iter().collect();
+        while let Some(&(i, c)) = chars.peek() {
+            if c.is_ascii_digit() {
+                digits.extend(c.to_digit(10));
+                end = i + 1;
+            } else if !" -().".contains(c) {
+                break;
+            }
+            chars.next();
+        }
+        let kind = match digits.len() {
+            13..=19 if luhn(&digits) => PiiKind::CardNumber,
+            9..=15 => PiiKind::Phone,
+            _ => continue,
+        };
+        ret.push((start..end, kind));
+    }
+    ret
+}
+
+fn luhn(digits: &[u32]) -> bool {
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, d)| match (i % 2, d * 2) {
+            (1, d) if d > 9 => d - 9,
+            (1, d) => d,
+            _ => *d,
+        })
+        .sum();
+    sum % 10 == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pii_masker_should_mask_consistently() {
+        let masker = PiiMasker::default();
+        let text =
+            "Mail (jane.doe@example.com) or call +1 (555) 123-4567, card 4111 1111 1111 1111.";
+        let masked = masker.mask(text);
+        assert_eq!(
+            masked,
+            "Mail (<EMAIL_1>) or call <PHONE_1>, card <CARD_NUMBER_1>."
+        );
+        assert_eq!(
+            masker.mask("again jane.doe@example.com, bob@example.org"),
+            "again <EMAIL_1>, <EMAIL_2>"
+        );
+        // short numbers and dates are kept
+        assert_eq!(
+            masker.mask("order 42 on 2024-01-01"),
+            "order 42 on 2024-01-01"
+        );
+        assert_eq!(masker.mapping().restore(&masked), text);
+    }
+
+    #[test]
+    fn export_anonymized_should_mask_messages() -> Result<()> {
+        let dir = std::env::temp_dir();
+        let id = std::process::id();
+        let (input, output, mapping) = (
+            dir.join(format!("corpus-{}.jsonl", id)),
+            dir.join(format!("corpus-{}.anon.jsonl", id)),
+            dir.join(format!("corpus-{}.mapping.json", id)),
+        );
+        fs::write(
+            &input,
+            r#"{"messages":[{"role":"user","content":"I'm bob@example.org"},{"role":"assistant","content":"Hi!"}]}
+"#,
+        )?;
+        let masker = PiiMasker::default();
+        assert_eq!(export_anonymized(&input, &output, &masker)?, 1);
+        let line: Value = serde_json::from_str(fs::read_to_string(&output)?.trim())?;
+        assert_eq!(line["messages"][0]["content"], "I'm <EMAIL_1>");
+        assert_eq!(line["messages"][0]["role"], "user");
+
+        masker.mapping().save(&mapping)?;
+        let masker = PiiMasker::with_mapping(PiiMapping::load(&mapping)?);
+        assert_eq!(masker.mask("bob@example.org"), "<EMAIL_1>");
+        for path in [input, output, mapping] {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,345 @@
+use crate::{
+    with_assistants_beta, BuildError, ChatCompleteModel, FunctionInfo, IntoRequest, ToSchema,
+};
+use derive_builder::Builder;
+use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Serialize, Builder)]
+#[builder(pattern = "mutable", build_fn(error = "BuildError"))]
+pub struct CreateAssistantRequest {
+    /// ID of the model to use.
+    #[builder(default)]
+    pub(crate) model: ChatCompleteModel,
+    /// The name of the assistant. The maximum length is 256 characters.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    /// The description of the assistant. The maximum length is 512 characters.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    /// The system instructions that the assistant uses. The maximum length is 256,000 characters.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instructions: Option<String>,
+    /// A list of tool enabled on the assistant. There can be a maximum of 128 tools per assistant. Tools can be of types code_interpreter, file_search, or function.
+    #[builder(default, setter(into))]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<AssistantTool>,
+    /// A set of resources that are used by the assistant's tools. The resources are specific to the type of tool. For example, the code_interpreter tool requires a list of file IDs, while the file_search tool requires a list of vector store IDs.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_resources: Option<ToolResources>,
+    /// Set of 16 key-value pairs that can be attached to an object. Keys can be a maximum of 64 characters long and values can be a maximum of 512 characters long.
+    #[builder(default, setter(into))]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    metadata: HashMap<String, String>,
+    /// What sampling temperature to use, between 0 and 2. Higher values like 0.8 will make the output more random, while lower values like 0.2 will make it more focused and deterministic.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    /// An alternative to sampling with temperature, called nucleus sampling, where the model considers the results of the tokens with top_p probability mass.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Builder)]
+#[builder(pattern = "mutable", build_fn(error = "BuildError"))]
+pub struct ModifyAssistantRequest {
+    /// ID of the model to use.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<ChatCompleteModel>,
+    /// The name of the assistant. The maximum length is 256 characters.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    /// The description of the assistant. The maximum length is 512 characters.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    /// The system instructions that the assistant uses. The maximum length is 256,000 characters.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instructions: Option<String>,
+    /// A list of tool enabled on the assistant. If set, it replaces the existing tools.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<AssistantTool>>,
+    /// A set of resources that are used by the assistant's tools. If set, it replaces the existing resources.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_resources: Option<ToolResources>,
+    /// Set of 16 key-value pairs that can be attached to an object. If set, it replaces the existing metadata.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<HashMap<String, String>>,
+    /// What sampling temperature to use, between 0 and 2.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    /// An alternative to sampling with temperature, called nucleus sampling.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum AssistantTool {
+    /// Let the assistant write and run python code in a sandboxed environment.
+    CodeInterpreter,
+    /// Let the assistant search the files attached through `tool_resources`.
+    FileSearch {
+        /// Overrides for the file search tool.
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        file_search: Option<FileSearchOptions>,
+    },
+    /// A function the assistant may ask the caller to run.
+    Function {
+        /// The definition of the function.
+        function: FunctionInfo,
+    },
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FileSearchOptions {
+    /// The maximum number of results the file search tool should output. Should be between 1 and 50 inclusive.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub max_num_results: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ToolResources {
+    /// Resources for the code_interpreter tool.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub code_interpreter: Option<CodeInterpreterResources>,
+    /// Resources for the file_search tool.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub file_search: Option<FileSearchResources>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CodeInterpreterResources {
+    /// A list of file IDs made available to the code_interpreter tool. There can be a maximum of 20 files associated with the tool.
+    #[serde(default)]
+    pub file_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FileSearchResources {
+    /// The vector store attached to this assistant. There can be a maximum of 1 vector store attached to the assistant.
+    #[serde(default)]
+    pub vector_store_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Assistant {
+    /// The identifier, which can be referenced in API endpoints.
+    pub id: String,
+    /// The object type, which is always assistant.
+    pub object: String,
+    /// The Unix timestamp (in seconds) for when the assistant was created.
+    pub created_at: u64,
+    /// The name of the assistant.
+    pub name: Option<String>,
+    /// The description of the assistant.
+    pub description: Option<String>,
+    /// ID of the model to use.
+    pub model: String,
+    /// The system instructions that the assistant uses.
+    pub instructions: Option<String>,
+    /// A list of tool enabled on the assistant.
+    #[serde(default)]
+    pub tools: Vec<AssistantTool>,
+    /// A set of resources that are used by the assistant's tools.
+    #[serde(default)]
+    pub tool_resources: Option<ToolResources>,
+    /// Set of 16 key-value pairs attached to the assistant.
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    /// What sampling temperature to use, between 0 and 2.
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    /// An alternative to sampling with temperature, called nucleus sampling.
+    #[serde(default)]
+    pub top_p: Option<f32>,
+}
+
+impl IntoRequest for CreateAssistantRequest {
+    fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+        let url = format!("{}/assistants", base_url);
+        with_assistants_beta(client.post(url).json(&self))
+    }
+}
+
+impl CreateAssistantRequest {
+    pub fn new(model: ChatCompleteModel, instructions: impl Into<String>) -> Self {
+        CreateAssistantRequestBuilder::default()
+            .model(model)
+            .instructions(instructions)
+            .build()
+            .unwrap()
+    }
+}
+
+impl AssistantTool {
+    pub fn code_interpreter() -> Self {
+        Self::CodeInterpreter
+    }
+
+    pub fn file_search() -> Self {
+        Self::FileSearch { file_search: None }
+    }
+
+    pub fn new_function<T: ToSchema>(
+        name: impl Into<String>,
+        description: impl Into<String>,
+    ) -> Self {
+        Self::Function {
+            function: FunctionInfo::new::<T>(name, description),
+        }
+    }
+}
+
+impl ToolResources {
+    pub fn code_interpreter(file_ids: impl Into<Vec<String>>) -> Self {
+        Self {
+            code_interpreter: Some(CodeInterpreterResources {
+                file_ids: file_ids.into(),
+            }),
+            file_search: None,
+        }
+    }
+
+    pub fn file_search(vector_store_ids: impl Into<Vec<String>>) -> Self {
+        Self {
+            code_interpreter: None,
+            file_search: Some(FileSearchResources {
+                vector_store_ids: vector_store_ids.into(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ListRequest, SDK};
+    use anyhow::Result;
+    use schemars::JsonSchema;
+    use serde_json::json;
+
+    #[allow(dead_code)]
+    #[derive(Debug, Deserialize, JsonSchema)]
+    struct LookupOrderArgs {
+        /// The order number to look up.
+        pub order_id: String,
+    }
+
+    #[test]
+    fn create_assistant_request_should_serialize() -> Result<()> {
+        let req = CreateAssistantRequestBuilder::default()
+            .model(ChatCompleteModel::Gpt4Turbo)
+            .name("support")
+            .instructions("You are a customer support agent.")
+            .tools(vec![
+                AssistantTool::code_interpreter(),
+                AssistantTool::file_search(),
+                AssistantTool::new_function::<LookupOrderArgs>(
+                    "lookup_order",
+                    "Look up an order by its number.",
+                ),
+            ])
+            .tool_resources(ToolResources::file_search(vec!["vs_123".to_string()]))
+            .build()?;
+        assert_eq!(
+            serde_json::to_value(req)?,
+            json!({
+              "model": "gpt-4-1106-preview",
+              "name": "support",
+              "instructions": "You are a customer support agent.",
+              "tools": [
+                { "type": "code_interpreter" },
+                { "type": "file_search" },
+                {
+                  "type": "function",
+                  "function": {
+                    "description": "Look up an order by its number.",
+                    "name": "lookup_order",
+                    "parameters": LookupOrderArgs::to_schema()
+                  }
+                }
+              ],
+              "tool_resources": {
+                "file_search": { "vector_store_ids": ["vs_123"] }
+              }
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn assistant_should_deserialize() -> Result<()> {
+        let assistant: Assistant = serde_json::from_value(json!({
+          "id": "asst_abc123",
+          "object": "assistant",
+          "created_at": 1698984975,
+          "name": "Math Tutor",
+          "description": null,
+          "model": "gpt-4-turbo",
+          "instructions": "You are a personal math tutor.",
+          "tools": [
+            { "type": "code_interpreter" },
+            { "type": "file_search", "file_search": { "max_num_results": 10 } }
+          ],
+          "tool_resources": { "code_interpreter": { "file_ids": ["file-abc"] } },
+          "metadata": {},
+          "top_p": 1.0,
+          "temperature": 1.0,
+          "response_format": "auto"
+        }))?;
+        assert_eq!(assistant.id, "asst_abc123");
+        assert_eq!(assistant.tools.len(), 2);
+        assert!(matches!(
+            assistant.tools[1],
+            AssistantTool::FileSearch {
+                file_search: Some(FileSearchOptions {
+                    max_num_results: Some(10)
+                })
+            }
+        ));
+        let resources = assistant.tool_resources.unwrap();
+        assert_eq!(resources.code_interpreter.unwrap().file_ids, ["file-abc"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn assistant_lifecycle_should_work() -> Result<()> {
+        let req = CreateAssistantRequest::new(
+            ChatCompleteModel::Gpt3Turbo,
+            "You are a personal math tutor.",
+        );
+        let assistant = SDK.create_assistant(req).await?;
+        assert_eq!(assistant.object, "assistant");
+
+        let req = ModifyAssistantRequestBuilder::default()
+            .name("Math Tutor")
+            .build()?;
+        let modified = SDK.modify_assistant(&assistant.id, req).await?;
+        assert_eq!(modified.name.as_deref(), Some("Math Tutor"));
+
+        let retrieved = SDK.retrieve_assistant(&assistant.id).await?;
+        assert_eq!(retrieved.id, assistant.id);
+
+        let list = SDK.list_assistants(ListRequest::default()).await?;
+        assert_eq!(list.object, "list");
+
+        let status = SDK.delete_assistant(&assistant.id).await?;
+        assert!(status.deleted);
+        Ok(())
+    }
+}
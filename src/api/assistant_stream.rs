@@ -0,0 +1,223 @@
+use crate::{
+    sse::{CancelOnDrop, SseEvent},
+    Run, RunStatus, RunStep, ThreadMessage,
+};
+use anyhow::{anyhow, Result};
+use futures::{future, ready, stream::BoxStream, Stream, StreamExt};
+use serde::Deserialize;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// An event of a streamed assistant run.
+#[derive(Debug, Clone)]
+pub enum AssistantStreamEvent {
+    /// `thread.run.*`, e.g. `thread.run.requires_action` or `thread.run.completed`.
+    Run { event: String, run: Box<Run> },
+    /// `thread.run.step.*` except the deltas.
+    RunStep { event: String, step: Box<RunStep> },
+    /// `thread.message.*` except the deltas.
+    Message {
+        event: String,
+        message: Box<ThreadMessage>,
+    },
+    /// `thread.message.delta`, a fragment of a message being generated.
+    MessageDelta(MessageDelta),
+    /// Events this SDK doesn't know about yet, e.g. `thread.run.step.delta`.
+    Unknown { event: String, data: String },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MessageDelta {
+    /// The identifier of the message.
+    pub id: String,
+    /// The delta containing the fields that have changed on the message.
+    pub delta: MessageDeltaContent,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MessageDeltaContent {
+    /// The content of the message in array of text and/or images.
+    #[serde(default)]
+    pub content: Vec<MessageDeltaPart>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MessageDeltaPart {
+    /// The index of the content part in the message.
+    pub index: usize,
+    /// The text fragment, only set for text content.
+    #[serde(default)]
+    pub text: Option<MessageDeltaText>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MessageDeltaText {
+    /// The data that makes up the text.
+    #[serde(default)]
+    pub value: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamError {
+    #[serde(default)]
+    code: Option<String>,
+    message: String,
+}
+
+/// A stream of assistant run events, ends after the `done` event. Dropping the stream while the
+/// run is in progress cancels it.
+pub struct AssistantStream {
+    inner: BoxStream<'static, Result<AssistantStreamEvent>>,
+    cancel: Option<CancelOnDrop>,
+}
+
+impl AssistantStream {
+    pub(crate) fn new(events: impl Stream<Item = Result<SseEvent>> + Send + 'static) -> Self {
+        let inner = events
+            .take_while(|event| {
+                future::ready(
+                    !matches!(event, Ok(e) if e.event.as_deref() == Some("done") || e.data == "[DONE]"),
+                )
+            })
+            .map(|event| AssistantStreamEvent::parse(event?))
+            .boxed();
+        Self {
+            inner,
+            cancel: None,
+        }
+    }
+
+    /// `cancel` is called with the id of the run.
+    pub(crate) fn with_cancel(mut self, cancel: CancelOnDrop) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+}
+
+impl Stream for AssistantStream {
+    type Item = Result<AssistantStreamEvent>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let item = ready!(self.inner.poll_next_unpin(cx));
+        if let Some(cancel) = self.cancel.as_mut() {
+            match &item {
+                // a run requiring action is paused, waiting for the tool outputs
+                Some(Ok(AssistantStreamEvent::Run { run, .. }))
+                    if run.status.is_terminal()
+                        || matches!(
+                            run.status,
+                            RunStatus::RequiresAction | RunStatus::Cancelling
+                        ) =>
+                {
+                    cancel.disarm()
+                }
+                Some(Ok(AssistantStreamEvent::Run { run, .. })) => cancel.arm(run.id.clone()),
+                None => cancel.disarm(),
+                _ => {}
+            }
+        }
+        Poll::Ready(item)
+    }
+}
+
+impl AssistantStreamEvent {
+    fn parse(sse: SseEvent) -> Result<Self> {
+        let event = sse.event.unwrap_or_default();
+        let data = sse.data;
+        let parsed = match event.as_str() {
+            "error" => {
+                let err: StreamError = serde_json::from_str(&data)?;
+                return Err(anyhow!(
+                    "assistant stream error ({}): {}",
+                    err.code.unwrap_or_default(),
+                    err.message
+                ));
+            }
+            "thread.message.delta" => Self::MessageDelta(serde_json::from_str(&data)?),
+            e if e.starts_with("thread.run.step.") && !e.ends_with(".delta") => Self::RunStep {
+                step: Box::new(serde_json::from_str(&data)?),
+                event: event.clone(),
+            },
+            e if e.starts_with("thread.run.") && !e.starts_with("thread.run.step.") => Self::Run {
+                run: Box::new(serde_json::from_str(&data)?),
+                event: event.clone(),
+            },
+            e if e.starts_with("thread.message.") => Self::Message {
+                message: Box::new(serde_json::from_str(&data)?),
+                event: event.clone(),
+            },
+            _ => Self::Unknown {
+                event: event.clone(),
+                data,
+            },
+        };
+        Ok(parsed)
+    }
+}
+
+impl MessageDelta {
+    /// Concatenate the text fragments of this delta.
+    pub fn text(&self) -> String {
+        self.delta
+            .content
+            .iter()
+            .filter_map(|part| part.text.as_ref()?.value.as_deref())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+    use serde_json::json;
+
+    fn sse(event: &str, data: serde_json::Value) -> Result<SseEvent> {
+        Ok(SseEvent {
+            event: Some(event.into()),
+            data: data.to_string(),
+        })
+    }
+
+    #[tokio::test]
+    async fn assistant_stream_should_parse_events() -> Result<()> {
+        let run = json!({
+          "id": "run_abc123",
+          "object": "thread.run",
+          "created_at": 1699075072,
+          "assistant_id": "asst_abc123",
+          "thread_id": "thread_abc123",
+          "status": "completed",
+          "model": "gpt-4-turbo"
+        });
+        let events = vec![
+            sse(
+                "thread.message.delta",
+                json!({
+                  "id": "msg_abc123",
+                  "object": "thread.message.delta",
+                  "delta": { "content": [{ "index": 0, "type": "text", "text": { "value": "Hello" } }] }
+                }),
+            ),
+            sse("thread.run.completed", run),
+            Ok(SseEvent {
+                event: Some("done".into()),
+                data: "[DONE]".into(),
+            }),
+            sse("thread.run.completed", json!({})),
+        ];
+        let events: Vec<_> = AssistantStream::new(stream::iter(events))
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<_>>()?;
+        assert_eq!(events.len(), 2);
+        assert!(matches!(&events[0], AssistantStreamEvent::MessageDelta(d) if d.text() == "Hello"));
+        assert!(
+            matches!(&events[1], AssistantStreamEvent::Run { run, .. } if run.status == RunStatus::Completed)
+        );
+        Ok(())
+    }
+}
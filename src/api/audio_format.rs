@@ -0,0 +1,84 @@
+use strum::{Display, EnumString};
+
+/// The audio file formats accepted by the transcription and translation endpoints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum AudioFormat {
+    Flac,
+    M4a,
+    Mp3,
+    Mp4,
+    Ogg,
+    Wav,
+    Webm,
+}
+
+impl AudioFormat {
+    /// Detect the format by the magic bytes of the file, None if unknown.
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        match data {
+            [b'f', b'L', b'a', b'C', ..] => Some(Self::Flac),
+            [b'O', b'g', b'g', b'S', ..] => Some(Self::Ogg),
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Some(Self::Wav),
+            [0x1a, 0x45, 0xdf, 0xa3, ..] => Some(Self::Webm),
+            [_, _, _, _, b'f', b't', b'y', b'p', b'M', b'4', b'A', ..] => Some(Self::M4a),
+            [_, _, _, _, b'f', b't', b'y', b'p', ..] => Some(Self::Mp4),
+            [b'I', b'D', b'3', ..] => Some(Self::Mp3),
+            // mpeg audio frame sync
+            [0xff, b, ..] if b & 0xe0 == 0xe0 => Some(Self::Mp3),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Flac => "flac",
+            Self::M4a => "m4a",
+            Self::Mp3 => "mp3",
+            Self::Mp4 => "mp4",
+            Self::Ogg => "ogg",
+            Self::Wav => "wav",
+            Self::Webm => "webm",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Self::Flac => "audio/flac",
+            Self::M4a => "audio/mp4",
+            Self::Mp3 => "audio/mpeg",
+            Self::Mp4 => "video/mp4",
+            Self::Ogg => "audio/ogg",
+            Self::Wav => "audio/wav",
+            Self::Webm => "audio/webm",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn audio_format_should_be_detected_by_magic_bytes() -> Result<()> {
+        let data = std::fs::read("fixtures/speech.mp3")?;
+        assert_eq!(AudioFormat::from_bytes(&data), Some(AudioFormat::Mp3));
+        assert_eq!(
+            AudioFormat::from_bytes(b"RIFF\x24\x08\x00\x00WAVEfmt "),
+            Some(AudioFormat::Wav)
+        );
+        assert_eq!(
+            AudioFormat::from_bytes(b"\x00\x00\x00\x20ftypM4A \x00\x00"),
+            Some(AudioFormat::M4a)
+        );
+        assert_eq!(
+            AudioFormat::from_bytes(b"\x00\x00\x00\x20ftypisom"),
+            Some(AudioFormat::Mp4)
+        );
+        assert_eq!(AudioFormat::from_bytes(b"OggS\x00"), Some(AudioFormat::Ogg));
+        assert_eq!(AudioFormat::from_bytes(b"hello"), None);
+        assert_eq!(AudioFormat::Ogg.extension(), "ogg");
+        Ok(())
+    }
+}
@@ -0,0 +1,329 @@
+use crate::{BuildError, ChatCompletionRequest, FilePurpose, IntoRequest, UploadFileRequest};
+use anyhow::{anyhow, Result};
+use derive_builder::Builder;
+use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Collects chat completion requests into the JSONL input file of a batch, each line is keyed by
+/// a caller-chosen custom_id which is used to map the results back.
+#[derive(Debug, Clone, Default)]
+pub struct BatchRequestFile {
+    lines: Vec<BatchRequestLine>,
+    ids: HashSet<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct BatchRequestLine {
+    custom_id: String,
+    method: &'static str,
+    url: &'static str,
+    body: ChatCompletionRequest,
+}
+
+#[derive(Debug, Clone, Serialize, Builder)]
+#[builder(pattern = "mutable", build_fn(error = "BuildError"))]
+pub struct CreateBatchRequest {
+    /// The ID of an uploaded file that contains requests for the new batch. The file must be uploaded with the purpose batch.
+    #[builder(setter(into))]
+    input_file_id: String,
+    /// The endpoint to be used for all requests in the batch.
+    #[builder(default)]
+    endpoint: BatchEndpoint,
+    /// The time frame within which the batch should be processed. Currently only 24h is supported.
+    #[builder(default = r#""24h".into()"#, setter(into))]
+    completion_window: String,
+    /// Set of 16 key-value pairs that can be attached to an object.
+    #[builder(default, setter(into))]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    metadata: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BatchEndpoint {
+    #[default]
+    #[serde(rename = "/v1/chat/completions")]
+    ChatCompletions,
+    #[serde(rename = "/v1/embeddings")]
+    Embeddings,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Batch {
+    /// The identifier, which can be referenced in API endpoints.
+    pub id: String,
+    /// The object type, which is always batch.
+    pub object: String,
+    /// The OpenAI API endpoint used by the batch.
+    pub endpoint: BatchEndpoint,
+    /// The ID of the input file for the batch.
+    pub input_file_id: String,
+    /// The time frame within which the batch should be processed.
+    pub completion_window: String,
+    /// The current status of the batch.
+    pub status: BatchStatus,
+    /// The ID of the file containing the outputs of successfully executed requests.
+    #[serde(default)]
+    pub output_file_id: Option<String>,
+    /// The ID of the file containing the outputs of requests with errors.
+    #[serde(default)]
+    pub error_file_id: Option<String>,
+    /// The Unix timestamp (in seconds) for when the batch was created.
+    pub created_at: u64,
+    /// The Unix timestamp (in seconds) for when the batch will expire.
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    /// The Unix timestamp (in seconds) for when the batch was completed.
+    #[serde(default)]
+    pub completed_at: Option<u64>,
+    /// The request counts for different statuses within the batch.
+    #[serde(default)]
+    pub request_counts: Option<BatchRequestCounts>,
+    /// Set of 16 key-value pairs attached to the batch.
+    #[serde(default)]
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchStatus {
+    Validating,
+    Failed,
+    InProgress,
+    Finalizing,
+    Completed,
+    Expired,
+    Cancelling,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatchRequestCounts {
+    /// Total number of requests in the batch.
+    pub total: usize,
+    /// Number of requests that have been completed successfully.
+    pub completed: usize,
+    /// Number of requests that have failed.
+    pub failed: usize,
+}
+
+/// The content of a batch output (or error) file, keyed by custom_id.
+#[derive(Debug, Clone)]
+pub struct BatchOutput<T> {
+    /// Responses of the requests which succeeded.
+    pub responses: HashMap<String, T>,
+    /// Errors of the requests which failed.
+    pub errors: HashMap<String, BatchRequestError>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatchRequestError {
+    /// A machine-readable error code.
+    #[serde(default)]
+    pub code: Option<String>,
+    /// A human-readable error message.
+    pub message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchOutputLine {
+    custom_id: String,
+    #[serde(default)]
+    response: Option<BatchOutputResponse>,
+    #[serde(default)]
+    error: Option<BatchRequestError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchOutputResponse {
+    status_code: u16,
+    body: serde_json::Value,
+}
+
+impl BatchRequestFile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a request to the file. custom_id must be unique within the file.
+    pub fn add(&mut self, custom_id: impl Into<String>, req: ChatCompletionRequest) -> Result<()> {
+        let custom_id = custom_id.into();
+        if !self.ids.insert(custom_id.clone()) {
+            return Err(anyhow!("duplicated custom_id: {}", custom_id));
+        }
+        self.lines.push(BatchRequestLine {
+            custom_id,
+            method: "POST",
+            url: "/v1/chat/completions",
+            body: req,
+        });
+        Ok(())
+    }
+
+    /// The custom_ids of the requests, in insertion order.
+    pub fn custom_ids(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().map(|line| line.custom_id.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub fn to_jsonl(&self) -> Result<String> {
+        let mut out = String::new();
+        for line in &self.lines {
+            out.push_str(&serde_json::to_string(line)?);
+            out.push('\n');
+        }
+        Ok(out)
+    }
+
+    /// Build the upload request for this file, with purpose set to batch.
+    pub fn into_upload_request(self, filename: impl Into<String>) -> Result<UploadFileRequest> {
+        let data = self.to_jsonl()?;
+        Ok(UploadFileRequest::new(
+            data.into_bytes(),
+            filename,
+            FilePurpose::Batch,
+        ))
+    }
+}
+
+impl<T: DeserializeOwned> BatchOutput<T> {
+    /// Parse the JSONL content of a batch output or error file.
+    pub fn parse(data: &str) -> Result<Self> {
+        let mut responses = HashMap::new();
+        let mut errors = HashMap::new();
+        for line in data.lines().filter(|l| !l.trim().is_empty()) {
+            let line: BatchOutputLine = serde_json::from_str(line)?;
+            if let Some(error) = line.error {
+                errors.insert(line.custom_id, error);
+                continue;
+            }
+            let Some(res) = line.response else {
+                return Err(anyhow!("no response nor error for {}", line.custom_id));
+            };
+            if res.status_code == 200 {
+                responses.insert(line.custom_id, serde_json::from_value(res.body)?);
+            } else {
+                let error = res
+                    .body
+                    .get("error")
+                    .cloned()
+                    .and_then(|e| serde_json::from_value(e).ok())
+                    .unwrap_or_else(|| BatchRequestError {
+                        code: Some(res.status_code.to_string()),
+                        message: res.body.to_string(),
+                    });
+                errors.insert(line.custom_id, error);
+            }
+        }
+        Ok(Self { responses, errors })
+    }
+}
+
+impl IntoRequest for CreateBatchRequest {
+    fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+        let url = format!("{}/batches", base_url);
+        client.post(url).json(&self)
+    }
+}
+
+impl CreateBatchRequest {
+    pub fn new(input_file_id: impl Into<String>) -> Self {
+        CreateBatchRequestBuilder::default()
+            .input_file_id(input_file_id)
+            .build()
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChatCompleteModel, ChatCompletionMessage, ChatCompletionResponse};
+    use serde_json::json;
+
+    fn request(content: &str) -> ChatCompletionRequest {
+        ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt3Turbo,
+            vec![ChatCompletionMessage::new_user(content, "")],
+        )
+    }
+
+    #[test]
+    fn batch_request_file_should_serialize_to_jsonl() -> Result<()> {
+        let mut file = BatchRequestFile::new();
+        file.add("request-1", request("Hello"))?;
+        file.add("request-2", request("World"))?;
+        assert!(file.add("request-1", request("again")).is_err());
+        assert_eq!(
+            file.custom_ids().collect::<Vec<_>>(),
+            ["request-1", "request-2"]
+        );
+
+        let jsonl = file.to_jsonl()?;
+        let lines: Vec<serde_json::Value> = jsonl
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(
+            lines[1],
+            json!({
+              "custom_id": "request-2",
+              "method": "POST",
+              "url": "/v1/chat/completions",
+              "body": {
+                "model": "gpt-3.5-turbo-1106",
+                "messages": [{ "role": "user", "content": "World" }]
+              }
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn batch_output_should_parse_by_custom_id() -> Result<()> {
+        let ok = json!({
+          "id": "batch_req_1",
+          "custom_id": "request-1",
+          "response": {
+            "status_code": 200,
+            "request_id": "req_1",
+            "body": {
+              "id": "chatcmpl-1",
+              "object": "chat.completion",
+              "created": 1711652795,
+              "model": "gpt-3.5-turbo-1106",
+              "system_fingerprint": "fp_1",
+              "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": "Hello." },
+                "finish_reason": "stop"
+              }],
+              "usage": { "prompt_tokens": 22, "completion_tokens": 2, "total_tokens": 24 }
+            }
+          },
+          "error": null
+        });
+        let failed = json!({
+          "id": "batch_req_2",
+          "custom_id": "request-2",
+          "response": {
+            "status_code": 400,
+            "request_id": "req_2",
+            "body": { "error": { "message": "bad request", "code": "invalid" } }
+          },
+          "error": null
+        });
+        let data = format!("{}\n{}\n", ok, failed);
+        let output = BatchOutput::<ChatCompletionResponse>::parse(&data)?;
+        assert_eq!(output.responses["request-1"].id, "chatcmpl-1");
+        assert_eq!(output.errors["request-2"].message, "bad request");
+        Ok(())
+    }
+}
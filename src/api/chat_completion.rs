@@ -1,75 +1,122 @@
-use crate::{IntoRequest, ToSchema};
+use super::common::with_idempotency_key;
+use crate::{
+    sse::SseEvent, usage::model_name, BuildError, IntoRequest, PostProcess, ProviderExt,
+    SpeechVoice, ToSchema, UsageTracker,
+};
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
 use derive_builder::Builder;
+use futures::{future, stream::BoxStream, Stream, StreamExt};
 use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::BTreeMap,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
 use strum::{Display, EnumIter, EnumMessage, EnumString, EnumVariantNames};
 
 #[derive(Debug, Clone, Serialize, Builder)]
+#[builder(build_fn(error = "BuildError"))]
 pub struct ChatCompletionRequest {
     /// A list of messages comprising the conversation so far.
     #[builder(setter(into))]
-    messages: Vec<ChatCompletionMessage>,
+    pub(crate) messages: Vec<ChatCompletionMessage>,
     /// ID of the model to use. See the model endpoint compatibility table for details on which models work with the Chat API.
     #[builder(default)]
-    model: ChatCompleteModel,
+    pub(crate) model: ChatCompleteModel,
     /// Number between -2.0 and 2.0. Positive values penalize new tokens based on their existing frequency in the text so far, decreasing the model's likelihood to repeat the same line verbatim.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
-    frequency_penalty: Option<f32>,
+    pub(crate) frequency_penalty: Option<f32>,
 
     // Modify the likelihood of specified tokens appearing in the completion. Accepts a JSON object that maps tokens (specified by their token ID in the tokenizer) to an associated bias value from -100 to 100. Mathematically, the bias is added to the logits generated by the model prior to sampling. The exact effect will vary per model, but values between -1 and 1 should decrease or increase likelihood of selection; values like -100 or 100 should result in a ban or exclusive selection of the relevant token.
     // #[builder(default, setter(strip_option))]
     // #[serde(skip_serializing_if = "Option::is_none")]
     // logit_bias: Option<f32>,
+    /// Whether to return the log probabilities of the output tokens in the `logprobs` of each choice.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) logprobs: Option<bool>,
     /// The maximum number of tokens to generate in the chat completion.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
-    max_tokens: Option<usize>,
+    pub(crate) max_tokens: Option<usize>,
+    /// Output types that you would like the model to generate, e.g. `[Text, Audio]` for the
+    /// gpt-4o-audio models. Defaults to text only.
+    #[builder(default, setter(into))]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    modalities: Vec<Modality>,
+    /// Parameters for audio output. Required when audio output is requested with `modalities`.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    audio: Option<AudioConfig>,
     /// How many chat completion choices to generate for each input message. Note that you will be charged based on the number of generated tokens across all of the choices. Keep n as 1 to minimize costs.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
-    n: Option<usize>,
+    pub(crate) n: Option<usize>,
     /// Number between -2.0 and 2.0. Positive values penalize new tokens based on whether they appear in the text so far, increasing the model's likelihood to talk about new topics.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
-    presence_penalty: Option<f32>,
-    /// An object specifying the format that the model must output. Setting to { "type": "json_object" } enables JSON mode, which guarantees the message the model generates is valid JSON.
+    pub(crate) presence_penalty: Option<f32>,
+    /// An object specifying the format that the model must output. `JsonObject` enables JSON mode, which guarantees the message the model generates is valid JSON. `JsonSchema` enables Structured Outputs which ensures the model will match your supplied JSON schema.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
-    response_format: Option<ChatResponseFormatObject>,
+    pub(crate) response_format: Option<ResponseFormat>,
     /// This feature is in Beta. If specified, our system will make a best effort to sample deterministically, such that repeated requests with the same seed and parameters should return the same result. Determinism is not guaranteed, and you should refer to the system_fingerprint response parameter to monitor changes in the backend.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
-    seed: Option<usize>,
+    pub(crate) seed: Option<i64>,
     /// Up to 4 sequences where the API will stop generating further tokens.
     // TODO: make this as an enum
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
-    stop: Option<String>,
+    pub(crate) stop: Option<String>,
     /// If set, partial message deltas will be sent, like in ChatGPT. Tokens will be sent as data-only server-sent events as they become available, with the stream terminated by a data: [DONE] message.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
-    stream: Option<bool>,
+    pub(crate) stream: Option<bool>,
+    /// Options for streaming response. Only set this when you set stream: true.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) stream_options: Option<StreamOptions>,
     /// What sampling temperature to use, between 0 and 2. Higher values like 0.8 will make the output more random, while lower values like 0.2 will make it more focused and deterministic. We generally recommend altering this or top_p but not both.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
-    temperature: Option<f32>,
+    pub(crate) temperature: Option<f32>,
     /// An alternative to sampling with temperature, called nucleus sampling, where the model considers the results of the tokens with top_p probability mass. So 0.1 means only the tokens comprising the top 10% probability mass are considered. We generally recommend altering this or temperature but not both.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
-    top_p: Option<f32>,
+    pub(crate) top_p: Option<f32>,
     /// A list of tools the model may call. Currently, only functions are supported as a tool. Use this to provide a list of functions the model may generate JSON inputs for.
     #[builder(default, setter(into))]
     #[serde(skip_serializing_if = "Vec::is_empty")]
-    tools: Vec<Tool>,
+    pub(crate) tools: Vec<Tool>,
     /// Controls which (if any) function is called by the model. none means the model will not call a function and instead generates a message. auto means the model can pick between generating a message or calling a function. Specifying a particular function via {"type: "function", "function": {"name": "my_function"}} forces the model to call that function. none is the default when no functions are present. auto is the default if functions are present.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
-    tool_choice: Option<ToolChoice>,
+    pub(crate) tool_choice: Option<ToolChoice>,
     /// A unique identifier representing your end-user, which can help OpenAI to monitor and detect abuse.
     #[builder(default, setter(strip_option, into))]
     #[serde(skip_serializing_if = "Option::is_none")]
-    user: Option<String>,
+    pub(crate) user: Option<String>,
+    /// Post-processing of the texts of the response, applied by `LlmSdk::chat_completion`. Not
+    /// sent to the API.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip)]
+    pub(crate) post_process: Option<PostProcess>,
+    /// Sent as the `Idempotency-Key` header, so a retry isn't run (and billed) twice by the
+    /// gateways supporting it. A random key is generated if not set.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(skip)]
+    pub(crate) idempotency_key: Option<String>,
+    /// Overrides the timeout of the SDK (`LlmSdkBuilder::timeout`) for this request.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip)]
+    pub(crate) timeout: Option<Duration>,
 }
 
 #[derive(
@@ -91,35 +138,76 @@ pub struct Tool {
     /// The schema of the tool. Currently, only functions are supported.
     r#type: ToolType,
     /// The schema of the tool. Currently, only functions are supported.
-    function: FunctionInfo,
+    pub(crate) function: FunctionInfo,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionInfo {
     /// A description of what the function does, used by the model to choose when and how to call the function.
-    description: String,
+    pub(crate) description: String,
     /// The name of the function to be called. Must be a-z, A-Z, 0-9, or contain underscores and dashes, with a maximum length of 64.
-    name: String,
+    pub(crate) name: String,
     /// The parameters the functions accepts, described as a JSON Schema object.
-    parameters: serde_json::Value,
+    pub(crate) parameters: serde_json::Value,
 }
 
-#[derive(Debug, Clone, Serialize)]
-pub struct ChatResponseFormatObject {
-    r#type: ChatResponseFormat,
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct StreamOptions {
+    /// If set, an additional chunk will be streamed before the data: [DONE] message. The usage field on this chunk shows the token usage statistics for the entire request, and the choices field will always be an empty array.
+    pub include_usage: bool,
 }
 
 #[derive(
     Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, EnumString, Display, EnumVariantNames,
 )]
 #[serde(rename_all = "snake_case")]
-pub enum ChatResponseFormat {
+#[strum(serialize_all = "snake_case")]
+pub enum Modality {
+    #[default]
     Text,
+    Audio,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct AudioConfig {
+    /// The voice the model uses to respond.
+    pub voice: SpeechVoice,
+    /// The format of the output audio.
+    pub format: ChatAudioFormat,
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, EnumString, Display, EnumVariantNames,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ChatAudioFormat {
     #[default]
-    Json,
+    Wav,
+    Mp3,
+    Flac,
+    Opus,
+    Pcm16,
 }
 
-#[derive(Debug, Clone, Serialize, Display, EnumVariantNames, EnumMessage)]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ResponseFormat {
+    #[default]
+    Text,
+    /// JSON mode, which guarantees the message the model generates is valid JSON.
+    JsonObject,
+    /// Structured Outputs, the message matches the JSON schema. See `ResponseFormat::json_schema`.
+    JsonSchema {
+        /// The name of the response format. Must be a-z, A-Z, 0-9, or contain underscores and dashes, with a maximum length of 64.
+        name: String,
+        /// The schema for the response format, described as a JSON Schema object.
+        schema: serde_json::Value,
+        /// Whether to enable strict schema adherence when generating the output. Only a subset of JSON Schema is supported when strict is true.
+        strict: bool,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Display, EnumVariantNames, EnumMessage)]
 #[serde(rename_all = "snake_case", tag = "role")]
 pub enum ChatCompletionMessage {
     /// A message from a system.
@@ -135,7 +223,6 @@ pub enum ChatCompletionMessage {
 #[derive(
     Debug,
     Clone,
-    Copy,
     Default,
     PartialEq,
     Eq,
@@ -166,24 +253,125 @@ pub enum ChatCompleteModel {
     #[serde(rename = "gpt-4-1106-vision-preview")]
     #[strum(serialize = "gpt-4-turbo-vision")]
     Gpt4TurboVision,
+    /// GPT-4o, the multimodal flagship model which accepts text and image inputs.
+    #[serde(rename = "gpt-4o")]
+    #[strum(serialize = "gpt-4o")]
+    Gpt4o,
+    /// GPT-4o with audio input and output, see `modalities` and `audio` of the request.
+    #[serde(rename = "gpt-4o-audio-preview")]
+    #[strum(serialize = "gpt-4o-audio-preview")]
+    Gpt4oAudioPreview,
+    /// Anthropic Claude 3.5 Sonnet, served by the `Anthropic` provider.
+    #[serde(rename = "claude-3-5-sonnet-20240620")]
+    #[strum(serialize = "claude-3.5-sonnet")]
+    Claude35Sonnet,
+    /// Anthropic Claude 3 Opus, served by the `Anthropic` provider.
+    #[serde(rename = "claude-3-opus-20240229")]
+    #[strum(serialize = "claude-3-opus")]
+    Claude3Opus,
+    /// Anthropic Claude 3 Haiku, served by the `Anthropic` provider.
+    #[serde(rename = "claude-3-haiku-20240307")]
+    #[strum(serialize = "claude-3-haiku")]
+    Claude3Haiku,
+    /// Any other model by its name, e.g. the ones served by Ollama or other OpenAI compatible servers.
+    #[serde(untagged)]
+    #[strum(default)]
+    Other(String),
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SystemMessage {
     /// The contents of the system message.
-    content: String,
+    pub(crate) content: String,
     /// An optional name for the participant. Provides the model information to differentiate between participants of the same role.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub(crate) name: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserMessage {
     /// The contents of the user message.
-    content: String,
+    pub(crate) content: UserContent,
     /// An optional name for the participant. Provides the model information to differentiate between participants of the same role.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub(crate) name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum UserContent {
+    /// The text contents of the message.
+    Text(String),
+    /// An array of content parts, e.g. text and images for the vision models.
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+    InputAudio { input_audio: InputAudio },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageUrl {
+    /// Either a URL of the image or the base64 encoded image data as a data URI.
+    pub url: String,
+    /// Specifies the detail level of the image.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub detail: Option<ImageDetail>,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    EnumString,
+    Display,
+    EnumVariantNames,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ImageDetail {
+    Low,
+    High,
+    #[default]
+    Auto,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputAudio {
+    /// Base64 encoded audio data.
+    pub data: String,
+    /// The format of the encoded audio data.
+    pub format: InputAudioFormat,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    EnumString,
+    Display,
+    EnumVariantNames,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum InputAudioFormat {
+    #[default]
+    Wav,
+    Mp3,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -197,14 +385,39 @@ pub struct AssistantMessage {
     /// The tool calls generated by the model, such as function calls.
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub tool_calls: Vec<ToolCall>,
+    /// The audio response of the model when audio output is requested. When the message is sent
+    /// back in a conversation, only the `id` is serialized.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_audio_id",
+        default
+    )]
+    pub audio: Option<AudioOutput>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Deserialize)]
+pub struct AudioOutput {
+    /// Unique identifier for this audio response.
+    pub id: String,
+    /// The Unix timestamp (in seconds) for when this audio response will no longer be accessible
+    /// on the server for use in multi-turn conversations. Like the data and the transcript, it's
+    /// missing from a message restored from a transcript, which only keeps the `id`.
+    #[serde(default)]
+    pub expires_at: usize,
+    /// Base64 encoded audio bytes generated by the model, in the format specified in the request.
+    #[serde(default)]
+    pub data: String,
+    /// Transcript of the audio generated by the model.
+    #[serde(default)]
+    pub transcript: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolMessage {
     /// The contents of the tool message.
-    content: String,
+    pub(crate) content: String,
     /// Tool call that this message is responding to.
-    tool_call_id: String,
+    pub(crate) tool_call_id: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -254,12 +467,17 @@ pub struct ChatCompletionResponse {
     pub created: usize,
     /// The model used for the chat completion.
     pub model: ChatCompleteModel,
-    /// This fingerprint represents the backend configuration that the model runs with. Can be used in conjunction with the seed request parameter to understand when backend changes have been made that might impact determinism.
-    pub system_fingerprint: String,
+    /// This fingerprint represents the backend configuration that the model runs with. Can be used in conjunction with the seed request parameter to understand when backend changes have been made that might impact determinism. Not set by some providers and OpenAI compatible servers.
+    #[serde(default)]
+    pub system_fingerprint: Option<String>,
     /// The object type, which is always chat.completion.
     pub object: String,
-    /// Usage statistics for the completion request.
+    /// Usage statistics for the completion request. Some OpenAI compatible servers don't return it, in that case it's all zeros.
+    #[serde(default)]
     pub usage: ChatCompleteUsage,
+    /// The extras of the provider that have no OpenAI counterpart, e.g. the Groq timing.
+    #[serde(skip)]
+    pub provider_ext: Option<ProviderExt>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -270,9 +488,26 @@ pub struct ChatCompletionChoice {
     pub index: usize,
     /// A chat completion message generated by the model.
     pub message: AssistantMessage,
+    /// The log probabilities of the tokens, only set when the request asked for `logprobs`.
+    #[serde(default)]
+    pub logprobs: Option<ChatLogprobs>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ChatLogprobs {
+    /// The tokens of the content with their log probabilities.
+    pub content: Vec<TokenLogprob>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
+pub struct TokenLogprob {
+    pub token: String,
+    pub logprob: f32,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
 pub struct ChatCompleteUsage {
     /// Number of tokens in the generated completion.
     pub completion_tokens: usize,
@@ -294,10 +529,268 @@ pub enum FinishReason {
     ToolCalls,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChatCompletionChunk {
+    /// A unique identifier for the chat completion. Each chunk has the same ID.
+    pub id: String,
+    /// A list of chat completion choices. Can be more than one if n is greater than 1.
+    pub choices: Vec<ChatCompletionChunkChoice>,
+    /// The Unix timestamp (in seconds) of when the chat completion was created. Each chunk has the same timestamp.
+    pub created: usize,
+    /// The model to generate the completion.
+    pub model: ChatCompleteModel,
+    /// This fingerprint represents the backend configuration that the model runs with.
+    #[serde(default)]
+    pub system_fingerprint: Option<String>,
+    /// The object type, which is always chat.completion.chunk.
+    pub object: String,
+    /// Usage statistics for the completion request. Only present in the last chunk when requested by stream_options.
+    #[serde(default)]
+    pub usage: Option<ChatCompleteUsage>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChatCompletionChunkChoice {
+    /// A chat completion delta generated by streamed model responses.
+    pub delta: ChatCompletionDelta,
+    /// The reason the model stopped generating tokens. Only set in the last chunk of a choice.
+    #[serde(default)]
+    pub finish_reason: Option<FinishReason>,
+    /// The index of the choice in the list of choices.
+    pub index: usize,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ChatCompletionDelta {
+    /// The role of the author of this message. Only set in the first chunk.
+    #[serde(default)]
+    pub role: Option<String>,
+    /// The contents of the chunk message.
+    #[serde(default)]
+    pub content: Option<String>,
+    /// The partial tool calls in this chunk.
+    #[serde(default)]
+    pub tool_calls: Vec<ToolCallDelta>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ToolCallDelta {
+    /// The index of the tool call this fragment belongs to.
+    pub index: usize,
+    /// The ID of the tool call. Only set in the first fragment.
+    #[serde(default)]
+    pub id: Option<String>,
+    /// The type of the tool. Only set in the first fragment.
+    #[serde(default)]
+    pub r#type: Option<ToolType>,
+    /// The partial function call.
+    #[serde(default)]
+    pub function: Option<FunctionCallDelta>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FunctionCallDelta {
+    /// The name of the function. Only set in the first fragment.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// A fragment of the JSON arguments.
+    #[serde(default)]
+    pub arguments: Option<String>,
+}
+
+/// Merges the streamed tool call fragments of a choice (by their index) into complete tool calls.
+#[derive(Debug, Clone, Default)]
+pub struct ToolCallAccumulator {
+    calls: BTreeMap<usize, ToolCall>,
+}
+
+/// A stream of chat completion chunks, ends after the `[DONE]` message.
+pub struct ChatCompletionStream {
+    inner: BoxStream<'static, Result<ChatCompletionChunk>>,
+}
+
+impl ChatCompletionStream {
+    pub(crate) fn new(events: impl Stream<Item = Result<SseEvent>> + Send + 'static) -> Self {
+        let inner = events
+            .take_while(|event| future::ready(!matches!(event, Ok(e) if e.data == "[DONE]")))
+            .map(|event| {
+                let event = event?;
+                serde_json::from_str::<ChatCompletionChunk>(&event.data)
+                    .map_err(|e| anyhow!("failed to parse chunk {}: {}", event.data, e))
+            })
+            .boxed();
+        Self { inner }
+    }
+
+    pub(crate) fn from_chunks(inner: BoxStream<'static, Result<ChatCompletionChunk>>) -> Self {
+        Self { inner }
+    }
+
+    /// Record the usage of the last chunk (requested with `stream_options.include_usage`).
+    pub(crate) fn track_usage(self, tracker: Arc<UsageTracker>) -> Self {
+        let inner = self
+            .inner
+            .inspect(move |chunk| {
+                if let Ok(ChatCompletionChunk {
+                    model,
+                    usage: Some(usage),
+                    ..
+                }) = chunk
+                {
+                    tracker.record(
+                        &model_name(model),
+                        "chat/completions",
+                        usage.prompt_tokens,
+                        usage.completion_tokens,
+                    );
+                }
+            })
+            .boxed();
+        Self { inner }
+    }
+}
+
+impl ChatCompletionStream {
+    /// Consume the stream and rebuild the whole response: the content is concatenated and the tool
+    /// calls merged per choice. `on_chunk` is called for every chunk, e.g. to show the progress.
+    /// The usage is only available when the request set `stream_options.include_usage`, otherwise
+    /// it's all zeros.
+    pub async fn collect_message(
+        mut self,
+        mut on_chunk: impl FnMut(&ChatCompletionChunk),
+    ) -> Result<ChatCompletionResponse> {
+        let mut first: Option<ChatCompletionChunk> = None;
+        let mut system_fingerprint = None;
+        let mut usage = None;
+        let mut choices: BTreeMap<usize, (String, ToolCallAccumulator, Option<FinishReason>)> =
+            BTreeMap::new();
+        while let Some(chunk) = self.next().await {
+            let chunk = chunk?;
+            on_chunk(&chunk);
+            for choice in &chunk.choices {
+                let (content, tool_calls, finish_reason) = choices.entry(choice.index).or_default();
+                if let Some(delta) = &choice.delta.content {
+                    content.push_str(delta);
+                }
+                for delta in &choice.delta.tool_calls {
+                    tool_calls.push_delta(delta);
+                }
+                if choice.finish_reason.is_some() {
+                    *finish_reason = choice.finish_reason;
+                }
+            }
+            if chunk.system_fingerprint.is_some() {
+                system_fingerprint = chunk.system_fingerprint.clone();
+            }
+            if chunk.usage.is_some() {
+                usage = chunk.usage.clone();
+            }
+            if first.is_none() {
+                first = Some(chunk);
+            }
+        }
+        let first = first.ok_or_else(|| anyhow!("the stream has no chunk"))?;
+        let choices = choices
+            .into_iter()
+            .map(
+                |(index, (content, mut tool_calls, finish_reason))| ChatCompletionChoice {
+                    finish_reason: finish_reason.unwrap_or_default(),
+                    index,
+                    message: AssistantMessage {
+                        content: (!content.is_empty()).then_some(content),
+                        name: None,
+                        tool_calls: tool_calls.take(),
+                        audio: None,
+                    },
+                    logprobs: None,
+                },
+            )
+            .collect();
+        Ok(ChatCompletionResponse {
+            id: first.id,
+            choices,
+            created: first.created,
+            model: first.model,
+            system_fingerprint,
+            object: "chat.completion".into(),
+            usage: usage.unwrap_or_default(),
+            provider_ext: None,
+        })
+    }
+}
+
+impl Stream for ChatCompletionStream {
+    type Item = Result<ChatCompletionChunk>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+impl ToolCallAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Merge a fragment of a tool call. Only the arguments are streamed in fragments, the id and
+    /// the name are kept from their first delta (some servers repeat them in every delta).
+    pub fn push_delta(&mut self, delta: &ToolCallDelta) {
+        let call = self.calls.entry(delta.index).or_insert_with(|| ToolCall {
+            id: String::new(),
+            r#type: ToolType::Function,
+            function: FunctionCall {
+                name: String::new(),
+                arguments: String::new(),
+            },
+        });
+        if let Some(id) = delta.id.as_ref().filter(|_| call.id.is_empty()) {
+            call.id.clone_from(id);
+        }
+        if let Some(r#type) = delta.r#type {
+            call.r#type = r#type;
+        }
+        if let Some(function) = &delta.function {
+            if let Some(name) = function
+                .name
+                .as_ref()
+                .filter(|_| call.function.name.is_empty())
+            {
+                call.function.name.clone_from(name);
+            }
+            if let Some(arguments) = &function.arguments {
+                call.function.arguments.push_str(arguments);
+            }
+        }
+    }
+
+    /// Merge the tool call fragments of a chunk choice. Once the choice has a finish_reason, the
+    /// complete tool calls (in index order) are returned and the accumulator is reset.
+    pub fn push(&mut self, choice: &ChatCompletionChunkChoice) -> Option<Vec<ToolCall>> {
+        for delta in &choice.delta.tool_calls {
+            self.push_delta(delta);
+        }
+        choice.finish_reason.map(|_| self.take())
+    }
+
+    /// The tool calls merged so far, in index order. The accumulator is reset.
+    pub fn take(&mut self) -> Vec<ToolCall> {
+        std::mem::take(&mut self.calls).into_values().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+}
+
 impl IntoRequest for ChatCompletionRequest {
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
     fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
         let url = format!("{}/chat/completions", base_url);
-        client.post(url).json(&self)
+        let req = client.post(url).json(&self);
+        with_idempotency_key(req, self.idempotency_key)
     }
 }
 
@@ -322,6 +815,67 @@ impl ChatCompletionRequest {
             .build()
             .unwrap()
     }
+
+    pub fn messages(&self) -> &[ChatCompletionMessage] {
+        &self.messages
+    }
+
+    /// Adjust the conversation in place, e.g. to truncate it or to redact the messages.
+    pub fn messages_mut(&mut self) -> &mut Vec<ChatCompletionMessage> {
+        &mut self.messages
+    }
+
+    pub fn model(&self) -> &ChatCompleteModel {
+        &self.model
+    }
+
+    pub fn set_model(&mut self, model: ChatCompleteModel) -> &mut Self {
+        self.model = model;
+        self
+    }
+
+    pub fn max_tokens(&self) -> Option<usize> {
+        self.max_tokens
+    }
+
+    pub fn set_max_tokens(&mut self, max_tokens: Option<usize>) -> &mut Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    pub fn set_temperature(&mut self, temperature: Option<f32>) -> &mut Self {
+        self.temperature = temperature;
+        self
+    }
+
+    pub fn tools_mut(&mut self) -> &mut Vec<Tool> {
+        &mut self.tools
+    }
+
+    pub fn set_tool_choice(&mut self, tool_choice: Option<ToolChoice>) -> &mut Self {
+        self.tool_choice = tool_choice;
+        self
+    }
+
+    pub fn set_user(&mut self, user: Option<String>) -> &mut Self {
+        self.user = user;
+        self
+    }
+}
+
+impl ChatCompleteModel {
+    /// The context window in tokens (prompt and completion), `None` when unknown, e.g. `Other`.
+    pub fn context_window(&self) -> Option<usize> {
+        match self {
+            Self::Gpt3Turbo => Some(16_385),
+            Self::Gpt3TurboInstruct => Some(4_096),
+            Self::Gpt4Turbo | Self::Gpt4TurboVision | Self::Gpt4o | Self::Gpt4oAudioPreview => {
+                Some(128_000)
+            }
+            Self::Claude35Sonnet | Self::Claude3Opus | Self::Claude3Haiku => Some(200_000),
+            Self::Other(_) => None,
+        }
+    }
 }
 
 impl ChatCompletionMessage {
@@ -332,9 +886,37 @@ impl ChatCompletionMessage {
         })
     }
 
+    pub fn new_assistant(content: impl Into<String>) -> ChatCompletionMessage {
+        ChatCompletionMessage::Assistant(AssistantMessage {
+            content: Some(content.into()),
+            name: None,
+            tool_calls: vec![],
+            audio: None,
+        })
+    }
+
     pub fn new_user(content: impl Into<String>, name: &str) -> ChatCompletionMessage {
         ChatCompletionMessage::User(UserMessage {
+            content: UserContent::Text(content.into()),
+            name: Self::get_name(name),
+        })
+    }
+
+    /// The result of the tool call `tool_call_id`.
+    pub fn new_tool(content: impl Into<String>, tool_call_id: impl Into<String>) -> Self {
+        ChatCompletionMessage::Tool(ToolMessage {
             content: content.into(),
+            tool_call_id: tool_call_id.into(),
+        })
+    }
+
+    /// A user message made of content parts, e.g. text and images for the vision models.
+    pub fn new_user_with_parts(
+        parts: impl Into<Vec<ContentPart>>,
+        name: &str,
+    ) -> ChatCompletionMessage {
+        ChatCompletionMessage::User(UserMessage {
+            content: UserContent::Parts(parts.into()),
             name: Self::get_name(name),
         })
     }
@@ -348,19 +930,175 @@ impl ChatCompletionMessage {
     }
 }
 
+impl ContentPart {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self::Text { text: text.into() }
+    }
+
+    /// An image by its URL (or data URI).
+    pub fn image_url(url: impl Into<String>, detail: Option<ImageDetail>) -> Self {
+        Self::ImageUrl {
+            image_url: ImageUrl {
+                url: url.into(),
+                detail,
+            },
+        }
+    }
+
+    /// An image from local bytes, sent as a base64 data URI. `mime` is e.g. `image/png`.
+    pub fn image_bytes(data: &[u8], mime: &str, detail: Option<ImageDetail>) -> Self {
+        Self::image_url(
+            format!("data:{};base64,{}", mime, STANDARD.encode(data)),
+            detail,
+        )
+    }
+}
+
+impl ContentPart {
+    /// Audio from local bytes, for the models accepting audio input.
+    pub fn input_audio(data: &[u8], format: InputAudioFormat) -> Self {
+        Self::InputAudio {
+            input_audio: InputAudio {
+                data: STANDARD.encode(data),
+                format,
+            },
+        }
+    }
+}
+
+impl AudioOutput {
+    /// Decode the base64 audio data.
+    pub fn bytes(&self) -> Result<Vec<u8>> {
+        Ok(STANDARD.decode(&self.data)?)
+    }
+}
+
+fn serialize_audio_id<S: serde::Serializer>(
+    audio: &Option<AudioOutput>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct AudioId<'a> {
+        id: &'a str,
+    }
+    audio
+        .as_ref()
+        .map(|a| AudioId { id: &a.id })
+        .serialize(serializer)
+}
+
+impl Serialize for ResponseFormat {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Text => json!({ "type": "text" }).serialize(serializer),
+            Self::JsonObject => json!({ "type": "json_object" }).serialize(serializer),
+            Self::JsonSchema {
+                name,
+                schema,
+                strict,
+            } => json!({
+                "type": "json_schema",
+                "json_schema": { "name": name, "schema": schema, "strict": strict }
+            })
+            .serialize(serializer),
+        }
+    }
+}
+
+impl ResponseFormat {
+    /// A strict JSON schema response format generated from `T`. The name is the title of the
+    /// schema (i.e. the type name).
+    pub fn json_schema<T: ToSchema>() -> Self {
+        let mut schema = T::to_schema();
+        let name = schema["title"]
+            .as_str()
+            .map(|title| {
+                title
+                    .chars()
+                    .map(|c| match c {
+                        'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' => c,
+                        _ => '_',
+                    })
+                    .take(64)
+                    .collect()
+            })
+            .unwrap_or_else(|| "response".to_string());
+        if let Some(obj) = schema.as_object_mut() {
+            obj.remove("$schema");
+        }
+        make_strict(&mut schema);
+        Self::JsonSchema {
+            name,
+            schema,
+            strict: true,
+        }
+    }
+}
+
+/// Strict mode requires `additionalProperties: false` on every object and all the properties in
+/// `required` (schemars already makes the `Option` fields nullable), and doesn't support `oneOf`,
+/// which schemars emits for documented enum variants.
+fn make_strict(schema: &mut serde_json::Value) {
+    match schema {
+        serde_json::Value::Object(obj) => {
+            if let Some(serde_json::Value::Object(properties)) = obj.get("properties") {
+                let required: Vec<serde_json::Value> =
+                    properties.keys().map(|k| k.clone().into()).collect();
+                obj.insert("additionalProperties".into(), false.into());
+                obj.insert("required".into(), required.into());
+            }
+            if let Some(variants) = obj.remove("oneOf") {
+                obj.insert("anyOf".into(), variants);
+            }
+            obj.values_mut().for_each(make_strict);
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(make_strict),
+        _ => {}
+    }
+}
+
+impl ChatCompletionResponse {
+    /// Deserialize the content of the first choice, e.g. for JSON mode or Structured Outputs.
+    /// A markdown code fence around the JSON, which some models add, is ignored.
+    pub fn parse_content<T: DeserializeOwned>(&self) -> Result<T> {
+        let content = self
+            .choices
+            .first()
+            .and_then(|choice| choice.message.content.as_deref())
+            .ok_or_else(|| anyhow!("the response has no content"))?;
+        serde_json::from_str(strip_code_fence(content))
+            .map_err(|e| anyhow!("failed to parse content {}: {}", content, e))
+    }
+}
+
+fn strip_code_fence(content: &str) -> &str {
+    let trimmed = content.trim();
+    trimmed
+        .strip_prefix("```")
+        .and_then(|rest| rest.strip_suffix("```"))
+        // skip the language tag, e.g. ```json
+        .map(|rest| rest.trim_start_matches(|c: char| c.is_ascii_alphanumeric()))
+        .unwrap_or(trimmed)
+}
+
 impl Tool {
     pub fn new_function<T: ToSchema>(
         name: impl Into<String>,
         description: impl Into<String>,
     ) -> Self {
-        let parameters = T::to_schema();
         Self {
             r#type: ToolType::Function,
-            function: FunctionInfo {
-                name: name.into(),
-                description: description.into(),
-                parameters,
-            },
+            function: FunctionInfo::new::<T>(name, description),
+        }
+    }
+}
+
+impl FunctionInfo {
+    pub fn new<T: ToSchema>(name: impl Into<String>, description: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            parameters: T::to_schema(),
         }
     }
 }
@@ -464,6 +1202,73 @@ mod tests {
         );
     }
 
+    #[test]
+    fn chat_completion_request_should_be_mutable_in_place() {
+        let mut req = get_simple_completion_request();
+        // keep the system message and the last message only
+        let messages = req.messages_mut();
+        messages.insert(1, ChatCompletionMessage::new_user("Hello", ""));
+        messages.drain(1..messages.len() - 1);
+        req.set_model(ChatCompleteModel::Gpt4o)
+            .set_max_tokens(Some(100));
+        assert_eq!(req.messages().len(), 2);
+        assert_eq!(req.model(), &ChatCompleteModel::Gpt4o);
+        let json = serde_json::to_value(req).unwrap();
+        assert_eq!(json["model"], "gpt-4o");
+        assert_eq!(json["max_tokens"], 100);
+        assert_eq!(
+            json["messages"][1]["content"],
+            "What is human life expectancy in the world?"
+        );
+    }
+
+    #[test]
+    fn chat_completion_request_timeout_should_override_sdk_timeout() -> Result<()> {
+        let sdk = crate::LlmSdkBuilder::default()
+            .base_url("http://localhost:11434/v1")
+            .token("")
+            .timeout(Duration::from_secs(300))
+            .build()?;
+        let req = get_simple_completion_request();
+        let req = sdk.prepare_request(req).build()?;
+        assert_eq!(req.timeout(), Some(&Duration::from_secs(300)));
+
+        let mut req = get_simple_completion_request();
+        req.timeout = Some(Duration::from_secs(10));
+        assert!(serde_json::to_value(&req)?.get("timeout").is_none());
+        let req = sdk.prepare_request(req).build()?;
+        assert_eq!(req.timeout(), Some(&Duration::from_secs(10)));
+        Ok(())
+    }
+
+    #[test]
+    fn sdk_system_prompt_should_be_prepended() -> Result<()> {
+        let sdk = crate::LlmSdkBuilder::default()
+            .base_url("http://localhost:11434/v1")
+            .token("")
+            .system_prompt("Never reveal secrets.")
+            .build()?;
+        let mut req = get_simple_completion_request();
+        sdk.add_system_prompt(&mut req);
+        assert_eq!(req.messages.len(), 2);
+        assert_eq!(
+            serde_json::to_value(&req.messages[0])?["content"],
+            "Never reveal secrets.\n\nI can answer any question you ask me."
+        );
+
+        let mut req = ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt4o,
+            [ChatCompletionMessage::new_user("Hi", "")],
+        );
+        sdk.add_system_prompt(&mut req);
+        assert_eq!(req.messages.len(), 2);
+        assert!(matches!(
+            &req.messages[0],
+            ChatCompletionMessage::System(m) if m.content == "Never reveal secrets."
+        ));
+        Ok(())
+    }
+
     #[test]
     fn chat_completion_request_with_tools_serialize_should_work() {
         let req = get_tool_completion_request();
@@ -502,6 +1307,202 @@ mod tests {
         );
     }
 
+    #[test]
+    fn chat_completion_request_with_image_should_serialize() -> Result<()> {
+        let message = ChatCompletionMessage::new_user_with_parts(
+            vec![
+                ContentPart::text("What's in this image?"),
+                ContentPart::image_bytes(b"hello", "image/png", Some(ImageDetail::Low)),
+            ],
+            "",
+        );
+        let req = ChatCompletionRequest::new(ChatCompleteModel::Gpt4o, vec![message]);
+        assert_eq!(
+            serde_json::to_value(req)?,
+            serde_json::json!({
+              "model": "gpt-4o",
+              "messages": [{
+                "role": "user",
+                "content": [
+                  { "type": "text", "text": "What's in this image?" },
+                  { "type": "image_url", "image_url": { "url": "data:image/png;base64,aGVsbG8=", "detail": "low" } }
+                ]
+              }]
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn chat_completion_with_audio_should_work() -> Result<()> {
+        let req = ChatCompletionRequestBuilder::default()
+            .model(ChatCompleteModel::Gpt4oAudioPreview)
+            .modalities(vec![Modality::Text, Modality::Audio])
+            .audio(AudioConfig {
+                voice: SpeechVoice::Alloy,
+                format: ChatAudioFormat::Wav,
+            })
+            .messages(vec![ChatCompletionMessage::new_user_with_parts(
+                vec![ContentPart::input_audio(b"hello", InputAudioFormat::Mp3)],
+                "",
+            )])
+            .build()?;
+        let value = serde_json::to_value(req)?;
+        assert_eq!(value["modalities"], serde_json::json!(["text", "audio"]));
+        assert_eq!(
+            value["audio"],
+            serde_json::json!({ "voice": "alloy", "format": "wav" })
+        );
+        assert_eq!(
+            value["messages"][0]["content"][0],
+            serde_json::json!({
+              "type": "input_audio",
+              "input_audio": { "data": "aGVsbG8=", "format": "mp3" }
+            })
+        );
+
+        let message: AssistantMessage = serde_json::from_value(serde_json::json!({
+          "role": "assistant",
+          "content": null,
+          "audio": { "id": "audio_1", "expires_at": 1729018505, "data": "aGVsbG8=", "transcript": "Hello" }
+        }))?;
+        let audio = message.audio.as_ref().unwrap();
+        assert_eq!(audio.bytes()?, b"hello");
+        assert_eq!(audio.transcript, "Hello");
+        assert_eq!(
+            serde_json::to_value(&message)?["audio"],
+            serde_json::json!({ "id": "audio_1" })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn response_format_json_schema_should_serialize() -> Result<()> {
+        let format = ResponseFormat::json_schema::<GetWeatherArgs>();
+        let value = serde_json::to_value(&format)?;
+        assert_eq!(value["type"], "json_schema");
+        assert_eq!(value["json_schema"]["name"], "GetWeatherArgs");
+        assert_eq!(value["json_schema"]["strict"], true);
+        let schema = &value["json_schema"]["schema"];
+        assert_eq!(schema["additionalProperties"], false);
+        assert!(schema.get("$schema").is_none());
+        assert_eq!(
+            serde_json::to_value(ResponseFormat::JsonObject)?,
+            json!({ "type": "json_object" })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn response_format_json_schema_should_require_optional_fields() -> Result<()> {
+        #[allow(dead_code)]
+        #[derive(Debug, Deserialize, JsonSchema)]
+        struct Forecast {
+            city: String,
+            days: Option<u8>,
+            unit: Option<TemperatureUnit>,
+        }
+
+        let ResponseFormat::JsonSchema { schema, .. } = ResponseFormat::json_schema::<Forecast>()
+        else {
+            panic!("not a json schema");
+        };
+        assert_eq!(schema["required"], json!(["city", "days", "unit"]));
+        assert_eq!(
+            schema["properties"]["days"]["type"],
+            json!(["integer", "null"])
+        );
+        assert_eq!(
+            schema["properties"]["unit"]["anyOf"][1],
+            json!({ "type": "null" })
+        );
+        let unit = &schema["definitions"]["TemperatureUnit"];
+        assert!(unit.get("required").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_content_should_ignore_code_fence() -> Result<()> {
+        let res: ChatCompletionResponse = serde_json::from_value(json!({
+          "id": "chatcmpl-1",
+          "object": "chat.completion",
+          "created": 1715000000,
+          "model": "llama3",
+          "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": "```json\n{\"city\": \"Boston\"}\n```" },
+            "finish_reason": "stop"
+          }]
+        }))?;
+        let value: serde_json::Value = res.parse_content()?;
+        assert_eq!(value, json!({ "city": "Boston" }));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn chat_completion_json_should_work() -> Result<()> {
+        let messages = vec![ChatCompletionMessage::new_user(
+            "Give me the city and unit of \"Boston in celsius\" as JSON with the keys city and unit",
+            "",
+        )];
+        let req = ChatCompletionRequest::new(ChatCompleteModel::Gpt4o, messages);
+        let value: serde_json::Value = SDK.chat_completion_json(req, 3).await?;
+        assert_eq!(value["city"], "Boston");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn chat_completion_structured_should_work() -> Result<()> {
+        let messages = vec![ChatCompletionMessage::new_user(
+            "What's the weather like in Boston in celsius?",
+            "",
+        )];
+        let req = ChatCompletionRequest::new(ChatCompleteModel::Gpt4o, messages);
+        let args: GetWeatherArgs = SDK.chat_completion_structured(req).await?;
+        assert_eq!(args.city, "Boston");
+        assert_eq!(args.unit, TemperatureUnit::Celsius);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn chat_completion_with_seed_should_work() -> Result<()> {
+        let req = ChatCompletionRequestBuilder::default()
+            .model(ChatCompleteModel::Gpt4o)
+            .messages(vec![ChatCompletionMessage::new_user("Say hi", "")])
+            .seed(-42)
+            .build()?;
+        assert_eq!(serde_json::to_value(&req)?["seed"], -42);
+        let res = SDK.chat_completion(req).await?;
+        assert!(res.system_fingerprint.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn chat_completion_with_custom_model_should_work() -> Result<()> {
+        let model: ChatCompleteModel = "llama3".parse()?;
+        assert_eq!(model, ChatCompleteModel::Other("llama3".into()));
+        assert_eq!(model.to_string(), "llama3");
+        let req = ChatCompletionRequest::new(model, vec![]);
+        assert_eq!(serde_json::to_value(req)?["model"], "llama3");
+
+        // ollama doesn't return usage for some endpoints
+        let res: ChatCompletionResponse = serde_json::from_value(serde_json::json!({
+          "id": "chatcmpl-1",
+          "object": "chat.completion",
+          "created": 1715000000,
+          "model": "llama3",
+          "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": "Hi!" },
+            "finish_reason": "stop"
+          }]
+        }))?;
+        assert_eq!(res.model, ChatCompleteModel::Other("llama3".into()));
+        assert_eq!(res.usage.total_tokens, 0);
+        assert_eq!(res.system_fingerprint, None);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn simple_chat_completion_should_work() -> Result<()> {
         let req = get_simple_completion_request();
@@ -536,6 +1537,146 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn chat_completion_stream_should_parse_chunks() -> Result<()> {
+        let events = [
+            r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","created":1700000000,"model":"gpt-3.5-turbo-1106","system_fingerprint":"fp_1","choices":[{"index":0,"delta":{"role":"assistant","content":""},"finish_reason":null}]}"#,
+            r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","created":1700000000,"model":"gpt-3.5-turbo-1106","choices":[{"index":0,"delta":{"content":"Hi"},"finish_reason":"stop"}]}"#,
+            "[DONE]",
+        ]
+        .into_iter()
+        .map(|data| {
+            Ok(SseEvent {
+                event: None,
+                data: data.to_string(),
+            })
+        });
+        let chunks: Vec<_> = futures::executor::block_on(
+            ChatCompletionStream::new(futures::stream::iter(events)).collect::<Vec<_>>(),
+        );
+        assert_eq!(chunks.len(), 2);
+        let last = chunks[1].as_ref().unwrap();
+        assert_eq!(last.choices[0].delta.content.as_deref(), Some("Hi"));
+        assert_eq!(last.choices[0].finish_reason, Some(FinishReason::Stop));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn collect_message_should_rebuild_response() -> Result<()> {
+        let events = [
+            r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","created":1700000000,"model":"gpt-3.5-turbo-1106","system_fingerprint":"fp_1","choices":[{"index":0,"delta":{"role":"assistant","content":""},"finish_reason":null}]}"#,
+            r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","created":1700000000,"model":"gpt-3.5-turbo-1106","choices":[{"index":0,"delta":{"content":"Hello"},"finish_reason":null}]}"#,
+            r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","created":1700000000,"model":"gpt-3.5-turbo-1106","choices":[{"index":0,"delta":{"content":" world"},"finish_reason":"stop"}]}"#,
+            r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","created":1700000000,"model":"gpt-3.5-turbo-1106","choices":[],"usage":{"prompt_tokens":5,"completion_tokens":2,"total_tokens":7}}"#,
+            "[DONE]",
+        ]
+        .into_iter()
+        .map(|data| {
+            Ok(SseEvent {
+                event: None,
+                data: data.to_string(),
+            })
+        });
+        let mut count = 0;
+        let res = ChatCompletionStream::new(futures::stream::iter(events))
+            .collect_message(|_| count += 1)
+            .await?;
+        assert_eq!(count, 4);
+        assert_eq!(res.system_fingerprint.as_deref(), Some("fp_1"));
+        assert_eq!(
+            res.choices[0].message.content.as_deref(),
+            Some("Hello world")
+        );
+        assert_eq!(res.choices[0].finish_reason, FinishReason::Stop);
+        assert_eq!(res.usage.total_tokens, 7);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn chat_completion_stream_should_track_usage() -> Result<()> {
+        let events = [
+            r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","created":1700000000,"model":"gpt-4o","choices":[{"index":0,"delta":{"content":"Hi"},"finish_reason":"stop"}]}"#,
+            r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","created":1700000000,"model":"gpt-4o","choices":[],"usage":{"prompt_tokens":5,"completion_tokens":2,"total_tokens":7}}"#,
+            "[DONE]",
+        ]
+        .into_iter()
+        .map(|data| {
+            Ok(SseEvent {
+                event: None,
+                data: data.to_string(),
+            })
+        });
+        let tracker = Arc::new(UsageTracker::default());
+        ChatCompletionStream::new(futures::stream::iter(events))
+            .track_usage(tracker.clone())
+            .collect_message(|_| {})
+            .await?;
+        let usage = tracker.by_endpoint()["chat/completions"];
+        assert_eq!(usage.requests, 1);
+        assert_eq!(usage.prompt_tokens, 5);
+        assert_eq!(usage.completion_tokens, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn tool_call_accumulator_should_merge_deltas() -> Result<()> {
+        let chunks = [
+            r#"{"index":0,"delta":{"role":"assistant","tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"get_weather_forecast","arguments":""}}]},"finish_reason":null}"#,
+            r#"{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"{\"city\":"}}]},"finish_reason":null}"#,
+            r#"{"index":0,"delta":{"tool_calls":[{"index":1,"id":"call_2","type":"function","function":{"name":"explain_mood","arguments":"{\"name\":\"happy\"}"}}]},"finish_reason":null}"#,
+            r#"{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"\"Boston\"}"}}]},"finish_reason":null}"#,
+            r#"{"index":0,"delta":{},"finish_reason":"tool_calls"}"#,
+        ];
+        let mut acc = ToolCallAccumulator::new();
+        let mut calls = None;
+        for chunk in chunks {
+            let choice: ChatCompletionChunkChoice = serde_json::from_str(chunk)?;
+            calls = acc.push(&choice);
+        }
+        let calls = calls.unwrap();
+        assert!(acc.is_empty());
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].id, "call_1");
+        assert_eq!(calls[0].function.arguments, r#"{"city":"Boston"}"#);
+        assert_eq!(calls[1].function.name, "explain_mood");
+        Ok(())
+    }
+
+    #[test]
+    fn tool_call_accumulator_should_keep_repeated_id_and_name() -> Result<()> {
+        let chunks = [
+            r#"{"index":0,"delta":{"tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"get_weather","arguments":"{\"city\":"}}]},"finish_reason":null}"#,
+            r#"{"index":0,"delta":{"tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"get_weather","arguments":"\"Boston\"}"}}]},"finish_reason":null}"#,
+            r#"{"index":0,"delta":{},"finish_reason":"tool_calls"}"#,
+        ];
+        let mut acc = ToolCallAccumulator::new();
+        let mut calls = None;
+        for chunk in chunks {
+            let choice: ChatCompletionChunkChoice = serde_json::from_str(chunk)?;
+            calls = acc.push(&choice);
+        }
+        let calls = calls.unwrap();
+        assert_eq!(calls[0].id, "call_1");
+        assert_eq!(calls[0].function.name, "get_weather");
+        assert_eq!(calls[0].function.arguments, r#"{"city":"Boston"}"#);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn simple_chat_completion_stream_should_work() -> Result<()> {
+        let req = get_simple_completion_request();
+        let mut stream = SDK.chat_completion_stream(req).await?;
+        let mut content = String::new();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            if let Some(text) = &chunk.choices[0].delta.content {
+                content.push_str(text);
+            }
+        }
+        assert!(!content.is_empty());
+        Ok(())
+    }
+
     fn get_simple_completion_request() -> ChatCompletionRequest {
         let messages = vec![
             ChatCompletionMessage::new_system("I can answer any question you ask me.", ""),
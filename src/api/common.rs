@@ -0,0 +1,367 @@
+use crate::{BuildError, IntoRequest};
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::Bytes;
+use derive_builder::Builder;
+use reqwest::Method;
+use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{hash_map::RandomState, BTreeMap},
+    hash::{BuildHasher, Hasher},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Header of the key which identifies the retries of a request.
+pub(crate) const IDEMPOTENCY_KEY: &str = "Idempotency-Key";
+
+/// Header required by the beta APIs (assistants, threads, runs).
+const ASSISTANTS_BETA: (&str, &str) = ("OpenAI-Beta", "assistants=v2");
+
+#[derive(Debug, Clone, Default, Serialize, Builder)]
+#[builder(pattern = "mutable", build_fn(error = "BuildError"))]
+pub struct ListRequest {
+    /// A limit on the number of objects to be returned. Limit can range between 1 and 100, and the default is 20.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit: Option<usize>,
+    /// Sort order by the created_at timestamp of the objects. asc for ascending order and desc for descending order.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    order: Option<ListOrder>,
+    /// A cursor for use in pagination. after is an object ID that defines your place in the list.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    after: Option<String>,
+    /// A cursor for use in pagination. before is an object ID that defines your place in the list.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    before: Option<String>,
+    /// Only list the objects matching the filter. Supported by the assistants, runs and batches.
+    #[builder(default)]
+    #[serde(skip)]
+    filter: ListFilter,
+}
+
+/// A filter of the list endpoints, sent as `metadata[key]=value`, `created_after` and
+/// `created_before` query parameters. The conditions are combined with AND.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListFilter {
+    metadata: BTreeMap<String, String>,
+    created_after: Option<u64>,
+    created_before: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ListOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListResponse<T> {
+    /// The object type, which is always "list".
+    pub object: String,
+    /// The objects in this page.
+    pub data: Vec<T>,
+    /// The ID of the first object in this page.
+    #[serde(default)]
+    pub first_id: Option<String>,
+    /// The ID of the last object in this page. Use it as the `after` cursor to fetch the next page.
+    #[serde(default)]
+    pub last_id: Option<String>,
+    /// Whether there are more objects after this page.
+    #[serde(default)]
+    pub has_more: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeletionStatus {
+    /// The ID of the deleted object.
+    pub id: String,
+    /// The object type, e.g. "assistant.deleted".
+    pub object: String,
+    /// Whether the object was deleted.
+    pub deleted: bool,
+}
+
+/// Base64 payload (b64_json images, audio data) backed by `Bytes`. When it's parsed by the SDK it
+/// shares the buffer of the response body instead of copying it into a `String`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "String")]
+pub struct Base64Data(Bytes);
+
+/// A request addressed by its path, used for the retrieve / modify / delete / list style endpoints
+/// which have no dedicated request type.
+#[derive(Debug, Clone)]
+pub(crate) struct PathRequest<T = ()> {
+    method: Method,
+    path: String,
+    body: Option<T>,
+    query: Option<ListRequest>,
+    include: Vec<&'static str>,
+    beta: bool,
+}
+
+impl ListFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The metadata `key` of the object equals `value`.
+    pub fn metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// Created after the unix timestamp (in seconds).
+    pub fn created_after(mut self, timestamp: u64) -> Self {
+        self.created_after = Some(timestamp);
+        self
+    }
+
+    /// Created before the unix timestamp (in seconds).
+    pub fn created_before(mut self, timestamp: u64) -> Self {
+        self.created_before = Some(timestamp);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// The query parameters, the nested `metadata` can't be serialized by `serde_urlencoded`.
+    fn query(&self) -> Vec<(String, String)> {
+        let mut ret: Vec<_> = self
+            .metadata
+            .iter()
+            .map(|(key, value)| (format!("metadata[{}]", key), value.clone()))
+            .collect();
+        if let Some(timestamp) = self.created_after {
+            ret.push(("created_after".into(), timestamp.to_string()));
+        }
+        if let Some(timestamp) = self.created_before {
+            ret.push(("created_before".into(), timestamp.to_string()));
+        }
+        ret
+    }
+}
+
+impl PathRequest {
+    pub(crate) fn get(path: impl Into<String>) -> Self {
+        Self::new(Method::GET, path, None)
+    }
+
+    pub(crate) fn delete(path: impl Into<String>) -> Self {
+        Self::new(Method::DELETE, path, None)
+    }
+
+    /// A POST without a body, e.g. `runs/{id}/cancel`.
+    pub(crate) fn post_empty(path: impl Into<String>) -> Self {
+        Self::new(Method::POST, path, None)
+    }
+
+    pub(crate) fn list(path: impl Into<String>, query: ListRequest) -> Self {
+        let mut req = Self::get(path);
+        req.query = Some(query);
+        req
+    }
+}
+
+impl<T> PathRequest<T> {
+    pub(crate) fn post(path: impl Into<String>, body: T) -> Self {
+        Self::new(Method::POST, path, Some(body))
+    }
+
+    pub(crate) fn assistants_beta(mut self) -> Self {
+        self.beta = true;
+        self
+    }
+
+    /// Ask for an additional field which is omitted from the response by default, sent as `include[]`.
+    pub(crate) fn include(mut self, field: &'static str) -> Self {
+        self.include.push(field);
+        self
+    }
+
+    fn new(method: Method, path: impl Into<String>, body: Option<T>) -> Self {
+        Self {
+            method,
+            path: path.into(),
+            body,
+            query: None,
+            include: Vec::new(),
+            beta: false,
+        }
+    }
+}
+
+impl<T: Serialize> IntoRequest for PathRequest<T> {
+    fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+        let url = format!("{}/{}", base_url, self.path);
+        let mut req = client.request(self.method, url);
+        if let Some(query) = &self.query {
+            req = req.query(query).query(&query.filter.query());
+        }
+        for field in &self.include {
+            req = req.query(&[("include[]", field)]);
+        }
+        if let Some(body) = &self.body {
+            req = req.json(body);
+        }
+        if self.beta {
+            req = req.header(ASSISTANTS_BETA.0, ASSISTANTS_BETA.1);
+        }
+        req
+    }
+}
+
+impl Base64Data {
+    /// The raw base64 text.
+    pub fn as_str(&self) -> &str {
+        // it's always built from a str
+        std::str::from_utf8(&self.0).unwrap_or_default()
+    }
+
+    /// The raw base64 bytes.
+    pub fn as_bytes(&self) -> &Bytes {
+        &self.0
+    }
+
+    /// An upper bound of the decoded size, useful to reserve the caller's buffer.
+    pub fn decoded_len_estimate(&self) -> usize {
+        base64::decoded_len_estimate(self.0.len())
+    }
+
+    /// Decode into the caller's buffer (appending to it), so it can be reused across payloads.
+    pub fn decode_into(&self, buf: &mut Vec<u8>) -> Result<()> {
+        buf.reserve(self.decoded_len_estimate());
+        STANDARD.decode_vec(&self.0, buf)?;
+        Ok(())
+    }
+
+    /// Decode into a newly allocated buffer.
+    pub fn decode(&self) -> Result<Bytes> {
+        let mut buf = Vec::new();
+        self.decode_into(&mut buf)?;
+        Ok(buf.into())
+    }
+
+    /// Reuse the buffer of `body` if `data` points into it, otherwise copy it.
+    pub(crate) fn from_body(body: &Bytes, data: &str) -> Self {
+        let range = body.as_ptr_range();
+        let ptr = data.as_ptr();
+        if !data.is_empty() && range.start <= ptr && ptr < range.end {
+            Self(body.slice_ref(data.as_bytes()))
+        } else {
+            Self(Bytes::copy_from_slice(data.as_bytes()))
+        }
+    }
+}
+
+impl From<String> for Base64Data {
+    fn from(s: String) -> Self {
+        Self(s.into())
+    }
+}
+
+/// Attach the `Idempotency-Key` header, with `key` or a new random one, so a gateway supporting it
+/// runs a retried POST only once.
+pub(crate) fn with_idempotency_key(req: RequestBuilder, key: Option<String>) -> RequestBuilder {
+    req.header(IDEMPOTENCY_KEY, key.unwrap_or_else(new_idempotency_key))
+}
+
+/// A random key formatted as a UUID v4.
+pub(crate) fn new_idempotency_key() -> String {
+    let (a, b) = (random_u64(), random_u64());
+    format!(
+        "{:08x}-{:04x}-4{:03x}-{:04x}-{:012x}",
+        a >> 32,
+        (a >> 16) & 0xffff,
+        a & 0x0fff,
+        (b >> 48) & 0x3fff | 0x8000,
+        b & 0xffff_ffff_ffff
+    )
+}
+
+/// A random number from the randomly seeded hasher of std, unique per call thanks to a counter.
+pub(crate) fn random_u64() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.finish()
+}
+
+pub(crate) fn with_assistants_beta(req: RequestBuilder) -> RequestBuilder {
+    req.header(ASSISTANTS_BETA.0, ASSISTANTS_BETA.1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn idempotency_key_should_be_unique_uuid() {
+        let key = new_idempotency_key();
+        assert_eq!(key.len(), 36);
+        assert_eq!(key.as_bytes()[14], b'4');
+        assert!(matches!(key.as_bytes()[19], b'8' | b'9' | b'a' | b'b'));
+        assert_ne!(key, new_idempotency_key());
+    }
+
+    #[test]
+    fn base64_data_should_share_body() -> Result<()> {
+        let body = Bytes::from_static(br#"{"b64":"aGVsbG8="}"#);
+        let data = Base64Data::from_body(&body, &std::str::from_utf8(&body)?[8..16]);
+        assert_eq!(data.as_str(), "aGVsbG8=");
+        assert_eq!(data.as_bytes().as_ptr(), body[8..].as_ptr());
+        assert_eq!(data.decode()?, Bytes::from_static(b"hello"));
+
+        let mut buf = b"say ".to_vec();
+        data.decode_into(&mut buf)?;
+        assert_eq!(buf, b"say hello");
+        Ok(())
+    }
+
+    #[test]
+    fn list_request_should_send_filter() -> Result<()> {
+        let filter = ListFilter::new()
+            .metadata("team", "search")
+            .metadata("env", "prod")
+            .created_after(1_700_000_000);
+        assert!(!filter.is_empty());
+        let query = ListRequestBuilder::default()
+            .limit(10)
+            .filter(filter)
+            .build()?;
+        let req = PathRequest::list("assistants", query)
+            .into_request("http://localhost", reqwest::Client::new().into())
+            .build()?;
+        assert_eq!(
+            req.url().query(),
+            Some("limit=10&metadata%5Benv%5D=prod&metadata%5Bteam%5D=search&created_after=1700000000")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn list_request_should_serialize() -> Result<()> {
+        let req = ListRequestBuilder::default()
+            .limit(10)
+            .order(ListOrder::Asc)
+            .after("asst_abc123")
+            .build()?;
+        assert_eq!(
+            serde_json::to_value(req)?,
+            serde_json::json!({
+              "limit": 10,
+              "order": "asc",
+              "after": "asst_abc123",
+            })
+        );
+        Ok(())
+    }
+}
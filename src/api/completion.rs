@@ -0,0 +1,285 @@
+use crate::{
+    sse::SseEvent, usage::model_name, BuildError, ChatCompleteUsage, FinishReason, IntoRequest,
+    StreamOptions, UsageTracker,
+};
+use anyhow::{anyhow, Result};
+use derive_builder::Builder;
+use futures::{future, stream::BoxStream, Stream, StreamExt};
+use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+/// A request of the legacy text completions API, which some OpenAI compatible servers still
+/// implement instead of (or in addition to) chat completions.
+#[derive(Debug, Clone, Serialize, Builder)]
+#[builder(pattern = "mutable", build_fn(error = "BuildError"))]
+pub struct CompletionRequest {
+    /// ID of the model to use.
+    #[builder(default)]
+    pub(crate) model: CompletionModel,
+    /// The prompt to generate completions for.
+    #[builder(setter(into))]
+    prompt: String,
+    /// The suffix that comes after a completion of inserted text.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suffix: Option<String>,
+    /// The maximum number of tokens that can be generated in the completion.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<usize>,
+    /// What sampling temperature to use, between 0 and 2.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    /// An alternative to sampling with temperature, called nucleus sampling.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    /// How many completions to generate for each prompt.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n: Option<usize>,
+    /// Generates best_of completions server-side and returns the "best" (the one with the highest log probability per token). Results cannot be streamed. When used with n, best_of controls the number of candidate completions and n specifies how many to return, best_of must be greater than n.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    best_of: Option<usize>,
+    /// Include the log probabilities on the logprobs most likely output tokens, as well the chosen tokens. The maximum value for logprobs is 5.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    logprobs: Option<u8>,
+    /// Echo back the prompt in addition to the completion.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    echo: Option<bool>,
+    /// Up to 4 sequences where the API will stop generating further tokens.
+    #[builder(default, setter(into))]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    /// Number between -2.0 and 2.0. Positive values penalize new tokens based on whether they appear in the text so far.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    presence_penalty: Option<f32>,
+    /// Number between -2.0 and 2.0. Positive values penalize new tokens based on their existing frequency in the text so far.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frequency_penalty: Option<f32>,
+    /// If specified, the system will make a best effort to sample deterministically.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+    /// Whether to stream back partial progress. Set by `LlmSdk::completion_stream`.
+    #[builder(setter(skip))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) stream: Option<bool>,
+    /// Options for streaming response. Only used by `LlmSdk::completion_stream`.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) stream_options: Option<StreamOptions>,
+    /// A unique identifier representing your end-user, which can help OpenAI to monitor and detect abuse.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    /// Overrides the timeout of the SDK (`LlmSdkBuilder::timeout`) for this request.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip)]
+    pub(crate) timeout: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompletionModel {
+    #[default]
+    #[serde(rename = "gpt-3.5-turbo-instruct")]
+    Gpt3TurboInstruct,
+    #[serde(rename = "davinci-002")]
+    Davinci002,
+    #[serde(rename = "babbage-002")]
+    Babbage002,
+    /// Any other model by its name, e.g. one served by an OpenAI compatible server.
+    #[serde(untagged)]
+    Other(String),
+}
+
+/// The response of a completion. With `LlmSdk::completion_stream`, each chunk has the same shape
+/// with a fragment of the text.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompletionResponse {
+    /// A unique identifier for the completion.
+    pub id: String,
+    /// The object type, which is always "text_completion".
+    pub object: String,
+    /// The Unix timestamp (in seconds) of when the completion was created.
+    pub created: usize,
+    /// The model used for completion.
+    pub model: CompletionModel,
+    /// This fingerprint represents the backend configuration that the model runs with.
+    #[serde(default)]
+    pub system_fingerprint: Option<String>,
+    /// The list of completion choices the model generated for the input prompt.
+    pub choices: Vec<CompletionChoice>,
+    /// Usage statistics for the completion request. All zeros for the stream chunks, except the
+    /// last one when requested by stream_options.
+    #[serde(default)]
+    pub usage: ChatCompleteUsage,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompletionChoice {
+    pub text: String,
+    pub index: usize,
+    /// Only set when the request asked for logprobs.
+    #[serde(default)]
+    pub logprobs: Option<CompletionLogprobs>,
+    /// The reason the model stopped generating tokens. Not set in the stream chunks except the last one of a choice.
+    #[serde(default)]
+    pub finish_reason: Option<FinishReason>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct CompletionLogprobs {
+    pub tokens: Vec<String>,
+    /// The logprob of each token, `None` for the first token of an echoed prompt.
+    pub token_logprobs: Vec<Option<f32>>,
+    /// The most likely tokens and their logprobs at each position.
+    pub top_logprobs: Vec<Option<HashMap<String, f32>>>,
+    /// The character offset of each token in the text.
+    pub text_offset: Vec<usize>,
+}
+
+/// A stream of completion chunks, ends after the `[DONE]` message.
+pub struct CompletionStream {
+    inner: BoxStream<'static, Result<CompletionResponse>>,
+}
+
+impl CompletionRequest {
+    pub fn new(model: CompletionModel, prompt: impl Into<String>) -> Self {
+        CompletionRequestBuilder::default()
+            .model(model)
+            .prompt(prompt)
+            .build()
+            .unwrap()
+    }
+}
+
+impl IntoRequest for CompletionRequest {
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+        let url = format!("{}/completions", base_url);
+        client.post(url).json(&self)
+    }
+}
+
+impl CompletionStream {
+    pub(crate) fn new(events: impl Stream<Item = Result<SseEvent>> + Send + 'static) -> Self {
+        let inner = events
+            .take_while(|event| future::ready(!matches!(event, Ok(e) if e.data == "[DONE]")))
+            .map(|event| {
+                let event = event?;
+                serde_json::from_str::<CompletionResponse>(&event.data)
+                    .map_err(|e| anyhow!("failed to parse chunk {}: {}", event.data, e))
+            })
+            .boxed();
+        Self { inner }
+    }
+
+    /// Record the usage of the last chunk (requested with `stream_options.include_usage`), the
+    /// other chunks have no usage.
+    pub(crate) fn track_usage(self, tracker: Arc<UsageTracker>) -> Self {
+        let inner = self
+            .inner
+            .inspect(move |chunk| match chunk {
+                Ok(chunk) if chunk.usage.total_tokens > 0 => tracker.record(
+                    &model_name(&chunk.model),
+                    "completions",
+                    chunk.usage.prompt_tokens,
+                    chunk.usage.completion_tokens,
+                ),
+                _ => {}
+            })
+            .boxed();
+        Self { inner }
+    }
+}
+
+impl Stream for CompletionStream {
+    type Item = Result<CompletionResponse>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SDK;
+    use serde_json::json;
+
+    #[test]
+    fn completion_request_should_serialize() -> Result<()> {
+        let req = CompletionRequestBuilder::default()
+            .prompt("Say this is a test")
+            .suffix("!")
+            .logprobs(2)
+            .echo(true)
+            .best_of(3)
+            .build()?;
+        assert_eq!(
+            serde_json::to_value(req)?,
+            json!({
+              "model": "gpt-3.5-turbo-instruct",
+              "prompt": "Say this is a test",
+              "suffix": "!",
+              "best_of": 3,
+              "logprobs": 2,
+              "echo": true
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn completion_stream_should_parse_chunks() -> Result<()> {
+        let events = [
+            r#"{"id":"cmpl-1","object":"text_completion","created":1700000000,"model":"gpt-3.5-turbo-instruct","choices":[{"text":"This","index":0,"logprobs":null,"finish_reason":null}]}"#,
+            r#"{"id":"cmpl-1","object":"text_completion","created":1700000000,"model":"gpt-3.5-turbo-instruct","choices":[{"text":" is a test","index":0,"logprobs":null,"finish_reason":"stop"}]}"#,
+            "[DONE]",
+        ]
+        .into_iter()
+        .map(|data| {
+            Ok(SseEvent {
+                event: None,
+                data: data.to_string(),
+            })
+        });
+        let chunks: Vec<_> = futures::executor::block_on(
+            CompletionStream::new(futures::stream::iter(events)).collect::<Vec<_>>(),
+        )
+        .into_iter()
+        .collect::<Result<_>>()?;
+        let text: String = chunks.iter().map(|c| c.choices[0].text.as_str()).collect();
+        assert_eq!(text, "This is a test");
+        assert_eq!(chunks[1].choices[0].finish_reason, Some(FinishReason::Stop));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn completion_should_work() -> Result<()> {
+        let req = CompletionRequest::new(CompletionModel::Gpt3TurboInstruct, "Say this is a test");
+        let res = SDK.completion(req).await?;
+        assert_eq!(res.object, "text_completion");
+        assert_eq!(res.choices.len(), 1);
+        assert!(!res.choices[0].text.is_empty());
+        Ok(())
+    }
+}
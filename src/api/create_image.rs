@@ -1,30 +1,43 @@
-use crate::IntoRequest;
+use super::common::with_idempotency_key;
+use crate::{Base64Data, BuildError, IntoRequest};
+use anyhow::Result;
+use bytes::Bytes;
 use derive_builder::Builder;
 use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
 use serde::{Deserialize, Serialize};
+use std::{borrow::Cow, time::Duration};
+use strum::Display;
 
 #[derive(Debug, Clone, Serialize, Builder)]
-#[builder(pattern = "mutable")]
+#[builder(
+    pattern = "mutable",
+    build_fn(validate = "Self::validate", error = "BuildError")
+)]
 pub struct CreateImageRequest {
-    /// A text description of the desired image(s). The maximum length is 4000 characters for dall-e-3.
+    /// A text description of the desired image(s). The maximum length is 1000 characters for dall-e-2, 4000 characters for dall-e-3 and 32000 characters for gpt-image-1.
     #[builder(setter(into))]
-    prompt: String,
-    /// The model to use for image generation. Only support Dall-e-3
+    pub(crate) prompt: String,
+    /// Ask dall-e-3 to use the prompt as-is instead of rewriting it. It's done by prepending an
+    /// instruction to the prompt, so the model may still revise it a bit.
     #[builder(default)]
-    model: ImageModel,
+    #[serde(skip)]
+    keep_prompt: bool,
+    /// The model to use for image generation. Defaults to dall-e-3.
+    #[builder(default)]
+    pub(crate) model: ImageModel,
     /// The number of images to generate. Must be between 1 and 10. For dall-e-3, only n=1 is supported.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
     n: Option<usize>,
-    /// The quality of the image that will be generated. hd creates images with finer details and greater consistency across the image. This param is only supported for dall-e-3.
+    /// The quality of the image that will be generated. hd creates images with finer details and greater consistency across the image. standard and hd are supported for dall-e-3, low, medium, high and auto for gpt-image-1.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
     quality: Option<ImageQuality>,
-    /// The format in which the generated images are returned. Must be one of url or b64_json.
+    /// The format in which the generated images are returned. Must be one of url or b64_json. Not supported by gpt-image-1, which always returns b64_json.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
     response_format: Option<ImageResponseFormat>,
-    /// The size of the generated images. Must be one of 1024x1024, 1792x1024, or 1024x1792 for dall-e-3 models.
+    /// The size of the generated images. Must be one of 256x256, 512x512, or 1024x1024 for dall-e-2. Must be one of 1024x1024, 1792x1024, or 1024x1792 for dall-e-3 models. Must be one of 1024x1024, 1536x1024, 1024x1536 or auto for gpt-image-1.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
     size: Option<ImageSize>,
@@ -36,40 +49,100 @@ pub struct CreateImageRequest {
     #[builder(default, setter(strip_option, into))]
     #[serde(skip_serializing_if = "Option::is_none")]
     user: Option<String>,
+    /// The format in which the generated images are returned. This param is only supported for gpt-image-1.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_format: Option<ImageOutputFormat>,
+    /// The compression level (0-100%) for the generated images. This param is only supported for gpt-image-1 with the webp or jpeg output formats.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_compression: Option<u8>,
+    /// Allows to set transparency for the background of the generated image(s). This param is only supported for gpt-image-1.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    background: Option<ImageBackground>,
+    /// Control the content-moderation level for images generated by gpt-image-1. Must be either low for less restrictive filtering or auto (default value).
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    moderation: Option<ImageModeration>,
+    /// Sent as the `Idempotency-Key` header, so a retry isn't run (and billed) twice by the
+    /// gateways supporting it. A random key is generated if not set.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(skip)]
+    pub(crate) idempotency_key: Option<String>,
+    /// Overrides the timeout of the SDK (`LlmSdkBuilder::timeout`) for this request.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip)]
+    pub(crate) timeout: Option<Duration>,
 }
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Display)]
 pub enum ImageModel {
+    #[serde(rename = "dall-e-2")]
+    #[strum(serialize = "dall-e-2")]
+    DallE2,
     #[serde(rename = "dall-e-3")]
+    #[strum(serialize = "dall-e-3")]
     #[default]
     DallE3,
+    #[serde(rename = "gpt-image-1")]
+    #[strum(serialize = "gpt-image-1")]
+    GptImage1,
+    /// Any other model by its name, the parameters are not validated for it.
+    #[serde(untagged)]
+    #[strum(default)]
+    Other(String),
 }
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Display)]
 #[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
 pub enum ImageQuality {
     #[default]
     Standard,
     Hd,
+    Low,
+    Medium,
+    High,
+    Auto,
 }
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Display)]
 #[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
 pub enum ImageResponseFormat {
     #[default]
     Url,
     B64Json,
 }
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Display)]
 pub enum ImageSize {
+    #[serde(rename = "256x256")]
+    #[strum(serialize = "256x256")]
+    Small,
+    #[serde(rename = "512x512")]
+    #[strum(serialize = "512x512")]
+    Medium,
     #[serde(rename = "1024x1024")]
+    #[strum(serialize = "1024x1024")]
     #[default]
     Large,
     #[serde(rename = "1792x1024")]
+    #[strum(serialize = "1792x1024")]
     LargeWide,
     #[serde(rename = "1024x1792")]
+    #[strum(serialize = "1024x1792")]
     LargeTall,
+    #[serde(rename = "1536x1024")]
+    #[strum(serialize = "1536x1024")]
+    Landscape,
+    #[serde(rename = "1024x1536")]
+    #[strum(serialize = "1024x1536")]
+    Portrait,
+    #[serde(rename = "auto")]
+    #[strum(serialize = "auto")]
+    Auto,
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
@@ -80,35 +153,358 @@ pub enum ImageStyle {
     Natural,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ImageOutputFormat {
+    #[default]
+    Png,
+    Jpeg,
+    Webp,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ImageBackground {
+    #[default]
+    Auto,
+    Transparent,
+    Opaque,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ImageModeration {
+    #[default]
+    Auto,
+    Low,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct CreateImageResponse {
     pub created: u64,
     pub data: Vec<ImageObject>,
+    /// Token usage of the request, only returned for gpt-image-1.
+    #[serde(default)]
+    pub usage: Option<ImageUsage>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct ImageUsage {
+    /// The number of tokens (images and text) in the input prompt.
+    pub input_tokens: usize,
+    /// The number of image tokens in the output image(s).
+    pub output_tokens: usize,
+    /// The total number of tokens (images and text) used for the image generation.
+    pub total_tokens: usize,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct ImageObject {
     /// The base64-encoded JSON of the generated image, if response_format is b64_json
-    pub b64_json: Option<String>,
+    #[serde(default)]
+    pub b64_json: Option<Base64Data>,
     /// The URL of the generated image, if response_format is url (default).
     pub url: Option<String>,
     /// The prompt that was used to generate the image, if there was any revision to the prompt.
-    pub revised_prompt: String,
+    #[serde(default)]
+    pub revised_prompt: Option<String>,
+    /// The prompt of the request.
+    #[serde(skip)]
+    pub original_prompt: String,
+}
+
+/// A word level change between the original and the revised prompt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PromptDiff {
+    Same(String),
+    Added(String),
+    Removed(String),
+}
+
+/// Borrowed view of the response, so b64_json can be sliced out of the body without copying.
+#[derive(Deserialize)]
+struct RawImageResponse<'a> {
+    created: u64,
+    #[serde(borrow)]
+    data: Vec<RawImageObject<'a>>,
+    #[serde(default)]
+    usage: Option<ImageUsage>,
 }
 
+#[derive(Deserialize)]
+struct RawImageObject<'a> {
+    #[serde(borrow, default)]
+    b64_json: Option<RawStr<'a>>,
+    #[serde(default)]
+    url: Option<String>,
+    #[serde(default)]
+    revised_prompt: Option<String>,
+}
+
+/// serde only borrows a `Cow<str>` when it's a direct field, not inside an `Option`.
+#[derive(Deserialize)]
+struct RawStr<'a>(#[serde(borrow)] Cow<'a, str>);
+
+impl CreateImageResponse {
+    /// Parse the response body, the b64_json payloads share the buffer of `body`. `prompt` is the
+    /// prompt of the request.
+    pub(crate) fn from_bytes(body: Bytes, prompt: &str) -> Result<Self> {
+        let raw: RawImageResponse = serde_json::from_slice(&body)?;
+        let data = raw
+            .data
+            .into_iter()
+            .map(|image| ImageObject {
+                b64_json: image
+                    .b64_json
+                    .map(|RawStr(data)| Base64Data::from_body(&body, &data)),
+                url: image.url,
+                revised_prompt: image.revised_prompt,
+                original_prompt: prompt.to_string(),
+            })
+            .collect();
+        Ok(Self {
+            created: raw.created,
+            data,
+            usage: raw.usage,
+        })
+    }
+}
+
+/// The instruction which asks dall-e-3 not to rewrite the prompt, as suggested by OpenAI.
+const KEEP_PROMPT_PRESET: &str = "I NEED to test how the tool works with extremely simple prompts. DO NOT add any detail, just use it AS-IS: ";
+
 impl IntoRequest for CreateImageRequest {
-    fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    fn into_request(mut self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
         let url = format!("{}/images/generations", base_url);
-        client.post(url).json(&self)
+        if self.keep_prompt {
+            self.prompt = format!("{}{}", KEEP_PROMPT_PRESET, self.prompt);
+        }
+        let req = client.post(url).json(&self);
+        with_idempotency_key(req, self.idempotency_key)
+    }
+}
+
+impl ImageObject {
+    /// Whether the model used a different prompt than the one of the request.
+    pub fn is_prompt_revised(&self) -> bool {
+        matches!(&self.revised_prompt, Some(revised) if *revised != self.original_prompt)
+    }
+
+    /// Word level diff from the original prompt to the revised one, empty if it wasn't revised.
+    pub fn prompt_diff(&self) -> Vec<PromptDiff> {
+        match &self.revised_prompt {
+            Some(revised) => diff_words(&self.original_prompt, revised),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Longest common subsequence based diff of the words of two texts.
+fn diff_words(from: &str, to: &str) -> Vec<PromptDiff> {
+    let a: Vec<&str> = from.split_whitespace().collect();
+    let b: Vec<&str> = to.split_whitespace().collect();
+    // lcs[i][j] is the LCS length of a[i..] and b[j..]
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut ret = Vec::new();
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            ret.push(PromptDiff::Same(a[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if j < b.len() && (i == a.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            ret.push(PromptDiff::Added(b[j].to_string()));
+            j += 1;
+        } else {
+            ret.push(PromptDiff::Removed(a[i].to_string()));
+            i += 1;
+        }
+    }
+    ret
+}
+
+impl CreateImageRequestBuilder {
+    fn validate(&self) -> Result<(), String> {
+        let model = self.model.clone().unwrap_or_default();
+        let n = self.n.flatten();
+        let size = self.size.flatten();
+        let quality = self.quality.flatten();
+        if let (Some(prompt), Some(max)) = (&self.prompt, model.max_prompt_len()) {
+            if prompt.chars().count() > max {
+                return Err(format!(
+                    "prompt must be at most {} characters for {}",
+                    max, model
+                ));
+            }
+        }
+        if matches!(model, ImageModel::DallE2 | ImageModel::DallE3) {
+            let unsupported = [
+                ("output_format", self.output_format.flatten().is_some()),
+                (
+                    "output_compression",
+                    self.output_compression.flatten().is_some(),
+                ),
+                ("background", self.background.flatten().is_some()),
+                ("moderation", self.moderation.flatten().is_some()),
+            ];
+            if let Some((name, _)) = unsupported.iter().find(|(_, set)| *set) {
+                return Err(format!("{} is not supported by {}", name, model));
+            }
+        }
+        match model {
+            ImageModel::DallE2 => {
+                if matches!(n, Some(n) if !(1..=10).contains(&n)) {
+                    return Err(format!("n must be between 1 and 10 for {}", model));
+                }
+                if !matches!(quality, None | Some(ImageQuality::Standard)) {
+                    return Err(format!(
+                        "quality {} is not supported by {}",
+                        quality.unwrap(),
+                        model
+                    ));
+                }
+                if self.style.flatten().is_some() {
+                    return Err(format!("style is not supported by {}", model));
+                }
+                if !matches!(
+                    size,
+                    None | Some(ImageSize::Small | ImageSize::Medium | ImageSize::Large)
+                ) {
+                    return Err(format!(
+                        "size {} is not supported by {}",
+                        size.unwrap(),
+                        model
+                    ));
+                }
+            }
+            ImageModel::DallE3 => {
+                if matches!(n, Some(n) if n != 1) {
+                    return Err(format!("n must be 1 for {}", model));
+                }
+                if !matches!(
+                    quality,
+                    None | Some(ImageQuality::Standard | ImageQuality::Hd)
+                ) {
+                    return Err(format!(
+                        "quality {} is not supported by {}",
+                        quality.unwrap(),
+                        model
+                    ));
+                }
+                if !matches!(
+                    size,
+                    None | Some(ImageSize::Large | ImageSize::LargeWide | ImageSize::LargeTall)
+                ) {
+                    return Err(format!(
+                        "size {} is not supported by {}",
+                        size.unwrap(),
+                        model
+                    ));
+                }
+            }
+            ImageModel::GptImage1 => {
+                if matches!(n, Some(n) if !(1..=10).contains(&n)) {
+                    return Err(format!("n must be between 1 and 10 for {}", model));
+                }
+                if matches!(quality, Some(ImageQuality::Standard | ImageQuality::Hd)) {
+                    return Err(format!(
+                        "quality {} is not supported by {}",
+                        quality.unwrap(),
+                        model
+                    ));
+                }
+                if self.style.flatten().is_some() {
+                    return Err(format!("style is not supported by {}", model));
+                }
+                // gpt-image-1 always returns b64_json
+                if self.response_format.flatten().is_some() {
+                    return Err(format!("response_format is not supported by {}", model));
+                }
+                let format = self.output_format.flatten().unwrap_or_default();
+                if let Some(compression) = self.output_compression.flatten() {
+                    if compression > 100 {
+                        return Err("output_compression must be between 0 and 100".into());
+                    }
+                    if format == ImageOutputFormat::Png {
+                        return Err(format!(
+                            "output_compression is not supported for {}",
+                            format
+                        ));
+                    }
+                }
+                if self.background.flatten() == Some(ImageBackground::Transparent)
+                    && format == ImageOutputFormat::Jpeg
+                {
+                    return Err(format!(
+                        "transparent background is not supported for {}",
+                        format
+                    ));
+                }
+                if !matches!(
+                    size,
+                    None | Some(
+                        ImageSize::Large
+                            | ImageSize::Landscape
+                            | ImageSize::Portrait
+                            | ImageSize::Auto
+                    )
+                ) {
+                    return Err(format!(
+                        "size {} is not supported by {}",
+                        size.unwrap(),
+                        model
+                    ));
+                }
+            }
+            // not validated, it's up to the server
+            ImageModel::Other(_) => {}
+        }
+        Ok(())
+    }
+}
+
+impl ImageModel {
+    /// The maximum length of the prompt in characters, None if unknown.
+    fn max_prompt_len(&self) -> Option<usize> {
+        match self {
+            Self::DallE2 => Some(1000),
+            Self::DallE3 => Some(4000),
+            Self::GptImage1 => Some(32000),
+            Self::Other(_) => None,
+        }
+    }
+
+    /// The price in USD of a 1024x1024 image at the standard quality, 0 for gpt-image-1 (priced
+    /// by token) and `Other`.
+    pub fn price_per_image(&self) -> f64 {
+        match self {
+            Self::DallE2 => 0.02,
+            Self::DallE3 => 0.04,
+            Self::GptImage1 | Self::Other(_) => 0.0,
+        }
     }
 }
 
 impl CreateImageRequest {
-    pub fn new(prompt: impl Into<String>) -> Self {
-        CreateImageRequestBuilder::default()
-            .prompt(prompt)
-            .build()
-            .unwrap()
+    /// Fails if the prompt is too long for the default model.
+    pub fn new(prompt: impl Into<String>) -> Result<Self, BuildError> {
+        CreateImageRequestBuilder::default().prompt(prompt).build()
     }
 }
 
@@ -116,12 +512,95 @@ impl CreateImageRequest {
 mod tests {
     use super::*;
     use crate::SDK;
-    use anyhow::Result;
     use serde_json::json;
 
+    #[test]
+    fn create_image_response_should_share_body() -> Result<()> {
+        let body = Bytes::from(
+            json!({
+              "created": 1700000000,
+              "data": [{ "b64_json": "aGVsbG8=", "revised_prompt": "a cute caterpillar" }]
+            })
+            .to_string(),
+        );
+        let res = CreateImageResponse::from_bytes(body.clone(), "a caterpillar")?;
+        let image = res.data[0].b64_json.as_ref().unwrap();
+        let range = body.as_ptr_range();
+        assert!(range.contains(&image.as_bytes().as_ptr()));
+        assert_eq!(image.decode()?, Bytes::from_static(b"hello"));
+        Ok(())
+    }
+
+    #[test]
+    fn image_object_should_diff_prompts() -> Result<()> {
+        let body = Bytes::from(
+            json!({
+              "created": 1700000000,
+              "data": [{ "url": "https://example.com/1.png", "revised_prompt": "a cute green caterpillar" }]
+            })
+            .to_string(),
+        );
+        let res = CreateImageResponse::from_bytes(body, "a cute caterpillar on a leaf")?;
+        let image = &res.data[0];
+        assert!(image.is_prompt_revised());
+        assert_eq!(
+            image.prompt_diff(),
+            [
+                PromptDiff::Same("a".into()),
+                PromptDiff::Same("cute".into()),
+                PromptDiff::Added("green".into()),
+                PromptDiff::Same("caterpillar".into()),
+                PromptDiff::Removed("on".into()),
+                PromptDiff::Removed("a".into()),
+                PromptDiff::Removed("leaf".into()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn create_image_request_should_keep_prompt() -> Result<()> {
+        let req = CreateImageRequestBuilder::default()
+            .prompt("a caterpillar")
+            .keep_prompt(true)
+            .build()?;
+        let req = req
+            .into_request("http://localhost", reqwest::Client::new().into())
+            .build()?;
+        let body: serde_json::Value =
+            serde_json::from_slice(req.body().and_then(|b| b.as_bytes()).unwrap())?;
+        assert_eq!(
+            body["prompt"],
+            format!("{}a caterpillar", KEEP_PROMPT_PRESET)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn create_image_request_should_send_idempotency_key() -> Result<()> {
+        let req = CreateImageRequestBuilder::default()
+            .prompt("a caterpillar")
+            .idempotency_key("key-1")
+            .build()?;
+        let req = req
+            .into_request("http://localhost", reqwest::Client::new().into())
+            .build()?;
+        assert_eq!(req.headers()["idempotency-key"], "key-1");
+        let body = req.body().and_then(|b| b.as_bytes()).unwrap();
+        assert!(serde_json::from_slice::<serde_json::Value>(body)?
+            .get("idempotency_key")
+            .is_none());
+
+        let req = CreateImageRequest::new("a caterpillar")?
+            .into_request("http://localhost", reqwest::Client::new().into())
+            .build()?;
+        assert_eq!(req.headers()["idempotency-key"].len(), 36);
+        Ok(())
+    }
+
     #[test]
     fn create_image_request_should_serialize() -> Result<()> {
-        let req = CreateImageRequest::new("draw a cute caterpillar");
+        let req = CreateImageRequest::new("draw a cute caterpillar")?;
         assert_eq!(
             serde_json::to_value(req)?,
             json!({
@@ -151,11 +630,180 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn create_image_request_for_dall_e_2_should_serialize() -> Result<()> {
+        let req = CreateImageRequestBuilder::default()
+            .prompt("draw a cute caterpillar")
+            .model(ImageModel::DallE2)
+            .n(4)
+            .size(ImageSize::Small)
+            .build()?;
+        assert_eq!(
+            serde_json::to_value(req)?,
+            json!({
+              "prompt": "draw a cute caterpillar",
+              "model": "dall-e-2",
+              "n": 4,
+              "size": "256x256",
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn create_image_request_for_gpt_image_1_should_serialize() -> Result<()> {
+        let req = CreateImageRequestBuilder::default()
+            .prompt("draw a cute caterpillar")
+            .model(ImageModel::GptImage1)
+            .quality(ImageQuality::High)
+            .size(ImageSize::Landscape)
+            .output_format(ImageOutputFormat::Webp)
+            .output_compression(80)
+            .background(ImageBackground::Transparent)
+            .moderation(ImageModeration::Low)
+            .build()?;
+        assert_eq!(
+            serde_json::to_value(req)?,
+            json!({
+              "prompt": "draw a cute caterpillar",
+              "model": "gpt-image-1",
+              "quality": "high",
+              "size": "1536x1024",
+              "output_format": "webp",
+              "output_compression": 80,
+              "background": "transparent",
+              "moderation": "low",
+            })
+        );
+
+        let mut builder = CreateImageRequestBuilder::default();
+        builder
+            .prompt("draw a cute caterpillar")
+            .model(ImageModel::GptImage1)
+            .response_format(ImageResponseFormat::Url);
+        let err = builder.build().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "response_format is not supported by gpt-image-1"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn create_image_response_for_gpt_image_1_should_parse() -> Result<()> {
+        let body = Bytes::from(
+            json!({
+              "created": 1713833628,
+              "data": [{ "b64_json": "aGVsbG8=" }],
+              "usage": {
+                "total_tokens": 100,
+                "input_tokens": 50,
+                "output_tokens": 50,
+                "input_tokens_details": { "text_tokens": 10, "image_tokens": 40 }
+              }
+            })
+            .to_string(),
+        );
+        let res = CreateImageResponse::from_bytes(body, "draw a cute caterpillar")?;
+        assert_eq!(res.usage.unwrap().total_tokens, 100);
+        let image = &res.data[0];
+        assert!(image.url.is_none());
+        assert_eq!(
+            image.b64_json.as_ref().unwrap().decode()?,
+            Bytes::from_static(b"hello")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn create_image_request_should_reject_unsupported_params() {
+        let mut builder = CreateImageRequestBuilder::default();
+        builder
+            .prompt("draw a cute caterpillar")
+            .model(ImageModel::DallE2)
+            .quality(ImageQuality::Hd);
+        let err = builder.build().unwrap_err();
+        assert_eq!(err.to_string(), "quality hd is not supported by dall-e-2");
+
+        let mut builder = CreateImageRequestBuilder::default();
+        builder.prompt("draw a cute caterpillar").n(2);
+        let err = builder.build().unwrap_err();
+        assert_eq!(err.to_string(), "n must be 1 for dall-e-3");
+
+        let mut builder = CreateImageRequestBuilder::default();
+        builder
+            .prompt("draw a cute caterpillar")
+            .size(ImageSize::Medium);
+        let err = builder.build().unwrap_err();
+        assert_eq!(err.to_string(), "size 512x512 is not supported by dall-e-3");
+    }
+
+    #[test]
+    fn create_image_request_should_validate_output_options() {
+        let mut builder = CreateImageRequestBuilder::default();
+        builder
+            .prompt("draw a cute caterpillar")
+            .moderation(ImageModeration::Low);
+        let err = builder.build().unwrap_err();
+        assert_eq!(err.to_string(), "moderation is not supported by dall-e-3");
+
+        let mut builder = CreateImageRequestBuilder::default();
+        builder
+            .prompt("draw a cute caterpillar")
+            .model(ImageModel::GptImage1)
+            .output_compression(50);
+        let err = builder.build().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "output_compression is not supported for png"
+        );
+
+        builder.output_format(ImageOutputFormat::Jpeg);
+        assert!(builder.build().is_ok());
+
+        builder.output_compression(101);
+        let err = builder.build().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "output_compression must be between 0 and 100"
+        );
+
+        let mut builder = CreateImageRequestBuilder::default();
+        builder
+            .prompt("draw a cute caterpillar")
+            .model(ImageModel::GptImage1)
+            .output_format(ImageOutputFormat::Jpeg)
+            .background(ImageBackground::Transparent);
+        let err = builder.build().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "transparent background is not supported for jpeg"
+        );
+    }
+
+    #[test]
+    fn create_image_request_with_custom_model_should_skip_validation() -> Result<()> {
+        let req = CreateImageRequestBuilder::default()
+            .prompt("draw a cute caterpillar")
+            .model(ImageModel::Other("my-diffusion".into()))
+            .n(20)
+            .build()?;
+        assert_eq!(
+            serde_json::to_value(req)?,
+            json!({
+              "prompt": "draw a cute caterpillar",
+              "model": "my-diffusion",
+              "n": 20,
+            })
+        );
+        Ok(())
+    }
+
     // this test is too expensive to run, skip for CI
     #[tokio::test]
     #[ignore]
     async fn create_image_should_work() -> Result<()> {
-        let req = CreateImageRequest::new("draw a cute caterpillar");
+        let req = CreateImageRequest::new("draw a cute caterpillar")?;
         let res = SDK.create_image(req).await?;
         assert_eq!(res.data.len(), 1);
         let image = &res.data[0];
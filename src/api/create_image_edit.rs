@@ -0,0 +1,115 @@
+use super::{common::with_idempotency_key, multipart::MultipartForm};
+use crate::{BuildError, ImageResponseFormat, ImageSize, IntoRequest};
+use derive_builder::Builder;
+use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Builder)]
+#[builder(pattern = "mutable", build_fn(error = "BuildError"))]
+pub struct CreateImageEditRequest {
+    /// The image to edit. Must be a valid PNG file, less than 4MB, and square. If mask is not provided, image must have transparency, which will be used as the mask.
+    image: Vec<u8>,
+    /// A text description of the desired image(s). The maximum length is 1000 characters.
+    #[builder(setter(into))]
+    pub(crate) prompt: String,
+    /// An additional image whose fully transparent areas (e.g. where alpha is zero) indicate where image should be edited. Must be a valid PNG file, less than 4MB, and have the same dimensions as image.
+    #[builder(default, setter(strip_option))]
+    mask: Option<Vec<u8>>,
+    /// The number of images to generate. Must be between 1 and 10.
+    #[builder(default, setter(strip_option))]
+    n: Option<usize>,
+    /// The size of the generated images.
+    #[builder(default, setter(strip_option))]
+    size: Option<ImageSize>,
+    /// The format in which the generated images are returned. Must be one of url or b64_json.
+    #[builder(default, setter(strip_option))]
+    response_format: Option<ImageResponseFormat>,
+    /// A unique identifier representing your end-user, which can help OpenAI to monitor and detect abuse.
+    #[builder(default, setter(strip_option, into))]
+    user: Option<String>,
+    /// Sent as the `Idempotency-Key` header, so a retry isn't run (and billed) twice by the
+    /// gateways supporting it. A random key is generated if not set.
+    #[builder(default, setter(strip_option, into))]
+    idempotency_key: Option<String>,
+    /// Overrides the timeout of the SDK (`LlmSdkBuilder::timeout`) for this request.
+    #[builder(default, setter(strip_option))]
+    pub(crate) timeout: Option<Duration>,
+}
+
+impl CreateImageEditRequest {
+    pub fn new(image: Vec<u8>, prompt: impl Into<String>) -> Self {
+        CreateImageEditRequestBuilder::default()
+            .image(image)
+            .prompt(prompt)
+            .build()
+            .unwrap()
+    }
+
+    pub fn new_with_mask(image: Vec<u8>, mask: Vec<u8>, prompt: impl Into<String>) -> Self {
+        CreateImageEditRequestBuilder::default()
+            .image(image)
+            .mask(mask)
+            .prompt(prompt)
+            .build()
+            .unwrap()
+    }
+
+    fn into_form(self) -> MultipartForm {
+        let mut form = MultipartForm::new()
+            .file("image", "image.png", Some("image/png"), &self.image)
+            .text("prompt", self.prompt);
+        if let Some(mask) = self.mask {
+            form = form.file("mask", "mask.png", Some("image/png"), &mask);
+        }
+        if let Some(n) = self.n {
+            form = form.text("n", n.to_string());
+        }
+        if let Some(size) = self.size {
+            form = form.text("size", size.to_string());
+        }
+        if let Some(response_format) = self.response_format {
+            form = form.text("response_format", response_format.to_string());
+        }
+        if let Some(user) = self.user {
+            form = form.text("user", user);
+        }
+        form
+    }
+}
+
+impl IntoRequest for CreateImageEditRequest {
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    fn into_request(mut self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+        let url = format!("{}/images/edits", base_url);
+        let req = with_idempotency_key(client.post(url), self.idempotency_key.take());
+        self.into_form().attach(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_params_should_format_for_multipart() {
+        assert_eq!(ImageSize::Large.to_string(), "1024x1024");
+        assert_eq!(ImageResponseFormat::B64Json.to_string(), "b64_json");
+    }
+
+    #[test]
+    fn create_image_edit_request_should_build() {
+        let req = CreateImageEditRequestBuilder::default()
+            .image(vec![1, 2, 3])
+            .mask(vec![4, 5, 6])
+            .prompt("add a hat to the caterpillar")
+            .n(2)
+            .build()
+            .unwrap();
+        assert_eq!(req.mask.as_deref(), Some(&[4u8, 5, 6][..]));
+        assert_eq!(req.n, Some(2));
+        assert!(req.size.is_none());
+    }
+}
@@ -0,0 +1,139 @@
+use crate::{WhisperSegment, WhisperVerboseResponse};
+
+/// A speaker turn from an external diarization service or model, e.g. pyannote. Whisper itself
+/// doesn't know who is speaking, so the turns are merged with its segments by
+/// `attribute_speakers`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeakerTurn {
+    /// The label of the speaker, e.g. `SPEAKER_00`.
+    pub speaker: String,
+    /// Start time of the turn in seconds, from the beginning of the whole recording.
+    pub start: f32,
+    /// End time of the turn in seconds, from the beginning of the whole recording.
+    pub end: f32,
+}
+
+/// A piece of transcript attributed to a speaker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeakerSegment {
+    /// The speaker with the largest overlap, `None` if no turn overlaps the segment.
+    pub speaker: Option<String>,
+    /// Start time in seconds, from the beginning of the whole recording.
+    pub start: f32,
+    /// End time in seconds, from the beginning of the whole recording.
+    pub end: f32,
+    pub text: String,
+}
+
+/// Attribute whisper segments to the speaker turns. `offset` is the start (in seconds) of the
+/// transcribed audio within the whole recording, so a long recording can be transcribed chunk by
+/// chunk and attributed against the same turns. Consecutive segments of the same speaker are
+/// merged.
+pub fn attribute_speakers(
+    segments: &[WhisperSegment],
+    offset: f32,
+    turns: &[SpeakerTurn],
+) -> Vec<SpeakerSegment> {
+    let mut ret: Vec<SpeakerSegment> = Vec::new();
+    for segment in segments {
+        let start = segment.start + offset;
+        let end = segment.end + offset;
+        let text = segment.text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        let speaker = turns
+            .iter()
+            .map(|turn| (turn, turn.end.min(end) - turn.start.max(start)))
+            .filter(|(_, overlap)| *overlap > 0.0)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(turn, _)| turn.speaker.clone());
+
+        match ret.last_mut() {
+            Some(last) if last.speaker == speaker => {
+                last.end = end;
+                last.text.push(' ');
+                last.text.push_str(text);
+            }
+            _ => ret.push(SpeakerSegment {
+                speaker,
+                start,
+                end,
+                text: text.to_string(),
+            }),
+        }
+    }
+    ret
+}
+
+impl WhisperVerboseResponse {
+    /// Attribute the segments of this transcription to the speaker turns, see
+    /// `attribute_speakers`.
+    pub fn attribute_speakers(&self, turns: &[SpeakerTurn]) -> Vec<SpeakerSegment> {
+        attribute_speakers(&self.segments, 0.0, turns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(start: f32, end: f32, text: &str) -> WhisperSegment {
+        WhisperSegment {
+            id: 0,
+            seek: 0,
+            start,
+            end,
+            text: text.into(),
+            tokens: vec![],
+            temperature: 0.0,
+            avg_logprob: -0.2,
+            compression_ratio: 1.0,
+            no_speech_prob: 0.0,
+        }
+    }
+
+    fn turn(speaker: &str, start: f32, end: f32) -> SpeakerTurn {
+        SpeakerTurn {
+            speaker: speaker.into(),
+            start,
+            end,
+        }
+    }
+
+    #[test]
+    fn attribute_speakers_should_merge_turns_and_segments() {
+        let segments = vec![
+            segment(0.0, 2.0, " Hi there."),
+            segment(2.0, 3.5, " How are you?"),
+            segment(3.5, 6.0, " Fine, thanks."),
+            segment(20.0, 21.0, " Anyone?"),
+        ];
+        let turns = vec![turn("A", 10.0, 13.4), turn("B", 13.4, 16.0)];
+        // the chunk starts at 10s of the recording
+        let ret = attribute_speakers(&segments, 10.0, &turns);
+        assert_eq!(
+            ret,
+            vec![
+                SpeakerSegment {
+                    speaker: Some("A".into()),
+                    start: 10.0,
+                    end: 13.5,
+                    text: "Hi there. How are you?".into(),
+                },
+                SpeakerSegment {
+                    speaker: Some("B".into()),
+                    start: 13.5,
+                    end: 16.0,
+                    text: "Fine, thanks.".into(),
+                },
+                SpeakerSegment {
+                    speaker: None,
+                    start: 30.0,
+                    end: 31.0,
+                    text: "Anyone?".into(),
+                },
+            ]
+        );
+    }
+}
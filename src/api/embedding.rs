@@ -1,39 +1,66 @@
-use crate::IntoRequest;
+use crate::{BuildError, IntoRequest};
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
 use derive_builder::Builder;
 use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
 use serde::{Deserialize, Serialize};
+use std::{ops::Range, time::Duration};
+
+/// A rough estimate when no tokenizer is available, ~4 characters per token for English.
+const CHARS_PER_TOKEN: usize = 4;
 
 #[derive(Debug, Clone, Serialize, Builder)]
-#[builder(pattern = "mutable")]
+#[builder(
+    pattern = "mutable",
+    build_fn(validate = "Self::validate", error = "BuildError")
+)]
 pub struct EmbeddingRequest {
     /// Input text to embed, encoded as a string or array of tokens. To embed multiple inputs in a single request, pass an array of strings or array of token arrays. The input must not exceed the max input tokens for the model (8192 tokens for text-embedding-ada-002), cannot be an empty string, and any array must be 2048 dimensions or less.
-    input: EmbeddingInput,
+    pub(crate) input: EmbeddingInput,
     /// ID of the model to use. You can use the List models API to see all of your available models, or see our Model overview for descriptions of them.
     #[builder(default)]
-    model: EmbeddingModel,
+    pub(crate) model: EmbeddingModel,
     /// The format to return the embeddings in. Can be either float or base64.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
-    encoding_format: Option<EmbeddingEncodingFormat>,
+    pub(crate) encoding_format: Option<EmbeddingEncodingFormat>,
+    /// The number of dimensions the resulting output embeddings should have. Only supported in text-embedding-3 and later models.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) dimensions: Option<usize>,
     /// A unique identifier representing your end-user, which can help OpenAI to monitor and detect abuse. Learn more.
     #[builder(default, setter(strip_option, into))]
     #[serde(skip_serializing_if = "Option::is_none")]
-    user: Option<String>,
+    pub(crate) user: Option<String>,
+    /// Overrides the timeout of the SDK (`LlmSdkBuilder::timeout`) for this request.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip)]
+    pub(crate) timeout: Option<Duration>,
 }
 
-// currently we don't support array of integers, or array of array of integers
 #[derive(Debug, Clone, Serialize)]
 #[serde(untagged)]
 pub enum EmbeddingInput {
     String(String),
     StringArray(Vec<String>),
+    /// A pre-tokenized input.
+    Tokens(Vec<u32>),
+    /// Multiple pre-tokenized inputs.
+    TokensArray(Vec<Vec<u32>>),
 }
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum EmbeddingModel {
     #[default]
     #[serde(rename = "text-embedding-ada-002")]
     TextEmbeddingAda002,
+    #[serde(rename = "text-embedding-3-small")]
+    TextEmbedding3Small,
+    #[serde(rename = "text-embedding-3-large")]
+    TextEmbedding3Large,
+    /// Any other model by its name, e.g. a newer model or one served by a proxy.
+    #[serde(untagged)]
+    Other(String),
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
@@ -52,7 +79,7 @@ pub struct EmbeddingResponse {
     pub usage: EmbeddingUsage,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct EmbeddingUsage {
     pub prompt_tokens: usize,
     pub total_tokens: usize,
@@ -62,19 +89,144 @@ pub struct EmbeddingUsage {
 pub struct EmbeddingData {
     /// The index of the embedding in the list of embeddings.
     pub index: usize,
-    /// The embedding vector, which is a list of floats. The length of vector depends on the model as listed in the embedding guide.
-    pub embedding: Vec<f32>,
+    /// The embedding vector, which is a list of floats, or a base64 string if the request set encoding_format to base64. The length of vector depends on the model as listed in the embedding guide.
+    pub embedding: Embedding,
     /// The object type, which is always "embedding".
     pub object: String,
 }
 
+/// Options of `LlmSdk::embed_all`.
+#[derive(Debug, Clone)]
+pub struct EmbedAllOptions {
+    /// Max number of inputs per request, the API accepts up to 2048.
+    pub max_inputs: usize,
+    /// Max (estimated) tokens of all the inputs of a request.
+    pub max_tokens: usize,
+    /// Number of requests in flight.
+    pub concurrency: usize,
+    /// The number of dimensions of the embeddings, for text-embedding-3 and later models.
+    pub dimensions: Option<usize>,
+}
+
+/// The embeddings of `LlmSdk::embed_all`, in the order of the inputs.
+#[derive(Debug, Clone)]
+pub struct EmbedAllResponse {
+    pub embeddings: Vec<Vec<f32>>,
+    /// The usage summed over all the requests.
+    pub usage: EmbeddingUsage,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Embedding {
+    Float(Vec<f32>),
+    /// The little-endian f32 values, base64 encoded.
+    Base64(String),
+}
+
+impl Embedding {
+    /// The embedding as floats, decoding the base64 payload if needed.
+    pub fn as_floats(&self) -> Result<Vec<f32>> {
+        match self {
+            Self::Float(v) => Ok(v.clone()),
+            Self::Base64(s) => {
+                let bytes = STANDARD.decode(s)?;
+                if bytes.len() % 4 != 0 {
+                    return Err(anyhow!("invalid base64 embedding of {} bytes", bytes.len()));
+                }
+                Ok(bytes
+                    .chunks_exact(4)
+                    .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                    .collect())
+            }
+        }
+    }
+}
+
+impl Default for EmbedAllOptions {
+    fn default() -> Self {
+        Self {
+            max_inputs: 2048,
+            max_tokens: 300_000,
+            concurrency: 4,
+            dimensions: None,
+        }
+    }
+}
+
+impl EmbedAllOptions {
+    /// Split the inputs into the ranges of the requests. An input larger than `max_tokens` gets
+    /// a request of its own (and is likely rejected by the server).
+    pub(crate) fn split(&self, texts: &[String]) -> Vec<Range<usize>> {
+        let mut ret = Vec::new();
+        let mut start = 0;
+        let mut tokens = 0;
+        for (i, text) in texts.iter().enumerate() {
+            let n = estimate_tokens(text);
+            if i > start && (i - start >= self.max_inputs.max(1) || tokens + n > self.max_tokens) {
+                ret.push(start..i);
+                start = i;
+                tokens = 0;
+            }
+            tokens += n;
+        }
+        if start < texts.len() {
+            ret.push(start..texts.len());
+        }
+        ret
+    }
+}
+
+pub(crate) fn estimate_tokens(s: &str) -> usize {
+    s.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
 impl IntoRequest for EmbeddingRequest {
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
     fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
         let url = format!("{}/embeddings", base_url);
         client.post(url).json(&self)
     }
 }
 
+impl EmbeddingModel {
+    /// The (default) number of dimensions of the embeddings, `None` for `Other`.
+    pub fn dimensions(&self) -> Option<usize> {
+        match self {
+            Self::TextEmbeddingAda002 | Self::TextEmbedding3Small => Some(1536),
+            Self::TextEmbedding3Large => Some(3072),
+            Self::Other(_) => None,
+        }
+    }
+}
+
+impl EmbeddingRequestBuilder {
+    fn validate(&self) -> Result<(), String> {
+        let model = self.model.clone().unwrap_or_default();
+        let Some(dimensions) = self.dimensions.flatten() else {
+            return Ok(());
+        };
+        match model {
+            EmbeddingModel::TextEmbeddingAda002 => {
+                Err("dimensions is not supported by text-embedding-ada-002".into())
+            }
+            EmbeddingModel::TextEmbedding3Small | EmbeddingModel::TextEmbedding3Large => {
+                let max = model.dimensions().unwrap_or_default();
+                if dimensions == 0 || dimensions > max {
+                    Err(format!("dimensions must be between 1 and {}", max))
+                } else {
+                    Ok(())
+                }
+            }
+            // not validated, it's up to the server
+            EmbeddingModel::Other(_) => Ok(()),
+        }
+    }
+}
+
 impl EmbeddingRequest {
     pub fn new(input: impl Into<EmbeddingInput>) -> Self {
         EmbeddingRequestBuilder::default()
@@ -91,6 +243,56 @@ impl EmbeddingRequest {
     }
 }
 
+impl EmbeddingRequest {
+    /// The request split into two with the first and second halves of the inputs, `None` for a
+    /// single input.
+    pub(crate) fn split_in_half(&self) -> Option<(usize, Self, Self)> {
+        let mid = match &self.input {
+            EmbeddingInput::StringArray(v) if v.len() > 1 => v.len() / 2,
+            EmbeddingInput::TokensArray(v) if v.len() > 1 => v.len() / 2,
+            _ => return None,
+        };
+        let (first, second) = match &self.input {
+            EmbeddingInput::StringArray(v) => (
+                EmbeddingInput::StringArray(v[..mid].to_vec()),
+                EmbeddingInput::StringArray(v[mid..].to_vec()),
+            ),
+            EmbeddingInput::TokensArray(v) => (
+                EmbeddingInput::TokensArray(v[..mid].to_vec()),
+                EmbeddingInput::TokensArray(v[mid..].to_vec()),
+            ),
+            _ => unreachable!(),
+        };
+        let with_input = |input| Self {
+            input,
+            ..self.clone()
+        };
+        Some((mid, with_input(first), with_input(second)))
+    }
+}
+
+impl EmbeddingResponse {
+    /// Merge the responses of the parts of a split request, given with the offset of their first
+    /// input.
+    pub(crate) fn merge(mut parts: Vec<(usize, Self)>) -> Option<Self> {
+        parts.sort_by_key(|(offset, _)| *offset);
+        let mut parts = parts.into_iter();
+        let (offset, mut ret) = parts.next()?;
+        for data in &mut ret.data {
+            data.index += offset;
+        }
+        for (offset, res) in parts {
+            ret.data.extend(res.data.into_iter().map(|mut data| {
+                data.index += offset;
+                data
+            }));
+            ret.usage.prompt_tokens += res.usage.prompt_tokens;
+            ret.usage.total_tokens += res.usage.total_tokens;
+        }
+        Some(ret)
+    }
+}
+
 impl From<String> for EmbeddingInput {
     fn from(s: String) -> Self {
         Self::String(s)
@@ -109,6 +311,24 @@ impl From<&[String]> for EmbeddingInput {
     }
 }
 
+impl From<Vec<u32>> for EmbeddingInput {
+    fn from(tokens: Vec<u32>) -> Self {
+        Self::Tokens(tokens)
+    }
+}
+
+impl From<&[u32]> for EmbeddingInput {
+    fn from(tokens: &[u32]) -> Self {
+        Self::Tokens(tokens.to_vec())
+    }
+}
+
+impl From<Vec<Vec<u32>>> for EmbeddingInput {
+    fn from(tokens: Vec<Vec<u32>>) -> Self {
+        Self::TokensArray(tokens)
+    }
+}
+
 impl From<&str> for EmbeddingInput {
     fn from(s: &str) -> Self {
         Self::String(s.to_owned())
@@ -119,7 +339,154 @@ impl From<&str> for EmbeddingInput {
 mod tests {
     use super::*;
     use crate::SDK;
-    use anyhow::Result;
+
+    #[test]
+    fn embedding_request_with_custom_model_should_serialize() -> Result<()> {
+        let req = EmbeddingRequestBuilder::default()
+            .input("hello".into())
+            .model(EmbeddingModel::Other("nomic-embed-text".into()))
+            .build()?;
+        assert_eq!(serde_json::to_value(req)?["model"], "nomic-embed-text");
+        Ok(())
+    }
+
+    #[test]
+    fn embedding_request_should_split_and_merge() -> Result<()> {
+        let texts: Vec<String> = ["a", "b", "c"].iter().map(|s| s.to_string()).collect();
+        let req = EmbeddingRequest::new_array(texts);
+        let (mid, first, second) = req.split_in_half().unwrap();
+        assert_eq!(mid, 1);
+        assert_eq!(
+            serde_json::to_value(&first)?["input"],
+            serde_json::json!(["a"])
+        );
+        assert_eq!(
+            serde_json::to_value(&second)?["input"],
+            serde_json::json!(["b", "c"])
+        );
+        assert!(EmbeddingRequest::new("a".to_string())
+            .split_in_half()
+            .is_none());
+
+        let response = |n: usize| EmbeddingResponse {
+            object: "list".into(),
+            data: (0..n)
+                .map(|index| EmbeddingData {
+                    index,
+                    embedding: Embedding::Float(vec![index as f32]),
+                    object: "embedding".into(),
+                })
+                .collect(),
+            model: "text-embedding-ada-002".into(),
+            usage: EmbeddingUsage {
+                prompt_tokens: n,
+                total_tokens: n,
+            },
+        };
+        let res = EmbeddingResponse::merge(vec![(1, response(2)), (0, response(1))]).unwrap();
+        let indexes: Vec<_> = res.data.iter().map(|d| d.index).collect();
+        assert_eq!(indexes, [0, 1, 2]);
+        assert_eq!(res.usage.total_tokens, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn embedding_request_should_validate_dimensions() -> Result<()> {
+        let req = EmbeddingRequestBuilder::default()
+            .input("hello".into())
+            .model(EmbeddingModel::TextEmbedding3Small)
+            .dimensions(256)
+            .build()?;
+        let value = serde_json::to_value(req)?;
+        assert_eq!(value["model"], "text-embedding-3-small");
+        assert_eq!(value["dimensions"], 256);
+
+        assert!(EmbeddingRequestBuilder::default()
+            .input("hello".into())
+            .dimensions(256)
+            .build()
+            .is_err());
+        assert!(EmbeddingRequestBuilder::default()
+            .input("hello".into())
+            .model(EmbeddingModel::TextEmbedding3Large)
+            .dimensions(4096)
+            .build()
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn embed_all_options_should_split_by_inputs_and_tokens() {
+        let texts: Vec<String> = [
+            "a".repeat(40),
+            "b".repeat(40),
+            "c".repeat(80),
+            "d".repeat(4),
+        ]
+        .into_iter()
+        .collect();
+        let opts = EmbedAllOptions {
+            max_inputs: 2,
+            max_tokens: 20,
+            ..Default::default()
+        };
+        // 10, 10 | 20 | 1 tokens
+        assert_eq!(opts.split(&texts), vec![0..2, 2..3, 3..4]);
+        assert!(opts.split(&[]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn embed_all_should_keep_order() -> Result<()> {
+        let texts: Vec<String> = (0..5).map(|i| format!("text {}", i)).collect();
+        let opts = EmbedAllOptions {
+            max_inputs: 2,
+            ..Default::default()
+        };
+        let res = SDK
+            .embed_all(&texts, EmbeddingModel::TextEmbedding3Small, opts)
+            .await?;
+        assert_eq!(res.embeddings.len(), 5);
+        assert!(res.embeddings.iter().all(|e| e.len() == 1536));
+        assert!(res.usage.total_tokens > 0);
+        Ok(())
+    }
+
+    #[test]
+    fn token_embedding_input_should_serialize() -> Result<()> {
+        let req = EmbeddingRequest::new(vec![1u32, 2, 3]);
+        assert_eq!(
+            serde_json::to_value(req)?["input"],
+            serde_json::json!([1, 2, 3])
+        );
+        let req = EmbeddingRequest::new(vec![vec![1u32, 2], vec![3]]);
+        assert_eq!(
+            serde_json::to_value(req)?["input"],
+            serde_json::json!([[1, 2], [3]])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn base64_embedding_should_decode() -> Result<()> {
+        let bytes: Vec<u8> = [1.0f32, -0.5, 0.25]
+            .iter()
+            .flat_map(|f| f.to_le_bytes())
+            .collect();
+        let data: EmbeddingData = serde_json::from_value(serde_json::json!({
+          "index": 0,
+          "object": "embedding",
+          "embedding": STANDARD.encode(bytes)
+        }))?;
+        assert_eq!(data.embedding.as_floats()?, vec![1.0, -0.5, 0.25]);
+
+        let data: EmbeddingData = serde_json::from_value(serde_json::json!({
+          "index": 0,
+          "object": "embedding",
+          "embedding": [0.5, 0.5]
+        }))?;
+        assert_eq!(data.embedding, Embedding::Float(vec![0.5, 0.5]));
+        Ok(())
+    }
 
     #[tokio::test]
     async fn string_embedding_should_work() -> Result<()> {
@@ -130,7 +497,7 @@ mod tests {
         // response model id is different
         assert_eq!(res.model, "text-embedding-ada-002-v2");
         let data = &res.data[0];
-        assert_eq!(data.embedding.len(), 1536);
+        assert_eq!(data.embedding.as_floats()?.len(), 1536);
         assert_eq!(data.index, 0);
         assert_eq!(data.object, "embedding");
         Ok(())
@@ -148,7 +515,7 @@ mod tests {
         // response model id is different
         assert_eq!(res.model, "text-embedding-ada-002-v2");
         let data = &res.data[1];
-        assert_eq!(data.embedding.len(), 1536);
+        assert_eq!(data.embedding.as_floats()?.len(), 1536);
         assert_eq!(data.index, 1);
         assert_eq!(data.object, "embedding");
         Ok(())
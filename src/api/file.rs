@@ -0,0 +1,104 @@
+use super::multipart::MultipartForm;
+use crate::{BuildError, IntoRequest};
+use derive_builder::Builder;
+use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+
+#[derive(Debug, Clone, Builder)]
+#[builder(pattern = "mutable", build_fn(error = "BuildError"))]
+pub struct UploadFileRequest {
+    /// The file content to be uploaded.
+    pub(crate) file: Vec<u8>,
+    /// The name of the file, OpenAI uses its extension to validate the content.
+    #[builder(setter(into))]
+    pub(crate) filename: String,
+    /// The intended purpose of the uploaded file.
+    #[builder(default)]
+    pub(crate) purpose: FilePurpose,
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, EnumString, Display,
+)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum FilePurpose {
+    #[default]
+    Assistants,
+    Batch,
+    FineTune,
+    Vision,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FileObject {
+    /// The file identifier, which can be referenced in the API endpoints.
+    pub id: String,
+    /// The object type, which is always file.
+    pub object: String,
+    /// The size of the file, in bytes.
+    pub bytes: u64,
+    /// The Unix timestamp (in seconds) for when the file was created.
+    pub created_at: u64,
+    /// The name of the file.
+    pub filename: String,
+    /// The intended purpose of the file.
+    pub purpose: String,
+}
+
+impl UploadFileRequest {
+    pub fn new(file: Vec<u8>, filename: impl Into<String>, purpose: FilePurpose) -> Self {
+        UploadFileRequestBuilder::default()
+            .file(file)
+            .filename(filename)
+            .purpose(purpose)
+            .build()
+            .unwrap()
+    }
+
+    fn into_form(self) -> MultipartForm {
+        MultipartForm::new()
+            .file("file", &self.filename, None, &self.file)
+            .text("purpose", self.purpose.to_string())
+    }
+}
+
+impl IntoRequest for UploadFileRequest {
+    fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+        let url = format!("{}/files", base_url);
+        self.into_form().attach(client.post(url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SDK;
+    use anyhow::Result;
+
+    #[test]
+    fn file_purpose_should_use_kebab_case() {
+        assert_eq!(FilePurpose::FineTune.to_string(), "fine-tune");
+        assert_eq!(
+            serde_json::to_value(FilePurpose::Batch).unwrap(),
+            serde_json::json!("batch")
+        );
+    }
+
+    #[tokio::test]
+    async fn file_lifecycle_should_work() -> Result<()> {
+        let req = UploadFileRequest::new(
+            b"hello world".to_vec(),
+            "hello.txt",
+            FilePurpose::Assistants,
+        );
+        let file = SDK.upload_file(req).await?;
+        assert_eq!(file.bytes, 11);
+        assert_eq!(file.filename, "hello.txt");
+
+        let status = SDK.delete_file(&file.id).await?;
+        assert!(status.deleted);
+        Ok(())
+    }
+}
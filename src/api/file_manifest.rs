@@ -0,0 +1,168 @@
+use crate::{FileObject, FilePurpose};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// A local manifest of the uploaded files keyed by the sha256 of their content, used by
+/// `LlmSdk::upload_file_dedup` to skip re-uploading identical files (e.g. training or batch
+/// files). It is persisted as JSON after every change.
+#[derive(Debug)]
+pub struct FileManifest {
+    path: PathBuf,
+    /// Also look for a file with the same name, size and purpose in the remote file list.
+    pub(crate) check_remote: bool,
+    entries: Mutex<HashMap<String, ManifestEntry>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub file_id: String,
+    pub filename: String,
+    pub bytes: u64,
+    pub purpose: FilePurpose,
+}
+
+impl FileManifest {
+    /// Load the manifest at `path`, an empty one if the file doesn't exist.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let entries = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path,
+            check_remote: false,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// On a manifest miss, look for a file with the same name, size and purpose in the most
+    /// recent 100 files of the account before uploading.
+    pub fn with_remote_check(mut self) -> Self {
+        self.check_remote = true;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The sha256 of the content, hex encoded.
+    pub fn hash(content: &[u8]) -> String {
+        Sha256::digest(content)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    pub fn get(&self, hash: &str) -> Option<ManifestEntry> {
+        self.lock().get(hash).cloned()
+    }
+
+    pub(crate) fn insert(
+        &self,
+        hash: String,
+        file: &FileObject,
+        purpose: FilePurpose,
+    ) -> Result<()> {
+        let entry = ManifestEntry {
+            file_id: file.id.clone(),
+            filename: file.filename.clone(),
+            bytes: file.bytes,
+            purpose,
+        };
+        let mut entries = self.lock();
+        entries.insert(hash, entry);
+        self.save(&entries)
+    }
+
+    pub(crate) fn remove(&self, hash: &str) -> Result<()> {
+        let mut entries = self.lock();
+        if entries.remove(hash).is_some() {
+            self.save(&entries)?;
+        }
+        Ok(())
+    }
+
+    fn save(&self, entries: &HashMap<String, ManifestEntry>) -> Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(entries)?)?;
+        fs::rename(tmp, &self.path)?;
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, ManifestEntry>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_manifest_should_persist_entries() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("manifest-{}.json", std::process::id()));
+        let manifest = FileManifest::open(&path)?;
+        let hash = FileManifest::hash(b"hello");
+        assert_eq!(
+            hash,
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert_eq!(manifest.get(&hash), None);
+
+        let file: FileObject = serde_json::from_value(serde_json::json!({
+          "id": "file-abc123",
+          "object": "file",
+          "bytes": 5,
+          "created_at": 1613677385,
+          "filename": "hello.jsonl",
+          "purpose": "batch"
+        }))?;
+        manifest.insert(hash.clone(), &file, FilePurpose::Batch)?;
+
+        let manifest = FileManifest::open(&path)?;
+        assert_eq!(manifest.get(&hash).unwrap().file_id, "file-abc123");
+        manifest.remove(&hash)?;
+        assert_eq!(FileManifest::open(&path)?.get(&hash), None);
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn upload_file_dedup_should_keep_entry_on_transient_errors() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("manifest-dedup-{}.json", std::process::id()));
+        let manifest = FileManifest::open(&path)?;
+        let hash = FileManifest::hash(b"hello");
+        let file: FileObject = serde_json::from_value(serde_json::json!({
+          "id": "file-abc123",
+          "object": "file",
+          "bytes": 5,
+          "created_at": 1613677385,
+          "filename": "hello.jsonl",
+          "purpose": "batch"
+        }))?;
+        manifest.insert(hash.clone(), &file, FilePurpose::Batch)?;
+
+        // nothing listens there: the retrieval fails without a 404
+        let sdk = crate::LlmSdkBuilder::default()
+            .base_url("http://127.0.0.1:1/v1")
+            .token("")
+            .max_retries(0)
+            .build()?;
+        let req =
+            crate::UploadFileRequest::new(b"hello".to_vec(), "hello.jsonl", FilePurpose::Batch);
+        assert!(sdk.upload_file_dedup(req, &manifest).await.is_err());
+        assert_eq!(manifest.get(&hash).unwrap().file_id, "file-abc123");
+        fs::remove_file(path)?;
+        Ok(())
+    }
+}
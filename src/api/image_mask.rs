@@ -0,0 +1,177 @@
+use anyhow::{anyhow, Result};
+use flate2::{write::ZlibEncoder, Compression, Crc};
+use std::io::Write;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// A mask for `CreateImageEditRequest`. The image is edited where the mask is fully transparent,
+/// the rest is kept. Start from a fully opaque mask and clear the areas to edit, then encode it
+/// with `to_png`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageMask {
+    width: u32,
+    height: u32,
+    /// Alpha of each pixel, row by row. 0 means "edit this pixel".
+    alpha: Vec<u8>,
+}
+
+impl ImageMask {
+    /// A fully opaque mask, i.e. nothing is edited.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            alpha: vec![u8::MAX; width as usize * height as usize],
+        }
+    }
+
+    /// Build the mask from the alpha channel of an RGBA image, e.g. one exported from an editor.
+    #[cfg(feature = "image")]
+    pub fn from_alpha(image: &image::RgbaImage) -> Self {
+        Self {
+            width: image.width(),
+            height: image.height(),
+            alpha: image.pixels().map(|p| p.0[3]).collect(),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Whether the pixel at (x, y) will be edited.
+    pub fn is_cleared(&self, x: u32, y: u32) -> bool {
+        x < self.width && y < self.height && self.alpha[self.index(x, y)] == 0
+    }
+
+    /// Mark the rectangle to be edited, it's clipped to the mask.
+    pub fn clear_rect(&mut self, x: u32, y: u32, width: u32, height: u32) -> &mut Self {
+        let (x_end, y_end) = (
+            x.saturating_add(width).min(self.width),
+            y.saturating_add(height).min(self.height),
+        );
+        for row in y..y_end {
+            for col in x..x_end {
+                let idx = self.index(col, row);
+                self.alpha[idx] = 0;
+            }
+        }
+        self
+    }
+
+    /// Mark the polygon (a list of (x, y) vertices) to be edited, using the even-odd rule. A pixel
+    /// is inside if its center is.
+    pub fn clear_polygon(&mut self, points: &[(f32, f32)]) -> &mut Self {
+        if points.len() < 3 {
+            return self;
+        }
+        for row in 0..self.height {
+            let y = row as f32 + 0.5;
+            // x of the crossings of the scanline with the edges
+            let mut xs: Vec<f32> = points
+                .iter()
+                .zip(points.iter().cycle().skip(1))
+                .filter(|((_, y0), (_, y1))| (*y0 <= y) != (*y1 <= y))
+                .map(|((x0, y0), (x1, y1))| x0 + (y - y0) / (y1 - y0) * (x1 - x0))
+                .collect();
+            xs.sort_by(|a, b| a.total_cmp(b));
+            for span in xs.chunks_exact(2) {
+                let start = (span[0] - 0.5).ceil().max(0.0) as u32;
+                let end = ((span[1] - 0.5).ceil().max(0.0) as u32).min(self.width);
+                for col in start..end {
+                    let idx = self.index(col, row);
+                    self.alpha[idx] = 0;
+                }
+            }
+        }
+        self
+    }
+
+    /// Encode the mask as an RGBA PNG, which is what the image edit endpoint expects.
+    pub fn to_png(&self) -> Result<Vec<u8>> {
+        if self.width == 0 || self.height == 0 {
+            return Err(anyhow!("mask must not be empty"));
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        let mut row = Vec::with_capacity(1 + self.width as usize * 4);
+        for line in self.alpha.chunks_exact(self.width as usize) {
+            row.clear();
+            // filter type: none
+            row.push(0);
+            row.extend(line.iter().flat_map(|a| [0, 0, 0, *a]));
+            encoder.write_all(&row)?;
+        }
+        let data = encoder.finish()?;
+
+        let mut header = Vec::with_capacity(13);
+        header.extend(self.width.to_be_bytes());
+        header.extend(self.height.to_be_bytes());
+        // 8 bits per channel, RGBA, default compression / filter / no interlace
+        header.extend([8, 6, 0, 0, 0]);
+
+        let mut png = PNG_SIGNATURE.to_vec();
+        write_chunk(&mut png, b"IHDR", &header);
+        write_chunk(&mut png, b"IDAT", &data);
+        write_chunk(&mut png, b"IEND", &[]);
+        Ok(png)
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    png.extend(kind);
+    png.extend(data);
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    png.extend(crc.sum().to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::ZlibDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn image_mask_should_clear_areas() {
+        let mut mask = ImageMask::new(10, 10);
+        mask.clear_rect(8, 8, 5, 5)
+            .clear_polygon(&[(0.0, 0.0), (4.0, 0.0), (0.0, 4.0)]);
+        assert!(mask.is_cleared(9, 9));
+        assert!(!mask.is_cleared(7, 9));
+        assert!(mask.is_cleared(0, 0));
+        assert!(mask.is_cleared(1, 1));
+        assert!(!mask.is_cleared(2, 1));
+        assert!(!mask.is_cleared(3, 3));
+    }
+
+    #[test]
+    fn image_mask_should_encode_png() -> Result<()> {
+        let mut mask = ImageMask::new(4, 2);
+        mask.clear_rect(1, 1, 2, 1);
+        let png = mask.to_png()?;
+        assert_eq!(png[..8], PNG_SIGNATURE);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(png[16..24], [0, 0, 0, 4, 0, 0, 0, 2]);
+
+        let len = u32::from_be_bytes(png[33..37].try_into()?) as usize;
+        assert_eq!(&png[37..41], b"IDAT");
+        let mut raw = Vec::new();
+        ZlibDecoder::new(&png[41..41 + len]).read_to_end(&mut raw)?;
+        assert_eq!(raw.len(), 2 * (1 + 4 * 4));
+        // second row, second pixel is transparent
+        assert_eq!(raw[17 + 1 + 4..17 + 1 + 8], [0, 0, 0, 0]);
+        assert_eq!(raw[17 + 1..17 + 1 + 4], [0, 0, 0, 255]);
+        assert!(png.ends_with(&[0xae, 0x42, 0x60, 0x82]));
+        Ok(())
+    }
+}
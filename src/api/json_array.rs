@@ -0,0 +1,199 @@
+use crate::ChatCompletionStream;
+use anyhow::{anyhow, Result};
+use futures::{stream, Stream, StreamExt};
+use serde::de::DeserializeOwned;
+
+/// Incrementally splits a streamed JSON array into its elements. Text before the opening bracket
+/// and after the closing one (e.g. a markdown code fence) is ignored.
+#[derive(Debug, Clone, Default)]
+pub struct JsonArrayParser {
+    state: ParserState,
+    /// The nesting depth within the current element.
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    element: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum ParserState {
+    #[default]
+    Before,
+    Inside,
+    Done,
+}
+
+impl JsonArrayParser {
+    /// Feed a fragment of the text, and get the raw JSON of the elements it completes.
+    pub fn feed(&mut self, text: &str) -> Vec<String> {
+        let mut ret = Vec::new();
+        for c in text.chars() {
+            match self.state {
+                ParserState::Before => {
+                    if c == '[' {
+                        self.state = ParserState::Inside;
+                    }
+                }
+                ParserState::Inside => self.feed_char(c, &mut ret),
+                ParserState::Done => break,
+            }
+        }
+        ret
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.state == ParserState::Done
+    }
+
+    fn feed_char(&mut self, c: char, ret: &mut Vec<String>) {
+        if self.in_string {
+            self.element.push(c);
+            if self.escaped {
+                self.escaped = false;
+            } else if c == '\\' {
+                self.escaped = true;
+            } else if c == '"' {
+                self.in_string = false;
+            }
+            return;
+        }
+        match c {
+            '"' => {
+                self.in_string = true;
+                self.element.push(c);
+            }
+            '{' | '[' => {
+                self.depth += 1;
+                self.element.push(c);
+            }
+            '}' | ']' if self.depth > 0 => {
+                self.depth -= 1;
+                self.element.push(c);
+                // objects and arrays are complete without waiting for the next comma
+                if self.depth == 0 {
+                    self.finish_element(ret);
+                }
+            }
+            ']' => {
+                self.finish_element(ret);
+                self.state = ParserState::Done;
+            }
+            ',' if self.depth == 0 => self.finish_element(ret),
+            c if c.is_whitespace() && self.depth == 0 => {}
+            c => self.element.push(c),
+        }
+    }
+
+    fn finish_element(&mut self, ret: &mut Vec<String>) {
+        if !self.element.is_empty() {
+            ret.push(std::mem::take(&mut self.element));
+        }
+    }
+}
+
+impl ChatCompletionStream {
+    /// Parse the content of the first choice as a JSON array, and yield each element as soon as
+    /// it is complete, e.g. to process the items of a long list while it is being generated.
+    pub fn json_array<T>(self) -> impl Stream<Item = Result<T>> + Send
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let mut parser = JsonArrayParser::default();
+        self.map(move |chunk| {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => return vec![Err(e)],
+            };
+            chunk
+                .choices
+                .iter()
+                .filter(|choice| choice.index == 0)
+                .filter_map(|choice| choice.delta.content.as_deref())
+                .flat_map(|content| parser.feed(content))
+                .map(|element| {
+                    serde_json::from_str(&element)
+                        .map_err(|e| anyhow!("failed to parse element {}: {}", element, e))
+                })
+                .collect()
+        })
+        .flat_map(stream::iter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sse::SseEvent;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Item {
+        name: String,
+        tags: Vec<String>,
+    }
+
+    #[test]
+    fn json_array_parser_should_split_elements_across_fragments() {
+        let text = r#"```json
+[{"name": "a, [b]", "tags": ["x"]}, 1, "s\"]", [2, 3] , true]
+```"#;
+        for size in [1, 3, 7, text.len()] {
+            let mut parser = JsonArrayParser::default();
+            let chars: Vec<_> = text.chars().collect();
+            let elements: Vec<_> = chars
+                .chunks(size)
+                .flat_map(|chunk| parser.feed(&chunk.iter().collect::<String>()))
+                .collect();
+            assert_eq!(
+                elements,
+                [
+                    r#"{"name": "a, [b]", "tags": ["x"]}"#,
+                    "1",
+                    r#""s\"]""#,
+                    "[2, 3]",
+                    "true"
+                ]
+            );
+            assert!(parser.is_done());
+        }
+    }
+
+    #[test]
+    fn json_array_should_yield_typed_elements() {
+        let content = [
+            r#"[{"name":"a","ta"#,
+            r#"gs":[]},{"name":"b","tags":["x","y"]}"#,
+            "]",
+        ];
+        let events = content
+            .into_iter()
+            .map(|content| {
+                serde_json::json!({
+                    "id": "chatcmpl-1",
+                    "object": "chat.completion.chunk",
+                    "created": 1700000000,
+                    "model": "gpt-3.5-turbo-1106",
+                    "choices": [{ "index": 0, "delta": { "content": content } }]
+                })
+                .to_string()
+            })
+            .chain(["[DONE]".to_string()])
+            .map(|data| Ok(SseEvent { event: None, data }));
+        let stream = ChatCompletionStream::new(stream::iter(events)).json_array::<Item>();
+        let items: Vec<_> = futures::executor::block_on(stream.collect::<Vec<_>>());
+        let items: Vec<_> = items.into_iter().map(|item| item.unwrap()).collect();
+        assert_eq!(
+            items,
+            [
+                Item {
+                    name: "a".into(),
+                    tags: vec![]
+                },
+                Item {
+                    name: "b".into(),
+                    tags: vec!["x".into(), "y".into()]
+                }
+            ]
+        );
+    }
+}
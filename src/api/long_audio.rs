@@ -0,0 +1,181 @@
+use crate::WhisperVerboseResponse;
+use std::ops::Range;
+
+/// The smallest chunks tried when an audio is too large for the server.
+const MIN_CHUNK_BYTES: usize = 64 * 1024;
+
+/// Options of `LlmSdk::transcribe_long`.
+#[derive(Debug, Clone)]
+pub struct TranscribeLongOptions {
+    /// Max size of a chunk, a bit below the 25MB limit of the API.
+    pub max_bytes: usize,
+    /// Bytes shared by two consecutive chunks, so that the words at a boundary are not lost.
+    pub overlap_bytes: usize,
+    /// Number of chunks transcribed at the same time.
+    pub concurrency: usize,
+    /// The language of the audio in ISO-639-1 format, detected per chunk if not set.
+    pub language: Option<String>,
+}
+
+impl Default for TranscribeLongOptions {
+    fn default() -> Self {
+        Self {
+            max_bytes: 24 * 1024 * 1024,
+            overlap_bytes: 256 * 1024,
+            concurrency: 2,
+            language: None,
+        }
+    }
+}
+
+impl TranscribeLongOptions {
+    /// The byte ranges of the chunks of an audio of `len` bytes.
+    pub(crate) fn split(&self, len: usize) -> Vec<Range<usize>> {
+        let max_bytes = self.max_bytes.max(2);
+        let overlap = self.overlap_bytes.min(max_bytes / 2);
+        let mut ret = Vec::new();
+        let mut start = 0;
+        loop {
+            let end = (start + max_bytes).min(len);
+            ret.push(start..end);
+            if end >= len {
+                return ret;
+            }
+            start = end - overlap;
+        }
+    }
+}
+
+/// The size of the chunks to retry with after chunks of `last` bytes were too large, a bit below
+/// `limit` (the limit reported by the error) if it's known, and at most half of `last`. `None`
+/// once the chunks would be too small to be worth it.
+pub(crate) fn smaller_chunks(limit: Option<usize>, last: usize) -> Option<usize> {
+    let size = limit.map_or(last / 2, |limit| (limit / 10 * 9).min(last / 2));
+    (size >= MIN_CHUNK_BYTES).then_some(size)
+}
+
+/// Merge the verbose transcriptions of the chunks, in order. The chunks are placed on the
+/// timeline by their byte offsets (i.e. assuming a constant bitrate), and a segment (or word) is
+/// only kept by the chunk which owns its start: the boundary between two chunks is the middle of
+/// their overlap.
+pub(crate) fn stitch(parts: Vec<(Range<usize>, WhisperVerboseResponse)>) -> WhisperVerboseResponse {
+    let bytes: usize = parts.iter().map(|(range, _)| range.len()).sum();
+    let duration: f32 = parts.iter().map(|(_, res)| res.duration).sum();
+    let secs_per_byte = if bytes == 0 {
+        0.0
+    } else {
+        duration / bytes as f32
+    };
+    // the boundaries in bytes: the middle of the overlap with the previous chunk
+    let cuts: Vec<f32> = parts
+        .iter()
+        .enumerate()
+        .map(|(i, (range, _))| match i {
+            0 => 0.0,
+            _ => (range.start + parts[i - 1].0.end) as f32 / 2.0 * secs_per_byte,
+        })
+        .collect();
+
+    let mut parts = parts.into_iter().enumerate().peekable();
+    let (_, (_, first)) = parts.peek().cloned().expect("at least one chunk");
+    let mut ret = WhisperVerboseResponse {
+        task: first.task,
+        language: first.language,
+        duration: 0.0,
+        text: String::new(),
+        segments: Vec::new(),
+        words: Vec::new(),
+    };
+    for (i, (range, res)) in parts {
+        let offset = range.start as f32 * secs_per_byte;
+        let end = cuts.get(i + 1).copied().unwrap_or(f32::INFINITY);
+        let owned = |start: f32| (cuts[i]..end).contains(&(offset + start));
+        for mut segment in res.segments.into_iter().filter(|s| owned(s.start)) {
+            segment.id = ret.segments.len();
+            segment.start += offset;
+            segment.end += offset;
+            ret.text.push_str(&segment.text);
+            ret.segments.push(segment);
+        }
+        for mut word in res.words.into_iter().filter(|w| owned(w.start)) {
+            word.start += offset;
+            word.end += offset;
+            ret.words.push(word);
+        }
+        ret.duration = offset + res.duration;
+    }
+    ret.text = ret.text.trim().to_string();
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use serde_json::json;
+
+    fn response(duration: f32, segments: &[(f32, f32, &str)]) -> Result<WhisperVerboseResponse> {
+        let segments: Vec<_> = segments
+            .iter()
+            .enumerate()
+            .map(|(id, (start, end, text))| {
+                json!({
+                  "id": id, "start": start, "end": end, "text": text,
+                  "avg_logprob": -0.2, "compression_ratio": 1.0, "no_speech_prob": 0.0
+                })
+            })
+            .collect();
+        Ok(serde_json::from_value(json!({
+          "task": "transcribe",
+          "language": "english",
+          "duration": duration,
+          "text": "",
+          "segments": segments
+        }))?)
+    }
+
+    #[test]
+    fn transcribe_long_options_should_split_with_overlap() {
+        let opts = TranscribeLongOptions {
+            max_bytes: 100,
+            overlap_bytes: 20,
+            ..Default::default()
+        };
+        assert_eq!(opts.split(50), vec![0..50]);
+        assert_eq!(opts.split(100), vec![0..100]);
+        assert_eq!(opts.split(250), [0..100, 80..180, 160..250]);
+    }
+
+    #[test]
+    fn smaller_chunks_should_fit_the_limit() {
+        const MB: usize = 1024 * 1024;
+        assert_eq!(smaller_chunks(None, 100 * MB), Some(50 * MB));
+        assert_eq!(smaller_chunks(Some(10 * MB), 100 * MB), Some(9 * MB));
+        assert_eq!(smaller_chunks(Some(10 * MB), 12 * MB), Some(6 * MB));
+        assert_eq!(smaller_chunks(None, MIN_CHUNK_BYTES), None);
+    }
+
+    #[test]
+    fn stitch_should_drop_overlapping_segments() -> Result<()> {
+        let parts = vec![
+            (
+                0..100,
+                response(
+                    10.0,
+                    &[(0.0, 4.0, " A."), (4.0, 8.5, " B."), (8.5, 10.0, " C.")],
+                )?,
+            ),
+            (
+                80..180,
+                response(10.0, &[(0.5, 1.0, " C."), (1.2, 5.0, " D.")])?,
+            ),
+        ];
+        let res = stitch(parts);
+        assert_eq!(res.text, "A. B. C. D.");
+        assert_eq!(res.duration, 18.0);
+        let last = res.segments.last().unwrap();
+        assert_eq!(last.id, 3);
+        assert!((last.start - 9.2).abs() < 1e-3 && (last.end - 13.0).abs() < 1e-3);
+        Ok(())
+    }
+}
@@ -1,11 +1,52 @@
+mod assistant;
+mod assistant_stream;
+mod audio_format;
+mod batch;
 mod chat_completion;
+mod common;
+mod completion;
 mod create_image;
+mod create_image_edit;
+mod diarization;
 mod embedding;
+mod file;
+mod file_manifest;
+mod image_mask;
+mod json_array;
+mod long_audio;
+mod multipart;
+mod request_explain;
+mod response;
+mod run;
+mod run_step;
 mod speech;
+mod thread;
+#[cfg(feature = "chrono")]
+mod timestamp;
+mod transcript_stream;
 mod whisper;
 
+pub use assistant::*;
+pub use assistant_stream::*;
+pub use audio_format::*;
+pub use batch::*;
 pub use chat_completion::*;
+pub use common::*;
+pub use completion::*;
 pub use create_image::*;
+pub use create_image_edit::*;
+pub use diarization::*;
 pub use embedding::*;
+pub use file::*;
+pub use file_manifest::*;
+pub use image_mask::*;
+pub use json_array::*;
+pub use long_audio::*;
+pub use request_explain::*;
+pub use response::*;
+pub use run::*;
+pub use run_step::*;
 pub use speech::*;
+pub use thread::*;
+pub use transcript_stream::*;
 pub use whisper::*;
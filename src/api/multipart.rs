@@ -0,0 +1,103 @@
+use super::common::random_u64;
+use bytes::{BufMut, Bytes, BytesMut};
+use reqwest::header::CONTENT_TYPE;
+use reqwest_middleware::RequestBuilder;
+
+/// A `multipart/form-data` body encoded upfront. Unlike a `reqwest::multipart::Form` (a stream),
+/// the request can be cloned, so the uploads are retried on transient failures.
+#[derive(Debug)]
+pub(crate) struct MultipartForm {
+    boundary: String,
+    body: BytesMut,
+}
+
+impl MultipartForm {
+    pub(crate) fn new() -> Self {
+        Self {
+            boundary: format!("llm-sdk-{:016x}", random_u64()),
+            body: BytesMut::new(),
+        }
+    }
+
+    pub(crate) fn text(mut self, name: &str, value: impl AsRef<str>) -> Self {
+        self.header(name, None, None);
+        self.body.put_slice(value.as_ref().as_bytes());
+        self.body.put_slice(b"\r\n");
+        self
+    }
+
+    pub(crate) fn file(
+        mut self,
+        name: &str,
+        file_name: &str,
+        mime: Option<&str>,
+        data: &[u8],
+    ) -> Self {
+        self.header(name, Some(file_name), mime);
+        self.body.put_slice(data);
+        self.body.put_slice(b"\r\n");
+        self
+    }
+
+    /// Set the body and its content type on `builder`.
+    pub(crate) fn attach(self, builder: RequestBuilder) -> RequestBuilder {
+        let content_type = format!("multipart/form-data; boundary={}", self.boundary);
+        builder
+            .header(CONTENT_TYPE, content_type)
+            .body(self.into_bytes())
+    }
+
+    fn into_bytes(mut self) -> Bytes {
+        self.body
+            .put_slice(format!("--{}--\r\n", self.boundary).as_bytes());
+        self.body.freeze()
+    }
+
+    fn header(&mut self, name: &str, file_name: Option<&str>, mime: Option<&str>) {
+        let mut header = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"",
+            self.boundary,
+            escape(name)
+        );
+        if let Some(file_name) = file_name {
+            header.push_str(&format!("; filename=\"{}\"", escape(file_name)));
+        }
+        if let Some(mime) = mime {
+            header.push_str(&format!("\r\nContent-Type: {}", mime));
+        }
+        header.push_str("\r\n\r\n");
+        self.body.put_slice(header.as_bytes());
+    }
+}
+
+/// Escape the quoted names like the browsers do.
+fn escape(s: &str) -> String {
+    s.replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multipart_form_should_encode_parts() {
+        let form = MultipartForm::new()
+            .file("file", "a\"b.mp3", Some("audio/mpeg"), b"data")
+            .text("model", "whisper-1");
+        let boundary = form.boundary.clone();
+        let body = String::from_utf8(form.into_bytes().to_vec()).unwrap();
+        assert_eq!(
+            body,
+            format!(
+                "--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a%22b.mp3\"\r\n\
+                 Content-Type: audio/mpeg\r\n\r\ndata\r\n\
+                 --{b}\r\nContent-Disposition: form-data; name=\"model\"\r\n\r\nwhisper-1\r\n\
+                 --{b}--\r\n",
+                b = boundary
+            )
+        );
+        assert_ne!(MultipartForm::new().boundary, boundary);
+    }
+}
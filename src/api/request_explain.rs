@@ -0,0 +1,155 @@
+use crate::{ChatCompleteModel, ChatCompletionRequest};
+use serde_json::Value;
+use std::fmt;
+
+/// The fields of a chat completion request with the default the server applies when they're not
+/// sent, in the order of the request struct.
+const FIELDS: [(&str, &str); 17] = [
+    ("messages", "required"),
+    ("model", "required"),
+    ("frequency_penalty", "0"),
+    ("max_tokens", "the max of the model"),
+    ("modalities", "[\"text\"]"),
+    ("audio", "no audio output"),
+    ("n", "1"),
+    ("presence_penalty", "0"),
+    ("response_format", "text"),
+    ("seed", "random"),
+    ("stop", "none"),
+    ("stream", "false"),
+    ("stream_options", "none"),
+    ("temperature", "1"),
+    ("top_p", "1"),
+    ("tools", "none"),
+    ("tool_choice", "none, or auto when tools are given"),
+];
+
+/// What a `ChatCompletionRequest` actually sends, from `ChatCompletionRequest::explain`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestExplanation {
+    pub fields: Vec<FieldExplanation>,
+    /// The serialized body, as sent to an OpenAI compatible server.
+    pub body: Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldExplanation {
+    pub name: String,
+    pub source: FieldSource,
+    /// The serialized value, `None` if the field isn't sent.
+    pub value: Option<Value>,
+    /// What the server uses when the field isn't sent.
+    pub server_default: Option<&'static str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldSource {
+    /// Set on the request.
+    Set,
+    /// Filled in by the SDK (e.g. the default model), and sent.
+    SdkDefault,
+    /// Not sent, the server applies its default.
+    ServerDefault,
+}
+
+impl ChatCompletionRequest {
+    /// Report which fields are set, defaulted by the SDK or left to the server, and the body
+    /// that is serialized, e.g. to debug a behavior change after upgrading the crate or
+    /// switching the model. A field explicitly set to its SDK default is reported as a default.
+    pub fn explain(&self) -> RequestExplanation {
+        let body = serde_json::to_value(self).unwrap_or_default();
+        let mut fields: Vec<_> = FIELDS
+            .iter()
+            .map(|(name, server_default)| {
+                let value = body.get(*name).cloned();
+                let source = match (*name, &value) {
+                    ("model", _) if self.model == ChatCompleteModel::default() => {
+                        FieldSource::SdkDefault
+                    }
+                    (_, Some(_)) => FieldSource::Set,
+                    (_, None) => FieldSource::ServerDefault,
+                };
+                FieldExplanation {
+                    name: name.to_string(),
+                    source,
+                    value,
+                    server_default: Some(*server_default).filter(|d| *d != "required"),
+                }
+            })
+            .collect();
+        // fields added to the request after this list
+        if let Value::Object(obj) = &body {
+            for (name, value) in obj {
+                if !FIELDS.iter().any(|(n, _)| n == name) {
+                    fields.push(FieldExplanation {
+                        name: name.clone(),
+                        source: FieldSource::Set,
+                        value: Some(value.clone()),
+                        server_default: None,
+                    });
+                }
+            }
+        }
+        RequestExplanation { fields, body }
+    }
+}
+
+impl RequestExplanation {
+    pub fn field(&self, name: &str) -> Option<&FieldExplanation> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+}
+
+impl fmt::Display for RequestExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.fields.iter().map(|f| f.name.len()).max().unwrap_or(0);
+        for field in &self.fields {
+            let detail = match (&field.value, field.server_default) {
+                // the messages are too long to be useful here
+                (Some(Value::Array(items)), _) if field.name == "messages" => {
+                    format!("{} messages", items.len())
+                }
+                (Some(value), _) => value.to_string(),
+                (None, Some(default)) => format!("(server default: {})", default),
+                (None, None) => String::new(),
+            };
+            let source = match field.source {
+                FieldSource::Set => "set",
+                FieldSource::SdkDefault => "sdk default",
+                FieldSource::ServerDefault => "not sent",
+            };
+            writeln!(f, "{:width$}  {:11}  {}", field.name, source, detail)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChatCompletionMessage, ChatCompletionRequestBuilder};
+
+    #[test]
+    fn explain_should_report_field_sources() -> anyhow::Result<()> {
+        let req = ChatCompletionRequestBuilder::default()
+            .messages(vec![ChatCompletionMessage::new_user("Hi", "")])
+            .temperature(0.2)
+            .build()?;
+        let explanation = req.explain();
+        assert_eq!(
+            explanation.field("model").unwrap().source,
+            FieldSource::SdkDefault
+        );
+        let temperature = explanation.field("temperature").unwrap();
+        assert_eq!(temperature.source, FieldSource::Set);
+        assert!((temperature.value.as_ref().unwrap().as_f64().unwrap() - 0.2).abs() < 1e-6);
+        let top_p = explanation.field("top_p").unwrap();
+        assert_eq!(top_p.source, FieldSource::ServerDefault);
+        assert_eq!(top_p.server_default, Some("1"));
+
+        let text = explanation.to_string();
+        assert!(text.contains("messages           set          1 messages"));
+        assert!(text.contains("top_p              not sent     (server default: 1)"));
+        Ok(())
+    }
+}
@@ -0,0 +1,569 @@
+use crate::{
+    sse::{CancelOnDrop, SseEvent},
+    BuildError, ChatCompleteModel, IntoRequest, UsageTracker,
+};
+use anyhow::{anyhow, Result};
+use derive_builder::Builder;
+use futures::{ready, stream::BoxStream, Stream, StreamExt};
+use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+/// A request of the Responses API (`/responses`), which unifies the chat completions with the
+/// hosted tools and multimodal inputs.
+#[derive(Debug, Clone, Serialize, Builder)]
+#[builder(pattern = "mutable", build_fn(error = "BuildError"))]
+pub struct ResponseRequest {
+    /// ID of the model to use.
+    #[builder(default)]
+    pub(crate) model: ChatCompleteModel,
+    /// A text, or a list of input items (messages, function call outputs, etc.).
+    #[builder(setter(into))]
+    input: ResponseInput,
+    /// The system (or developer) message, which is not carried over by `previous_response_id`.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instructions: Option<String>,
+    /// The ID of the previous response, to continue a conversation without resending it.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous_response_id: Option<String>,
+    /// An upper bound for the number of tokens generated, including the reasoning tokens.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<usize>,
+    /// The tools the model may call.
+    #[builder(default, setter(into))]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<ResponseTool>,
+    /// What sampling temperature to use, between 0 and 2.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    /// Whether to store the response, so it can be retrieved or continued later. Defaults to true.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    store: Option<bool>,
+    /// Up to 16 key-value pairs attached to the response.
+    #[builder(default, setter(into))]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    metadata: HashMap<String, String>,
+    /// A unique identifier representing your end-user.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    /// Set by `LlmSdk::response_stream`.
+    #[builder(default, setter(skip))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) stream: Option<bool>,
+    /// Overrides the timeout of the SDK (`LlmSdkBuilder::timeout`) for this request.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip)]
+    pub(crate) timeout: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum ResponseInput {
+    Text(String),
+    /// Raw input items, e.g. `{"role": "user", "content": "..."}` or
+    /// `{"type": "function_call_output", "call_id": "...", "output": "..."}`.
+    Items(Vec<serde_json::Value>),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseTool {
+    /// A function defined by the caller. Unlike chat completions, the definition is flat.
+    Function {
+        name: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        description: Option<String>,
+        /// The JSON schema of the arguments.
+        parameters: serde_json::Value,
+    },
+    /// The hosted web search, its sources are cited with `ResponseAnnotation::UrlCitation`.
+    WebSearchPreview {
+        /// `low`, `medium` (default) or `high`.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        search_context_size: Option<String>,
+        /// The approximate location of the user, e.g.
+        /// `{"type": "approximate", "country": "GB", "city": "London"}`.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        user_location: Option<serde_json::Value>,
+    },
+    /// The hosted search over vector stores, its sources are cited with
+    /// `ResponseAnnotation::FileCitation`.
+    FileSearch {
+        vector_store_ids: Vec<String>,
+        /// Between 1 and 50.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        max_num_results: Option<usize>,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Response {
+    pub id: String,
+    /// The Unix timestamp (in seconds) of when the response was created.
+    pub created_at: u64,
+    pub model: String,
+    /// `completed`, `failed`, `in_progress` or `incomplete`.
+    pub status: String,
+    #[serde(default)]
+    pub output: Vec<ResponseOutputItem>,
+    #[serde(default)]
+    pub error: Option<ResponseError>,
+    #[serde(default)]
+    pub usage: Option<ResponseUsage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseOutputItem {
+    Message {
+        id: String,
+        role: String,
+        content: Vec<ResponseContent>,
+    },
+    FunctionCall {
+        id: String,
+        call_id: String,
+        name: String,
+        /// The arguments as a JSON string.
+        arguments: String,
+    },
+    /// An item of a type not modeled here yet, e.g. a reasoning summary.
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseContent {
+    OutputText {
+        text: String,
+        /// The citations of the hosted tools within the text.
+        #[serde(default)]
+        annotations: Vec<ResponseAnnotation>,
+    },
+    Refusal {
+        refusal: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseAnnotation {
+    /// A web page cited by the web search, for the text in `start_index..end_index`.
+    UrlCitation {
+        start_index: usize,
+        end_index: usize,
+        url: String,
+        title: String,
+    },
+    /// A file cited by the file search, at the `index` of the text.
+    FileCitation {
+        index: usize,
+        file_id: String,
+        #[serde(default)]
+        filename: Option<String>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ResponseError {
+    pub code: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct ResponseUsage {
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+    pub total_tokens: usize,
+}
+
+/// The events of a streamed response, the ones not modeled here are kept as `Other` with their
+/// type.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type")]
+pub enum ResponseStreamEvent {
+    #[serde(rename = "response.created")]
+    Created { response: Response },
+    #[serde(rename = "response.output_text.delta")]
+    OutputTextDelta {
+        item_id: String,
+        output_index: usize,
+        delta: String,
+    },
+    #[serde(rename = "response.output_text.done")]
+    OutputTextDone {
+        item_id: String,
+        output_index: usize,
+        text: String,
+    },
+    #[serde(rename = "response.function_call_arguments.delta")]
+    FunctionCallArgumentsDelta {
+        item_id: String,
+        output_index: usize,
+        delta: String,
+    },
+    #[serde(rename = "response.output_item.done")]
+    OutputItemDone {
+        output_index: usize,
+        item: ResponseOutputItem,
+    },
+    #[serde(rename = "response.completed")]
+    Completed { response: Response },
+    #[serde(rename = "response.incomplete")]
+    Incomplete { response: Response },
+    #[serde(rename = "response.failed")]
+    Failed { response: Response },
+    #[serde(rename = "error")]
+    Error {
+        code: Option<String>,
+        message: String,
+    },
+    #[serde(skip)]
+    Other(String),
+}
+
+/// Dropping the stream before the response is done cancels it, see `LlmSdk::cancel_response`.
+pub struct ResponseStream {
+    inner: BoxStream<'static, Result<ResponseStreamEvent>>,
+    cancel: Option<CancelOnDrop>,
+}
+
+impl ResponseRequest {
+    pub fn new(model: ChatCompleteModel, input: impl Into<ResponseInput>) -> Self {
+        ResponseRequestBuilder::default()
+            .model(model)
+            .input(input)
+            .build()
+            .unwrap()
+    }
+}
+
+impl From<String> for ResponseInput {
+    fn from(text: String) -> Self {
+        Self::Text(text)
+    }
+}
+
+impl From<&str> for ResponseInput {
+    fn from(text: &str) -> Self {
+        Self::Text(text.to_string())
+    }
+}
+
+impl From<Vec<serde_json::Value>> for ResponseInput {
+    fn from(items: Vec<serde_json::Value>) -> Self {
+        Self::Items(items)
+    }
+}
+
+impl Response {
+    /// The text of all the output messages, concatenated.
+    pub fn output_text(&self) -> String {
+        self.output
+            .iter()
+            .filter_map(|item| match item {
+                ResponseOutputItem::Message { content, .. } => Some(content),
+                _ => None,
+            })
+            .flatten()
+            .filter_map(|content| match content {
+                ResponseContent::OutputText { text, .. } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl ResponseStreamEvent {
+    fn parse(data: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(data)
+            .map_err(|e| anyhow!("failed to parse event {}: {}", data, e))?;
+        let kind = value["type"].as_str().unwrap_or_default().to_string();
+        match serde_json::from_value(value) {
+            Ok(event) => Ok(event),
+            // a known type that fails to parse is an error, an unknown one is skipped over
+            Err(e) if KNOWN_EVENTS.contains(&kind.as_str()) => {
+                Err(anyhow!("failed to parse event {}: {}", data, e))
+            }
+            Err(_) => Ok(Self::Other(kind)),
+        }
+    }
+}
+
+const KNOWN_EVENTS: [&str; 9] = [
+    "response.created",
+    "response.output_text.delta",
+    "response.output_text.done",
+    "response.function_call_arguments.delta",
+    "response.output_item.done",
+    "response.completed",
+    "response.incomplete",
+    "response.failed",
+    "error",
+];
+
+impl ResponseStream {
+    pub(crate) fn new(events: impl Stream<Item = Result<SseEvent>> + Send + 'static) -> Self {
+        let inner = events
+            .map(|event| ResponseStreamEvent::parse(&event?.data))
+            .boxed();
+        Self {
+            inner,
+            cancel: None,
+        }
+    }
+
+    pub(crate) fn with_cancel(mut self, cancel: CancelOnDrop) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Record the usage of the final response, which ends the stream.
+    pub(crate) fn track_usage(mut self, tracker: Arc<UsageTracker>) -> Self {
+        self.inner = self
+            .inner
+            .inspect(move |event| {
+                if let Ok(
+                    ResponseStreamEvent::Completed { response }
+                    | ResponseStreamEvent::Incomplete { response }
+                    | ResponseStreamEvent::Failed { response },
+                ) = event
+                {
+                    if let Some(usage) = &response.usage {
+                        tracker.record(
+                            &response.model,
+                            "responses",
+                            usage.input_tokens,
+                            usage.output_tokens,
+                        );
+                    }
+                }
+            })
+            .boxed();
+        self
+    }
+}
+
+impl Stream for ResponseStream {
+    type Item = Result<ResponseStreamEvent>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let item = ready!(self.inner.poll_next_unpin(cx));
+        if let Some(cancel) = self.cancel.as_mut() {
+            match &item {
+                Some(Ok(ResponseStreamEvent::Created { response })) => {
+                    cancel.arm(response.id.clone())
+                }
+                Some(Ok(
+                    ResponseStreamEvent::Completed { .. }
+                    | ResponseStreamEvent::Incomplete { .. }
+                    | ResponseStreamEvent::Failed { .. }
+                    | ResponseStreamEvent::Error { .. },
+                ))
+                | None => cancel.disarm(),
+                _ => {}
+            }
+        }
+        Poll::Ready(item)
+    }
+}
+
+impl IntoRequest for ResponseRequest {
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+        let url = format!("{}/responses", base_url);
+        client.post(url).json(&self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SDK;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn response_request_should_serialize() -> Result<()> {
+        let req = ResponseRequestBuilder::default()
+            .model(ChatCompleteModel::Gpt4o)
+            .input("Hi")
+            .instructions("Be brief.")
+            .tools(vec![ResponseTool::Function {
+                name: "get_weather".into(),
+                description: None,
+                parameters: json!({"type": "object", "properties": {}}),
+            }])
+            .build()?;
+        assert_eq!(
+            serde_json::to_value(req)?,
+            json!({
+              "model": "gpt-4o",
+              "input": "Hi",
+              "instructions": "Be brief.",
+              "tools": [{
+                "type": "function",
+                "name": "get_weather",
+                "parameters": {"type": "object", "properties": {}}
+              }]
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn response_tool_should_serialize_hosted_tools() -> Result<()> {
+        let tools = vec![
+            ResponseTool::WebSearchPreview {
+                search_context_size: Some("low".into()),
+                user_location: None,
+            },
+            ResponseTool::FileSearch {
+                vector_store_ids: vec!["vs_1".into()],
+                max_num_results: None,
+            },
+        ];
+        assert_eq!(
+            serde_json::to_value(tools)?,
+            json!([
+              { "type": "web_search_preview", "search_context_size": "low" },
+              { "type": "file_search", "vector_store_ids": ["vs_1"] }
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn response_annotations_should_deserialize() -> Result<()> {
+        let content: ResponseContent = serde_json::from_value(json!({
+          "type": "output_text",
+          "text": "Rust 1.75 is out.",
+          "annotations": [
+            { "type": "url_citation", "start_index": 0, "end_index": 17, "url": "https://blog.rust-lang.org", "title": "Rust Blog" },
+            { "type": "file_citation", "index": 17, "file_id": "file-1", "filename": "notes.md" },
+            { "type": "container_file_citation", "file_id": "cfile-1" }
+          ]
+        }))?;
+        let ResponseContent::OutputText { annotations, .. } = content else {
+            panic!("expected an output text");
+        };
+        assert_eq!(
+            annotations[0],
+            ResponseAnnotation::UrlCitation {
+                start_index: 0,
+                end_index: 17,
+                url: "https://blog.rust-lang.org".into(),
+                title: "Rust Blog".into(),
+            }
+        );
+        assert!(
+            matches!(&annotations[1], ResponseAnnotation::FileCitation { file_id, .. } if file_id == "file-1")
+        );
+        assert_eq!(annotations[2], ResponseAnnotation::Other);
+        Ok(())
+    }
+
+    #[test]
+    fn response_should_deserialize() -> Result<()> {
+        let res: Response = serde_json::from_value(json!({
+          "id": "resp_123",
+          "object": "response",
+          "created_at": 1741476542,
+          "model": "gpt-4o-2024-08-06",
+          "status": "completed",
+          "output": [
+            { "type": "reasoning", "id": "rs_1", "summary": [] },
+            {
+              "type": "message",
+              "id": "msg_1",
+              "role": "assistant",
+              "status": "completed",
+              "content": [{ "type": "output_text", "text": "Hello!", "annotations": [] }]
+            }
+          ],
+          "usage": { "input_tokens": 10, "output_tokens": 2, "total_tokens": 12 }
+        }))?;
+        assert_eq!(res.output[0], ResponseOutputItem::Other);
+        assert_eq!(res.output_text(), "Hello!");
+        assert_eq!(res.usage.unwrap().total_tokens, 12);
+        Ok(())
+    }
+
+    #[test]
+    fn response_stream_should_parse_events() -> Result<()> {
+        let events = [
+            r#"{"type":"response.output_text.delta","item_id":"msg_1","output_index":0,"content_index":0,"delta":"Hel"}"#,
+            r#"{"type":"response.content_part.added","item_id":"msg_1","output_index":0}"#,
+        ]
+        .into_iter()
+        .map(|data| {
+            Ok(SseEvent {
+                event: None,
+                data: data.to_string(),
+            })
+        });
+        let events = futures::executor::block_on(
+            ResponseStream::new(futures::stream::iter(events)).collect::<Vec<_>>(),
+        )
+        .into_iter()
+        .collect::<Result<Vec<_>>>()?;
+        assert!(
+            matches!(&events[0], ResponseStreamEvent::OutputTextDelta { delta, .. } if delta == "Hel")
+        );
+        assert!(
+            matches!(&events[1], ResponseStreamEvent::Other(kind) if kind == "response.content_part.added")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn response_stream_should_cancel_running_response_on_drop() {
+        let cancelled = Arc::new(Mutex::new(None));
+        let events = [
+            r#"{"type":"response.created","response":{"id":"resp_1","created_at":0,"model":"gpt-4o","status":"in_progress","output":[]}}"#,
+            r#"{"type":"response.output_text.delta","item_id":"msg_1","output_index":0,"delta":"Hel"}"#,
+        ]
+        .into_iter()
+        .map(|data| {
+            Ok(SseEvent {
+                event: None,
+                data: data.to_string(),
+            })
+        });
+        let c = cancelled.clone();
+        let mut stream = ResponseStream::new(futures::stream::iter(events))
+            .with_cancel(CancelOnDrop::new(move |id| *c.lock().unwrap() = Some(id)));
+        futures::executor::block_on(stream.next()).unwrap().unwrap();
+        drop(stream);
+        assert_eq!(cancelled.lock().unwrap().as_deref(), Some("resp_1"));
+    }
+
+    #[tokio::test]
+    async fn response_should_work() -> Result<()> {
+        let req = ResponseRequest::new(ChatCompleteModel::Gpt4o, "Say hello in one word.");
+        let res = SDK.response(req).await?;
+        assert_eq!(res.status, "completed");
+        assert!(!res.output_text().is_empty());
+        Ok(())
+    }
+}
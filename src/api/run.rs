@@ -0,0 +1,449 @@
+use crate::{AssistantTool, BuildError, ChatCompleteModel, ToolCall};
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::json;
+use std::{collections::HashMap, time::Duration};
+
+#[derive(Debug, Clone, Serialize, Builder)]
+#[builder(pattern = "mutable", build_fn(error = "BuildError"))]
+pub struct CreateRunRequest {
+    /// The ID of the assistant to use to execute this run.
+    #[builder(setter(into))]
+    assistant_id: String,
+    /// The ID of the Model to be used to execute this run. If provided, this overrides the model associated with the assistant.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) model: Option<ChatCompleteModel>,
+    /// Overrides the instructions of the assistant. This is useful for modifying the behavior on a per-run basis.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instructions: Option<String>,
+    /// Appends additional instructions at the end of the instructions for the run.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    additional_instructions: Option<String>,
+    /// Override the tools the assistant can use for this run.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<AssistantTool>>,
+    /// Set of 16 key-value pairs that can be attached to an object.
+    #[builder(default, setter(into))]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    metadata: HashMap<String, String>,
+    /// What sampling temperature to use, between 0 and 2.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    /// An alternative to sampling with temperature, called nucleus sampling.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    /// The maximum number of prompt tokens that may be used over the course of the run.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_prompt_tokens: Option<usize>,
+    /// The maximum number of completion tokens that may be used over the course of the run.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_completion_tokens: Option<usize>,
+    /// Controls for how a thread will be truncated prior to the run. Use this to control the initial context window of the run.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    truncation_strategy: Option<TruncationStrategy>,
+    /// Controls which (if any) tool is called by the model.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<RunToolChoice>,
+    /// Whether to enable parallel function calling during tool use.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parallel_tool_calls: Option<bool>,
+    /// Specifies the format that the model must output.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<RunResponseFormat>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TruncationStrategy {
+    /// The truncation strategy to use for the thread.
+    pub r#type: TruncationType,
+    /// The number of most recent messages from the thread when constructing the context for the run.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub last_messages: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TruncationType {
+    /// Messages in the middle of the thread will be dropped to fit the context length of the model.
+    #[default]
+    Auto,
+    /// The thread will be truncated to the n most recent messages in the thread.
+    LastMessages,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunToolChoice {
+    /// The model will not call any tools and instead generates a message.
+    None,
+    /// The model can pick between generating a message or calling one or more tools.
+    Auto,
+    /// The model must call one or more tools before responding to the user.
+    Required,
+    /// Force the model to use the code_interpreter tool.
+    CodeInterpreter,
+    /// Force the model to use the file_search tool.
+    FileSearch,
+    /// Force the model to call the given function.
+    Function(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunResponseFormat {
+    /// The default, the format is chosen by the model.
+    Auto,
+    Text,
+    /// JSON mode, which guarantees the message the model generates is valid JSON.
+    JsonObject,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SubmitToolOutputsRequest {
+    /// A list of tools for which the outputs are being submitted.
+    tool_outputs: Vec<ToolOutput>,
+    /// If true, returns a stream of events that happen during the run as server-sent events.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) stream: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolOutput {
+    /// The ID of the tool call in the required_action object within the run object the output is being submitted for.
+    pub tool_call_id: String,
+    /// The output of the tool call to be submitted to continue the run.
+    pub output: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Run {
+    /// The identifier, which can be referenced in API endpoints.
+    pub id: String,
+    /// The object type, which is always thread.run.
+    pub object: String,
+    /// The Unix timestamp (in seconds) for when the run was created.
+    pub created_at: u64,
+    /// The ID of the thread that was executed on as a part of this run.
+    pub thread_id: String,
+    /// The ID of the assistant used for execution of this run.
+    pub assistant_id: String,
+    /// The status of the run.
+    pub status: RunStatus,
+    /// Details on the action required to continue the run. Will be null if no action is required.
+    #[serde(default)]
+    pub required_action: Option<RequiredAction>,
+    /// The last error associated with this run. Will be null if there are no errors.
+    #[serde(default)]
+    pub last_error: Option<RunError>,
+    /// The Unix timestamp (in seconds) for when the run will expire.
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    /// The Unix timestamp (in seconds) for when the run was started.
+    #[serde(default)]
+    pub started_at: Option<u64>,
+    /// The Unix timestamp (in seconds) for when the run was cancelled.
+    #[serde(default)]
+    pub cancelled_at: Option<u64>,
+    /// The Unix timestamp (in seconds) for when the run failed.
+    #[serde(default)]
+    pub failed_at: Option<u64>,
+    /// The Unix timestamp (in seconds) for when the run was completed.
+    #[serde(default)]
+    pub completed_at: Option<u64>,
+    /// The model that the assistant used for this run.
+    pub model: String,
+    /// The instructions that the assistant used for this run.
+    #[serde(default)]
+    pub instructions: Option<String>,
+    /// The list of tools that the assistant used for this run.
+    #[serde(default)]
+    pub tools: Vec<AssistantTool>,
+    /// Set of 16 key-value pairs attached to the run.
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    /// Usage statistics related to the run. This value will be null if the run is not in a terminal state.
+    #[serde(default)]
+    pub usage: Option<RunUsage>,
+    /// Controls for how a thread will be truncated prior to the run.
+    #[serde(default)]
+    pub truncation_strategy: Option<TruncationStrategy>,
+    /// Whether to enable parallel function calling during tool use.
+    #[serde(default)]
+    pub parallel_tool_calls: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Queued,
+    InProgress,
+    RequiresAction,
+    Cancelling,
+    Cancelled,
+    Failed,
+    Completed,
+    Incomplete,
+    Expired,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RequiredAction {
+    /// For now, this is always submit_tool_outputs.
+    pub r#type: String,
+    /// Details on the tool outputs needed for this run to continue.
+    pub submit_tool_outputs: RequiredToolOutputs,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RequiredToolOutputs {
+    /// A list of the relevant tool calls.
+    pub tool_calls: Vec<ToolCall>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RunError {
+    /// One of server_error, rate_limit_exceeded, or invalid_prompt.
+    pub code: String,
+    /// A human-readable description of the error.
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RunUsage {
+    /// Number of completion tokens used over the course of the run.
+    pub completion_tokens: usize,
+    /// Number of prompt tokens used over the course of the run.
+    pub prompt_tokens: usize,
+    /// Total number of tokens used (prompt + completion).
+    pub total_tokens: usize,
+}
+
+/// How `LlmSdk::wait_for_run` polls a run.
+#[derive(Debug, Clone, Builder)]
+#[builder(pattern = "mutable", build_fn(error = "BuildError"))]
+pub struct RunPollOptions {
+    /// Delay before the first poll.
+    #[builder(default = "Duration::from_millis(500)")]
+    pub(crate) initial_interval: Duration,
+    /// Upper bound of the delay between two polls.
+    #[builder(default = "Duration::from_secs(5)")]
+    pub(crate) max_interval: Duration,
+    /// The delay is multiplied by this factor after every poll.
+    #[builder(default = "1.5")]
+    pub(crate) multiplier: f64,
+    /// Give up after this long. The run itself is left untouched.
+    #[builder(default, setter(strip_option))]
+    pub(crate) timeout: Option<Duration>,
+}
+
+impl RunStatus {
+    /// Whether the run has finished and will not change any more.
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            Self::Cancelled | Self::Failed | Self::Completed | Self::Incomplete | Self::Expired
+        )
+    }
+}
+
+impl CreateRunRequest {
+    pub fn new(assistant_id: impl Into<String>) -> Self {
+        CreateRunRequestBuilder::default()
+            .assistant_id(assistant_id)
+            .build()
+            .unwrap()
+    }
+}
+
+impl TruncationStrategy {
+    pub fn auto() -> Self {
+        Self {
+            r#type: TruncationType::Auto,
+            last_messages: None,
+        }
+    }
+
+    pub fn last_messages(n: usize) -> Self {
+        Self {
+            r#type: TruncationType::LastMessages,
+            last_messages: Some(n),
+        }
+    }
+}
+
+impl RunToolChoice {
+    pub fn function(name: impl Into<String>) -> Self {
+        Self::Function(name.into())
+    }
+}
+
+impl Serialize for RunToolChoice {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::None => serializer.serialize_str("none"),
+            Self::Auto => serializer.serialize_str("auto"),
+            Self::Required => serializer.serialize_str("required"),
+            Self::CodeInterpreter => json!({ "type": "code_interpreter" }).serialize(serializer),
+            Self::FileSearch => json!({ "type": "file_search" }).serialize(serializer),
+            Self::Function(name) => {
+                json!({ "type": "function", "function": { "name": name } }).serialize(serializer)
+            }
+        }
+    }
+}
+
+impl Serialize for RunResponseFormat {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Auto => serializer.serialize_str("auto"),
+            Self::Text => json!({ "type": "text" }).serialize(serializer),
+            Self::JsonObject => json!({ "type": "json_object" }).serialize(serializer),
+        }
+    }
+}
+
+impl SubmitToolOutputsRequest {
+    pub fn new(tool_outputs: impl Into<Vec<ToolOutput>>) -> Self {
+        Self {
+            tool_outputs: tool_outputs.into(),
+            stream: None,
+        }
+    }
+}
+
+impl ToolOutput {
+    pub fn new(tool_call_id: impl Into<String>, output: impl Into<String>) -> Self {
+        Self {
+            tool_call_id: tool_call_id.into(),
+            output: output.into(),
+        }
+    }
+}
+
+impl Default for RunPollOptions {
+    fn default() -> Self {
+        RunPollOptionsBuilder::default().build().unwrap()
+    }
+}
+
+impl RunPollOptions {
+    pub(crate) fn next_interval(&self, current: Duration) -> Duration {
+        current.mul_f64(self.multiplier).min(self.max_interval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CreateAssistantRequest, CreateMessageRequest, CreateThreadRequest, SDK};
+    use anyhow::Result;
+
+    #[test]
+    fn run_should_deserialize() -> Result<()> {
+        let run: Run = serde_json::from_value(json!({
+          "id": "run_abc123",
+          "object": "thread.run",
+          "created_at": 1699075072,
+          "assistant_id": "asst_abc123",
+          "thread_id": "thread_abc123",
+          "status": "requires_action",
+          "required_action": {
+            "type": "submit_tool_outputs",
+            "submit_tool_outputs": {
+              "tool_calls": [{
+                "id": "call_abc123",
+                "type": "function",
+                "function": { "name": "get_weather", "arguments": "{\"city\":\"Boston\"}" }
+              }]
+            }
+          },
+          "started_at": 1699075072,
+          "expires_at": 1699075672,
+          "cancelled_at": null,
+          "failed_at": null,
+          "completed_at": null,
+          "last_error": null,
+          "model": "gpt-4-turbo",
+          "instructions": null,
+          "tools": [],
+          "metadata": {},
+          "usage": null
+        }))?;
+        assert_eq!(run.status, RunStatus::RequiresAction);
+        assert!(!run.status.is_terminal());
+        let action = run.required_action.unwrap();
+        assert_eq!(action.submit_tool_outputs.tool_calls[0].id, "call_abc123");
+        Ok(())
+    }
+
+    #[test]
+    fn create_run_request_should_serialize_controls() -> Result<()> {
+        let req = CreateRunRequestBuilder::default()
+            .assistant_id("asst_abc123")
+            .truncation_strategy(TruncationStrategy::last_messages(10))
+            .tool_choice(RunToolChoice::function("get_weather"))
+            .parallel_tool_calls(false)
+            .response_format(RunResponseFormat::JsonObject)
+            .build()?;
+        assert_eq!(
+            serde_json::to_value(req)?,
+            json!({
+              "assistant_id": "asst_abc123",
+              "truncation_strategy": { "type": "last_messages", "last_messages": 10 },
+              "tool_choice": { "type": "function", "function": { "name": "get_weather" } },
+              "parallel_tool_calls": false,
+              "response_format": { "type": "json_object" },
+            })
+        );
+        assert_eq!(serde_json::to_value(RunToolChoice::Required)?, "required");
+        Ok(())
+    }
+
+    #[test]
+    fn run_poll_options_should_back_off() {
+        let opts = RunPollOptionsBuilder::default()
+            .initial_interval(Duration::from_secs(1))
+            .max_interval(Duration::from_secs(3))
+            .multiplier(2.0)
+            .build()
+            .unwrap();
+        let next = opts.next_interval(opts.initial_interval);
+        assert_eq!(next, Duration::from_secs(2));
+        assert_eq!(opts.next_interval(next), Duration::from_secs(3));
+    }
+
+    #[tokio::test]
+    async fn run_should_complete() -> Result<()> {
+        let req = CreateAssistantRequest::new(
+            ChatCompleteModel::Gpt3Turbo,
+            "You are a personal math tutor. Answer with the number only.",
+        );
+        let assistant = SDK.create_assistant(req).await?;
+        let thread = SDK
+            .create_thread(CreateThreadRequest::new(vec![
+                CreateMessageRequest::new_user("What is 1 + 1?"),
+            ]))
+            .await?;
+        let run = SDK
+            .create_run(&thread.id, CreateRunRequest::new(&assistant.id))
+            .await?;
+        let run = SDK
+            .wait_for_run(&thread.id, &run.id, RunPollOptions::default())
+            .await?;
+        assert_eq!(run.status, RunStatus::Completed);
+
+        SDK.delete_thread(&thread.id).await?;
+        SDK.delete_assistant(&assistant.id).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,293 @@
+use crate::RunError;
+use serde::Deserialize;
+
+/// The `include[]` value which makes the API return the content of the file_search results.
+pub(crate) const FILE_SEARCH_RESULT_CONTENT: &str =
+    "step_details.tool_calls[*].file_search.results[*].content";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RunStep {
+    /// The identifier of the run step, which can be referenced in API endpoints.
+    pub id: String,
+    /// The object type, which is always thread.run.step.
+    pub object: String,
+    /// The Unix timestamp (in seconds) for when the run step was created.
+    pub created_at: u64,
+    /// The ID of the assistant associated with the run step.
+    pub assistant_id: String,
+    /// The ID of the thread that was run.
+    pub thread_id: String,
+    /// The ID of the run that this run step is a part of.
+    pub run_id: String,
+    /// The type of run step, which can be either message_creation or tool_calls.
+    pub r#type: RunStepType,
+    /// The status of the run step.
+    pub status: RunStepStatus,
+    /// The details of the run step.
+    pub step_details: RunStepDetails,
+    /// The last error associated with this run step. Will be null if there are no errors.
+    #[serde(default)]
+    pub last_error: Option<RunError>,
+    /// The Unix timestamp (in seconds) for when the run step expired.
+    #[serde(default)]
+    pub expired_at: Option<u64>,
+    /// The Unix timestamp (in seconds) for when the run step was cancelled.
+    #[serde(default)]
+    pub cancelled_at: Option<u64>,
+    /// The Unix timestamp (in seconds) for when the run step failed.
+    #[serde(default)]
+    pub failed_at: Option<u64>,
+    /// The Unix timestamp (in seconds) for when the run step completed.
+    #[serde(default)]
+    pub completed_at: Option<u64>,
+    /// Usage statistics related to the run step. This value will be null while the run step's status is in_progress.
+    #[serde(default)]
+    pub usage: Option<RunStepUsage>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStepType {
+    MessageCreation,
+    ToolCalls,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStepStatus {
+    InProgress,
+    Cancelled,
+    Failed,
+    Completed,
+    Expired,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RunStepUsage {
+    /// Number of completion tokens used over the course of the run step.
+    pub completion_tokens: usize,
+    /// Number of prompt tokens used over the course of the run step.
+    pub prompt_tokens: usize,
+    /// Total number of tokens used (prompt + completion).
+    pub total_tokens: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum RunStepDetails {
+    /// The assistant created a message.
+    MessageCreation {
+        /// The message created by this step.
+        message_creation: MessageCreation,
+    },
+    /// The assistant called one or more tools.
+    ToolCalls {
+        /// The tool calls the run step was involved in.
+        tool_calls: Vec<RunStepToolCall>,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum RunStepToolCall {
+    CodeInterpreter {
+        /// The ID of the tool call.
+        id: String,
+        /// The code interpreter tool call definition.
+        code_interpreter: CodeInterpreterCall,
+    },
+    FileSearch {
+        /// The ID of the tool call object.
+        id: String,
+        /// The results of the file search.
+        file_search: FileSearchCall,
+    },
+    Function {
+        /// The ID of the tool call object.
+        id: String,
+        /// The definition of the function that was called.
+        function: RunStepFunctionCall,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MessageCreation {
+    /// The ID of the message that was created by this run step.
+    pub message_id: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CodeInterpreterCall {
+    /// The input to the Code Interpreter tool call.
+    pub input: String,
+    /// The outputs from the Code Interpreter tool call.
+    #[serde(default)]
+    pub outputs: Vec<CodeInterpreterOutput>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum CodeInterpreterOutput {
+    /// Text output from the Code Interpreter tool call.
+    Logs { logs: String },
+    /// An image generated by the Code Interpreter tool call.
+    Image { image: CodeInterpreterImage },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CodeInterpreterImage {
+    /// The file ID of the image.
+    pub file_id: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RunStepFunctionCall {
+    /// The name of the function.
+    pub name: String,
+    /// The arguments passed to the function.
+    pub arguments: String,
+    /// The output of the function. This will be null if the outputs have not been submitted yet.
+    #[serde(default)]
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FileSearchCall {
+    /// The chunks retrieved by the file search, empty unless requested with `include[]`.
+    #[serde(default)]
+    pub results: Vec<FileSearchResult>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FileSearchResult {
+    /// The ID of the file that result was found in.
+    pub file_id: String,
+    /// The name of the file that result was found in.
+    pub file_name: String,
+    /// The score of the result. All values must be a floating point number between 0 and 1.
+    pub score: f32,
+    /// The content of the result that was found.
+    #[serde(default)]
+    pub content: Vec<FileSearchResultContent>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FileSearchResultContent {
+    /// The type of the content, which is always text.
+    pub r#type: String,
+    /// The text content of the file.
+    pub text: String,
+}
+
+impl RunStep {
+    /// The tool calls of this step, empty for message creation steps.
+    pub fn tool_calls(&self) -> &[RunStepToolCall] {
+        match &self.step_details {
+            RunStepDetails::ToolCalls { tool_calls } => tool_calls,
+            _ => &[],
+        }
+    }
+
+    /// The file_search results retrieved in this step, empty for other kinds of steps.
+    pub fn file_search_results(&self) -> impl Iterator<Item = &FileSearchResult> {
+        self.tool_calls().iter().flat_map(|call| match call {
+            RunStepToolCall::FileSearch { file_search, .. } => file_search.results.iter(),
+            _ => [].iter(),
+        })
+    }
+}
+
+impl FileSearchResult {
+    /// The text of the retrieved chunk.
+    pub fn text(&self) -> String {
+        self.content
+            .iter()
+            .map(|c| c.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use serde_json::json;
+
+    #[test]
+    fn run_step_should_expose_file_search_results() -> Result<()> {
+        let step: RunStep = serde_json::from_value(json!({
+          "id": "step_abc123",
+          "object": "thread.run.step",
+          "created_at": 1699063291,
+          "run_id": "run_abc123",
+          "assistant_id": "asst_abc123",
+          "thread_id": "thread_abc123",
+          "type": "tool_calls",
+          "status": "completed",
+          "step_details": {
+            "type": "tool_calls",
+            "tool_calls": [{
+              "id": "call_abc123",
+              "type": "file_search",
+              "file_search": {
+                "ranking_options": { "ranker": "default_2024_08_21", "score_threshold": 0.0 },
+                "results": [{
+                  "file_id": "file-abc123",
+                  "file_name": "manual.pdf",
+                  "score": 0.82,
+                  "content": [{ "type": "text", "text": "Press the red button." }]
+                }]
+              }
+            }, {
+              "id": "call_abc456",
+              "type": "code_interpreter",
+              "code_interpreter": { "input": "1 + 1", "outputs": [] }
+            }]
+          }
+        }))?;
+        assert_eq!(step.r#type, RunStepType::ToolCalls);
+        assert!(matches!(
+            &step.tool_calls()[1],
+            RunStepToolCall::CodeInterpreter { code_interpreter, .. } if code_interpreter.input == "1 + 1"
+        ));
+        let results: Vec<_> = step.file_search_results().collect();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].file_name, "manual.pdf");
+        assert_eq!(results[0].text(), "Press the red button.");
+        Ok(())
+    }
+
+    #[test]
+    fn run_step_should_deserialize_message_creation() -> Result<()> {
+        let step: RunStep = serde_json::from_value(json!({
+          "id": "step_abc123",
+          "object": "thread.run.step",
+          "created_at": 1699063291,
+          "run_id": "run_abc123",
+          "assistant_id": "asst_abc123",
+          "thread_id": "thread_abc123",
+          "type": "message_creation",
+          "status": "completed",
+          "cancelled_at": null,
+          "completed_at": 1699063291,
+          "expired_at": null,
+          "failed_at": null,
+          "last_error": null,
+          "step_details": {
+            "type": "message_creation",
+            "message_creation": { "message_id": "msg_abc123" }
+          },
+          "usage": { "prompt_tokens": 123, "completion_tokens": 456, "total_tokens": 579 }
+        }))?;
+        assert_eq!(step.status, RunStepStatus::Completed);
+        assert!(step.tool_calls().is_empty());
+        assert!(matches!(
+            step.step_details,
+            RunStepDetails::MessageCreation { message_creation } if message_creation.message_id == "msg_abc123"
+        ));
+        Ok(())
+    }
+}
@@ -1,48 +1,73 @@
-use crate::IntoRequest;
+use crate::{BuildError, IntoRequest};
 use derive_builder::Builder;
 use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
 use serde::Serialize;
+use std::{mem, time::Duration};
+
+/// The maximum length of the input of a speech request, in characters.
+pub(crate) const SPEECH_MAX_INPUT: usize = 4096;
 
 #[derive(Debug, Clone, Serialize, Builder)]
-#[builder(pattern = "mutable")]
+#[builder(
+    pattern = "mutable",
+    build_fn(validate = "Self::validate", error = "BuildError")
+)]
 pub struct SpeechRequest {
-    /// One of the available TTS models: tts-1 or tts-1-hd
+    /// One of the available TTS models: tts-1, tts-1-hd or gpt-4o-mini-tts
     #[builder(default)]
-    model: SpeechModel,
+    pub(crate) model: SpeechModel,
     /// The text to generate audio for. The maximum length is 4096 characters.
     #[builder(setter(into))]
-    input: String,
-    /// The voice to use when generating the audio. Supported voices are alloy, echo, fable, onyx, nova, and shimmer. Previews of the voices are available in the Text to speech guide.
+    pub(crate) input: String,
+    /// The voice to use when generating the audio. Supported voices are alloy, ash, ballad, coral, echo, fable, onyx, nova, sage, shimmer and verse. Ballad and verse are not supported by tts-1 and tts-1-hd. Previews of the voices are available in the Text to speech guide.
     #[builder(default)]
     voice: SpeechVoice,
-    /// The format to audio in. Supported formats are mp3, opus, aac, and flac.
+    /// The format to audio in. Supported formats are mp3, opus, aac, flac, wav, and pcm.
     #[builder(default)]
-    response_format: SpeechResponseFormat,
+    pub(crate) response_format: SpeechResponseFormat,
     /// The speed of the generated audio. Select a value from 0.25 to 4.0. 1.0 is the default.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
     speed: Option<f32>,
+    /// Control the voice with additional instructions, e.g. "Speak like a calm narrator". Does not work with tts-1 or tts-1-hd.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instructions: Option<String>,
+    /// Overrides the timeout of the SDK (`LlmSdkBuilder::timeout`) for this request.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip)]
+    pub(crate) timeout: Option<Duration>,
 }
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
 pub enum SpeechModel {
     #[default]
     #[serde(rename = "tts-1")]
     Tts1,
     #[serde(rename = "tts-1-hd")]
     Tts1Hd,
+    #[serde(rename = "gpt-4o-mini-tts")]
+    Gpt4oMiniTts,
+    /// Any other model by its name, e.g. a newer model or one served by a proxy.
+    #[serde(untagged)]
+    Other(String),
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum SpeechVoice {
     Alloy,
+    Ash,
+    Ballad,
+    Coral,
     Echo,
     Fable,
     Onyx,
     #[default]
     Nova,
+    Sage,
     Shimmer,
+    Verse,
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
@@ -53,15 +78,61 @@ pub enum SpeechResponseFormat {
     Opus,
     Aac,
     Flac,
+    /// Uncompressed, with a wav header.
+    Wav,
+    /// Raw 24kHz 16-bit signed little-endian samples, without a header.
+    Pcm,
 }
 
 impl IntoRequest for SpeechRequest {
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
     fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
         let url = format!("{}/audio/speech", base_url);
         client.post(url).json(&self)
     }
 }
 
+impl SpeechRequestBuilder {
+    fn validate(&self) -> Result<(), String> {
+        let model = self.model.clone().unwrap_or_default();
+        let voice = self.voice.unwrap_or_default();
+        if !model.supports_voice(voice) {
+            return Err(format!("voice {:?} is not supported by {:?}", voice, model));
+        }
+        if self.instructions.as_ref().is_some_and(|i| i.is_some())
+            && matches!(model, SpeechModel::Tts1 | SpeechModel::Tts1Hd)
+        {
+            return Err(format!("instructions is not supported by {:?}", model));
+        }
+        Ok(())
+    }
+}
+
+impl SpeechModel {
+    /// Whether the model supports the voice, unknown models are assumed to support all of them.
+    pub fn supports_voice(&self, voice: SpeechVoice) -> bool {
+        match self {
+            SpeechModel::Tts1 | SpeechModel::Tts1Hd => {
+                !matches!(voice, SpeechVoice::Ballad | SpeechVoice::Verse)
+            }
+            SpeechModel::Gpt4oMiniTts | SpeechModel::Other(_) => true,
+        }
+    }
+
+    /// The price in USD per 1M characters of input, 0 for gpt-4o-mini-tts (priced by token) and
+    /// `Other`.
+    pub fn price_per_million_chars(&self) -> f64 {
+        match self {
+            SpeechModel::Tts1 => 15.0,
+            SpeechModel::Tts1Hd => 30.0,
+            SpeechModel::Gpt4oMiniTts | SpeechModel::Other(_) => 0.0,
+        }
+    }
+}
+
 impl SpeechRequest {
     pub fn new(input: impl Into<String>) -> Self {
         SpeechRequestBuilder::default()
@@ -71,12 +142,140 @@ impl SpeechRequest {
     }
 }
 
+/// Split the input into chunks of at most `max_chars` characters at the sentence boundaries, or
+/// at whitespaces (and anywhere as the last resort) for the sentences longer than a chunk.
+pub(crate) fn split_input(text: &str, max_chars: usize) -> Vec<String> {
+    let mut chunker = Chunker {
+        max_chars: max_chars.max(1),
+        chunks: Vec::new(),
+        current: String::new(),
+        len: 0,
+    };
+    for sentence in sentences(text) {
+        chunker.push(sentence);
+    }
+    chunker.flush();
+    chunker.chunks
+}
+
+fn sentences(text: &str) -> Vec<&str> {
+    let mut ret = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let boundary = match c {
+            '.' | '!' | '?' | '\n' => !matches!(chars.peek(), Some((_, c)) if !c.is_whitespace()),
+            '。' | '！' | '？' => true,
+            _ => false,
+        };
+        if boundary {
+            let end = i + c.len_utf8();
+            ret.push(&text[start..end]);
+            start = end;
+        }
+    }
+    if start < text.len() {
+        ret.push(&text[start..]);
+    }
+    ret
+}
+
+struct Chunker {
+    max_chars: usize,
+    chunks: Vec<String>,
+    current: String,
+    len: usize,
+}
+
+impl Chunker {
+    fn push(&mut self, piece: &str) {
+        let len = piece.chars().count();
+        if self.len + len > self.max_chars {
+            self.flush();
+        }
+        if len <= self.max_chars {
+            self.current.push_str(piece);
+            self.len += len;
+            return;
+        }
+        let words: Vec<_> = piece.split_inclusive(char::is_whitespace).collect();
+        if words.len() > 1 {
+            words.into_iter().for_each(|word| self.push(word));
+        } else {
+            let chars: Vec<_> = piece.chars().collect();
+            for chunk in chars.chunks(self.max_chars) {
+                self.push(&chunk.iter().collect::<String>());
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        let chunk = mem::take(&mut self.current);
+        self.len = 0;
+        if !chunk.trim().is_empty() {
+            self.chunks.push(chunk.trim().to_string());
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::SDK;
     use anyhow::Result;
 
+    #[test]
+    fn speech_request_should_validate_voice() {
+        let err = SpeechRequestBuilder::default()
+            .input("hello")
+            .voice(SpeechVoice::Verse)
+            .build();
+        assert!(err.is_err());
+
+        let err = SpeechRequestBuilder::default()
+            .input("hello")
+            .instructions("Whisper.")
+            .build();
+        assert!(err.is_err());
+        let value = serde_json::to_value(SpeechRequest::new("hello")).unwrap();
+        assert!(value.get("instructions").is_none());
+
+        let req = SpeechRequestBuilder::default()
+            .input("hello")
+            .model(SpeechModel::Gpt4oMiniTts)
+            .voice(SpeechVoice::Verse)
+            .response_format(SpeechResponseFormat::Wav)
+            .instructions("Speak like a calm narrator.")
+            .build()
+            .unwrap();
+        let value = serde_json::to_value(req).unwrap();
+        assert_eq!(value["instructions"], "Speak like a calm narrator.");
+        assert_eq!(value["model"], "gpt-4o-mini-tts");
+        assert_eq!(value["voice"], "verse");
+        assert_eq!(value["response_format"], "wav");
+    }
+
+    #[test]
+    fn split_input_should_respect_sentences() {
+        let text = "Hello world. How are you? I'm fine, thanks! Version 3.14 is out.";
+        assert_eq!(
+            split_input(text, 30),
+            [
+                "Hello world. How are you?",
+                "I'm fine, thanks!",
+                "Version 3.14 is out."
+            ]
+        );
+        assert_eq!(split_input(text, 1000), [text]);
+        // a sentence longer than a chunk is split at whitespaces
+        assert_eq!(
+            split_input("one two three four", 8),
+            ["one two", "three", "four"]
+        );
+        assert_eq!(split_input("abcdefgh", 3), ["abc", "def", "gh"]);
+        assert!(split_input("  ", 10).is_empty());
+    }
+
     #[tokio::test]
     async fn speech_should_work() -> Result<()> {
         let req = SpeechRequest::new("The quick brown fox jumped over the lazy dog.");
@@ -84,4 +283,14 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn speech_stream_should_work() -> Result<()> {
+        use futures::TryStreamExt;
+
+        let req = SpeechRequest::new("The quick brown fox jumped over the lazy dog.");
+        let chunks: Vec<_> = SDK.speech_stream(req).await?.try_collect().await?;
+        assert!(chunks.iter().map(|c| c.len()).sum::<usize>() > 0);
+        Ok(())
+    }
 }
@@ -0,0 +1,292 @@
+use crate::{with_assistants_beta, AssistantTool, BuildError, IntoRequest, ToolResources};
+use derive_builder::Builder;
+use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Default, Serialize, Builder)]
+#[builder(pattern = "mutable", build_fn(error = "BuildError"))]
+pub struct CreateThreadRequest {
+    /// A list of messages to start the thread with.
+    #[builder(default, setter(into))]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    messages: Vec<CreateMessageRequest>,
+    /// A set of resources that are made available to the assistant's tools in this thread.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_resources: Option<ToolResources>,
+    /// Set of 16 key-value pairs that can be attached to an object.
+    #[builder(default, setter(into))]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    metadata: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Builder)]
+#[builder(pattern = "mutable", build_fn(error = "BuildError"))]
+pub struct ModifyThreadRequest {
+    /// A set of resources that are made available to the assistant's tools in this thread.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_resources: Option<ToolResources>,
+    /// Set of 16 key-value pairs that can be attached to an object. If set, it replaces the existing metadata.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Builder)]
+#[builder(pattern = "mutable", build_fn(error = "BuildError"))]
+pub struct CreateMessageRequest {
+    /// The role of the entity that is creating the message.
+    #[builder(default)]
+    role: MessageRole,
+    /// The text contents of the message.
+    #[builder(setter(into))]
+    content: String,
+    /// A list of files attached to the message, and the tools they should be added to.
+    #[builder(default, setter(into))]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<MessageAttachment>,
+    /// Set of 16 key-value pairs that can be attached to an object.
+    #[builder(default, setter(into))]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    metadata: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageRole {
+    #[default]
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageAttachment {
+    /// The ID of the file to attach to the message.
+    pub file_id: String,
+    /// The tools to add this file to, e.g. `[{ "type": "file_search" }]`.
+    #[serde(default)]
+    pub tools: Vec<AssistantTool>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Thread {
+    /// The identifier, which can be referenced in API endpoints.
+    pub id: String,
+    /// The object type, which is always thread.
+    pub object: String,
+    /// The Unix timestamp (in seconds) for when the thread was created.
+    pub created_at: u64,
+    /// A set of resources that are made available to the assistant's tools in this thread.
+    #[serde(default)]
+    pub tool_resources: Option<ToolResources>,
+    /// Set of 16 key-value pairs attached to the thread.
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ThreadMessage {
+    /// The identifier, which can be referenced in API endpoints.
+    pub id: String,
+    /// The object type, which is always thread.message.
+    pub object: String,
+    /// The Unix timestamp (in seconds) for when the message was created.
+    pub created_at: u64,
+    /// The thread ID that this message belongs to.
+    pub thread_id: String,
+    /// The entity that produced the message.
+    pub role: MessageRole,
+    /// The content of the message in array of text and/or images.
+    #[serde(default)]
+    pub content: Vec<MessageContent>,
+    /// If applicable, the ID of the assistant that authored this message.
+    #[serde(default)]
+    pub assistant_id: Option<String>,
+    /// The ID of the run associated with the creation of this message.
+    #[serde(default)]
+    pub run_id: Option<String>,
+    /// A list of files attached to the message, and the tools they were added to.
+    #[serde(default)]
+    pub attachments: Vec<MessageAttachment>,
+    /// Set of 16 key-value pairs attached to the message.
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum MessageContent {
+    /// The text content that is part of a message.
+    Text { text: MessageText },
+    /// References an image file in the content of a message.
+    ImageFile { image_file: MessageImageFile },
+    /// References an image URL in the content of a message.
+    ImageUrl { image_url: MessageImageUrl },
+    /// Content types this SDK doesn't know about yet.
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MessageText {
+    /// The data that makes up the text.
+    pub value: String,
+    /// Annotations (citations, file paths) within the text.
+    #[serde(default)]
+    pub annotations: Vec<MessageAnnotation>,
+}
+
+/// An annotation of `text`, at `start_index..end_index` of the message text.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MessageAnnotation {
+    /// A citation of a file found by the file search tool.
+    FileCitation {
+        text: String,
+        start_index: usize,
+        end_index: usize,
+        file_citation: AnnotationFile,
+    },
+    /// A file generated by the code interpreter tool.
+    FilePath {
+        text: String,
+        start_index: usize,
+        end_index: usize,
+        file_path: AnnotationFile,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct AnnotationFile {
+    pub file_id: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MessageImageFile {
+    /// The file ID of the image in the message content.
+    pub file_id: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MessageImageUrl {
+    /// The external URL of the image.
+    pub url: String,
+}
+
+impl IntoRequest for CreateThreadRequest {
+    fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+        let url = format!("{}/threads", base_url);
+        with_assistants_beta(client.post(url).json(&self))
+    }
+}
+
+impl CreateThreadRequest {
+    pub fn new(messages: impl Into<Vec<CreateMessageRequest>>) -> Self {
+        CreateThreadRequestBuilder::default()
+            .messages(messages)
+            .build()
+            .unwrap()
+    }
+}
+
+impl CreateMessageRequest {
+    pub fn new_user(content: impl Into<String>) -> Self {
+        CreateMessageRequestBuilder::default()
+            .content(content)
+            .build()
+            .unwrap()
+    }
+}
+
+impl ThreadMessage {
+    /// Concatenate all the text content of the message.
+    pub fn text(&self) -> String {
+        self.content
+            .iter()
+            .filter_map(|c| match c {
+                MessageContent::Text { text } => Some(text.value.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ListRequest, SDK};
+    use anyhow::Result;
+    use serde_json::json;
+
+    #[test]
+    fn create_thread_request_should_serialize() -> Result<()> {
+        let req = CreateThreadRequest::new(vec![CreateMessageRequest::new_user(
+            "I need to solve the equation `3x + 11 = 14`.",
+        )]);
+        assert_eq!(
+            serde_json::to_value(req)?,
+            json!({
+              "messages": [{
+                "role": "user",
+                "content": "I need to solve the equation `3x + 11 = 14`."
+              }]
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn thread_message_should_deserialize() -> Result<()> {
+        let message: ThreadMessage = serde_json::from_value(json!({
+          "id": "msg_abc123",
+          "object": "thread.message",
+          "created_at": 1699017614,
+          "assistant_id": "asst_abc123",
+          "thread_id": "thread_abc123",
+          "run_id": "run_abc123",
+          "role": "assistant",
+          "content": [
+            { "type": "text", "text": { "value": "x = 1【4:0†source】", "annotations": [
+              { "type": "file_citation", "text": "【4:0†source】", "start_index": 5, "end_index": 17, "file_citation": { "file_id": "file-abc123" } }
+            ] } },
+            { "type": "refusal", "refusal": "no" }
+          ],
+          "attachments": [],
+          "metadata": {}
+        }))?;
+        assert_eq!(message.role, MessageRole::Assistant);
+        assert_eq!(message.text(), "x = 1【4:0†source】");
+        let MessageContent::Text { text } = &message.content[0] else {
+            panic!("expected a text");
+        };
+        assert!(
+            matches!(&text.annotations[0], MessageAnnotation::FileCitation { file_citation, .. } if file_citation.file_id == "file-abc123")
+        );
+        assert!(matches!(message.content[1], MessageContent::Unknown));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn thread_lifecycle_should_work() -> Result<()> {
+        let thread = SDK.create_thread(CreateThreadRequest::default()).await?;
+        assert_eq!(thread.object, "thread");
+
+        let req = CreateMessageRequest::new_user("What is 1 + 1?");
+        let message = SDK.create_message(&thread.id, req).await?;
+        assert_eq!(message.text(), "What is 1 + 1?");
+
+        let messages = SDK
+            .list_messages(&thread.id, ListRequest::default())
+            .await?;
+        assert_eq!(messages.data.len(), 1);
+
+        let status = SDK.delete_thread(&thread.id).await?;
+        assert!(status.deleted);
+        Ok(())
+    }
+}
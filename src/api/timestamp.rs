@@ -0,0 +1,132 @@
+//! The Unix timestamps of the responses as `chrono::DateTime<Utc>`, e.g. `Run::created_at_utc()`.
+//! The raw fields are kept as they are.
+
+use crate::{
+    Assistant, AudioOutput, Batch, ChatCompletionChunk, ChatCompletionResponse, CompletionResponse,
+    CreateImageResponse, FileObject, Run, RunStep, Thread, ThreadMessage,
+};
+use chrono::{DateTime, TimeZone, Utc};
+
+trait UnixTime {
+    type Output;
+    fn to_utc(&self) -> Self::Output;
+}
+
+impl UnixTime for u64 {
+    type Output = DateTime<Utc>;
+
+    /// Out of range timestamps (which the API never returns) map to the epoch.
+    fn to_utc(&self) -> DateTime<Utc> {
+        i64::try_from(*self)
+            .ok()
+            .and_then(|secs| Utc.timestamp_opt(secs, 0).single())
+            .unwrap_or_default()
+    }
+}
+
+impl UnixTime for usize {
+    type Output = DateTime<Utc>;
+
+    fn to_utc(&self) -> DateTime<Utc> {
+        (*self as u64).to_utc()
+    }
+}
+
+impl<T: UnixTime> UnixTime for Option<T> {
+    type Output = Option<T::Output>;
+
+    fn to_utc(&self) -> Self::Output {
+        self.as_ref().map(T::to_utc)
+    }
+}
+
+macro_rules! utc_accessors {
+    ($($ty:ty { $($field:ident => $method:ident: $ret:ty),* $(,)? })*) => {
+        $(
+            impl $ty {
+                $(
+                    #[doc = concat!("`", stringify!($field), "` as a UTC date time.")]
+                    pub fn $method(&self) -> $ret {
+                        self.$field.to_utc()
+                    }
+                )*
+            }
+        )*
+    };
+}
+
+utc_accessors! {
+    Assistant {
+        created_at => created_at_utc: DateTime<Utc>,
+    }
+    AudioOutput {
+        expires_at => expires_at_utc: DateTime<Utc>,
+    }
+    Batch {
+        created_at => created_at_utc: DateTime<Utc>,
+        expires_at => expires_at_utc: Option<DateTime<Utc>>,
+        completed_at => completed_at_utc: Option<DateTime<Utc>>,
+    }
+    ChatCompletionChunk {
+        created => created_utc: DateTime<Utc>,
+    }
+    ChatCompletionResponse {
+        created => created_utc: DateTime<Utc>,
+    }
+    CompletionResponse {
+        created => created_utc: DateTime<Utc>,
+    }
+    CreateImageResponse {
+        created => created_utc: DateTime<Utc>,
+    }
+    FileObject {
+        created_at => created_at_utc: DateTime<Utc>,
+    }
+    Run {
+        created_at => created_at_utc: DateTime<Utc>,
+        expires_at => expires_at_utc: Option<DateTime<Utc>>,
+        started_at => started_at_utc: Option<DateTime<Utc>>,
+        cancelled_at => cancelled_at_utc: Option<DateTime<Utc>>,
+        failed_at => failed_at_utc: Option<DateTime<Utc>>,
+        completed_at => completed_at_utc: Option<DateTime<Utc>>,
+    }
+    RunStep {
+        created_at => created_at_utc: DateTime<Utc>,
+        expired_at => expired_at_utc: Option<DateTime<Utc>>,
+        cancelled_at => cancelled_at_utc: Option<DateTime<Utc>>,
+        failed_at => failed_at_utc: Option<DateTime<Utc>>,
+        completed_at => completed_at_utc: Option<DateTime<Utc>>,
+    }
+    Thread {
+        created_at => created_at_utc: DateTime<Utc>,
+    }
+    ThreadMessage {
+        created_at => created_at_utc: DateTime<Utc>,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn utc_accessors_should_convert_unix_timestamps() -> Result<()> {
+        let file: FileObject = serde_json::from_value(serde_json::json!({
+          "id": "file-abc123",
+          "object": "file",
+          "bytes": 5,
+          "created_at": 1613677385,
+          "filename": "hello.jsonl",
+          "purpose": "batch"
+        }))?;
+        assert_eq!(file.created_at, 1613677385);
+        assert_eq!(
+            file.created_at_utc().to_rfc3339(),
+            "2021-02-18T19:43:05+00:00"
+        );
+        assert_eq!(None::<u64>.to_utc(), None);
+        assert_eq!(u64::MAX.to_utc(), DateTime::<Utc>::default());
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+use crate::sse::SseEvent;
+use anyhow::{anyhow, Result};
+use futures::{future, stream::BoxStream, Stream, StreamExt};
+use serde::Deserialize;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// A piece of the transcript, as sent by the `transcript.text.delta` events of a streaming
+/// transcription. The concatenation of the deltas is the full transcript.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TranscriptDelta {
+    pub delta: String,
+}
+
+/// The stream of `LlmSdk::whisper_stream`. It ends with the `transcript.text.done` event, whose
+/// text is the concatenation of the deltas.
+pub struct TranscriptStream {
+    inner: BoxStream<'static, Result<TranscriptDelta>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TranscriptEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    delta: String,
+}
+
+impl TranscriptStream {
+    pub(crate) fn new(events: impl Stream<Item = Result<SseEvent>> + Send + 'static) -> Self {
+        let inner = events
+            .map(|event| {
+                let event = event?;
+                serde_json::from_str::<TranscriptEvent>(&event.data)
+                    .map_err(|e| anyhow!("failed to parse event {}: {}", event.data, e))
+            })
+            .take_while(|event| {
+                future::ready(!matches!(event, Ok(e) if e.kind == "transcript.text.done"))
+            })
+            // other events (e.g. ones added to the API later) carry no transcript
+            .filter_map(|event| {
+                future::ready(match event {
+                    Ok(e) if e.kind == "transcript.text.delta" => {
+                        Some(Ok(TranscriptDelta { delta: e.delta }))
+                    }
+                    Ok(_) => None,
+                    Err(e) => Some(Err(e)),
+                })
+            })
+            .boxed();
+        Self { inner }
+    }
+}
+
+impl Stream for TranscriptStream {
+    type Item = Result<TranscriptDelta>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transcript_stream_should_yield_deltas() -> Result<()> {
+        let events = [
+            r#"{"type":"transcript.text.delta","delta":"The quick"}"#,
+            r#"{"type":"transcript.text.delta","delta":" brown fox."}"#,
+            r#"{"type":"transcript.text.done","text":"The quick brown fox."}"#,
+        ]
+        .into_iter()
+        .map(|data| {
+            Ok(SseEvent {
+                event: None,
+                data: data.to_string(),
+            })
+        });
+        let deltas = futures::executor::block_on(
+            TranscriptStream::new(futures::stream::iter(events)).collect::<Vec<_>>(),
+        )
+        .into_iter()
+        .collect::<Result<Vec<_>>>()?;
+        assert_eq!(deltas.len(), 2);
+        let text: String = deltas.into_iter().map(|d| d.delta).collect();
+        assert_eq!(text, "The quick brown fox.");
+        Ok(())
+    }
+}
@@ -1,21 +1,37 @@
-use crate::IntoRequest;
+use super::multipart::MultipartForm;
+use crate::{AudioFormat, BuildError, IntoRequest};
+use anyhow::Result;
+use bytes::Bytes;
 use derive_builder::Builder;
-use reqwest::multipart::{Form, Part};
+use futures::Stream;
+use reqwest::{
+    multipart::{Form, Part},
+    Body,
+};
 use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
 use serde::Deserialize;
+use std::{io::Read, path::PathBuf, time::Duration};
 use strum::{Display, EnumString};
+use tokio::io::AsyncReadExt;
+
+/// The size of the chunks a file is streamed in.
+const FILE_CHUNK_SIZE: usize = 64 * 1024;
 
 #[derive(Debug, Clone, Builder)]
-#[builder(pattern = "mutable")]
+#[builder(pattern = "mutable", build_fn(error = "BuildError"))]
 pub struct WhisperRequest {
     /// The audio file object (not file name) to transcribe/translate, in one of these formats: flac, mp3, mp4, mpeg, mpga, m4a, ogg, wav, or webm.
-    file: Vec<u8>,
-    /// ID of the model to use. Only whisper-1 is currently available.
+    #[builder(setter(into))]
+    pub(crate) file: WhisperFile,
+    /// The format of the file, detected from its content if not set (and mp3 if unknown).
+    #[builder(default, setter(strip_option))]
+    pub(crate) format: Option<AudioFormat>,
+    /// ID of the model to use.
     #[builder(default)]
-    model: WhisperModel,
+    pub(crate) model: WhisperModel,
     /// The language of the input audio. Supplying the input language in ISO-639-1 format will improve accuracy and latency. Should not use this for translation
     #[builder(default, setter(strip_option, into))]
-    language: Option<String>,
+    pub(crate) language: Option<String>,
     /// An optional text to guide the model's style or continue a previous audio segment. The prompt should match the audio language for transcription, and should be English only for translation.
     #[builder(default, setter(strip_option, into))]
     prompt: Option<String>,
@@ -25,15 +41,94 @@ pub struct WhisperRequest {
     /// The sampling temperature, between 0 and 1. Higher values like 0.8 will make the output more random, while lower values like 0.2 will make it more focused and deterministic. If set to 0, the model will use log probability to automatically increase the temperature until certain thresholds are hit.
     #[builder(default, setter(strip_option))]
     temperature: Option<f32>,
+    /// The timestamp granularities to populate for this transcription. The response_format must be set to verbose_json (which `LlmSdk::whisper_verbose` does). Segment timestamps are returned by default, word timestamps incur additional latency.
+    #[builder(default, setter(into))]
+    timestamp_granularities: Vec<TimestampGranularity>,
 
-    request_type: WhisperRequestType,
+    pub(crate) request_type: WhisperRequestType,
+    /// Stream the transcript as server-sent events, set by `LlmSdk::whisper_stream`.
+    #[builder(default, setter(skip))]
+    pub(crate) stream: bool,
+    /// Overrides the timeout of the SDK (`LlmSdkBuilder::timeout`) for this request.
+    #[builder(default, setter(strip_option))]
+    pub(crate) timeout: Option<Duration>,
 }
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, EnumString, Display)]
+/// The audio of a `WhisperRequest`, in memory or streamed from a file when the request is sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WhisperFile {
+    Bytes(Vec<u8>),
+    Path {
+        path: PathBuf,
+        /// The size of the file.
+        len: u64,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, EnumString, Display)]
 pub enum WhisperModel {
     #[default]
     #[strum(serialize = "whisper-1")]
     Whisper1,
+    #[strum(serialize = "gpt-4o-transcribe")]
+    Gpt4oTranscribe,
+    #[strum(serialize = "gpt-4o-mini-transcribe")]
+    Gpt4oMiniTranscribe,
+    /// Any other model by its name, e.g. a newer model or one served by a proxy.
+    #[strum(default)]
+    Other(String),
+}
+
+impl WhisperResponseFormat {
+    /// Whether the response is a plain text document rather than JSON.
+    pub fn is_text(&self) -> bool {
+        matches!(self, Self::Text | Self::Srt | Self::Vtt)
+    }
+
+    /// The `Accept` header of the format.
+    pub(crate) fn accept(&self) -> &'static str {
+        match self {
+            Self::Json | Self::VerboseJson => "application/json",
+            Self::Text => "text/plain",
+            Self::Srt => "application/x-subrip, text/plain",
+            Self::Vtt => "text/vtt, text/plain",
+        }
+    }
+
+    /// Whether a response of `content_type` is in this format. Some servers send the subtitles
+    /// as `text/plain`, so any text type is accepted for the text formats.
+    pub(crate) fn matches_content_type(&self, content_type: &str) -> bool {
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+        match self {
+            Self::Json | Self::VerboseJson => mime == "application/json",
+            _ => mime.starts_with("text/") || mime == "application/x-subrip",
+        }
+    }
+}
+
+impl WhisperRequestType {
+    pub(crate) fn endpoint(&self) -> &'static str {
+        match self {
+            Self::Transcription => "audio/transcriptions",
+            Self::Translation => "audio/translations",
+        }
+    }
+}
+
+impl WhisperModel {
+    /// Whether the model can stream the transcript, whisper-1 ignores the `stream` parameter.
+    pub fn supports_streaming(&self) -> bool {
+        !matches!(self, Self::Whisper1)
+    }
+
+    /// The price in USD per minute of audio, 0 for `Other`.
+    pub fn price_per_minute(&self) -> f64 {
+        match self {
+            Self::Whisper1 | Self::Gpt4oTranscribe => 0.006,
+            Self::Gpt4oMiniTranscribe => 0.003,
+            Self::Other(_) => 0.0,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, EnumString, Display)]
@@ -47,6 +142,13 @@ pub enum WhisperResponseFormat {
     Vtt,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum TimestampGranularity {
+    Word,
+    Segment,
+}
+
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, EnumString, Display)]
 pub enum WhisperRequestType {
     #[default]
@@ -59,6 +161,82 @@ pub struct WhisperResponse {
     pub text: String,
 }
 
+/// A transcript in one of the text formats (text, srt or vtt), from `LlmSdk::whisper_text`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TranscriptText {
+    pub format: WhisperResponseFormat,
+    pub text: String,
+}
+
+/// The `verbose_json` response of a transcription or translation.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WhisperVerboseResponse {
+    /// `transcribe` or `translate`.
+    #[serde(default)]
+    pub task: String,
+    /// The language of the input audio in lower case English, e.g. `english` or `chinese`.
+    pub language: String,
+    /// The duration of the input audio in seconds.
+    pub duration: f32,
+    /// The transcribed text.
+    pub text: String,
+    /// Segments of the transcribed text and their corresponding details.
+    #[serde(default)]
+    pub segments: Vec<WhisperSegment>,
+    /// The words with their timestamps, only present when word timestamps are requested with
+    /// `timestamp_granularities`.
+    #[serde(default)]
+    pub words: Vec<WhisperWord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct WhisperWord {
+    /// The text content of the word.
+    pub word: String,
+    /// Start time of the word in seconds.
+    pub start: f32,
+    /// End time of the word in seconds.
+    pub end: f32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WhisperSegment {
+    /// Unique identifier of the segment.
+    pub id: usize,
+    /// Seek offset of the segment.
+    #[serde(default)]
+    pub seek: usize,
+    /// Start time of the segment in seconds.
+    pub start: f32,
+    /// End time of the segment in seconds.
+    pub end: f32,
+    /// Text content of the segment.
+    pub text: String,
+    /// Array of token IDs for the text content.
+    #[serde(default)]
+    pub tokens: Vec<u32>,
+    /// Temperature parameter used for generating the segment.
+    #[serde(default)]
+    pub temperature: f32,
+    /// Average logprob of the segment. If the value is lower than -1, consider the logprobs failed.
+    pub avg_logprob: f32,
+    /// Compression ratio of the segment. If the value is greater than 2.4, consider the compression failed.
+    pub compression_ratio: f32,
+    /// Probability of no speech in the segment. If the value is higher than 1.0 and the
+    /// `avg_logprob` is below -1, consider this segment silent.
+    pub no_speech_prob: f32,
+}
+
+/// The language whisper detected for a piece of audio, see `LlmSdk::detect_language`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectedLanguage {
+    /// The language in lower case English as reported by whisper, e.g. `english`.
+    pub language: String,
+    /// A 0.0 - 1.0 estimate of how reliable the transcription (and thus the detection) is,
+    /// derived from the average token logprob of the speech segments.
+    pub confidence: f32,
+}
+
 impl WhisperRequest {
     pub fn transcription(data: Vec<u8>) -> Self {
         WhisperRequestBuilder::default()
@@ -76,41 +254,162 @@ impl WhisperRequest {
             .unwrap()
     }
 
-    fn into_form(self) -> Form {
-        let part = Part::bytes(self.file)
-            .file_name("file")
-            .mime_str("audio/mp3")
-            .unwrap();
-        let mut form = Form::new()
-            .part("file", part)
-            .text("model", self.model.to_string())
-            .text("response_format", self.response_format.to_string());
+    /// A `verbose_json` transcription of (at most) the first `max_bytes` of the audio, used to
+    /// detect its language. Clipping keeps the request cheap for long recordings, and works
+    /// best for frame based formats like mp3.
+    pub fn language_detection(mut data: Vec<u8>, max_bytes: usize) -> Self {
+        data.truncate(max_bytes);
+        WhisperRequestBuilder::default()
+            .file(data)
+            .response_format(WhisperResponseFormat::VerboseJson)
+            .request_type(WhisperRequestType::Transcription)
+            .build()
+            .unwrap()
+    }
+
+    /// A transcription of the file at `path`, which is streamed from the disk when the request is
+    /// sent rather than read into memory. The format is detected from the first bytes of the file,
+    /// or its extension.
+    pub fn transcription_from_path(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let file = std::fs::File::open(&path)?;
+        let len = file.metadata()?.len();
+        let mut header = Vec::with_capacity(16);
+        file.take(16).read_to_end(&mut header)?;
+        let format = AudioFormat::from_bytes(&header).or_else(|| {
+            let ext = path.extension()?.to_str()?.to_lowercase();
+            ext.parse().ok()
+        });
+
+        let mut builder = WhisperRequestBuilder::default();
+        builder
+            .file(WhisperFile::Path { path, len })
+            .request_type(WhisperRequestType::Transcription);
+        if let Some(format) = format {
+            builder.format(format);
+        }
+        Ok(builder.build()?)
+    }
+
+    /// The fields of the form other than the file.
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![
+            ("model", self.model.to_string()),
+            ("response_format", self.response_format.to_string()),
+        ];
 
         // translation doesn't need language
-        form = match (self.request_type, self.language) {
-            (WhisperRequestType::Transcription, Some(language)) => form.text("language", language),
-            _ => form,
-        };
-        form = if let Some(prompt) = self.prompt {
-            form.text("prompt", prompt)
-        } else {
-            form
-        };
+        if let (WhisperRequestType::Transcription, Some(language)) =
+            (self.request_type, &self.language)
+        {
+            fields.push(("language", language.clone()));
+        }
+        if let Some(prompt) = &self.prompt {
+            fields.push(("prompt", prompt.clone()));
+        }
+        if self.stream {
+            fields.push(("stream", "true".to_string()));
+        }
+        for granularity in &self.timestamp_granularities {
+            fields.push(("timestamp_granularities[]", granularity.to_string()));
+        }
         if let Some(temperature) = self.temperature {
-            form.text("temperature", temperature.to_string())
+            fields.push(("temperature", temperature.to_string()));
+        }
+        fields
+    }
+
+    /// In-memory files are sent as a buffered body, so the request can be retried. Files on disk
+    /// are streamed, and not retried.
+    fn attach_form(self, builder: RequestBuilder) -> RequestBuilder {
+        let format = match &self.file {
+            WhisperFile::Bytes(data) => self.format.or_else(|| AudioFormat::from_bytes(data)),
+            WhisperFile::Path { .. } => self.format,
+        }
+        .unwrap_or(AudioFormat::Mp3);
+        let file_name = format!("file.{}", format.extension());
+        let fields = self.fields();
+        match self.file {
+            WhisperFile::Bytes(data) => fields
+                .into_iter()
+                .fold(
+                    MultipartForm::new().file("file", &file_name, Some(format.mime_type()), &data),
+                    |form, (name, value)| form.text(name, value),
+                )
+                .attach(builder),
+            WhisperFile::Path { path, len } => {
+                let part = Part::stream_with_length(Body::wrap_stream(file_stream(path)), len)
+                    .file_name(file_name)
+                    .mime_str(format.mime_type())
+                    .unwrap();
+                let form = fields
+                    .into_iter()
+                    .fold(Form::new().part("file", part), |form, (name, value)| {
+                        form.text(name, value)
+                    });
+                builder.multipart(form)
+            }
+        }
+    }
+}
+
+impl From<&WhisperVerboseResponse> for DetectedLanguage {
+    fn from(res: &WhisperVerboseResponse) -> Self {
+        // segments that are most likely silence say nothing about the language
+        let logprobs: Vec<f32> = res
+            .segments
+            .iter()
+            .filter(|s| !(s.no_speech_prob > 0.5 && s.avg_logprob < -1.0))
+            .map(|s| s.avg_logprob)
+            .collect();
+        let confidence = if logprobs.is_empty() {
+            0.0
         } else {
-            form
+            let mean = logprobs.iter().sum::<f32>() / logprobs.len() as f32;
+            mean.exp().clamp(0.0, 1.0)
+        };
+        Self {
+            language: res.language.clone(),
+            confidence,
         }
     }
 }
 
+impl From<Vec<u8>> for WhisperFile {
+    fn from(data: Vec<u8>) -> Self {
+        Self::Bytes(data)
+    }
+}
+
+/// Read the file chunk by chunk, it is opened on the first poll.
+fn file_stream(path: PathBuf) -> impl Stream<Item = std::io::Result<Bytes>> + Send + 'static {
+    futures::stream::try_unfold(None, move |file: Option<tokio::fs::File>| {
+        let path = path.clone();
+        async move {
+            let mut file = match file {
+                Some(file) => file,
+                None => tokio::fs::File::open(&path).await?,
+            };
+            let mut buf = vec![0; FILE_CHUNK_SIZE];
+            let n = file.read(&mut buf).await?;
+            if n == 0 {
+                return Ok(None);
+            }
+            buf.truncate(n);
+            Ok(Some((Bytes::from(buf), Some(file))))
+        }
+    })
+}
+
 impl IntoRequest for WhisperRequest {
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
     fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
-        let url = match self.request_type {
-            WhisperRequestType::Transcription => format!("{}/audio/transcriptions", base_url),
-            WhisperRequestType::Translation => format!("{}/audio/translations", base_url),
-        };
-        client.post(url).multipart(self.into_form())
+        let url = format!("{}/{}", base_url, self.request_type.endpoint());
+        let accept = self.response_format.accept();
+        self.attach_form(client.post(url).header(reqwest::header::ACCEPT, accept))
     }
 }
 
@@ -119,8 +418,108 @@ mod tests {
     use super::*;
     use crate::SDK;
     use anyhow::Result;
+    use serde_json::json;
     use std::fs;
 
+    #[test]
+    fn whisper_response_format_should_match_content_types() {
+        use WhisperResponseFormat::*;
+        assert!(Srt.matches_content_type("text/plain; charset=utf-8"));
+        assert!(Srt.matches_content_type("application/x-subrip"));
+        assert!(Vtt.matches_content_type("text/vtt"));
+        assert!(!Text.matches_content_type("application/json"));
+        assert!(Json.matches_content_type("application/json; charset=utf-8"));
+        assert!(Vtt.is_text() && !VerboseJson.is_text());
+    }
+
+    #[test]
+    fn detected_language_should_skip_silent_segments() -> Result<()> {
+        let res: WhisperVerboseResponse = serde_json::from_value(json!({
+          "task": "transcribe",
+          "language": "english",
+          "duration": 4.0,
+          "text": "The quick brown fox.",
+          "segments": [
+            { "id": 0, "start": 0.0, "end": 2.0, "text": "The quick brown fox.", "avg_logprob": -0.2, "compression_ratio": 0.8, "no_speech_prob": 0.01 },
+            { "id": 1, "start": 2.0, "end": 4.0, "text": "", "avg_logprob": -1.5, "compression_ratio": 0.1, "no_speech_prob": 0.9 }
+          ]
+        }))?;
+        let detected = DetectedLanguage::from(&res);
+        assert_eq!(detected.language, "english");
+        assert!((detected.confidence - (-0.2f32).exp()).abs() < 1e-6);
+        Ok(())
+    }
+
+    #[test]
+    fn verbose_response_should_parse_segments() -> Result<()> {
+        let res: WhisperVerboseResponse = serde_json::from_value(json!({
+          "task": "transcribe",
+          "language": "english",
+          "duration": 2.0,
+          "text": "Hello.",
+          "segments": [{
+            "id": 0, "seek": 0, "start": 0.0, "end": 2.0, "text": " Hello.",
+            "tokens": [50364, 2425, 13, 50464], "temperature": 0.0,
+            "avg_logprob": -0.3, "compression_ratio": 0.6, "no_speech_prob": 0.02
+          }]
+        }))?;
+        let segment = &res.segments[0];
+        assert_eq!(segment.tokens, [50364, 2425, 13, 50464]);
+        assert!(res.words.is_empty());
+        assert_eq!(segment.end, 2.0);
+        assert_eq!(segment.no_speech_prob, 0.02);
+        Ok(())
+    }
+
+    #[test]
+    fn transcription_from_path_should_detect_format() -> Result<()> {
+        let req = WhisperRequest::transcription_from_path("fixtures/speech.mp3")?;
+        assert_eq!(req.format, Some(AudioFormat::Mp3));
+        let len = fs::metadata("fixtures/speech.mp3")?.len();
+        assert_eq!(
+            req.file,
+            WhisperFile::Path {
+                path: "fixtures/speech.mp3".into(),
+                len
+            }
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn file_stream_should_read_the_whole_file() -> Result<()> {
+        use futures::TryStreamExt;
+
+        let chunks: Vec<Bytes> = file_stream("fixtures/speech.mp3".into())
+            .try_collect()
+            .await?;
+        assert_eq!(chunks.concat(), fs::read("fixtures/speech.mp3")?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn transcription_from_path_should_work() -> Result<()> {
+        let req = WhisperRequest::transcription_from_path("fixtures/speech.mp3")?;
+        let res = SDK.whisper(req).await?;
+        assert!(!res.text.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn whisper_stream_should_work() -> Result<()> {
+        use futures::TryStreamExt;
+
+        let data = fs::read("fixtures/speech.mp3")?;
+        let req = WhisperRequestBuilder::default()
+            .file(data)
+            .model(WhisperModel::Gpt4oMiniTranscribe)
+            .request_type(WhisperRequestType::Transcription)
+            .build()?;
+        let deltas: Vec<_> = SDK.whisper_stream(req).await?.try_collect().await?;
+        assert!(!deltas.is_empty());
+        Ok(())
+    }
+
     #[tokio::test]
     async fn transcription_should_work() -> Result<()> {
         let data = fs::read("fixtures/speech.mp3")?;
@@ -156,6 +555,29 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn transcription_with_word_timestamps_should_work() -> Result<()> {
+        let data = fs::read("fixtures/speech.mp3")?;
+        let req = WhisperRequestBuilder::default()
+            .file(data)
+            .request_type(WhisperRequestType::Transcription)
+            .timestamp_granularities([TimestampGranularity::Word])
+            .build()?;
+        let res = SDK.whisper_verbose(req).await?;
+        assert!(!res.words.is_empty());
+        assert!(res.words.windows(2).all(|w| w[0].start <= w[1].start));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn detect_language_should_work() -> Result<()> {
+        let data = fs::read("fixtures/chinese.mp3")?;
+        let res = SDK.detect_language(data).await?;
+        assert_eq!(res.language, "chinese");
+        assert!(res.confidence > 0.0);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn translate_should_work() -> Result<()> {
         let data = fs::read("fixtures/chinese.mp3")?;
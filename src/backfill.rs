@@ -0,0 +1,193 @@
+//! Long running embedding backfills. `Backfill` embeds a list of inputs batch by batch while
+//! staying under a tokens per minute budget, and persists its progress to a checkpoint file
+//! after every batch, so a backfill that runs for hours can be stopped and resumed.
+
+use crate::{api::estimate_tokens, EmbeddingModel, EmbeddingRequestBuilder, LlmSdk};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use tracing::info;
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct Backfill {
+    checkpoint: PathBuf,
+    model: EmbeddingModel,
+    batch_size: usize,
+    tokens_per_minute: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackfillProgress {
+    /// Number of inputs embedded (and handed to the callback) so far.
+    pub done: usize,
+    /// Number of inputs of the backfill, used to detect a checkpoint of a different backfill.
+    pub total: usize,
+}
+
+/// Tracks the tokens spent in the current one minute window.
+#[derive(Debug)]
+struct TokenWindow {
+    limit: usize,
+    used: usize,
+    started_at: Instant,
+}
+
+impl Backfill {
+    /// A backfill persisting its progress to the `checkpoint` file.
+    pub fn new(checkpoint: impl Into<PathBuf>) -> Self {
+        Self {
+            checkpoint: checkpoint.into(),
+            model: EmbeddingModel::default(),
+            batch_size: 100,
+            tokens_per_minute: 1_000_000,
+        }
+    }
+
+    pub fn model(mut self, model: EmbeddingModel) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Number of inputs per embedding request.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// The tokens per minute budget, usually a bit below the rate limit of the account.
+    pub fn tokens_per_minute(mut self, tokens_per_minute: usize) -> Self {
+        self.tokens_per_minute = tokens_per_minute;
+        self
+    }
+
+    /// The persisted progress, all zeros if the backfill hasn't started.
+    pub fn progress(&self) -> Result<BackfillProgress> {
+        match fs::read(&self.checkpoint) {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BackfillProgress::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Embed `inputs`, starting after the persisted progress. `on_batch` receives the offset of
+    /// the batch in `inputs` and its embeddings (e.g. to write them to a vector store); the
+    /// progress is persisted after it returns, so a batch may be delivered again after a crash.
+    pub async fn run(
+        &self,
+        sdk: &LlmSdk,
+        inputs: &[String],
+        mut on_batch: impl FnMut(usize, Vec<Vec<f32>>) -> Result<()>,
+    ) -> Result<BackfillProgress> {
+        let mut progress = self.progress()?;
+        if progress.total != 0 && progress.total != inputs.len() {
+            return Err(anyhow!(
+                "checkpoint {} is for {} inputs, not {}",
+                self.checkpoint.display(),
+                progress.total,
+                inputs.len()
+            ));
+        }
+        progress.total = inputs.len();
+        let mut window = TokenWindow::new(self.tokens_per_minute);
+        while progress.done < inputs.len() {
+            let end = (progress.done + self.batch_size).min(inputs.len());
+            let batch = &inputs[progress.done..end];
+            let tokens = batch.iter().map(|s| estimate_tokens(s)).sum();
+            if let Some(wait) = window.reserve(tokens, Instant::now()) {
+                info!("backfill waits {:?} for the token budget", wait);
+                tokio::time::sleep(wait).await;
+                window.reserve(tokens, Instant::now());
+            }
+
+            let req = EmbeddingRequestBuilder::default()
+                .input(batch.to_vec().into())
+                .model(self.model.clone())
+                .build()?;
+            let mut data = sdk.embedding(req).await?.data;
+            data.sort_by_key(|d| d.index);
+            let embeddings = data
+                .iter()
+                .map(|d| d.embedding.as_floats())
+                .collect::<Result<Vec<_>>>()?;
+            on_batch(progress.done, embeddings)?;
+
+            progress.done = end;
+            self.save(&progress)?;
+            info!("backfill progress: {}/{}", progress.done, progress.total);
+        }
+        Ok(progress)
+    }
+
+    fn save(&self, progress: &BackfillProgress) -> Result<()> {
+        // write then rename, so a crash never leaves a truncated checkpoint
+        let tmp = self.checkpoint.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(progress)?)?;
+        fs::rename(tmp, &self.checkpoint)?;
+        Ok(())
+    }
+}
+
+impl TokenWindow {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            used: 0,
+            started_at: Instant::now(),
+        }
+    }
+
+    /// Spend `tokens` in the current window, or return how long to wait for the next one. A
+    /// batch larger than the whole budget gets a window of its own.
+    fn reserve(&mut self, tokens: usize, now: Instant) -> Option<Duration> {
+        let elapsed = now.duration_since(self.started_at);
+        if elapsed >= WINDOW {
+            self.used = 0;
+            self.started_at = now;
+        } else if self.used > 0 && self.used + tokens > self.limit {
+            return Some(WINDOW - elapsed);
+        }
+        self.used += tokens;
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_window_should_wait_for_budget() {
+        let mut window = TokenWindow::new(100);
+        let start = window.started_at;
+        assert_eq!(window.reserve(60, start), None);
+        assert_eq!(
+            window.reserve(60, start + Duration::from_secs(20)),
+            Some(Duration::from_secs(40))
+        );
+        assert_eq!(window.reserve(60, start + WINDOW), None);
+        // larger than the budget, but alone in the window
+        let mut window = TokenWindow::new(100);
+        assert_eq!(window.reserve(150, window.started_at), None);
+    }
+
+    #[test]
+    fn backfill_progress_should_persist() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("backfill-{}.json", std::process::id()));
+        let backfill = Backfill::new(&path);
+        assert_eq!(backfill.progress()?, BackfillProgress::default());
+        let progress = BackfillProgress {
+            done: 200,
+            total: 1000,
+        };
+        backfill.save(&progress)?;
+        assert_eq!(backfill.progress()?, progress);
+        fs::remove_file(path)?;
+        Ok(())
+    }
+}
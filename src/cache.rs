@@ -0,0 +1,181 @@
+//! In-memory cache of chat completions, see `LlmSdk::chat_completion_cached`. An entry is fresh
+//! for `fresh_for`, then stale until `max_age`: a stale entry is still served right away while
+//! it is refreshed in the background (stale-while-revalidate). That suits semi-static content,
+//! e.g. generated descriptions, where a slightly old answer beats waiting for a new one.
+
+use crate::{ChatCompletionRequest, ChatCompletionResponse};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug)]
+pub struct ResponseCache {
+    fresh_for: Duration,
+    max_age: Duration,
+    entries: Mutex<HashMap<String, CacheEntry>>,
+}
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    response: ChatCompletionResponse,
+    created_at: Instant,
+    revalidating: bool,
+}
+
+/// The result of a cache lookup.
+#[derive(Debug, Clone)]
+pub(crate) enum CacheLookup {
+    Fresh(ChatCompletionResponse),
+    /// Serve it, and refresh it if `revalidate` is true (no other refresh is in flight).
+    Stale {
+        response: ChatCompletionResponse,
+        revalidate: bool,
+    },
+    Miss,
+}
+
+impl ResponseCache {
+    /// Entries are fresh for `fresh_for`, and served stale (while revalidated) until `max_age`.
+    /// Use the same duration for both to disable stale-while-revalidate.
+    pub fn new(fresh_for: Duration, max_age: Duration) -> Self {
+        Self {
+            fresh_for,
+            max_age: max_age.max(fresh_for),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    pub fn clear(&self) {
+        self.lock().clear()
+    }
+
+    /// Drop the expired entries.
+    pub fn purge_expired(&self) {
+        let max_age = self.max_age;
+        self.lock()
+            .retain(|_, entry| entry.created_at.elapsed() < max_age);
+    }
+
+    pub(crate) fn key(req: &ChatCompletionRequest) -> String {
+        serde_json::to_string(req).unwrap_or_default()
+    }
+
+    pub(crate) fn lookup(&self, key: &str) -> CacheLookup {
+        let mut entries = self.lock();
+        let Some(entry) = entries.get_mut(key) else {
+            return CacheLookup::Miss;
+        };
+        let age = entry.created_at.elapsed();
+        if age < self.fresh_for {
+            CacheLookup::Fresh(entry.response.clone())
+        } else if age < self.max_age {
+            let revalidate = !entry.revalidating;
+            entry.revalidating = true;
+            CacheLookup::Stale {
+                response: entry.response.clone(),
+                revalidate,
+            }
+        } else {
+            entries.remove(key);
+            CacheLookup::Miss
+        }
+    }
+
+    pub(crate) fn insert(&self, key: String, response: ChatCompletionResponse) {
+        self.lock().insert(
+            key,
+            CacheEntry {
+                response,
+                created_at: Instant::now(),
+                revalidating: false,
+            },
+        );
+    }
+
+    /// A failed refresh keeps serving the stale entry, and allows the next lookup to retry.
+    pub(crate) fn revalidation_failed(&self, key: &str) {
+        if let Some(entry) = self.lock().get_mut(key) {
+            entry.revalidating = false;
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, CacheEntry>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChatCompleteModel, ChatCompletionMessage};
+
+    fn response() -> ChatCompletionResponse {
+        serde_json::from_value(serde_json::json!({
+          "id": "chatcmpl-1",
+          "object": "chat.completion",
+          "created": 1715000000,
+          "model": "gpt-4o",
+          "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": "Hi!" },
+            "finish_reason": "stop"
+          }]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn response_cache_should_serve_stale_once_revalidating() {
+        let req = ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt4o,
+            vec![ChatCompletionMessage::new_user("Hi", "")],
+        );
+        let key = ResponseCache::key(&req);
+
+        let cache = ResponseCache::new(Duration::from_secs(60), Duration::from_secs(120));
+        assert!(matches!(cache.lookup(&key), CacheLookup::Miss));
+        cache.insert(key.clone(), response());
+        assert!(matches!(cache.lookup(&key), CacheLookup::Fresh(_)));
+
+        let cache = ResponseCache::new(Duration::ZERO, Duration::from_secs(120));
+        cache.insert(key.clone(), response());
+        assert!(matches!(
+            cache.lookup(&key),
+            CacheLookup::Stale {
+                revalidate: true,
+                ..
+            }
+        ));
+        // a refresh is already in flight
+        assert!(matches!(
+            cache.lookup(&key),
+            CacheLookup::Stale {
+                revalidate: false,
+                ..
+            }
+        ));
+        cache.revalidation_failed(&key);
+        assert!(matches!(
+            cache.lookup(&key),
+            CacheLookup::Stale {
+                revalidate: true,
+                ..
+            }
+        ));
+
+        let cache = ResponseCache::new(Duration::ZERO, Duration::ZERO);
+        cache.insert(key.clone(), response());
+        assert!(matches!(cache.lookup(&key), CacheLookup::Miss));
+        assert!(cache.is_empty());
+    }
+}
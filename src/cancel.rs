@@ -0,0 +1,113 @@
+//! Cancellation of the calls, e.g. when the user stops a streaming answer or a long
+//! transcription. Any future or stream of the SDK can be bound to a `CancellationToken` with
+//! `CancelExt::cancel_on`: once the token is cancelled, the call is dropped, which closes its
+//! connection (and cancels a streamed run on the server), and it yields `LlmError::Cancelled`.
+
+use crate::LlmError;
+use anyhow::Result;
+use futures::Stream;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+pub use tokio_util::sync::CancellationToken;
+use tokio_util::sync::WaitForCancellationFutureOwned;
+
+/// A future or stream aborted once its token is cancelled.
+pub struct Cancellable<T> {
+    /// `None` once cancelled.
+    inner: Option<Pin<Box<T>>>,
+    cancelled: Pin<Box<WaitForCancellationFutureOwned>>,
+}
+
+pub trait CancelExt: Sized {
+    /// Abort `self` once `token` is cancelled. A future then resolves to `LlmError::Cancelled`,
+    /// a stream yields it and ends.
+    fn cancel_on(self, token: CancellationToken) -> Cancellable<Self> {
+        Cancellable {
+            inner: Some(Box::pin(self)),
+            cancelled: Box::pin(token.cancelled_owned()),
+        }
+    }
+}
+
+impl<T> CancelExt for T {}
+
+impl<T> Cancellable<T> {
+    /// Drop the inner call if the token is cancelled.
+    fn poll_cancelled(&mut self, cx: &mut Context<'_>) -> bool {
+        if self.inner.is_some() && self.cancelled.as_mut().poll(cx).is_ready() {
+            self.inner = None;
+            return true;
+        }
+        false
+    }
+}
+
+impl<F, T> Future for Cancellable<F>
+where
+    F: Future<Output = Result<T>>,
+{
+    type Output = Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        this.poll_cancelled(cx);
+        match &mut this.inner {
+            Some(inner) => inner.as_mut().poll(cx),
+            None => Poll::Ready(Err(LlmError::Cancelled.into())),
+        }
+    }
+}
+
+impl<S, T> Stream for Cancellable<S>
+where
+    S: Stream<Item = Result<T>>,
+{
+    type Item = Result<T>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.poll_cancelled(cx) {
+            return Poll::Ready(Some(Err(LlmError::Cancelled.into())));
+        }
+        match &mut this.inner {
+            Some(inner) => inner.as_mut().poll_next(cx),
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{future, stream, StreamExt};
+
+    fn is_cancelled(e: &anyhow::Error) -> bool {
+        matches!(e.downcast_ref::<LlmError>(), Some(LlmError::Cancelled))
+    }
+
+    #[tokio::test]
+    async fn cancel_on_should_abort_future() {
+        let token = CancellationToken::new();
+        let call = future::pending::<Result<()>>().cancel_on(token.clone());
+        token.cancel();
+        assert!(is_cancelled(&call.await.unwrap_err()));
+
+        let call = future::ready(Ok::<_, anyhow::Error>(1)).cancel_on(CancellationToken::new());
+        assert_eq!(call.await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn cancel_on_should_end_stream() {
+        let token = CancellationToken::new();
+        let chunks = stream::iter([Ok::<_, anyhow::Error>("a"), Ok("b")]).chain(stream::pending());
+        let mut stream = chunks.cancel_on(token.clone());
+        assert_eq!(stream.next().await.unwrap().unwrap(), "a");
+        assert_eq!(stream.next().await.unwrap().unwrap(), "b");
+        token.cancel();
+        assert!(is_cancelled(&stream.next().await.unwrap().unwrap_err()));
+        assert!(stream.next().await.is_none());
+    }
+}
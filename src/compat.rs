@@ -0,0 +1,139 @@
+//! Conversions between the types of this crate and the ones of `async-openai` (feature
+//! `async-openai-compat`), to migrate a code base one call at a time. Both crates model the same
+//! wire format, so a conversion is a JSON round trip: the requests convert to `async-openai`, and
+//! its responses convert back. `async-openai` doesn't deserialize its embedding requests, those
+//! are mapped field by field.
+
+use crate::{
+    ChatCompletionChunk, ChatCompletionRequest, ChatCompletionResponse, EmbeddingEncodingFormat,
+    EmbeddingInput, EmbeddingRequest, EmbeddingResponse,
+};
+use async_openai::types::{
+    self as openai, CreateChatCompletionRequest, CreateChatCompletionResponse,
+    CreateChatCompletionStreamResponse, CreateEmbeddingRequest, CreateEmbeddingResponse,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+fn convert<T: Serialize, U: DeserializeOwned>(value: &T) -> Result<U, serde_json::Error> {
+    let mut value = serde_json::to_value(value)?;
+    remove_nulls(&mut value);
+    serde_json::from_value(value)
+}
+
+/// `async-openai` serializes the absent fields as `null`, which some fields of this crate (e.g. the
+/// `tool_calls` of a message) don't accept: they are left out instead.
+fn remove_nulls(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.retain(|_, v| !v.is_null());
+            map.values_mut().for_each(remove_nulls);
+        }
+        Value::Array(values) => values.iter_mut().for_each(remove_nulls),
+        _ => {}
+    }
+}
+
+macro_rules! compat {
+    ($($from:ty => $to:ty),* $(,)?) => {
+        $(
+            impl TryFrom<$from> for $to {
+                type Error = serde_json::Error;
+
+                fn try_from(value: $from) -> Result<Self, Self::Error> {
+                    convert(&value)
+                }
+            }
+        )*
+    };
+}
+
+compat! {
+    ChatCompletionRequest => CreateChatCompletionRequest,
+    CreateChatCompletionResponse => ChatCompletionResponse,
+    CreateChatCompletionStreamResponse => ChatCompletionChunk,
+    CreateEmbeddingResponse => EmbeddingResponse,
+}
+
+impl TryFrom<EmbeddingRequest> for CreateEmbeddingRequest {
+    type Error = serde_json::Error;
+
+    fn try_from(req: EmbeddingRequest) -> Result<Self, Self::Error> {
+        let input = match req.input {
+            EmbeddingInput::String(s) => openai::EmbeddingInput::String(s),
+            EmbeddingInput::StringArray(v) => openai::EmbeddingInput::StringArray(v),
+            EmbeddingInput::Tokens(v) => openai::EmbeddingInput::IntegerArray(v),
+            EmbeddingInput::TokensArray(v) => openai::EmbeddingInput::ArrayOfIntegerArray(v),
+        };
+        let encoding_format = req.encoding_format.map(|format| match format {
+            EmbeddingEncodingFormat::Float => openai::EncodingFormat::Float,
+            EmbeddingEncodingFormat::Base64 => openai::EncodingFormat::Base64,
+        });
+        Ok(Self {
+            model: convert(&req.model)?,
+            input,
+            encoding_format,
+            user: req.user,
+            dimensions: req.dimensions.map(|dimensions| dimensions as u32),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChatCompleteModel, ChatCompletionMessage};
+    use serde_json::json;
+
+    #[test]
+    fn chat_completion_request_should_convert() -> anyhow::Result<()> {
+        let req = ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt4o,
+            vec![ChatCompletionMessage::new_user("Hi", "")],
+        );
+        let converted = CreateChatCompletionRequest::try_from(req.clone())?;
+        assert_eq!(converted.model, "gpt-4o");
+        assert_eq!(
+            serde_json::to_value(&converted)?["messages"],
+            serde_json::to_value(&req)?["messages"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn embedding_request_should_convert() -> anyhow::Result<()> {
+        let req = crate::EmbeddingRequestBuilder::default()
+            .input(EmbeddingInput::StringArray(vec!["a".into(), "b".into()]))
+            .model(crate::EmbeddingModel::TextEmbedding3Small)
+            .dimensions(256)
+            .build()?;
+        let converted = CreateEmbeddingRequest::try_from(req.clone())?;
+        assert_eq!(converted.model, "text-embedding-3-small");
+        assert_eq!(converted.dimensions, Some(256));
+        assert_eq!(
+            serde_json::to_value(&converted)?,
+            serde_json::to_value(&req)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn chat_completion_response_should_convert() -> anyhow::Result<()> {
+        let res: CreateChatCompletionResponse = serde_json::from_value(json!({
+          "id": "chatcmpl-123",
+          "object": "chat.completion",
+          "created": 1677652288,
+          "model": "gpt-4o",
+          "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": "Hello!" },
+            "finish_reason": "stop"
+          }],
+          "usage": { "prompt_tokens": 9, "completion_tokens": 2, "total_tokens": 11 }
+        }))?;
+        let converted = ChatCompletionResponse::try_from(res)?;
+        assert_eq!(converted.id, "chatcmpl-123");
+        assert_eq!(converted.usage.total_tokens, 11);
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+//! Turn production traffic into fine-tuning data. With `LlmSdkBuilder::dataset_logger`, every
+//! successful chat completion is appended as a line of the chat fine-tuning JSONL format:
+//! `{"messages": [...], "tools": [...], "metadata": {...}}`. The `DatasetHook` decides which
+//! pairs are kept, labels them and sanitizes the texts (e.g. to redact personal data).
+
+use crate::{ChatCompletionRequest, ChatCompletionResponse};
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    fmt,
+    fs::OpenOptions,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+/// Labels attached to a logged pair, written to the `metadata` of the line. Remove `metadata`
+/// before uploading the file if the fine-tuning job doesn't accept it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DatasetLabel {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// A quality score of the response, e.g. from user feedback or a grader.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quality: Option<f32>,
+}
+
+pub trait DatasetHook: Send + Sync {
+    /// Label the pair, or return `None` to skip it. Keeps everything unlabelled by default.
+    fn label(
+        &self,
+        _req: &ChatCompletionRequest,
+        _res: &ChatCompletionResponse,
+    ) -> Option<DatasetLabel> {
+        Some(DatasetLabel::default())
+    }
+
+    /// Sanitize a text (message content or tool call arguments) before it's written.
+    fn sanitize(&self, text: &str) -> String {
+        text.to_string()
+    }
+}
+
+/// Keeps every pair as is.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KeepAll;
+
+impl DatasetHook for KeepAll {}
+
+pub struct DatasetLogger {
+    writer: Mutex<Box<dyn Write + Send>>,
+    hook: Box<dyn DatasetHook>,
+}
+
+#[derive(Debug, Serialize)]
+struct DatasetLine<'a> {
+    messages: Value,
+    #[serde(skip_serializing_if = "Value::is_null")]
+    tools: Value,
+    #[serde(skip_serializing_if = "is_empty_label")]
+    metadata: &'a DatasetLabel,
+}
+
+impl DatasetLogger {
+    pub fn new(writer: impl Write + Send + 'static, hook: impl DatasetHook + 'static) -> Self {
+        Self {
+            writer: Mutex::new(Box::new(writer)),
+            hook: Box::new(hook),
+        }
+    }
+
+    /// Append to the JSONL file at `path`, creating it if needed.
+    pub fn open(path: impl AsRef<Path>, hook: impl DatasetHook + 'static) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(BufWriter::new(file), hook))
+    }
+
+    /// Write the pair of the request and the first choice of the response, if the hook keeps it.
+    pub fn log(&self, req: &ChatCompletionRequest, res: &ChatCompletionResponse) -> Result<()> {
+        let Some(choice) = res.choices.first() else {
+            return Ok(());
+        };
+        let Some(label) = self.hook.label(req, res) else {
+            return Ok(());
+        };
+        let mut messages = serde_json::to_value(&req.messages)?;
+        if let Value::Array(messages) = &mut messages {
+            let mut message = serde_json::to_value(&choice.message)?;
+            message["role"] = "assistant".into();
+            messages.push(message);
+        }
+        sanitize_texts(&mut messages, &|s| self.hook.sanitize(s));
+        let tools = if req.tools.is_empty() {
+            Value::Null
+        } else {
+            serde_json::to_value(&req.tools)?
+        };
+        let line = DatasetLine {
+            messages,
+            tools,
+            metadata: &label,
+        };
+
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        serde_json::to_writer(&mut *writer, &line)?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl fmt::Debug for DatasetLogger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DatasetLogger").finish_non_exhaustive()
+    }
+}
+
+/// Apply `f` to the texts of the messages: contents and tool call arguments.
+pub(crate) fn sanitize_texts(value: &mut Value, f: &dyn Fn(&str) -> String) {
+    match value {
+        Value::Object(obj) => {
+            for (key, value) in obj.iter_mut() {
+                match value {
+                    Value::String(s)
+                        if matches!(key.as_str(), "content" | "text" | "arguments") =>
+                    {
+                        *s = f(s);
+                    }
+                    other => sanitize_texts(other, f),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|v| sanitize_texts(v, f)),
+        _ => {}
+    }
+}
+
+fn is_empty_label(label: &&DatasetLabel) -> bool {
+    label.tags.is_empty() && label.quality.is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChatCompleteModel, ChatCompletionMessage};
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct RedactEmails;
+
+    impl DatasetHook for RedactEmails {
+        fn label(
+            &self,
+            _req: &ChatCompletionRequest,
+            res: &ChatCompletionResponse,
+        ) -> Option<DatasetLabel> {
+            let content = res.choices[0].message.content.as_deref()?;
+            Some(DatasetLabel {
+                tags: vec!["support".into()],
+                quality: Some(if content.len() > 5 { 1.0 } else { 0.5 }),
+            })
+        }
+
+        fn sanitize(&self, text: &str) -> String {
+            text.replace("alice@example.com", "[email]")
+        }
+    }
+
+    #[test]
+    fn dataset_logger_should_write_sanitized_pairs() -> Result<()> {
+        let buf = SharedBuf::default();
+        let logger = DatasetLogger::new(buf.clone(), RedactEmails);
+        let req = ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt4o,
+            vec![
+                ChatCompletionMessage::new_system("You are a support bot.", ""),
+                ChatCompletionMessage::new_user("I'm alice@example.com, reset my password", ""),
+            ],
+        );
+        let res: ChatCompletionResponse = serde_json::from_value(serde_json::json!({
+          "id": "chatcmpl-1",
+          "object": "chat.completion",
+          "created": 1715000000,
+          "model": "gpt-4o",
+          "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": "Sent a link to alice@example.com." },
+            "finish_reason": "stop"
+          }]
+        }))?;
+        logger.log(&req, &res)?;
+
+        let output = String::from_utf8(buf.0.lock().unwrap().clone())?;
+        let line: Value = serde_json::from_str(output.trim_end())?;
+        assert_eq!(
+            line,
+            serde_json::json!({
+              "messages": [
+                { "role": "system", "content": "You are a support bot." },
+                { "role": "user", "content": "I'm [email], reset my password" },
+                { "role": "assistant", "content": "Sent a link to [email]." }
+              ],
+              "metadata": { "tags": ["support"], "quality": 1.0 }
+            })
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+//! Deadline-aware streaming for interactive UIs. When a streaming completion is about to miss
+//! the deadline of the caller, `LlmSdk::chat_completion_stream_deadline` stops it and streams a
+//! short continuation asking the model to wrap up, so the message still ends coherently.
+
+use crate::{
+    ChatCompletionChunk, ChatCompletionMessage, ChatCompletionRequest, ChatCompletionStream, LlmSdk,
+};
+use anyhow::Result;
+use futures::{stream, StreamExt};
+use std::time::Duration;
+use tokio::time::{timeout_at, Instant};
+use tracing::info;
+
+const WRAP_UP_PROMPT: &str = "You ran out of time. Wrap up your answer in one sentence, \
+continuing exactly where you stopped.";
+
+#[derive(Debug, Clone)]
+pub struct StreamDeadline {
+    deadline: Instant,
+    reserve: Duration,
+    prompt: String,
+    max_tokens: usize,
+}
+
+enum State {
+    Main(Box<MainState>),
+    WrapUp(ChatCompletionStream),
+    Done,
+}
+
+/// The main stream, and what it streamed so far.
+struct MainState {
+    sdk: LlmSdk,
+    req: ChatCompletionRequest,
+    stream: ChatCompletionStream,
+    text: String,
+    tool_calls: bool,
+}
+
+impl StreamDeadline {
+    /// The stream must be finished within `timeout` from now.
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            deadline: Instant::now() + timeout,
+            reserve: Duration::from_secs(3),
+            prompt: WRAP_UP_PROMPT.to_string(),
+            max_tokens: 60,
+        }
+    }
+
+    /// The time kept for the wrap-up continuation, 3 seconds by default.
+    pub fn reserve(mut self, reserve: Duration) -> Self {
+        self.reserve = reserve;
+        self
+    }
+
+    /// The user message asking to wrap up.
+    pub fn prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.prompt = prompt.into();
+        self
+    }
+
+    /// The max tokens of the wrap-up continuation, 60 by default.
+    pub fn max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    /// When the main stream is cut.
+    fn cutoff(&self) -> Instant {
+        self.deadline
+            .checked_sub(self.reserve)
+            .unwrap_or(self.deadline)
+    }
+
+    /// The continuation of the partial answer `text`.
+    pub(crate) fn wrap_up_request(
+        &self,
+        mut req: ChatCompletionRequest,
+        text: String,
+    ) -> ChatCompletionRequest {
+        let messages = req.messages_mut();
+        messages.push(ChatCompletionMessage::new_assistant(text));
+        messages.push(ChatCompletionMessage::new_user(self.prompt.clone(), ""));
+        req.set_max_tokens(Some(self.max_tokens));
+        req.tools_mut().clear();
+        req.set_tool_choice(None);
+        req
+    }
+
+    /// Forward the chunks of `stream`, switching to the wrap-up continuation at the cutoff. A
+    /// stream cut in the middle of tool calls is ended instead, and the wrap-up is itself ended
+    /// at the deadline.
+    pub(crate) fn wrap(
+        self,
+        sdk: LlmSdk,
+        req: ChatCompletionRequest,
+        stream: ChatCompletionStream,
+    ) -> ChatCompletionStream {
+        let state = State::Main(Box::new(MainState {
+            sdk,
+            req,
+            stream,
+            text: String::new(),
+            tool_calls: false,
+        }));
+        let inner = stream::unfold(state, move |state| {
+            let this = self.clone();
+            async move { this.next(state).await }
+        })
+        .boxed();
+        ChatCompletionStream::from_chunks(inner)
+    }
+
+    async fn next(&self, mut state: State) -> Option<(Result<ChatCompletionChunk>, State)> {
+        loop {
+            state = match state {
+                State::Main(mut main) => {
+                    match timeout_at(self.cutoff(), main.stream.next()).await {
+                        Ok(Some(Ok(chunk))) => {
+                            if let Some(choice) = chunk.choices.first() {
+                                main.text
+                                    .push_str(choice.delta.content.as_deref().unwrap_or_default());
+                                main.tool_calls |= !choice.delta.tool_calls.is_empty();
+                            }
+                            return Some((Ok(chunk), State::Main(main)));
+                        }
+                        Ok(Some(Err(e))) => return Some((Err(e), State::Done)),
+                        Ok(None) => return None,
+                        Err(_) if main.tool_calls || main.text.is_empty() => return None,
+                        Err(_) => {
+                            info!("stream is about to miss its deadline, wrapping up");
+                            let MainState { sdk, req, text, .. } = *main;
+                            let req = self.wrap_up_request(req, text);
+                            match sdk.chat_completion_stream(req).await {
+                                Ok(stream) => State::WrapUp(stream),
+                                Err(e) => return Some((Err(e), State::Done)),
+                            }
+                        }
+                    }
+                }
+                State::WrapUp(mut stream) => {
+                    return match timeout_at(self.deadline, stream.next()).await {
+                        Ok(Some(Ok(chunk))) => Some((Ok(chunk), State::WrapUp(stream))),
+                        Ok(Some(Err(e))) => Some((Err(e), State::Done)),
+                        Ok(None) | Err(_) => None,
+                    }
+                }
+                State::Done => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChatCompleteModel;
+
+    #[test]
+    fn wrap_up_request_should_continue_the_answer() -> Result<()> {
+        let req = ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt4o,
+            vec![ChatCompletionMessage::new_user("Tell me about Rust.", "")],
+        );
+        let req = StreamDeadline::new(Duration::from_secs(10))
+            .max_tokens(30)
+            .wrap_up_request(req, "Rust is a systems language".into());
+        let value = serde_json::to_value(&req)?;
+        assert_eq!(value["messages"][1]["role"], "assistant");
+        assert_eq!(
+            value["messages"][1]["content"],
+            "Rust is a systems language"
+        );
+        assert_eq!(value["messages"][2]["content"], WRAP_UP_PROMPT);
+        assert_eq!(value["max_tokens"], 30);
+        Ok(())
+    }
+}
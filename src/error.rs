@@ -0,0 +1,107 @@
+//! The errors of the SDK.
+
+use crate::{Spend, SpendLimit};
+use derive_builder::UninitializedFieldError;
+use thiserror::Error;
+
+/// The error of the builders, e.g. `ChatCompletionRequestBuilder::build`.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum BuildError {
+    /// A required field was not set.
+    #[error("{0} must be initialized")]
+    UninitializedField(&'static str),
+    /// The fields are not valid, e.g. an option not supported by the selected model.
+    #[error("{0}")]
+    Validation(String),
+}
+
+/// The errors of the SDK calls that callers may want to handle, returned inside the
+/// `anyhow::Error`: use `err.downcast_ref::<LlmError>()`.
+#[derive(Debug, Clone, PartialEq, Error)]
+#[non_exhaustive]
+pub enum LlmError {
+    /// A spend limit of the `UsageTracker` is reached, the request was not sent.
+    #[error("spend limit of {limit} exceeded, {spent} spent")]
+    BudgetExceeded { limit: SpendLimit, spent: Spend },
+    /// The request body is larger than `LlmSdkBuilder::max_body_size` (the `limit`), or the
+    /// server rejected it with a 413 (the `size` and `limit` are unknown).
+    #[error("{}", payload_too_large(.size, .limit))]
+    PayloadTooLarge {
+        size: Option<usize>,
+        limit: Option<usize>,
+    },
+    /// The call was aborted by its `CancellationToken`, see `CancelExt::cancel_on`.
+    #[error("the request was cancelled")]
+    Cancelled,
+    /// The server answered with a 404, e.g. for a deleted file. The body of the response.
+    #[error("API failed: {0}")]
+    NotFound(String),
+}
+
+fn payload_too_large(size: &Option<usize>, limit: &Option<usize>) -> String {
+    match (size, limit) {
+        (Some(size), Some(limit)) => format!(
+            "request payload of {} bytes exceeds the limit of {} bytes",
+            size, limit
+        ),
+        _ => "request payload too large".to_string(),
+    }
+}
+
+impl From<UninitializedFieldError> for BuildError {
+    fn from(e: UninitializedFieldError) -> Self {
+        Self::UninitializedField(e.field_name())
+    }
+}
+
+impl From<String> for BuildError {
+    fn from(e: String) -> Self {
+        Self::Validation(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BuildError, ChatCompletionRequestBuilder, CreateImageRequest, LlmError};
+
+    #[test]
+    fn build_error_should_be_structured() {
+        let err = ChatCompletionRequestBuilder::default().build().unwrap_err();
+        assert_eq!(err, BuildError::UninitializedField("messages"));
+        assert_eq!(err.to_string(), "messages must be initialized");
+
+        let err = CreateImageRequest::new("a".repeat(4001)).unwrap_err();
+        assert!(matches!(err, BuildError::Validation(_)));
+        let err: Box<dyn std::error::Error + Send + Sync> = Box::new(err);
+        assert_eq!(
+            err.to_string(),
+            "prompt must be at most 4000 characters for dall-e-3"
+        );
+    }
+
+    #[test]
+    fn payload_too_large_should_display_sizes() {
+        let err = LlmError::PayloadTooLarge {
+            size: Some(2048),
+            limit: Some(1024),
+        };
+        assert_eq!(
+            err.to_string(),
+            "request payload of 2048 bytes exceeds the limit of 1024 bytes"
+        );
+        let err = LlmError::PayloadTooLarge {
+            size: None,
+            limit: None,
+        };
+        assert_eq!(err.to_string(), "request payload too large");
+    }
+
+    #[test]
+    fn not_found_should_keep_api_message() {
+        let err = LlmError::NotFound(r#"{"error":"No such File object"}"#.into());
+        assert_eq!(
+            err.to_string(),
+            r#"API failed: {"error":"No such File object"}"#
+        );
+    }
+}
@@ -0,0 +1,164 @@
+//! A/B experiments on chat completions, e.g. 90% of the users on gpt-4o-mini and 10% on gpt-4o,
+//! or two system prompts. Users are bucketed deterministically by a key (e.g. the user id), so
+//! the same user always gets the same arm. `LlmSdk::chat_completion_experiment` runs the request
+//! in a tracing span tagged with the experiment and the arm, and emits an event with the token
+//! usage, so the arms can be compared in the telemetry.
+
+use crate::{
+    ChatCompleteModel, ChatCompletionMessage, ChatCompletionRequest, ChatCompletionResponse,
+};
+
+#[derive(Debug, Clone)]
+pub struct Experiment {
+    name: String,
+    arms: Vec<ExperimentArm>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExperimentArm {
+    pub name: String,
+    /// The relative weight of the arm, e.g. 90 and 10 for a 90/10 split.
+    pub weight: u32,
+    /// Overrides the model of the request.
+    pub model: Option<ChatCompleteModel>,
+    /// Replaces the first system message of the request, or is inserted as the first message.
+    pub system_prompt: Option<String>,
+}
+
+/// The response of `LlmSdk::chat_completion_experiment`.
+#[derive(Debug, Clone)]
+pub struct ExperimentResponse {
+    pub experiment: String,
+    /// The name of the arm the request was assigned to.
+    pub arm: String,
+    pub response: ChatCompletionResponse,
+}
+
+impl Experiment {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            arms: Vec::new(),
+        }
+    }
+
+    /// Add an arm. Note that changing the arms (or their weights) re-buckets the users.
+    pub fn arm(mut self, arm: ExperimentArm) -> Self {
+        self.arms.push(arm);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The arm of `key`, `None` if there is no arm with a weight.
+    pub fn assign(&self, key: &str) -> Option<&ExperimentArm> {
+        let total: u64 = self.arms.iter().map(|arm| arm.weight as u64).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut bucket = fnv1a(format!("{}:{}", self.name, key).as_bytes()) % total;
+        self.arms.iter().find(|arm| {
+            if bucket < arm.weight as u64 {
+                true
+            } else {
+                bucket -= arm.weight as u64;
+                false
+            }
+        })
+    }
+}
+
+impl ExperimentArm {
+    pub fn new(name: impl Into<String>, weight: u32) -> Self {
+        Self {
+            name: name.into(),
+            weight,
+            model: None,
+            system_prompt: None,
+        }
+    }
+
+    pub fn with_model(mut self, model: ChatCompleteModel) -> Self {
+        self.model = Some(model);
+        self
+    }
+
+    pub fn with_system_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.system_prompt = Some(prompt.into());
+        self
+    }
+
+    /// Apply the overrides of the arm to the request.
+    pub fn apply(&self, req: &mut ChatCompletionRequest) {
+        if let Some(model) = &self.model {
+            req.model = model.clone();
+        }
+        if let Some(prompt) = &self.system_prompt {
+            let system = req.messages.iter_mut().find_map(|message| match message {
+                ChatCompletionMessage::System(m) => Some(m),
+                _ => None,
+            });
+            match system {
+                Some(m) => m.content = prompt.clone(),
+                None => req
+                    .messages
+                    .insert(0, ChatCompletionMessage::new_system(prompt.clone(), "")),
+            }
+        }
+    }
+}
+
+/// A stable hash, unlike the std hashers whose output may change between releases.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn experiment() -> Experiment {
+        Experiment::new("model-rollout")
+            .arm(
+                ExperimentArm::new("mini", 90)
+                    .with_model(ChatCompleteModel::Other("gpt-4o-mini".into())),
+            )
+            .arm(
+                ExperimentArm::new("full", 10)
+                    .with_model(ChatCompleteModel::Gpt4o)
+                    .with_system_prompt("Be concise."),
+            )
+    }
+
+    #[test]
+    fn experiment_should_bucket_deterministically() {
+        let experiment = experiment();
+        let counts = (0..1000).fold([0; 2], |mut counts, i| {
+            let key = format!("user-{}", i);
+            let arm = experiment.assign(&key).unwrap();
+            assert_eq!(experiment.assign(&key), Some(arm));
+            counts[(arm.name == "full") as usize] += 1;
+            counts
+        });
+        assert!(counts[1] > 50 && counts[1] < 150, "{:?}", counts);
+        assert!(Experiment::new("empty").assign("user-1").is_none());
+    }
+
+    #[test]
+    fn experiment_arm_should_apply_overrides() {
+        let mut req = ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt3Turbo,
+            vec![ChatCompletionMessage::new_user("Hi", "")],
+        );
+        experiment().arms[1].apply(&mut req);
+        assert_eq!(req.model, ChatCompleteModel::Gpt4o);
+        let value = serde_json::to_value(&req).unwrap();
+        assert_eq!(value["messages"][0]["role"], "system");
+        assert_eq!(value["messages"][0]["content"], "Be concise.");
+        assert_eq!(value["messages"][1]["content"], "Hi");
+    }
+}
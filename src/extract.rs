@@ -0,0 +1,171 @@
+//! Typed extraction that works across providers. Structured Outputs are used when the provider
+//! supports them; otherwise the model is forced to call a tool whose parameters are the schema
+//! of the type, or, as the last resort, the schema is given in the instructions and the reply is
+//! validated (and repaired once).
+
+use crate::{
+    ChatCompletionMessage, ChatCompletionRequest, ChatCompletionResponse, Provider, ResponseFormat,
+    ToSchema, Tool, ToolChoice,
+};
+use anyhow::{anyhow, Result};
+use serde::de::DeserializeOwned;
+
+/// The name of the tool of the `ToolCall` strategy.
+const EXTRACT_TOOL: &str = "extract";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtractStrategy {
+    /// The `json_schema` response format.
+    StructuredOutputs,
+    /// A forced call of a tool whose parameters are the schema of the type.
+    ToolCall,
+    /// The schema in the instructions, and a validation of the reply.
+    Instructions,
+}
+
+/// The result of `LlmSdk::extract`.
+#[derive(Debug, Clone)]
+pub struct Extraction<T> {
+    pub value: T,
+    /// How the value was obtained.
+    pub strategy: ExtractStrategy,
+}
+
+impl ExtractStrategy {
+    /// The most reliable strategy the provider supports.
+    pub fn for_provider(provider: &dyn Provider) -> Self {
+        if provider.supports_response_format() {
+            Self::StructuredOutputs
+        } else if provider.supports_tools() {
+            Self::ToolCall
+        } else {
+            Self::Instructions
+        }
+    }
+
+    pub(crate) fn prepare<T: ToSchema>(&self, req: &mut ChatCompletionRequest) {
+        match self {
+            Self::StructuredOutputs => {
+                req.response_format = Some(ResponseFormat::json_schema::<T>());
+            }
+            Self::ToolCall => {
+                req.tools.push(Tool::new_function::<T>(
+                    EXTRACT_TOOL,
+                    "Record the data extracted from the conversation.",
+                ));
+                req.tool_choice = Some(ToolChoice::Function {
+                    name: EXTRACT_TOOL.into(),
+                });
+            }
+            Self::Instructions => {
+                let prompt = format!(
+                    "Reply with a JSON value only, without any other text, matching this JSON \
+                     schema:\n{}",
+                    T::to_schema()
+                );
+                req.messages
+                    .push(ChatCompletionMessage::new_system(prompt, ""));
+            }
+        }
+    }
+
+    pub(crate) fn parse<T: DeserializeOwned>(&self, res: &ChatCompletionResponse) -> Result<T> {
+        match self {
+            Self::StructuredOutputs | Self::Instructions => res.parse_content(),
+            Self::ToolCall => {
+                let call = res
+                    .choices
+                    .iter()
+                    .flat_map(|choice| &choice.message.tool_calls)
+                    .find(|call| call.function.name == EXTRACT_TOOL)
+                    .ok_or_else(|| anyhow!("the model did not call the {} tool", EXTRACT_TOOL))?;
+                Ok(serde_json::from_str(&call.function.arguments)?)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Anthropic, ChatCompleteModel, OpenAi};
+    use schemars::JsonSchema;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, PartialEq, Deserialize, JsonSchema)]
+    struct Person {
+        name: String,
+        age: u8,
+    }
+
+    fn request() -> ChatCompletionRequest {
+        ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt4o,
+            vec![ChatCompletionMessage::new_user("Tom is 42.", "")],
+        )
+    }
+
+    #[test]
+    fn extract_strategy_should_follow_provider_support() {
+        assert_eq!(
+            ExtractStrategy::for_provider(&OpenAi),
+            ExtractStrategy::StructuredOutputs
+        );
+        assert_eq!(
+            ExtractStrategy::for_provider(&Anthropic),
+            ExtractStrategy::ToolCall
+        );
+    }
+
+    #[test]
+    fn tool_call_strategy_should_force_the_extract_tool() -> Result<()> {
+        let mut req = request();
+        ExtractStrategy::ToolCall.prepare::<Person>(&mut req);
+        let value = serde_json::to_value(&req)?;
+        assert_eq!(value["tools"][0]["function"]["name"], "extract");
+        assert_eq!(value["tool_choice"]["function"]["name"], "extract");
+
+        let res: ChatCompletionResponse = serde_json::from_value(json!({
+          "id": "chatcmpl-1",
+          "object": "chat.completion",
+          "created": 1715000000,
+          "model": "gpt-4o",
+          "choices": [{
+            "index": 0,
+            "message": {
+              "role": "assistant",
+              "tool_calls": [{
+                "id": "call_1",
+                "type": "function",
+                "function": { "name": "extract", "arguments": "{\"name\":\"Tom\",\"age\":42}" }
+              }]
+            },
+            "finish_reason": "tool_calls"
+          }]
+        }))?;
+        let person: Person = ExtractStrategy::ToolCall.parse(&res)?;
+        assert_eq!(
+            person,
+            Person {
+                name: "Tom".into(),
+                age: 42
+            }
+        );
+        assert!(ExtractStrategy::StructuredOutputs
+            .parse::<Person>(&res)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn instructions_strategy_should_add_the_schema() -> Result<()> {
+        let mut req = request();
+        ExtractStrategy::Instructions.prepare::<Person>(&mut req);
+        let value = serde_json::to_value(&req)?;
+        assert!(value.get("response_format").is_none());
+        let prompt = value["messages"][1]["content"].as_str().unwrap();
+        assert!(prompt.contains("\"age\""));
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+//! Jitter buffer for streamed PCM audio. Audio deltas arrive in bursts of arbitrary size, while
+//! playback devices want fixed-size frames at a steady cadence. `JitterBuffer` re-slices the
+//! deltas into frames and `paced` emits them on a timer, filling underruns with silence.
+
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::{stream, FutureExt, Stream, StreamExt};
+use std::{collections::VecDeque, time::Duration};
+use tokio::time::{self, Instant};
+
+/// The format of 16-bit little-endian PCM audio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PcmFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+/// Re-slices PCM audio into fixed-size frames. Playback starts after `prebuffer` frames are
+/// buffered, which absorbs the jitter of the network.
+#[derive(Debug, Clone)]
+pub struct JitterBuffer {
+    frame_bytes: usize,
+    prebuffer: usize,
+    started: bool,
+    buf: VecDeque<u8>,
+}
+
+impl PcmFormat {
+    /// 24kHz mono, the `pcm16` format of the realtime API.
+    pub const PCM16_24K: Self = Self {
+        sample_rate: 24_000,
+        channels: 1,
+    };
+
+    /// The size in bytes of a frame of the given duration.
+    pub fn frame_bytes(&self, duration: Duration) -> usize {
+        let samples = self.sample_rate as u128 * duration.as_micros() / 1_000_000;
+        samples as usize * self.channels as usize * 2
+    }
+}
+
+impl JitterBuffer {
+    pub fn new(format: PcmFormat, frame_duration: Duration, prebuffer: usize) -> Self {
+        Self {
+            frame_bytes: format.frame_bytes(frame_duration).max(2),
+            prebuffer,
+            started: false,
+            buf: VecDeque::new(),
+        }
+    }
+
+    pub fn frame_bytes(&self) -> usize {
+        self.frame_bytes
+    }
+
+    /// Number of complete frames buffered.
+    pub fn frames(&self) -> usize {
+        self.buf.len() / self.frame_bytes
+    }
+
+    pub fn push(&mut self, pcm: &[u8]) {
+        self.buf.extend(pcm);
+    }
+
+    /// Push a base64 encoded delta, e.g. the `delta` of a `response.audio.delta` event.
+    pub fn push_base64(&mut self, delta: &str) -> Result<()> {
+        self.push(&STANDARD.decode(delta)?);
+        Ok(())
+    }
+
+    /// The next frame, `None` while prebuffering or if there's not a whole frame. After an
+    /// underrun, playback waits for the prebuffer to fill again.
+    pub fn pop_frame(&mut self) -> Option<Vec<u8>> {
+        if !self.started && self.frames() < self.prebuffer.max(1) {
+            return None;
+        }
+        if self.frames() == 0 {
+            self.started = false;
+            return None;
+        }
+        self.started = true;
+        Some(self.buf.drain(..self.frame_bytes).collect())
+    }
+
+    /// The remaining audio as a frame padded with silence, `None` if the buffer is empty. Use it
+    /// at the end of the stream, after `pop_frame` returns `None`.
+    pub fn flush(&mut self) -> Option<Vec<u8>> {
+        if self.buf.is_empty() {
+            return None;
+        }
+        let len = self.buf.len().min(self.frame_bytes);
+        let mut frame: Vec<u8> = self.buf.drain(..len).collect();
+        frame.resize(self.frame_bytes, 0);
+        Some(frame)
+    }
+
+    fn silence(&self) -> Vec<u8> {
+        vec![0; self.frame_bytes]
+    }
+}
+
+/// Emit the audio of `deltas` as fixed-size frames, one every `frame_duration`. Underruns are
+/// filled with silent frames so the cadence never breaks; after the input ends the remaining
+/// audio is flushed and the stream ends.
+pub fn paced(
+    deltas: impl Stream<Item = Vec<u8>> + Send + Unpin + 'static,
+    format: PcmFormat,
+    frame_duration: Duration,
+    prebuffer: usize,
+) -> impl Stream<Item = Vec<u8>> + Send {
+    let buffer = JitterBuffer::new(format, frame_duration, prebuffer);
+    let state = (deltas, buffer, Instant::now(), false);
+    stream::unfold(
+        state,
+        move |(mut deltas, mut buffer, next, mut ended)| async move {
+            time::sleep_until(next).await;
+            // take whatever arrived since the last tick without waiting for more
+            while !ended {
+                match deltas.next().now_or_never() {
+                    Some(Some(delta)) => buffer.push(&delta),
+                    Some(None) => ended = true,
+                    None => break,
+                }
+            }
+            let frame = match buffer.pop_frame() {
+                Some(frame) => frame,
+                None if ended => buffer.flush()?,
+                None => buffer.silence(),
+            };
+            Some((frame, (deltas, buffer, next + frame_duration, ended)))
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jitter_buffer_should_slice_frames() {
+        // 10ms of 24kHz mono pcm16 is 480 bytes
+        let mut buffer = JitterBuffer::new(PcmFormat::PCM16_24K, Duration::from_millis(10), 2);
+        assert_eq!(buffer.frame_bytes(), 480);
+        buffer.push(&[1; 700]);
+        assert_eq!(buffer.pop_frame(), None);
+        buffer.push(&[2; 300]);
+        assert_eq!(buffer.pop_frame(), Some(vec![1; 480]));
+        let frame = buffer.pop_frame().unwrap();
+        assert_eq!(&frame[..220], &[1; 220]);
+        assert_eq!(&frame[220..], &[2; 260]);
+        assert_eq!(buffer.pop_frame(), None);
+        let frame = buffer.flush().unwrap();
+        assert_eq!(&frame[..40], &[2; 40]);
+        assert_eq!(&frame[40..], &[0; 440]);
+        assert_eq!(buffer.flush(), None);
+    }
+
+    #[tokio::test]
+    async fn paced_should_emit_fixed_frames() {
+        let deltas = stream::iter(vec![vec![1; 100], vec![2; 1000]]);
+        let frames: Vec<_> = paced(deltas, PcmFormat::PCM16_24K, Duration::from_millis(10), 1)
+            .collect()
+            .await;
+        assert_eq!(frames.len(), 3);
+        assert!(frames.iter().all(|f| f.len() == 480));
+        assert_eq!(&frames[2][..140], &[2; 140]);
+        assert_eq!(&frames[2][140..], &[0; 340]);
+    }
+}
@@ -0,0 +1,166 @@
+//! LLM-as-judge: score answers (or recorded conversations) against criteria with a judging
+//! prompt and Structured Outputs, e.g. to compare the arms of an `Experiment` or the responses
+//! of `ShadowTraffic`.
+
+use crate::{ChatCompleteModel, ChatCompletionMessage, ChatCompletionRequest, LlmSdk};
+use anyhow::Result;
+use futures::{stream, StreamExt};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+const JUDGE_PROMPT: &str = "You are an impartial judge. Score the answer against each of the \
+criteria from 1 (very poor) to 5 (excellent), with a short rationale for each score. Judge only \
+what is asked by the criteria.";
+
+/// The model and instructions of the judge.
+#[derive(Debug, Clone)]
+pub struct Judge {
+    pub model: ChatCompleteModel,
+    /// The system prompt of the judge.
+    pub instructions: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, JsonSchema)]
+pub struct Evaluation {
+    /// One score per criterion, in the order of the criteria.
+    pub scores: Vec<CriterionScore>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, JsonSchema)]
+pub struct CriterionScore {
+    /// The criterion as given to the judge.
+    pub criterion: String,
+    /// The score from 1 (very poor) to 5 (excellent).
+    pub score: u8,
+    /// Why the answer got this score.
+    pub rationale: String,
+}
+
+impl Default for Judge {
+    fn default() -> Self {
+        Self {
+            model: ChatCompleteModel::Gpt4o,
+            instructions: JUDGE_PROMPT.to_string(),
+        }
+    }
+}
+
+impl Judge {
+    pub fn new(model: ChatCompleteModel) -> Self {
+        Self {
+            model,
+            ..Default::default()
+        }
+    }
+
+    /// The judging request for `answer`.
+    pub fn request(&self, answer: &str, criteria: &[&str]) -> ChatCompletionRequest {
+        let criteria: String = criteria
+            .iter()
+            .enumerate()
+            .map(|(i, c)| format!("{}. {}\n", i + 1, c))
+            .collect();
+        let prompt = format!(
+            "Criteria:\n{}\nAnswer:\n<answer>\n{}\n</answer>",
+            criteria, answer
+        );
+        ChatCompletionRequest::new(
+            self.model.clone(),
+            vec![
+                ChatCompletionMessage::new_system(self.instructions.clone(), ""),
+                ChatCompletionMessage::new_user(prompt, ""),
+            ],
+        )
+    }
+
+    pub async fn evaluate(
+        &self,
+        sdk: &LlmSdk,
+        answer: &str,
+        criteria: &[&str],
+    ) -> Result<Evaluation> {
+        sdk.chat_completion_structured(self.request(answer, criteria))
+            .await
+    }
+
+    /// Evaluate recorded conversations, with at most `concurrency` requests in flight. The
+    /// results are in the order of the transcripts.
+    pub async fn evaluate_transcripts(
+        &self,
+        sdk: &LlmSdk,
+        transcripts: &[Vec<ChatCompletionMessage>],
+        criteria: &[&str],
+        concurrency: usize,
+    ) -> Vec<Result<Evaluation>> {
+        stream::iter(transcripts)
+            .map(|transcript| async move {
+                let answer = render_transcript(transcript)?;
+                self.evaluate(sdk, &answer, criteria).await
+            })
+            .buffered(concurrency.max(1))
+            .collect()
+            .await
+    }
+}
+
+impl Evaluation {
+    /// The mean score over the criteria, 0 if there is none.
+    pub fn mean(&self) -> f32 {
+        if self.scores.is_empty() {
+            return 0.0;
+        }
+        self.scores.iter().map(|s| s.score as f32).sum::<f32>() / self.scores.len() as f32
+    }
+}
+
+/// Render a conversation as `role: content` lines for the judge.
+fn render_transcript(messages: &[ChatCompletionMessage]) -> Result<String> {
+    let mut ret = String::new();
+    for message in serde_json::to_value(messages)?
+        .as_array()
+        .into_iter()
+        .flatten()
+    {
+        let role = message["role"].as_str().unwrap_or_default();
+        let content = match &message["content"] {
+            serde_json::Value::String(s) => s.clone(),
+            serde_json::Value::Null => message["tool_calls"].to_string(),
+            other => other.to_string(),
+        };
+        ret.push_str(&format!("{}: {}\n", role, content));
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SDK;
+
+    #[test]
+    fn render_transcript_should_work() -> Result<()> {
+        let messages = vec![
+            ChatCompletionMessage::new_system("You are a helpful bot.", ""),
+            ChatCompletionMessage::new_user("What's 1 + 1?", ""),
+        ];
+        assert_eq!(
+            render_transcript(&messages)?,
+            "system: You are a helpful bot.\nuser: What's 1 + 1?\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn evaluate_should_work() -> Result<()> {
+        let evaluation = Judge::default()
+            .evaluate(
+                &SDK,
+                "The capital of France is Paris.",
+                &["factual accuracy", "conciseness"],
+            )
+            .await?;
+        assert_eq!(evaluation.scores.len(), 2);
+        assert!(evaluation.mean() >= 4.0);
+        Ok(())
+    }
+}
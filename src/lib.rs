@@ -1,37 +1,205 @@
+mod anonymize;
 mod api;
+mod backfill;
+mod cache;
+mod cancel;
+#[cfg(feature = "async-openai-compat")]
+mod compat;
+mod dataset;
+mod deadline;
+mod error;
+mod experiment;
+mod extract;
+mod jitter;
+mod judge;
 mod middleware;
+mod models;
+mod postprocess;
+mod preflight;
+mod provider;
+mod rank;
+mod rate_limit;
+#[cfg(feature = "realtime")]
+mod realtime;
+mod session;
+mod shadow;
+mod similarity;
+mod sse;
+mod template;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "tokenizer")]
+mod tokenizer;
+mod tool_memo;
+mod tool_registry;
+mod truncation;
+mod usage;
 
+pub use anonymize::*;
 pub use api::*;
+pub use backfill::{Backfill, BackfillProgress};
+pub use cache::ResponseCache;
+pub use cancel::{CancelExt, Cancellable, CancellationToken};
+pub use dataset::*;
+pub use deadline::StreamDeadline;
+pub use error::{BuildError, LlmError};
+pub use experiment::*;
+pub use extract::{ExtractStrategy, Extraction};
+pub use jitter::*;
+pub use judge::*;
+pub use middleware::{BackoffJitter, MiddlewareLayer, PostRetry, RetryConfig};
+pub use models::{model_info, Deprecation, ModelInfo};
+pub use postprocess::{Locale, PostProcess};
+pub use preflight::{Preflight, PreflightEstimate, PreflightReport};
+pub use provider::{
+    Anthropic, AsyncProvider, DynAsyncProvider, GroqTiming, OpenAi, ParamProfile, Provider,
+    ProviderExt,
+};
+pub use rank::{Ranking, ScoredChoice, Scorer};
+pub use rate_limit::{RateLimitThrottle, RateLimits, ResponseMeta, WithMeta};
+#[cfg(feature = "realtime")]
+pub use realtime::*;
+pub use session::{Branch, ChatSession, ChatSessionSnapshot};
+#[cfg(feature = "session-codec")]
+pub use session::{ChaChaCipher, SnapshotCipher, SnapshotCodec};
+pub use shadow::ShadowTraffic;
+pub use similarity::*;
+pub use template::ChatTemplate;
+#[cfg(feature = "tokenizer")]
+pub use tokenizer::{count_chat_tokens, count_tokens, Tiktoken};
+pub use tool_memo::*;
+pub use tool_registry::{LlmTool, ToolFuture, ToolRegistry, ToolRun};
+pub use truncation::{EstimateTokenizer, Tokenizer, Truncation, TruncationStrategy};
+pub use usage::{ModelPrice, ModelUsage, PricingTable, Spend, SpendLimit, UsageTracker};
+
+#[cfg(feature = "macros")]
+pub use llm_sdk_macros::llm_tool;
+
+// lets the code generated by `#[llm_tool]` refer to `::llm_sdk` within this crate too
+extern crate self as llm_sdk;
 
 use anyhow::{anyhow, Result};
+use api::{new_idempotency_key, smaller_chunks, split_input, stitch, SPEECH_MAX_INPUT};
 use bytes::Bytes;
+use cache::CacheLookup;
 use derive_builder::Builder;
-use middleware::RetryMiddleware;
-use reqwest::Response;
+use futures::{stream::BoxStream, StreamExt, TryStreamExt};
+use middleware::{AzureDeploymentMiddleware, BodyLimitMiddleware};
+use models::warn_if_deprecated;
+use provider::ProviderRequest;
+use rate_limit::RateLimitState;
+use reqwest::Response as HttpResponse;
 use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, RequestBuilder};
-use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
-use reqwest_tracing::TracingMiddleware;
 use schemars::{schema_for, JsonSchema};
-use std::time::Duration;
-use tracing::error;
+use serde::de::DeserializeOwned;
+use sse::{CancelOnDrop, HEARTBEAT_EVENTS};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::{error, info, info_span, warn, Instrument};
 
 const TIMEOUT: u64 = 60;
 const MAX_RETRIES: u32 = 3;
+/// Roughly the first 30 seconds of a 128kbps mp3, enough to detect the spoken language.
+const LANGUAGE_DETECTION_BYTES: usize = 480 * 1024;
+/// Number of chunks of `speech_long` generated at the same time.
+const SPEECH_CONCURRENCY: usize = 4;
 
 #[derive(Debug, Clone, Builder)]
+#[builder(build_fn(error = "BuildError"))]
 pub struct LlmSdk {
     #[builder(setter(into), default = r#""https://api.openai.com/v1".into()"#)]
     pub(crate) base_url: String,
     #[builder(setter(into))]
     pub(crate) token: String,
+    /// How the token is sent to the server.
+    #[builder(default)]
+    pub(crate) auth: AuthMethod,
+    /// Sent as the `api-version` query parameter of every request, required by Azure OpenAI.
+    #[builder(default, setter(strip_option, into))]
+    pub(crate) api_version: Option<String>,
+    /// Azure OpenAI deployment name. If set, the per-deployment endpoints (chat completions,
+    /// embeddings, images, audio) are sent to `{base_url}/deployments/{deployment}/...`.
+    #[allow(dead_code)]
+    #[builder(default, setter(strip_option, into))]
+    pub(crate) deployment: Option<String>,
+    /// The wire format of the chat completion API, OpenAI by default.
+    #[builder(default = "Arc::new(OpenAi)")]
+    pub(crate) provider: Arc<dyn Provider>,
+    /// If set, the chat completions are sent to it instead of `provider`. The streams aren't
+    /// supported.
+    #[builder(default, setter(strip_option))]
+    pub(crate) async_provider: Option<Arc<dyn DynAsyncProvider>>,
+    /// Overrides the parameter ranges of the provider, e.g. `ParamProfile::gemini()` for Gemini
+    /// through the OpenAI compatible endpoint.
+    #[builder(default, setter(strip_option))]
+    pub(crate) param_profile: Option<ParamProfile>,
+    /// Prepended to the system prompt of every chat completion, e.g. an organization-wide policy.
+    /// It's sent like any system message, so in the `system` field for Anthropic.
+    #[builder(default, setter(strip_option, into))]
+    pub(crate) system_prompt: Option<String>,
     #[allow(dead_code)]
     #[builder(default = "3")]
     pub(crate) max_retries: u32,
+    /// The backoff, deadline and retried statuses of the `Retry` middleware.
+    #[allow(dead_code)]
+    #[builder(default)]
+    pub(crate) retry: RetryConfig,
+    /// Requests with a larger body (in bytes) fail with `LlmError::PayloadTooLarge` before they
+    /// are sent. Streamed uploads are not checked.
+    #[allow(dead_code)]
+    #[builder(default, setter(strip_option))]
+    pub(crate) max_body_size: Option<usize>,
+    /// The HTTP middleware stack, outermost first. Tracing then retry by default.
+    #[allow(dead_code)]
+    #[builder(default = "MiddlewareLayer::default_stack()")]
+    pub(crate) middlewares: Vec<MiddlewareLayer>,
+    /// The timeout of the requests, except the streams. Overridden per request with the
+    /// `timeout` of the request builders.
+    #[builder(default = "Duration::from_secs(TIMEOUT)")]
+    pub(crate) timeout: Duration,
+    /// Abort a stream if no bytes at all (including keep-alive comments) arrive within this duration.
+    #[builder(default = "Duration::from_secs(TIMEOUT)")]
+    pub(crate) stream_idle_timeout: Duration,
+    /// SSE event names that are treated as heartbeats and silently dropped from streams.
+    #[builder(default = "HEARTBEAT_EVENTS.iter().map(|s| s.to_string()).collect()")]
+    pub(crate) heartbeat_events: Vec<String>,
+    /// If set, successful chat completions are logged as fine-tuning data.
+    #[builder(default, setter(strip_option))]
+    pub(crate) dataset_logger: Option<Arc<DatasetLogger>>,
+    /// If set, a sampled fraction of the chat completions is mirrored to a secondary SDK.
+    #[builder(default, setter(strip_option))]
+    pub(crate) shadow: Option<Arc<ShadowTraffic>>,
+    /// If set, the token usage and cost of the calls are tracked, and the calls fail once its
+    /// budget is spent. The streamed chats and completions then ask for their usage
+    /// (`stream_options.include_usage`), sent in a last chunk without choices.
+    #[builder(default, setter(strip_option))]
+    pub(crate) usage_tracker: Option<Arc<UsageTracker>>,
+    /// The last rate limits reported by the API, see `LlmSdk::rate_limits`.
+    #[builder(setter(skip))]
+    pub(crate) rate_limits: Arc<RateLimitState>,
     #[builder(setter(skip), default = "self.default_client()")]
     pub(crate) client: ClientWithMiddleware,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AuthMethod {
+    /// `Authorization: Bearer {token}`, used by OpenAI and most compatible servers.
+    #[default]
+    Bearer,
+    /// `api-key: {token}`, used by Azure OpenAI.
+    ApiKey,
+    /// `x-api-key: {token}`, used by Anthropic.
+    XApiKey,
+}
+
 pub trait IntoRequest {
+    /// The timeout of this request, `None` for the timeout of the SDK.
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+
     fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder;
 }
 
@@ -45,14 +213,25 @@ pub trait ToSchema: JsonSchema {
 impl LlmSdkBuilder {
     // Private helper method with access to the builder struct.
     fn default_client(&self) -> ClientWithMiddleware {
-        let retry_policy = ExponentialBackoff::builder()
-            .build_with_max_retries(self.max_retries.unwrap_or(MAX_RETRIES));
-        let m = RetryTransientMiddleware::new_with_policy(retry_policy);
-        ClientBuilder::new(reqwest::Client::new())
-            // Trace HTTP requests. See the tracing crate to make use of these traces.
-            .with(TracingMiddleware::default())
-            // Retry failed requests.
-            .with(RetryMiddleware::from(m))
+        let max_retries = self.max_retries.unwrap_or(MAX_RETRIES);
+        let retry = self.retry.clone().unwrap_or_default();
+        let mut builder = ClientBuilder::new(reqwest::Client::new());
+        if let Some(Some(deployment)) = &self.deployment {
+            let base_url = self.base_url.as_deref().unwrap_or_default();
+            // Rewrite the urls first so that the traces show the real ones.
+            builder = builder.with(AzureDeploymentMiddleware::new(base_url, deployment));
+        }
+        if let Some(Some(max)) = self.max_body_size {
+            // before the retries, an oversized body is rejected once
+            builder = builder.with(BodyLimitMiddleware::new(max));
+        }
+        let default_stack = MiddlewareLayer::default_stack();
+        let layers = self.middlewares.as_ref().unwrap_or(&default_stack);
+        layers
+            .iter()
+            .fold(builder, |builder, layer| {
+                layer.apply(builder, max_retries, &retry)
+            })
             .build()
     }
 }
@@ -70,31 +249,680 @@ impl LlmSdk {
             .unwrap()
     }
 
+    /// Local OpenAI compatible server preset, e.g. `LlmSdk::ollama("http://localhost:11434/v1")`.
+    /// No auth header is sent, use `ChatCompleteModel::Other` for the model names.
+    pub fn ollama(base_url: impl Into<String>) -> Self {
+        LlmSdk::new_with_base_url("", base_url)
+    }
+
+    /// Azure OpenAI preset, `endpoint` is the resource endpoint, e.g.
+    /// `https://{resource}.openai.azure.com`.
+    pub fn new_azure(
+        endpoint: &str,
+        api_key: impl Into<String>,
+        deployment: impl Into<String>,
+        api_version: impl Into<String>,
+    ) -> Self {
+        LlmSdkBuilder::default()
+            .base_url(format!("{}/openai", endpoint.trim_end_matches('/')))
+            .token(api_key)
+            .auth(AuthMethod::ApiKey)
+            .deployment(deployment)
+            .api_version(api_version)
+            .build()
+            .unwrap()
+    }
+
+    /// Anthropic preset, chat completions are sent to the Messages API.
+    pub fn new_anthropic(api_key: impl Into<String>) -> Self {
+        LlmSdkBuilder::default()
+            .base_url("https://api.anthropic.com/v1")
+            .token(api_key)
+            .auth(AuthMethod::XApiKey)
+            .provider(Arc::new(Anthropic))
+            .build()
+            .unwrap()
+    }
+
     pub async fn chat_completion(
         &self,
         req: ChatCompletionRequest,
     ) -> Result<ChatCompletionResponse> {
+        Ok(self.chat_completion_with_meta(req).await?.data)
+    }
+
+    /// Same as `chat_completion`, with the request id and the rate limits of the response.
+    pub async fn chat_completion_with_meta(
+        &self,
+        req: ChatCompletionRequest,
+    ) -> Result<WithMeta<ChatCompletionResponse>> {
+        warn_if_deprecated(&req.model);
+        if let Some(shadow) = self.shadow.as_ref().filter(|shadow| shadow.sample()) {
+            shadow.mirror(req.clone());
+        }
+        let logged = self
+            .dataset_logger
+            .as_ref()
+            .map(|logger| (logger, req.clone()));
+        let post_process = req.post_process.clone();
+        let WithMeta {
+            data: mut res,
+            meta,
+        } = self.send_chat_completion_with_meta(req).await?;
+        if let Some(post_process) = post_process {
+            for choice in res.choices.iter_mut() {
+                if let Some(content) = choice.message.content.as_mut() {
+                    *content = post_process.apply(content);
+                }
+            }
+        }
+        if let Some((logger, req)) = logged {
+            if let Err(e) = logger.log(&req, &res) {
+                warn!("failed to log the completion to the dataset: {}", e);
+            }
+        }
+        Ok(WithMeta { data: res, meta })
+    }
+
+    /// Send the request through the provider, without the dataset logging and shadow traffic.
+    pub(crate) async fn send_chat_completion(
+        &self,
+        req: ChatCompletionRequest,
+    ) -> Result<ChatCompletionResponse> {
+        Ok(self.send_chat_completion_with_meta(req).await?.data)
+    }
+
+    async fn send_chat_completion_with_meta(
+        &self,
+        mut req: ChatCompletionRequest,
+    ) -> Result<WithMeta<ChatCompletionResponse>> {
+        self.clamp_params(&mut req);
+        self.add_system_prompt(&mut req);
+        self.check_budget()?;
+        let model = req.model.clone();
+        let idempotency_key = req
+            .idempotency_key
+            .get_or_insert_with(new_idempotency_key)
+            .clone();
+        let (res, meta) = match &self.async_provider {
+            Some(provider) => (
+                provider.chat_completion(req).await?,
+                ResponseMeta::default(),
+            ),
+            None => {
+                let req = self.prepare_request(ProviderRequest::new(self.provider.as_ref(), req));
+                let res = req.send_and_log().await?;
+                self.rate_limits.update(Instant::now(), res.headers());
+                let meta = ResponseMeta::from_headers(res.headers());
+                let res = self
+                    .provider
+                    .parse_chat_completion(model, &res.bytes().await?)?;
+                (res, meta)
+            }
+        };
+        let meta = ResponseMeta {
+            idempotency_key: Some(idempotency_key),
+            ..meta
+        };
+        if let Some(tracker) = &self.usage_tracker {
+            tracker.record(
+                &usage::model_name(&res.model),
+                "chat/completions",
+                res.usage.prompt_tokens,
+                res.usage.completion_tokens,
+            );
+        }
+        Ok(WithMeta { data: res, meta })
+    }
+
+    /// Estimate what `req` consumes, and whether the budget of the usage tracker and the rate
+    /// limits last reported by the API allow it.
+    pub fn preflight(&self, req: &impl Preflight) -> PreflightReport {
+        let default_pricing = PricingTable::default();
+        let pricing = self
+            .usage_tracker
+            .as_ref()
+            .map_or(&default_pricing, |tracker| tracker.pricing());
+        let estimate = req.estimate(pricing);
+        let (remaining_requests, remaining_tokens) = self.rate_limits.remaining(Instant::now());
+        let tokens = estimate.tokens as u64;
+        let rejection = if remaining_requests == Some(0) {
+            Some("no request left in the rate limit".to_string())
+        } else if let Some(remaining) = remaining_tokens.filter(|&remaining| remaining < tokens) {
+            Some(format!(
+                "needs {} tokens, {} remaining in the rate limit",
+                tokens, remaining
+            ))
+        } else {
+            self.usage_tracker.as_ref().and_then(|tracker| {
+                tracker
+                    .check_spend(estimate.tokens, estimate.cost)
+                    .err()
+                    .map(|e| e.to_string())
+            })
+        };
+        PreflightReport {
+            estimate,
+            rate_limit_requests: 1,
+            rate_limit_tokens: tokens,
+            remaining_requests,
+            remaining_tokens,
+            rejection,
+        }
+    }
+
+    /// The rate limits of the last response that reported them.
+    pub fn rate_limits(&self) -> Option<RateLimits> {
+        self.rate_limits.get().map(|(_, limits)| limits)
+    }
+
+    fn check_budget(&self) -> Result<()> {
+        match &self.usage_tracker {
+            Some(tracker) => tracker.check(),
+            None => Ok(()),
+        }
+    }
+
+    /// Record a call billed per unit, see `UsageTracker::record_cost`.
+    fn record_cost(&self, model: &str, endpoint: &'static str, cost: f64) {
+        if let Some(tracker) = &self.usage_tracker {
+            tracker.record_cost(model, endpoint, cost);
+        }
+    }
+
+    /// Ask a stream for its usage if it's tracked.
+    fn include_usage(&self, options: &mut Option<StreamOptions>) {
+        if self.usage_tracker.is_some() {
+            *options = Some(StreamOptions {
+                include_usage: true,
+            });
+        }
+    }
+
+    /// Clamp or strip the parameters the backend doesn't accept, see `ParamProfile`.
+    fn clamp_params(&self, req: &mut ChatCompletionRequest) {
+        match &self.param_profile {
+            Some(profile) => profile.apply(req),
+            None => self.provider.param_profile(&req.model).apply(req),
+        }
+    }
+
+    /// Merge the system prompt of the SDK into the first system message, or insert it, so the
+    /// request still has a single system message.
+    fn add_system_prompt(&self, req: &mut ChatCompletionRequest) {
+        let Some(prompt) = &self.system_prompt else {
+            return;
+        };
+        match req.messages.first_mut() {
+            Some(ChatCompletionMessage::System(m)) => {
+                m.content = format!("{}\n\n{}", prompt, m.content);
+            }
+            _ => req
+                .messages
+                .insert(0, ChatCompletionMessage::new_system(prompt, "")),
+        }
+    }
+
+    /// Send the request with a strict JSON schema response format generated from `T`, and parse
+    /// the content of the first choice into `T`.
+    pub async fn chat_completion_structured<T: JsonSchema + DeserializeOwned>(
+        &self,
+        mut req: ChatCompletionRequest,
+    ) -> Result<T> {
+        req.response_format = Some(ResponseFormat::json_schema::<T>());
+        self.chat_completion(req).await?.parse_content()
+    }
+
+    /// Serve the completion from `cache` if possible. A stale entry is returned right away and
+    /// refreshed in the background, see `ResponseCache`. Must be called within a tokio runtime.
+    pub async fn chat_completion_cached(
+        &self,
+        req: ChatCompletionRequest,
+        cache: &Arc<ResponseCache>,
+    ) -> Result<ChatCompletionResponse> {
+        let key = ResponseCache::key(&req);
+        match cache.lookup(&key) {
+            CacheLookup::Fresh(res) => Ok(res),
+            CacheLookup::Stale {
+                response,
+                revalidate,
+            } => {
+                if revalidate {
+                    let sdk = self.clone();
+                    let cache = cache.clone();
+                    tokio::spawn(async move {
+                        match sdk.chat_completion(req).await {
+                            Ok(res) => cache.insert(key, res),
+                            Err(e) => {
+                                warn!("failed to revalidate cached completion: {}", e);
+                                cache.revalidation_failed(&key);
+                            }
+                        }
+                    });
+                }
+                Ok(response)
+            }
+            CacheLookup::Miss => {
+                let res = self.chat_completion(req).await?;
+                cache.insert(key, res.clone());
+                Ok(res)
+            }
+        }
+    }
+
+    /// Run the request in the arm of the experiment `key` (e.g. the user id) is bucketed into.
+    /// If no arm has a weight, the request is sent unchanged with an empty arm name.
+    pub async fn chat_completion_experiment(
+        &self,
+        experiment: &Experiment,
+        key: &str,
+        mut req: ChatCompletionRequest,
+    ) -> Result<ExperimentResponse> {
+        let arm = experiment.assign(key);
+        if let Some(arm) = arm {
+            arm.apply(&mut req);
+        }
+        let arm = arm.map(|arm| arm.name.clone()).unwrap_or_default();
+        let span = info_span!("experiment", experiment = experiment.name(), arm = %arm);
+        let response = async {
+            let res = self.chat_completion(req).await?;
+            info!(
+                model = %res.model,
+                prompt_tokens = res.usage.prompt_tokens,
+                completion_tokens = res.usage.completion_tokens,
+                "experiment completion"
+            );
+            Ok::<_, anyhow::Error>(res)
+        }
+        .instrument(span)
+        .await?;
+        Ok(ExperimentResponse {
+            experiment: experiment.name().to_string(),
+            arm,
+            response,
+        })
+    }
+
+    /// Score `answer` against the criteria with the default `Judge`.
+    pub async fn evaluate(&self, answer: &str, criteria: &[&str]) -> Result<Evaluation> {
+        Judge::default().evaluate(self, answer, criteria).await
+    }
+
+    /// JSON mode with repair: the request is sent with the `JsonObject` response format (unless
+    /// it has one already) and the content of the first choice parsed into `T`. If the content
+    /// doesn't parse, the model is asked to fix it, up to `max_attempts` requests in total.
+    /// Note that JSON mode requires the word "JSON" somewhere in the messages.
+    pub async fn chat_completion_json<T: DeserializeOwned>(
+        &self,
+        mut req: ChatCompletionRequest,
+        max_attempts: usize,
+    ) -> Result<T> {
+        if req.response_format.is_none() {
+            req.response_format = Some(ResponseFormat::JsonObject);
+        }
+        self.repair_json(req, max_attempts).await
+    }
+
+    /// Extract a `T` from the conversation with the most reliable strategy the provider
+    /// supports: Structured Outputs, a forced tool call, or the schema in the instructions with
+    /// the reply validated (and repaired once). The strategy used is reported in the result.
+    pub async fn extract<T: JsonSchema + DeserializeOwned>(
+        &self,
+        mut req: ChatCompletionRequest,
+    ) -> Result<Extraction<T>> {
+        let strategy = ExtractStrategy::for_provider(self.provider.as_ref());
+        strategy.prepare::<T>(&mut req);
+        let value = match strategy {
+            ExtractStrategy::Instructions => self.repair_json(req, 2).await?,
+            _ => strategy.parse(&self.chat_completion(req).await?)?,
+        };
+        Ok(Extraction { value, strategy })
+    }
+
+    /// Parse the content of the first choice into `T`, asking the model to fix invalid JSON up
+    /// to `max_attempts` requests in total.
+    async fn repair_json<T: DeserializeOwned>(
+        &self,
+        mut req: ChatCompletionRequest,
+        max_attempts: usize,
+    ) -> Result<T> {
+        let mut attempt = 1;
+        loop {
+            let res = self.chat_completion(req.clone()).await?;
+            let err = match res.parse_content() {
+                Ok(v) => return Ok(v),
+                Err(e) if attempt >= max_attempts => return Err(e),
+                Err(e) => e,
+            };
+            warn!("attempt {} returned invalid JSON: {}", attempt, err);
+            let content = res
+                .choices
+                .into_iter()
+                .next()
+                .and_then(|choice| choice.message.content)
+                .unwrap_or_default();
+            req.messages
+                .push(ChatCompletionMessage::Assistant(AssistantMessage {
+                    content: Some(content),
+                    name: None,
+                    tool_calls: vec![],
+                    audio: None,
+                }));
+            req.messages.push(ChatCompletionMessage::new_user(
+                "Your response is not valid JSON. Reply with the corrected JSON only.",
+                "",
+            ));
+            attempt += 1;
+        }
+    }
+
+    pub async fn chat_completion_stream(
+        &self,
+        mut req: ChatCompletionRequest,
+    ) -> Result<ChatCompletionStream> {
+        warn_if_deprecated(&req.model);
+        if let Some(provider) = &self.async_provider {
+            return Err(anyhow!("streaming is not supported by {:?}", provider));
+        }
+        if !self.provider.supports_stream() {
+            return Err(anyhow!("streaming is not supported by {:?}", self.provider));
+        }
+        req.stream = Some(true);
+        self.include_usage(&mut req.stream_options);
+        self.clamp_params(&mut req);
+        self.add_system_prompt(&mut req);
+        self.check_budget()?;
+        let req = self.prepare_stream_request(req);
+        let res = req.send_and_log().await?;
+        let stream = ChatCompletionStream::new(self.event_stream(res));
+        Ok(match &self.usage_tracker {
+            Some(tracker) => stream.track_usage(tracker.clone()),
+            None => stream,
+        })
+    }
+
+    /// The Responses API, an alternative to the chat completions with hosted tools.
+    pub async fn response(&self, req: ResponseRequest) -> Result<Response> {
+        warn_if_deprecated(&req.model);
+        self.check_budget()?;
+        let req = self.prepare_request(req);
+        let res = req.send_and_log().await?;
+        let res = res.json::<Response>().await?;
+        if let (Some(tracker), Some(usage)) = (&self.usage_tracker, &res.usage) {
+            tracker.record(
+                &res.model,
+                "responses",
+                usage.input_tokens,
+                usage.output_tokens,
+            );
+        }
+        Ok(res)
+    }
+
+    pub async fn response_stream(&self, mut req: ResponseRequest) -> Result<ResponseStream> {
+        warn_if_deprecated(&req.model);
+        req.stream = Some(true);
+        self.check_budget()?;
+        let req = self.prepare_stream_request(req);
+        let res = req.send_and_log().await?;
+        let sdk = self.clone();
+        let cancel = CancelOnDrop::new(move |id| {
+            let sdk = sdk.clone();
+            spawn_cancel(async move { sdk.cancel_response(&id).await.map(|_| ()) });
+        });
+        let stream = ResponseStream::new(self.event_stream(res)).with_cancel(cancel);
+        Ok(match &self.usage_tracker {
+            Some(tracker) => stream.track_usage(tracker.clone()),
+            None => stream,
+        })
+    }
+
+    /// Cancel a response that is in progress, e.g. a streamed one.
+    pub async fn cancel_response(&self, id: &str) -> Result<Response> {
+        let req = PathRequest::post_empty(format!("responses/{}/cancel", id));
+        let res = self.prepare_request(req).send_and_log().await?;
+        Ok(res.json::<Response>().await?)
+    }
+
+    /// Answer with the tools of `registry`, which are added to the request: the tool calls of the
+    /// model are run locally and their results sent back, until the model answers or the max
+    /// rounds of the registry are reached. A failing tool sends its error to the model.
+    pub async fn chat_with_tools(
+        &self,
+        mut req: ChatCompletionRequest,
+        registry: &ToolRegistry,
+    ) -> Result<ToolRun> {
+        req.tools_mut().extend(registry.tools());
+        let mut memo = ToolMemo::new();
+        for round in 1..=registry.rounds() {
+            let res = self.chat_completion(req.clone()).await?;
+            let message = match res.choices.first() {
+                Some(choice) if !choice.message.tool_calls.is_empty() => choice.message.clone(),
+                _ => {
+                    return Ok(ToolRun {
+                        response: res,
+                        messages: req.messages().to_vec(),
+                        rounds: round,
+                        memo: memo.stats(),
+                    })
+                }
+            };
+            req.messages_mut()
+                .push(ChatCompletionMessage::Assistant(message.clone()));
+            for call in &message.tool_calls {
+                let owned = call.clone();
+                let output = memo
+                    .call(call, |_| async move { registry.call(&owned).await })
+                    .await
+                    .unwrap_or_else(|e| format!("error: {}", e));
+                req.messages_mut()
+                    .push(ChatCompletionMessage::new_tool(output, &call.id));
+            }
+        }
+        Err(anyhow!(
+            "no answer after {} rounds of tool calls",
+            registry.rounds()
+        ))
+    }
+
+    /// Stream a chat completion which must finish by `deadline`: if it's still streaming when
+    /// only the reserve of the deadline is left, it is cut and followed by a short wrap-up
+    /// continuation, whose chunks are appended to the stream.
+    pub async fn chat_completion_stream_deadline(
+        &self,
+        req: ChatCompletionRequest,
+        deadline: StreamDeadline,
+    ) -> Result<ChatCompletionStream> {
+        let stream = self.chat_completion_stream(req.clone()).await?;
+        Ok(deadline.wrap(self.clone(), req, stream))
+    }
+
+    /// The legacy text completions API.
+    pub async fn completion(&self, req: CompletionRequest) -> Result<CompletionResponse> {
+        warn_if_deprecated(&req.model);
+        self.check_budget()?;
         let req = self.prepare_request(req);
         let res = req.send_and_log().await?;
-        Ok(res.json::<ChatCompletionResponse>().await?)
+        let res = res.json::<CompletionResponse>().await?;
+        if let Some(tracker) = &self.usage_tracker {
+            tracker.record(
+                &usage::model_name(&res.model),
+                "completions",
+                res.usage.prompt_tokens,
+                res.usage.completion_tokens,
+            );
+        }
+        Ok(res)
+    }
+
+    pub async fn completion_stream(&self, mut req: CompletionRequest) -> Result<CompletionStream> {
+        warn_if_deprecated(&req.model);
+        self.check_budget()?;
+        req.stream = Some(true);
+        self.include_usage(&mut req.stream_options);
+        let req = self.prepare_stream_request(req);
+        let res = req.send_and_log().await?;
+        let stream = CompletionStream::new(self.event_stream(res));
+        Ok(match &self.usage_tracker {
+            Some(tracker) => stream.track_usage(tracker.clone()),
+            None => stream,
+        })
     }
 
     pub async fn create_image(&self, req: CreateImageRequest) -> Result<CreateImageResponse> {
+        warn_if_deprecated(&req.model);
+        self.check_budget()?;
+        let prompt = req.prompt.clone();
+        let model = req.model.clone();
         let req = self.prepare_request(req);
         let res = req.send_and_log().await?;
-        Ok(res.json::<CreateImageResponse>().await?)
+        let res = CreateImageResponse::from_bytes(res.bytes().await?, &prompt)?;
+        self.record_image_usage(&model, "images/generations", &res);
+        Ok(res)
+    }
+
+    pub async fn create_image_edit(
+        &self,
+        req: CreateImageEditRequest,
+    ) -> Result<CreateImageResponse> {
+        self.check_budget()?;
+        let prompt = req.prompt.clone();
+        let req = self.prepare_request(req);
+        let res = req.send_and_log().await?;
+        let res = CreateImageResponse::from_bytes(res.bytes().await?, &prompt)?;
+        self.record_image_usage(&ImageModel::DallE2, "images/edits", &res);
+        Ok(res)
+    }
+
+    /// gpt-image-1 reports its token usage, the other models are priced per image.
+    fn record_image_usage(
+        &self,
+        model: &ImageModel,
+        endpoint: &'static str,
+        res: &CreateImageResponse,
+    ) {
+        let Some(tracker) = &self.usage_tracker else {
+            return;
+        };
+        let name = model.to_string();
+        match &res.usage {
+            Some(usage) => tracker.record(&name, endpoint, usage.input_tokens, usage.output_tokens),
+            None => tracker.record_cost(
+                &name,
+                endpoint,
+                res.data.len() as f64 * model.price_per_image(),
+            ),
+        }
     }
 
     pub async fn speech(&self, req: SpeechRequest) -> Result<Bytes> {
+        warn_if_deprecated(&req.model);
+        self.check_budget()?;
+        let (model, cost) = speech_cost(&req);
         let req = self.prepare_request(req);
         let res = req.send_and_log().await?;
-        Ok(res.bytes().await?)
+        let audio = res.bytes().await?;
+        self.record_cost(&model, "audio/speech", cost);
+        Ok(audio)
+    }
+
+    /// Generate the speech of an input longer than the 4096 characters limit of the API: the input
+    /// is split at the sentence boundaries, the chunks are generated concurrently, and their audio
+    /// is concatenated. Only mp3 and pcm audio can be concatenated.
+    pub async fn speech_long(&self, req: SpeechRequest) -> Result<Bytes> {
+        if !matches!(
+            req.response_format,
+            SpeechResponseFormat::Mp3 | SpeechResponseFormat::Pcm
+        ) {
+            return Err(anyhow!(
+                "{:?} audio can't be concatenated, use mp3 or pcm",
+                req.response_format
+            ));
+        }
+        self.check_budget()?;
+        let chunks = split_input(&req.input, SPEECH_MAX_INPUT);
+        let parts: Vec<Bytes> = futures::stream::iter(chunks)
+            .map(|input| {
+                let mut req = req.clone();
+                req.input = input;
+                self.speech(req)
+            })
+            .buffered(SPEECH_CONCURRENCY)
+            .try_collect()
+            .await?;
+        Ok(parts.concat().into())
+    }
+
+    /// Stream the audio as it is generated, so that the playback can start before the whole
+    /// file is ready. The stream is guarded by `stream_idle_timeout` rather than the request
+    /// timeout.
+    pub async fn speech_stream(
+        &self,
+        req: SpeechRequest,
+    ) -> Result<BoxStream<'static, Result<Bytes>>> {
+        warn_if_deprecated(&req.model);
+        self.check_budget()?;
+        let (model, cost) = speech_cost(&req);
+        let req = self.prepare_stream_request(req);
+        let res = req.send_and_log().await?;
+        self.record_cost(&model, "audio/speech", cost);
+        let idle_timeout = self.stream_idle_timeout;
+        let body = res.bytes_stream().boxed();
+        let stream = futures::stream::unfold(Some(body), move |body| async move {
+            let mut body = body?;
+            match tokio::time::timeout(idle_timeout, body.next()).await {
+                Err(_) => Some((
+                    Err(anyhow!("stream idle for more than {:?}", idle_timeout)),
+                    None,
+                )),
+                Ok(Some(Ok(chunk))) => Some((Ok(chunk), Some(body))),
+                Ok(Some(Err(e))) => Some((Err(e.into()), None)),
+                Ok(None) => None,
+            }
+        });
+        Ok(stream.boxed())
+    }
+
+    /// Transcribe or translate into the text format (text, srt or vtt) of the request.
+    pub async fn whisper_text(&self, req: WhisperRequest) -> Result<TranscriptText> {
+        warn_if_deprecated(&req.model.to_string());
+        let format = req.response_format;
+        if !format.is_text() {
+            return Err(anyhow!("{} is not a text format", format));
+        }
+        self.check_budget()?;
+        let (model, endpoint, cost) = whisper_cost(&req);
+        let req = self.prepare_request(req);
+        let res = req.send_and_log().await?;
+        self.rate_limits.update(Instant::now(), res.headers());
+        self.record_cost(&model, endpoint, cost);
+        let content_type = res
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        if !format.matches_content_type(&content_type) {
+            return Err(anyhow!(
+                "expected a {} transcript, got content type {:?}",
+                format,
+                content_type
+            ));
+        }
+        let text = res.text().await?;
+        Ok(TranscriptText { format, text })
     }
 
+    /// Only the text of a `verbose_json` response is kept, use `whisper_verbose` for the segments.
     pub async fn whisper(&self, req: WhisperRequest) -> Result<WhisperResponse> {
+        warn_if_deprecated(&req.model.to_string());
         let is_json = req.response_format == WhisperResponseFormat::Json;
+        self.check_budget()?;
+        let (model, endpoint, cost) = whisper_cost(&req);
         let req = self.prepare_request(req);
         let res = req.send_and_log().await?;
+        self.rate_limits.update(Instant::now(), res.headers());
+        self.record_cost(&model, endpoint, cost);
         let ret = if is_json {
             res.json::<WhisperResponse>().await?
         } else {
@@ -104,34 +932,652 @@ impl LlmSdk {
         Ok(ret)
     }
 
+    /// Transcribe with the `verbose_json` response format, which includes the detected language,
+    /// the duration and the timed segments.
+    /// An in-memory mp3 too large for the server (or `max_body_size`) is transcribed in chunks
+    /// with `transcribe_long`, smaller ones while they are still too large.
+    pub async fn whisper_verbose(&self, req: WhisperRequest) -> Result<WhisperVerboseResponse> {
+        let fallback = match &req.file {
+            WhisperFile::Bytes(data)
+                if req.request_type == WhisperRequestType::Transcription
+                    && req.format.or_else(|| AudioFormat::from_bytes(data))
+                        == Some(AudioFormat::Mp3) =>
+            {
+                Some((data.clone(), req.language.clone()))
+            }
+            _ => None,
+        };
+        match self.send_whisper_verbose(req).await {
+            Err(e) if is_payload_too_large(&e) => {
+                let Some((audio, language)) = fallback else {
+                    return Err(e);
+                };
+                let (mut err, mut max_bytes) = (e, audio.len());
+                loop {
+                    let Some(size) = smaller_chunks(payload_limit(&err), max_bytes) else {
+                        return Err(err);
+                    };
+                    max_bytes = size;
+                    info!(
+                        "audio too large, transcribing it in chunks of {} bytes",
+                        max_bytes
+                    );
+                    let opts = TranscribeLongOptions {
+                        max_bytes,
+                        language: language.clone(),
+                        ..Default::default()
+                    };
+                    match self.transcribe_long(audio.clone(), opts).await {
+                        Err(e) if is_payload_too_large(&e) => err = e,
+                        res => return res,
+                    }
+                }
+            }
+            res => res,
+        }
+    }
+
+    async fn send_whisper_verbose(
+        &self,
+        mut req: WhisperRequest,
+    ) -> Result<WhisperVerboseResponse> {
+        warn_if_deprecated(&req.model.to_string());
+        self.check_budget()?;
+        req.response_format = WhisperResponseFormat::VerboseJson;
+        let (model, endpoint) = (req.model.clone(), req.request_type.endpoint());
+        let req = self.prepare_request(req);
+        let res = req.send_and_log().await?;
+        self.rate_limits.update(Instant::now(), res.headers());
+        let res = res.json::<WhisperVerboseResponse>().await?;
+        let cost = res.duration as f64 / 60.0 * model.price_per_minute();
+        self.record_cost(&model.to_string(), endpoint, cost);
+        Ok(res)
+    }
+
+    /// Transcribe with a model which streams the transcript (e.g. gpt-4o-transcribe), yielding
+    /// the pieces of the text as they are transcribed. Translations can't be streamed.
+    pub async fn whisper_stream(&self, mut req: WhisperRequest) -> Result<TranscriptStream> {
+        warn_if_deprecated(&req.model.to_string());
+        if !req.model.supports_streaming() {
+            return Err(anyhow!("{} doesn't support streaming", req.model));
+        }
+        if req.request_type != WhisperRequestType::Transcription {
+            return Err(anyhow!("only transcriptions can be streamed"));
+        }
+        req.stream = true;
+        req.response_format = WhisperResponseFormat::Json;
+        self.check_budget()?;
+        let (model, endpoint, cost) = whisper_cost(&req);
+        let req = self.prepare_stream_request(req);
+        let res = req.send_and_log().await?;
+        self.record_cost(&model, endpoint, cost);
+        Ok(TranscriptStream::new(self.event_stream(res)))
+    }
+
+    /// Open a realtime session with `model` (e.g. `REALTIME_MODEL`) over WebSocket.
+    #[cfg(feature = "realtime")]
+    pub async fn realtime(&self, model: &str) -> Result<RealtimeSession> {
+        self.check_budget()?;
+        let url = realtime::realtime_url(&self.base_url, model);
+        RealtimeSession::connect(&url, &self.token).await
+    }
+
+    /// Detect the spoken language of the audio from a short transcription of its beginning, e.g.
+    /// to decide between a transcription and a translation.
+    pub async fn detect_language(&self, audio: Vec<u8>) -> Result<DetectedLanguage> {
+        let req = WhisperRequest::language_detection(audio, LANGUAGE_DETECTION_BYTES);
+        let res = self.whisper_verbose(req).await?;
+        Ok(DetectedLanguage::from(&res))
+    }
+
+    /// Transcribe audio larger than the 25MB limit of the API: it is split into overlapping
+    /// chunks, which are transcribed with bounded concurrency, and the segments are stitched back
+    /// with their timestamps adjusted. The split is size based, which only works for a frame
+    /// based format like mp3; other formats have to be re-encoded first.
+    pub async fn transcribe_long(
+        &self,
+        audio: Vec<u8>,
+        opts: TranscribeLongOptions,
+    ) -> Result<WhisperVerboseResponse> {
+        let ranges = opts.split(audio.len());
+        let format = AudioFormat::from_bytes(&audio);
+        if ranges.len() > 1 && format != Some(AudioFormat::Mp3) {
+            return Err(anyhow!(
+                "{:?} audio can't be split into chunks, use mp3",
+                format
+            ));
+        }
+        let requests = ranges.into_iter().map(|range| {
+            let mut builder = WhisperRequestBuilder::default();
+            builder
+                .file(audio[range.clone()].to_vec())
+                .format(AudioFormat::Mp3)
+                .request_type(WhisperRequestType::Transcription);
+            if let Some(language) = &opts.language {
+                builder.language(language.clone());
+            }
+            (range, builder.build())
+        });
+        let parts = futures::stream::iter(requests)
+            .map(|(range, req)| async move {
+                let res = self.send_whisper_verbose(req?).await?;
+                Ok::<_, anyhow::Error>((range, res))
+            })
+            .buffered(opts.concurrency.max(1))
+            .try_collect()
+            .await?;
+        Ok(stitch(parts))
+    }
+
+    /// A request too large for the server (or `max_body_size`) is split in halves of its inputs
+    /// until the parts fit, and the responses are merged.
     pub async fn embedding(&self, req: EmbeddingRequest) -> Result<EmbeddingResponse> {
+        warn_if_deprecated(&req.model);
+        let mut pending = vec![(0, req)];
+        let mut parts = Vec::new();
+        while let Some((offset, req)) = pending.pop() {
+            let halves = req.split_in_half();
+            match self.send_embedding(req).await {
+                Ok(res) => parts.push((offset, res)),
+                Err(e) if is_payload_too_large(&e) => {
+                    let Some((mid, first, second)) = halves else {
+                        return Err(e);
+                    };
+                    info!("embedding request too large, splitting it in two");
+                    pending.push((offset + mid, second));
+                    pending.push((offset, first));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        EmbeddingResponse::merge(parts).ok_or_else(|| anyhow!("no embedding response"))
+    }
+
+    async fn send_embedding(&self, req: EmbeddingRequest) -> Result<EmbeddingResponse> {
+        self.check_budget()?;
         let req = self.prepare_request(req);
         let res = req.send_and_log().await?;
-        Ok(res.json().await?)
+        self.rate_limits.update(Instant::now(), res.headers());
+        let res: EmbeddingResponse = res.json().await?;
+        if let Some(tracker) = &self.usage_tracker {
+            tracker.record(&res.model, "embeddings", res.usage.prompt_tokens, 0);
+        }
+        Ok(res)
     }
 
-    fn prepare_request(&self, req: impl IntoRequest) -> RequestBuilder {
-        let req = req.into_request(&self.base_url, self.client.clone());
-        let req = if self.token.is_empty() {
-            req
-        } else {
-            req.bearer_auth(&self.token)
+    /// Embed a large number of texts: they are split into requests respecting the input count
+    /// and token limits of `opts`, which are sent with bounded concurrency.
+    pub async fn embed_all(
+        &self,
+        texts: &[String],
+        model: EmbeddingModel,
+        opts: EmbedAllOptions,
+    ) -> Result<EmbedAllResponse> {
+        let requests = opts.split(texts).into_iter().map(|range| {
+            let mut builder = EmbeddingRequestBuilder::default();
+            builder
+                .input(texts[range.clone()].into())
+                .model(model.clone());
+            if let Some(dimensions) = opts.dimensions {
+                builder.dimensions(dimensions);
+            }
+            (range, builder.build())
+        });
+        let mut responses: Vec<_> = futures::stream::iter(requests)
+            .map(|(range, req)| async move {
+                let res = self.embedding(req?).await?;
+                Ok::<_, anyhow::Error>((range, res))
+            })
+            .buffer_unordered(opts.concurrency.max(1))
+            .try_collect()
+            .await?;
+        responses.sort_by_key(|(range, _)| range.start);
+
+        let mut ret = EmbedAllResponse {
+            embeddings: Vec::with_capacity(texts.len()),
+            usage: EmbeddingUsage::default(),
         };
-        req.timeout(Duration::from_secs(TIMEOUT))
+        for (_, mut res) in responses {
+            res.data.sort_by_key(|d| d.index);
+            for data in res.data {
+                ret.embeddings.push(data.embedding.as_floats()?);
+            }
+            ret.usage.prompt_tokens += res.usage.prompt_tokens;
+            ret.usage.total_tokens += res.usage.total_tokens;
+        }
+        Ok(ret)
     }
+
+    pub async fn create_assistant(&self, req: CreateAssistantRequest) -> Result<Assistant> {
+        warn_if_deprecated(&req.model);
+        let req = self.prepare_request(req);
+        let res = req.send_and_log().await?;
+        Ok(res.json::<Assistant>().await?)
+    }
+
+    pub async fn retrieve_assistant(&self, id: &str) -> Result<Assistant> {
+        let req = PathRequest::get(format!("assistants/{}", id)).assistants_beta();
+        let res = self.prepare_request(req).send_and_log().await?;
+        Ok(res.json::<Assistant>().await?)
+    }
+
+    pub async fn modify_assistant(
+        &self,
+        id: &str,
+        req: ModifyAssistantRequest,
+    ) -> Result<Assistant> {
+        let req = PathRequest::post(format!("assistants/{}", id), req).assistants_beta();
+        let res = self.prepare_request(req).send_and_log().await?;
+        Ok(res.json::<Assistant>().await?)
+    }
+
+    pub async fn delete_assistant(&self, id: &str) -> Result<DeletionStatus> {
+        let req = PathRequest::delete(format!("assistants/{}", id)).assistants_beta();
+        let res = self.prepare_request(req).send_and_log().await?;
+        Ok(res.json::<DeletionStatus>().await?)
+    }
+
+    pub async fn list_assistants(&self, req: ListRequest) -> Result<ListResponse<Assistant>> {
+        let req = PathRequest::list("assistants", req).assistants_beta();
+        let res = self.prepare_request(req).send_and_log().await?;
+        Ok(res.json::<ListResponse<Assistant>>().await?)
+    }
+
+    pub async fn create_thread(&self, req: CreateThreadRequest) -> Result<Thread> {
+        let req = self.prepare_request(req);
+        let res = req.send_and_log().await?;
+        Ok(res.json::<Thread>().await?)
+    }
+
+    pub async fn retrieve_thread(&self, id: &str) -> Result<Thread> {
+        let req = PathRequest::get(format!("threads/{}", id)).assistants_beta();
+        let res = self.prepare_request(req).send_and_log().await?;
+        Ok(res.json::<Thread>().await?)
+    }
+
+    pub async fn modify_thread(&self, id: &str, req: ModifyThreadRequest) -> Result<Thread> {
+        let req = PathRequest::post(format!("threads/{}", id), req).assistants_beta();
+        let res = self.prepare_request(req).send_and_log().await?;
+        Ok(res.json::<Thread>().await?)
+    }
+
+    pub async fn delete_thread(&self, id: &str) -> Result<DeletionStatus> {
+        let req = PathRequest::delete(format!("threads/{}", id)).assistants_beta();
+        let res = self.prepare_request(req).send_and_log().await?;
+        Ok(res.json::<DeletionStatus>().await?)
+    }
+
+    pub async fn create_message(
+        &self,
+        thread_id: &str,
+        req: CreateMessageRequest,
+    ) -> Result<ThreadMessage> {
+        let req =
+            PathRequest::post(format!("threads/{}/messages", thread_id), req).assistants_beta();
+        let res = self.prepare_request(req).send_and_log().await?;
+        Ok(res.json::<ThreadMessage>().await?)
+    }
+
+    pub async fn retrieve_message(&self, thread_id: &str, id: &str) -> Result<ThreadMessage> {
+        let req =
+            PathRequest::get(format!("threads/{}/messages/{}", thread_id, id)).assistants_beta();
+        let res = self.prepare_request(req).send_and_log().await?;
+        Ok(res.json::<ThreadMessage>().await?)
+    }
+
+    pub async fn list_messages(
+        &self,
+        thread_id: &str,
+        req: ListRequest,
+    ) -> Result<ListResponse<ThreadMessage>> {
+        let req =
+            PathRequest::list(format!("threads/{}/messages", thread_id), req).assistants_beta();
+        let res = self.prepare_request(req).send_and_log().await?;
+        Ok(res.json::<ListResponse<ThreadMessage>>().await?)
+    }
+
+    pub async fn create_run(&self, thread_id: &str, req: CreateRunRequest) -> Result<Run> {
+        if let Some(model) = &req.model {
+            warn_if_deprecated(model);
+        }
+        self.check_budget()?;
+        let req = PathRequest::post(format!("threads/{}/runs", thread_id), req).assistants_beta();
+        let res = self.prepare_request(req).send_and_log().await?;
+        Ok(res.json::<Run>().await?)
+    }
+
+    pub async fn retrieve_run(&self, thread_id: &str, id: &str) -> Result<Run> {
+        let req = PathRequest::get(format!("threads/{}/runs/{}", thread_id, id)).assistants_beta();
+        let res = self.prepare_request(req).send_and_log().await?;
+        Ok(res.json::<Run>().await?)
+    }
+
+    pub async fn list_runs(&self, thread_id: &str, req: ListRequest) -> Result<ListResponse<Run>> {
+        let req = PathRequest::list(format!("threads/{}/runs", thread_id), req).assistants_beta();
+        let res = self.prepare_request(req).send_and_log().await?;
+        Ok(res.json::<ListResponse<Run>>().await?)
+    }
+
+    pub async fn list_run_steps(
+        &self,
+        thread_id: &str,
+        run_id: &str,
+        req: ListRequest,
+    ) -> Result<ListResponse<RunStep>> {
+        let req = PathRequest::list(format!("threads/{}/runs/{}/steps", thread_id, run_id), req)
+            .assistants_beta();
+        let res = self.prepare_request(req).send_and_log().await?;
+        Ok(res.json::<ListResponse<RunStep>>().await?)
+    }
+
+    pub async fn retrieve_run_step(
+        &self,
+        thread_id: &str,
+        run_id: &str,
+        id: &str,
+    ) -> Result<RunStep> {
+        let req = PathRequest::get(format!(
+            "threads/{}/runs/{}/steps/{}",
+            thread_id, run_id, id
+        ))
+        .assistants_beta();
+        let res = self.prepare_request(req).send_and_log().await?;
+        Ok(res.json::<RunStep>().await?)
+    }
+
+    /// The file_search results retrieved by a run, with the content of the chunks, in the order
+    /// of the run steps. Useful to show the sources of an answer.
+    pub async fn file_search_results(
+        &self,
+        thread_id: &str,
+        run_id: &str,
+    ) -> Result<Vec<FileSearchResult>> {
+        let query = ListRequestBuilder::default()
+            .order(ListOrder::Asc)
+            .limit(100)
+            .build()?;
+        let req = PathRequest::list(
+            format!("threads/{}/runs/{}/steps", thread_id, run_id),
+            query,
+        )
+        .include(FILE_SEARCH_RESULT_CONTENT)
+        .assistants_beta();
+        let res = self.prepare_request(req).send_and_log().await?;
+        let steps = res.json::<ListResponse<RunStep>>().await?;
+        Ok(steps
+            .data
+            .iter()
+            .flat_map(|step| step.file_search_results().cloned())
+            .collect())
+    }
+
+    pub async fn cancel_run(&self, thread_id: &str, id: &str) -> Result<Run> {
+        let req = PathRequest::post_empty(format!("threads/{}/runs/{}/cancel", thread_id, id))
+            .assistants_beta();
+        let res = self.prepare_request(req).send_and_log().await?;
+        Ok(res.json::<Run>().await?)
+    }
+
+    pub async fn submit_tool_outputs(
+        &self,
+        thread_id: &str,
+        id: &str,
+        req: SubmitToolOutputsRequest,
+    ) -> Result<Run> {
+        self.check_budget()?;
+        let path = format!("threads/{}/runs/{}/submit_tool_outputs", thread_id, id);
+        let req = PathRequest::post(path, req).assistants_beta();
+        let res = self.prepare_request(req).send_and_log().await?;
+        Ok(res.json::<Run>().await?)
+    }
+
+    /// Submit the tool outputs and stream the continuation of the run, instead of polling it.
+    pub async fn submit_tool_outputs_stream(
+        &self,
+        thread_id: &str,
+        id: &str,
+        mut req: SubmitToolOutputsRequest,
+    ) -> Result<AssistantStream> {
+        self.check_budget()?;
+        req.stream = Some(true);
+        let path = format!("threads/{}/runs/{}/submit_tool_outputs", thread_id, id);
+        let req = PathRequest::post(path, req).assistants_beta();
+        let res = self.prepare_stream_request(req).send_and_log().await?;
+        let (sdk, thread_id) = (self.clone(), thread_id.to_string());
+        let cancel = CancelOnDrop::new(move |id| {
+            let (sdk, thread_id) = (sdk.clone(), thread_id.clone());
+            spawn_cancel(async move { sdk.cancel_run(&thread_id, &id).await.map(|_| ()) });
+        });
+        Ok(AssistantStream::new(self.event_stream(res)).with_cancel(cancel))
+    }
+
+    /// Poll the run until it reaches a terminal state, or until it requires action (tool outputs)
+    /// from the caller.
+    pub async fn wait_for_run(
+        &self,
+        thread_id: &str,
+        id: &str,
+        opts: RunPollOptions,
+    ) -> Result<Run> {
+        let started = Instant::now();
+        let mut interval = opts.initial_interval;
+        loop {
+            tokio::time::sleep(interval).await;
+            let run = self.retrieve_run(thread_id, id).await?;
+            if run.status.is_terminal() || run.status == RunStatus::RequiresAction {
+                return Ok(run);
+            }
+            if let Some(timeout) = opts.timeout {
+                if started.elapsed() >= timeout {
+                    return Err(anyhow!(
+                        "run {} is still {:?} after {:?}",
+                        id,
+                        run.status,
+                        timeout
+                    ));
+                }
+            }
+            interval = opts.next_interval(interval);
+        }
+    }
+
+    pub async fn upload_file(&self, req: UploadFileRequest) -> Result<FileObject> {
+        let req = self.prepare_request(req);
+        let res = req.send_and_log().await?;
+        Ok(res.json::<FileObject>().await?)
+    }
+
+    /// Upload the file unless an identical one (by the sha256 of its content) was uploaded
+    /// before with the same purpose, in which case the existing file is returned.
+    pub async fn upload_file_dedup(
+        &self,
+        req: UploadFileRequest,
+        manifest: &FileManifest,
+    ) -> Result<FileObject> {
+        let hash = FileManifest::hash(&req.file);
+        if let Some(entry) = manifest.get(&hash).filter(|e| e.purpose == req.purpose) {
+            match self.retrieve_file(&entry.file_id).await {
+                Ok(file) => return Ok(file),
+                // deleted remotely, upload it again
+                Err(e) if is_not_found(&e) => manifest.remove(&hash)?,
+                Err(e) => return Err(e),
+            }
+        }
+        if manifest.check_remote {
+            let list = ListRequestBuilder::default().limit(100).build()?;
+            let purpose = req.purpose.to_string();
+            let existing = self.list_files(list).await?.data.into_iter().find(|f| {
+                f.filename == req.filename
+                    && f.bytes == req.file.len() as u64
+                    && f.purpose == purpose
+            });
+            if let Some(file) = existing {
+                manifest.insert(hash, &file, req.purpose)?;
+                return Ok(file);
+            }
+        }
+        let purpose = req.purpose;
+        let file = self.upload_file(req).await?;
+        manifest.insert(hash, &file, purpose)?;
+        Ok(file)
+    }
+
+    pub async fn retrieve_file(&self, id: &str) -> Result<FileObject> {
+        let req = PathRequest::get(format!("files/{}", id));
+        let res = self.prepare_request(req).send_and_log().await?;
+        Ok(res.json::<FileObject>().await?)
+    }
+
+    pub async fn retrieve_file_content(&self, id: &str) -> Result<Bytes> {
+        let req = PathRequest::get(format!("files/{}/content", id));
+        let res = self.prepare_request(req).send_and_log().await?;
+        Ok(res.bytes().await?)
+    }
+
+    pub async fn list_files(&self, req: ListRequest) -> Result<ListResponse<FileObject>> {
+        let req = PathRequest::list("files", req);
+        let res = self.prepare_request(req).send_and_log().await?;
+        Ok(res.json::<ListResponse<FileObject>>().await?)
+    }
+
+    pub async fn delete_file(&self, id: &str) -> Result<DeletionStatus> {
+        let req = PathRequest::delete(format!("files/{}", id));
+        let res = self.prepare_request(req).send_and_log().await?;
+        Ok(res.json::<DeletionStatus>().await?)
+    }
+
+    pub async fn create_batch(&self, req: CreateBatchRequest) -> Result<Batch> {
+        self.check_budget()?;
+        let req = self.prepare_request(req);
+        let res = req.send_and_log().await?;
+        Ok(res.json::<Batch>().await?)
+    }
+
+    pub async fn retrieve_batch(&self, id: &str) -> Result<Batch> {
+        let req = PathRequest::get(format!("batches/{}", id));
+        let res = self.prepare_request(req).send_and_log().await?;
+        Ok(res.json::<Batch>().await?)
+    }
+
+    pub async fn cancel_batch(&self, id: &str) -> Result<Batch> {
+        let req = PathRequest::post_empty(format!("batches/{}/cancel", id));
+        let res = self.prepare_request(req).send_and_log().await?;
+        Ok(res.json::<Batch>().await?)
+    }
+
+    pub async fn list_batches(&self, req: ListRequest) -> Result<ListResponse<Batch>> {
+        let req = PathRequest::list("batches", req);
+        let res = self.prepare_request(req).send_and_log().await?;
+        Ok(res.json::<ListResponse<Batch>>().await?)
+    }
+
+    fn prepare_request(&self, req: impl IntoRequest) -> RequestBuilder {
+        let timeout = req.timeout().unwrap_or(self.timeout);
+        self.prepare_stream_request(req).timeout(timeout)
+    }
+
+    /// Streams may legitimately run longer than the request timeout, they're guarded by
+    /// `stream_idle_timeout` instead.
+    fn prepare_stream_request(&self, req: impl IntoRequest) -> RequestBuilder {
+        let mut req = req.into_request(&self.base_url, self.client.clone());
+        if let Some(api_version) = &self.api_version {
+            req = req.query(&[("api-version", api_version)]);
+        }
+        match self.auth {
+            _ if self.token.is_empty() => req,
+            AuthMethod::Bearer => req.bearer_auth(&self.token),
+            AuthMethod::ApiKey => req.header("api-key", &self.token),
+            AuthMethod::XApiKey => req.header("x-api-key", &self.token),
+        }
+    }
+
+    fn event_stream(
+        &self,
+        res: HttpResponse,
+    ) -> impl futures::Stream<Item = Result<sse::SseEvent>> + Send + 'static {
+        sse::event_stream(
+            res.bytes_stream(),
+            self.stream_idle_timeout,
+            self.heartbeat_events.clone(),
+        )
+    }
+}
+
+fn is_payload_too_large(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<LlmError>(),
+        Some(LlmError::PayloadTooLarge { .. })
+    )
+}
+
+/// The model and cost of a speech, priced by the characters of its input.
+fn speech_cost(req: &SpeechRequest) -> (String, f64) {
+    let chars = req.input.chars().count() as f64;
+    let cost = chars * req.model.price_per_million_chars() / 1_000_000.0;
+    (usage::model_name(&req.model), cost)
+}
+
+/// The model, endpoint and cost of a transcription or translation, priced by the estimated
+/// minutes of its audio (see `Preflight`), the response only has the duration in `verbose_json`.
+fn whisper_cost(req: &WhisperRequest) -> (String, &'static str, f64) {
+    let estimate = req.estimate(&PricingTable::empty());
+    (estimate.model, req.request_type.endpoint(), estimate.cost)
+}
+
+fn is_not_found(e: &anyhow::Error) -> bool {
+    matches!(e.downcast_ref::<LlmError>(), Some(LlmError::NotFound(_)))
 }
 
+/// The body size limit reported by a `PayloadTooLarge` error, if known.
+fn payload_limit(e: &anyhow::Error) -> Option<usize> {
+    match e.downcast_ref::<LlmError>() {
+        Some(LlmError::PayloadTooLarge { limit, .. }) => *limit,
+        _ => None,
+    }
+}
+
+/// Run the cancellation of a dropped stream in the background; without a tokio runtime (e.g.
+/// dropped at shutdown) the job is left to finish.
+fn spawn_cancel(cancel: impl std::future::Future<Output = Result<()>> + Send + 'static) {
+    let Ok(handle) = tokio::runtime::Handle::try_current() else {
+        return;
+    };
+    handle.spawn(async move {
+        if let Err(e) = cancel.await {
+            info!("failed to cancel the job of a dropped stream: {}", e);
+        }
+    });
+}
+
+/// Native async fn in trait (hence the 1.75 MSRV): it is only used with static dispatch, so the
+/// future is neither boxed nor needs to be object safe.
 trait SendAndLog {
-    async fn send_and_log(self) -> Result<Response>;
+    async fn send_and_log(self) -> Result<HttpResponse>;
 }
 
 impl SendAndLog for RequestBuilder {
-    async fn send_and_log(self) -> Result<Response> {
-        let res = self.send().await?;
+    async fn send_and_log(self) -> Result<HttpResponse> {
+        let res = match self.send().await {
+            Ok(res) => res,
+            // keep the errors of our middlewares (e.g. `LlmError`) downcastable
+            Err(reqwest_middleware::Error::Middleware(e)) => return Err(e),
+            Err(e) => return Err(e.into()),
+        };
         let status = res.status();
+        if status == reqwest::StatusCode::PAYLOAD_TOO_LARGE {
+            error!("API failed: {}", res.text().await.unwrap_or_default());
+            return Err(LlmError::PayloadTooLarge {
+                size: None,
+                limit: None,
+            }
+            .into());
+        }
         if status.is_client_error() || status.is_server_error() {
             let text = res.text().await?;
             error!("API failed: {}", text);
+            if status == reqwest::StatusCode::NOT_FOUND {
+                return Err(LlmError::NotFound(text).into());
+            }
             return Err(anyhow!("API failed: {}", text));
         }
         Ok(res)
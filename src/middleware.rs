@@ -1,10 +1,241 @@
-use reqwest::{header, Request, Response};
-use reqwest_middleware::{Middleware, Next, Result};
-use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
+use crate::{LlmError, RateLimits};
+use reqwest::{header::HeaderMap, Method, Request, Response, StatusCode};
+use reqwest_middleware::{ClientBuilder, Error, Middleware, Next, Result};
+use reqwest_retry::{policies::ExponentialBackoff, RetryPolicy, Retryable};
+use reqwest_tracing::TracingMiddleware;
+use retry_policies::{Jitter, RetryDecision};
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 use task_local_extensions::Extensions;
+use tracing::warn;
 
+/// A layer of the HTTP middleware stack of `LlmSdk`, see `LlmSdkBuilder::middlewares`. The
+/// layers wrap each other in order: the first one sees the request first and the response last.
+/// E.g. `[Tracing, Custom(cache), Retry, Custom(limiter)]` serves cache hits without retries,
+/// and rate limits every retry attempt.
+#[derive(Clone)]
+pub enum MiddlewareLayer {
+    /// Trace the HTTP requests, see `reqwest_tracing`.
+    Tracing,
+    /// Retry the transient failures up to `max_retries` times as configured by `RetryConfig`,
+    /// except for streamed bodies. The delay asked by the server with `Retry-After` is honored.
+    Retry,
+    /// Any other middleware, e.g. a rate limiter or an HTTP cache.
+    Custom(Arc<dyn Middleware>),
+}
+
+impl MiddlewareLayer {
+    /// The default stack: tracing, then retry.
+    pub fn default_stack() -> Vec<Self> {
+        vec![Self::Tracing, Self::Retry]
+    }
+
+    pub fn custom(middleware: impl Middleware) -> Self {
+        Self::Custom(Arc::new(middleware))
+    }
+
+    pub(crate) fn apply(
+        &self,
+        builder: ClientBuilder,
+        max_retries: u32,
+        retry: &RetryConfig,
+    ) -> ClientBuilder {
+        match self {
+            Self::Tracing => builder.with(TracingMiddleware::default()),
+            Self::Retry => builder.with(RetryMiddleware::new(max_retries, retry.clone())),
+            Self::Custom(m) => builder.with_arc(m.clone()),
+        }
+    }
+}
+
+impl fmt::Debug for MiddlewareLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tracing => write!(f, "Tracing"),
+            Self::Retry => write!(f, "Retry"),
+            Self::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+/// The policy of the `Retry` middleware, see `LlmSdkBuilder::retry`. The number of retries is
+/// `LlmSdkBuilder::max_retries`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryConfig {
+    /// The bounds of the exponential backoff.
+    pub min_backoff: Duration,
+    pub max_backoff: Duration,
+    /// The longest delay asked by the server (`Retry-After`, or the reset of a rate limit) which
+    /// is waited for. A longer one is ignored, the backoff is used instead.
+    pub max_server_delay: Duration,
+    pub jitter: BackoffJitter,
+    /// No retry is started after this duration since the first attempt, `None` for no deadline.
+    pub deadline: Option<Duration>,
+    /// The HTTP statuses which are retried. Connection errors and timeouts are always retried.
+    pub retry_statuses: Vec<u16>,
+    /// Which POST requests are retried.
+    pub post: PostRetry,
+}
+
+/// The randomization of the backoff delays.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BackoffJitter {
+    None,
+    /// Between 0 and the backoff delay.
+    #[default]
+    Full,
+    /// Between `min_backoff` and the backoff delay.
+    Bounded,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PostRetry {
+    #[default]
+    Always,
+    /// Only the requests with an `Idempotency-Key` header (the chat completions and images), so
+    /// the server doesn't run a request twice.
+    WithIdempotencyKey,
+    Never,
+}
+
+/// Retries the transient failures. The delay asked by the server (`Retry-After`, or the reset of
+/// the exhausted rate limit on a 429) is honored, the exponential backoff is the fallback.
 pub(crate) struct RetryMiddleware {
-    inner: RetryTransientMiddleware<ExponentialBackoff>,
+    policy: ExponentialBackoff,
+    config: RetryConfig,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            min_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30 * 60),
+            max_server_delay: Duration::from_secs(10 * 60),
+            jitter: BackoffJitter::Full,
+            deadline: None,
+            retry_statuses: vec![408, 429, 500, 502, 503, 504],
+            post: PostRetry::Always,
+        }
+    }
+}
+
+impl RetryConfig {
+    fn should_retry(&self, req: &Request) -> bool {
+        // streamed bodies (e.g. the audio files read from disk) can't be replayed
+        if req.body().is_some_and(|body| body.as_bytes().is_none()) {
+            return false;
+        }
+        if req.method() != Method::POST {
+            return true;
+        }
+        match self.post {
+            PostRetry::Always => true,
+            PostRetry::WithIdempotencyKey => req.headers().contains_key("idempotency-key"),
+            PostRetry::Never => false,
+        }
+    }
+
+    fn is_transient(&self, result: &Result<Response>) -> bool {
+        match result {
+            Ok(res) => self.retry_statuses.contains(&res.status().as_u16()),
+            Err(_) => Retryable::from_reqwest_response(result) == Some(Retryable::Transient),
+        }
+    }
+}
+
+impl RetryMiddleware {
+    pub(crate) fn new(max_retries: u32, config: RetryConfig) -> Self {
+        let jitter = match config.jitter {
+            BackoffJitter::None => Jitter::None,
+            BackoffJitter::Full => Jitter::Full,
+            BackoffJitter::Bounded => Jitter::Bounded,
+        };
+        let policy = ExponentialBackoff::builder()
+            .retry_bounds(config.min_backoff, config.max_backoff)
+            .jitter(jitter)
+            .build_with_max_retries(max_retries);
+        Self { policy, config }
+    }
+
+    async fn retry(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        let start = Instant::now();
+        let mut n_past_retries = 0;
+        loop {
+            let duplicate = req
+                .try_clone()
+                .ok_or_else(|| Error::Middleware(anyhow::anyhow!("the request is not clonable")))?;
+            let result = next.clone().run(duplicate, extensions).await;
+            if !self.config.is_transient(&result) {
+                return result;
+            }
+            let RetryDecision::Retry { execute_after } = self.policy.should_retry(n_past_retries)
+            else {
+                return result;
+            };
+            let server_delay = result.as_ref().ok().and_then(|res| {
+                server_delay(res.status(), res.headers(), self.config.max_server_delay)
+            });
+            let delay = match server_delay {
+                Some(delay) => delay,
+                None => {
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_millis() as i64;
+                    Duration::from_millis((execute_after.timestamp_millis() - now).max(0) as u64)
+                }
+            };
+            if let Some(deadline) = self.config.deadline {
+                if start.elapsed() + delay > deadline {
+                    return result;
+                }
+            }
+            warn!(
+                "retry attempt #{}, sleeping {:?} before the next attempt",
+                n_past_retries, delay
+            );
+            tokio::time::sleep(delay).await;
+            n_past_retries += 1;
+        }
+    }
+}
+
+/// The delay the server asks for before retrying: `retry-after-ms`, `retry-after` in seconds
+/// (the HTTP date form falls back to the backoff), or for a 429 the reset of the exhausted rate
+/// limit. `None` if it's longer than `max`.
+fn server_delay(status: StatusCode, headers: &HeaderMap, max: Duration) -> Option<Duration> {
+    let get = |name: &str| headers.get(name)?.to_str().ok();
+    // non-finite or overflowing values are ignored like a missing header
+    let secs = |name: &str, scale: f64| {
+        let value = get(name)?.trim().parse::<f64>().ok()?;
+        if !value.is_finite() {
+            return None;
+        }
+        Duration::try_from_secs_f64((value / scale).max(0.0)).ok()
+    };
+    let delay = match secs("retry-after-ms", 1000.0).or_else(|| secs("retry-after", 1.0)) {
+        Some(delay) => delay,
+        None if status != StatusCode::TOO_MANY_REQUESTS => return None,
+        None => {
+            let limits = RateLimits::from_headers(headers)?;
+            let exhausted = |remaining: Option<u64>, reset: Option<Duration>| {
+                reset.filter(|_| remaining == Some(0))
+            };
+            exhausted(limits.remaining_requests, limits.reset_requests)
+                .into_iter()
+                .chain(exhausted(limits.remaining_tokens, limits.reset_tokens))
+                .max()?
+        }
+    };
+    (delay <= max).then_some(delay)
 }
 
 #[async_trait::async_trait]
@@ -15,29 +246,293 @@ impl Middleware for RetryMiddleware {
         extensions: &mut Extensions,
         next: Next<'_>,
     ) -> Result<Response> {
-        // check if req is cloneable without using try_clone
-        // check request header - if content-type is multipart/form-data, then don't retry
-        match req.headers().get(header::CONTENT_TYPE).map(|v| v.to_str()) {
-            Some(Ok(content_type)) => {
-                if content_type.contains("multipart/form-data")
-                    || content_type == "application/octet-stream"
-                {
-                    next.run(req, extensions).await
-                } else {
-                    // what about other content types? But at least for OpenAI APIs, we only see multipart/form-data as non-retryable
-                    self.inner.handle(req, extensions, next).await
-                }
-            }
-            _ => {
-                // does this mean, no body?
-                self.inner.handle(req, extensions, next).await
-            }
+        if !self.config.should_retry(&req) {
+            return next.run(req, extensions).await;
+        }
+        self.retry(req, extensions, next).await
+    }
+}
+
+/// Rejects the requests with a body larger than `max` bytes before they're sent, see
+/// `LlmSdkBuilder::max_body_size`. Streamed bodies are not checked.
+pub(crate) struct BodyLimitMiddleware {
+    max: usize,
+}
+
+impl BodyLimitMiddleware {
+    pub(crate) fn new(max: usize) -> Self {
+        Self { max }
+    }
+
+    fn check(&self, req: &Request) -> std::result::Result<(), LlmError> {
+        let size = req
+            .body()
+            .and_then(|body| body.as_bytes())
+            .map_or(0, |b| b.len());
+        if size > self.max {
+            return Err(LlmError::PayloadTooLarge {
+                size: Some(size),
+                limit: Some(self.max),
+            });
         }
+        Ok(())
     }
 }
 
-impl From<RetryTransientMiddleware<ExponentialBackoff>> for RetryMiddleware {
-    fn from(inner: RetryTransientMiddleware<ExponentialBackoff>) -> Self {
-        Self { inner }
+#[async_trait::async_trait]
+impl Middleware for BodyLimitMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        self.check(&req).map_err(|e| Error::Middleware(e.into()))?;
+        next.run(req, extensions).await
+    }
+}
+
+/// Endpoints which Azure OpenAI serves per deployment, i.e. under `/deployments/{name}`.
+const AZURE_DEPLOYMENT_PATHS: [&str; 5] = [
+    "/chat/completions",
+    "/completions",
+    "/embeddings",
+    "/images/",
+    "/audio/",
+];
+
+/// Rewrite the OpenAI style urls into the Azure OpenAI deployment urls, e.g.
+/// `{base}/chat/completions` to `{base}/deployments/{deployment}/chat/completions`.
+pub(crate) struct AzureDeploymentMiddleware {
+    base_path: String,
+    deployment: String,
+}
+
+impl AzureDeploymentMiddleware {
+    pub(crate) fn new(base_url: &str, deployment: impl Into<String>) -> Self {
+        let base_path = reqwest::Url::parse(base_url)
+            .map(|url| url.path().trim_end_matches('/').to_string())
+            .unwrap_or_default();
+        Self {
+            base_path,
+            deployment: deployment.into(),
+        }
+    }
+
+    fn rewrite(&self, path: &str) -> Option<String> {
+        let rest = path.strip_prefix(&self.base_path)?;
+        AZURE_DEPLOYMENT_PATHS
+            .iter()
+            .any(|p| rest.starts_with(p))
+            .then(|| format!("{}/deployments/{}{}", self.base_path, self.deployment, rest))
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for AzureDeploymentMiddleware {
+    async fn handle(
+        &self,
+        mut req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        if let Some(path) = self.rewrite(req.url().path()) {
+            req.url_mut().set_path(&path);
+        }
+        next.run(req, extensions).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// The default `max_server_delay`.
+    const MAX: Duration = Duration::from_secs(10 * 60);
+
+    /// Records its name, and fails the request instead of sending it if it is the last layer.
+    struct Recorder {
+        name: &'static str,
+        seen: Arc<Mutex<Vec<&'static str>>>,
+        last: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl Middleware for Recorder {
+        async fn handle(
+            &self,
+            req: Request,
+            extensions: &mut Extensions,
+            next: Next<'_>,
+        ) -> Result<Response> {
+            self.seen.lock().unwrap().push(self.name);
+            if self.last {
+                return Err(reqwest_middleware::Error::Middleware(anyhow::anyhow!(
+                    "stop"
+                )));
+            }
+            next.run(req, extensions).await
+        }
+    }
+
+    #[tokio::test]
+    async fn middleware_layers_should_run_in_order() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorder = |name, last| {
+            MiddlewareLayer::custom(Recorder {
+                name,
+                seen: seen.clone(),
+                last,
+            })
+        };
+        let layers = [recorder("cache", false), recorder("limiter", true)];
+        let client = layers
+            .iter()
+            .fold(
+                ClientBuilder::new(reqwest::Client::new()),
+                |builder, layer| layer.apply(builder, 0, &RetryConfig::default()),
+            )
+            .build();
+        assert!(client.get("http://localhost/").send().await.is_err());
+        assert_eq!(*seen.lock().unwrap(), ["cache", "limiter"]);
+    }
+
+    #[test]
+    fn azure_middleware_should_rewrite_deployment_paths() {
+        let m = AzureDeploymentMiddleware::new("https://test.openai.azure.com/openai", "gpt4");
+        assert_eq!(
+            m.rewrite("/openai/chat/completions").as_deref(),
+            Some("/openai/deployments/gpt4/chat/completions")
+        );
+        assert_eq!(
+            m.rewrite("/openai/audio/speech").as_deref(),
+            Some("/openai/deployments/gpt4/audio/speech")
+        );
+        assert_eq!(m.rewrite("/openai/assistants"), None);
+    }
+
+    #[test]
+    fn body_limit_should_reject_large_bodies() {
+        let url: reqwest::Url = "http://localhost/v1/embeddings".parse().unwrap();
+        let mut req = Request::new(Method::POST, url);
+        *req.body_mut() = Some(vec![0; 100].into());
+        assert!(BodyLimitMiddleware::new(100).check(&req).is_ok());
+        assert_eq!(
+            BodyLimitMiddleware::new(99).check(&req),
+            Err(LlmError::PayloadTooLarge {
+                size: Some(100),
+                limit: Some(99)
+            })
+        );
+    }
+
+    #[test]
+    fn retry_config_should_select_requests() {
+        let url: reqwest::Url = "http://localhost/v1/chat/completions".parse().unwrap();
+        let post = Request::new(Method::POST, url.clone());
+        let mut keyed = Request::new(Method::POST, url.clone());
+        keyed
+            .headers_mut()
+            .insert("idempotency-key", "abc".parse().unwrap());
+        let get = Request::new(Method::GET, url);
+
+        let config = RetryConfig::default();
+        assert!(config.should_retry(&post));
+        let config = RetryConfig {
+            post: PostRetry::WithIdempotencyKey,
+            ..Default::default()
+        };
+        assert!(!config.should_retry(&post));
+        assert!(config.should_retry(&keyed));
+        assert!(config.should_retry(&get));
+        let config = RetryConfig {
+            post: PostRetry::Never,
+            ..Default::default()
+        };
+        assert!(!config.should_retry(&keyed));
+    }
+
+    #[test]
+    fn server_delay_should_read_retry_headers() {
+        let headers = |pairs: &[(&'static str, &str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in pairs {
+                headers.insert(*name, value.parse().unwrap());
+            }
+            headers
+        };
+        let status = StatusCode::SERVICE_UNAVAILABLE;
+        assert_eq!(
+            server_delay(status, &headers(&[("retry-after", "2")]), MAX),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(
+            server_delay(
+                status,
+                &headers(&[("retry-after", "2"), ("retry-after-ms", "1500")]),
+                MAX
+            ),
+            Some(Duration::from_millis(1500))
+        );
+        let limits = headers(&[
+            ("x-ratelimit-remaining-requests", "10"),
+            ("x-ratelimit-remaining-tokens", "0"),
+            ("x-ratelimit-reset-requests", "1s"),
+            ("x-ratelimit-reset-tokens", "6s"),
+        ]);
+        assert_eq!(server_delay(status, &limits, MAX), None);
+        assert_eq!(
+            server_delay(StatusCode::TOO_MANY_REQUESTS, &limits, MAX),
+            Some(Duration::from_secs(6))
+        );
+        let limits = headers(&[
+            ("x-ratelimit-remaining-tokens", "0"),
+            ("x-ratelimit-reset-tokens", "6m0s"),
+        ]);
+        assert_eq!(
+            server_delay(StatusCode::TOO_MANY_REQUESTS, &limits, MAX),
+            Some(Duration::from_secs(360))
+        );
+        assert_eq!(
+            server_delay(status, &headers(&[("retry-after", "120")]), MAX),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            server_delay(StatusCode::TOO_MANY_REQUESTS, &HeaderMap::new(), MAX),
+            None
+        );
+    }
+
+    #[test]
+    fn server_delay_should_ignore_invalid_retry_headers() {
+        let headers = |name: &'static str, value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(name, value.parse().unwrap());
+            headers
+        };
+        let status = StatusCode::SERVICE_UNAVAILABLE;
+        for value in ["inf", "NaN", "-inf"] {
+            assert_eq!(
+                server_delay(status, &headers("retry-after", value), MAX),
+                None
+            );
+            assert_eq!(
+                server_delay(status, &headers("retry-after-ms", value), MAX),
+                None
+            );
+        }
+        assert_eq!(
+            server_delay(status, &headers("retry-after", "1e30"), MAX),
+            None
+        );
+        assert_eq!(
+            server_delay(status, &headers("retry-after", "3600"), MAX),
+            None
+        );
+        assert_eq!(
+            server_delay(status, &headers("retry-after-ms", "-5"), MAX),
+            Some(Duration::ZERO)
+        );
     }
 }
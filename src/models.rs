@@ -0,0 +1,134 @@
+use serde::Serialize;
+use std::{
+    collections::HashSet,
+    sync::{Mutex, OnceLock},
+};
+use tracing::warn;
+
+/// What the SDK knows about a model.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelInfo {
+    /// The model id as used by the API.
+    pub name: &'static str,
+    /// Set when the provider has announced a deprecation of the model.
+    pub deprecation: Option<Deprecation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deprecation {
+    /// The date (YYYY-MM-DD) the model is shut down.
+    pub shutdown_date: &'static str,
+    /// The model recommended as replacement.
+    pub replacement: &'static str,
+}
+
+const fn deprecated(
+    name: &'static str,
+    shutdown_date: &'static str,
+    replacement: &'static str,
+) -> ModelInfo {
+    ModelInfo {
+        name,
+        deprecation: Some(Deprecation {
+            shutdown_date,
+            replacement,
+        }),
+    }
+}
+
+const fn active(name: &'static str) -> ModelInfo {
+    ModelInfo {
+        name,
+        deprecation: None,
+    }
+}
+
+static MODELS: &[ModelInfo] = &[
+    active("gpt-3.5-turbo-1106"),
+    active("gpt-3.5-turbo-instruct"),
+    active("gpt-4-1106-preview"),
+    active("gpt-4o"),
+    active("gpt-4o-audio-preview"),
+    active("claude-3-5-sonnet-20240620"),
+    active("claude-3-opus-20240229"),
+    active("claude-3-haiku-20240307"),
+    deprecated("gpt-4-1106-vision-preview", "2024-12-06", "gpt-4o"),
+    deprecated("gpt-4-vision-preview", "2024-12-06", "gpt-4o"),
+    deprecated("gpt-3.5-turbo-0613", "2024-09-13", "gpt-3.5-turbo"),
+    deprecated("gpt-3.5-turbo-16k-0613", "2024-09-13", "gpt-3.5-turbo"),
+    deprecated("text-davinci-003", "2024-01-04", "gpt-3.5-turbo-instruct"),
+    active("davinci-002"),
+    active("babbage-002"),
+    active("text-embedding-ada-002"),
+    active("text-embedding-3-small"),
+    active("text-embedding-3-large"),
+    active("dall-e-2"),
+    active("dall-e-3"),
+    active("gpt-image-1"),
+    active("tts-1"),
+    active("tts-1-hd"),
+    active("gpt-4o-mini-tts"),
+    active("whisper-1"),
+    active("gpt-4o-transcribe"),
+    active("gpt-4o-mini-transcribe"),
+];
+
+/// Look up a model in the registry by its API name.
+pub fn model_info(name: &str) -> Option<&'static ModelInfo> {
+    MODELS.iter().find(|m| m.name == name)
+}
+
+/// Emit a tracing warning the first time (per process) a deprecated model is used.
+pub(crate) fn warn_if_deprecated(model: &impl Serialize) {
+    let Ok(serde_json::Value::String(name)) = serde_json::to_value(model) else {
+        return;
+    };
+    let Some(info) = model_info(&name) else {
+        return;
+    };
+    let Some(deprecation) = &info.deprecation else {
+        return;
+    };
+    static WARNED: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+    let mut warned = WARNED
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    if warned.insert(info.name) {
+        warn!(
+            "model {} is deprecated and will be shut down on {}, please migrate to {}",
+            info.name, deprecation.shutdown_date, deprecation.replacement
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChatCompleteModel;
+
+    #[test]
+    fn model_info_should_include_deprecation() {
+        let info = model_info("gpt-4-1106-vision-preview").unwrap();
+        assert_eq!(
+            info.deprecation,
+            Some(Deprecation {
+                shutdown_date: "2024-12-06",
+                replacement: "gpt-4o"
+            })
+        );
+        assert!(model_info("gpt-4-1106-preview")
+            .unwrap()
+            .deprecation
+            .is_none());
+        assert!(model_info("unknown-model").is_none());
+    }
+
+    #[test]
+    fn warn_if_deprecated_should_accept_model_enums() {
+        // nothing to assert other than it doesn't panic for known / unknown models
+        warn_if_deprecated(&ChatCompleteModel::Gpt4TurboVision);
+        warn_if_deprecated(&ChatCompleteModel::Gpt4TurboVision);
+        warn_if_deprecated(&"my-own-model");
+    }
+}
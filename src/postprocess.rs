@@ -0,0 +1,328 @@
+//! Post-processing of the completion texts, set per request with
+//! `ChatCompletionRequestBuilder::post_process` and applied by `LlmSdk::chat_completion`:
+//! normalize the typographic punctuation, strip the markdown for plain text output, and rewrite
+//! the numbers and ISO dates to the conventions of a locale.
+
+use strum::{Display, EnumString};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PostProcess {
+    normalize_punctuation: bool,
+    plain_text: bool,
+    locale: Option<Locale>,
+}
+
+/// The number and date conventions of a locale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display)]
+pub enum Locale {
+    /// `1,234.5` and `01/31/2024`.
+    #[strum(serialize = "en-US")]
+    EnUs,
+    /// `1,234.5` and `31/01/2024`.
+    #[strum(serialize = "en-GB")]
+    EnGb,
+    /// `1.234,5` and `31.01.2024`.
+    #[strum(serialize = "de-DE")]
+    DeDe,
+    /// `1 234,5` and `31/01/2024`.
+    #[strum(serialize = "fr-FR")]
+    FrFr,
+}
+
+impl PostProcess {
+    /// Replace the curly quotes, dashes, ellipses and non-breaking spaces by their ASCII forms.
+    pub fn normalize_punctuation(mut self) -> Self {
+        self.normalize_punctuation = true;
+        self
+    }
+
+    /// Strip the markdown syntax, for output shown as plain text (e.g. SMS or speech).
+    pub fn plain_text(mut self) -> Self {
+        self.plain_text = true;
+        self
+    }
+
+    /// Rewrite the unambiguous numbers (e.g. `3.5` or `1,234.5`, but not `1,234`) and the ISO
+    /// dates to the conventions of `locale`.
+    pub fn locale(mut self, locale: Locale) -> Self {
+        self.locale = Some(locale);
+        self
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        if self.plain_text {
+            text = strip_markdown(&text);
+        }
+        if self.normalize_punctuation {
+            text = normalize_punctuation(&text);
+        }
+        if let Some(locale) = self.locale {
+            text = localize_dates(&localize_numbers(&text, locale), locale);
+        }
+        text
+    }
+}
+
+impl Locale {
+    fn decimal_separator(&self) -> char {
+        match self {
+            Self::EnUs | Self::EnGb => '.',
+            Self::DeDe | Self::FrFr => ',',
+        }
+    }
+
+    fn thousands_separator(&self) -> char {
+        match self {
+            Self::EnUs | Self::EnGb => ',',
+            Self::DeDe => '.',
+            Self::FrFr => ' ',
+        }
+    }
+
+    fn format_date(&self, year: &str, month: &str, day: &str) -> String {
+        match self {
+            Self::EnUs => format!("{}/{}/{}", month, day, year),
+            Self::EnGb | Self::FrFr => format!("{}/{}/{}", day, month, year),
+            Self::DeDe => format!("{}.{}.{}", day, month, year),
+        }
+    }
+}
+
+fn normalize_punctuation(text: &str) -> String {
+    let mut ret = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}' => ret.push('\''),
+            '\u{201c}' | '\u{201d}' | '\u{201e}' | '\u{201f}' => ret.push('"'),
+            '\u{2013}' | '\u{2014}' => ret.push('-'),
+            '\u{2026}' => ret.push_str("..."),
+            '\u{00a0}' | '\u{202f}' => ret.push(' '),
+            c => ret.push(c),
+        }
+    }
+    ret
+}
+
+fn strip_markdown(text: &str) -> String {
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") {
+            continue;
+        }
+        let indent = &line[..line.len() - trimmed.len()];
+        let line = trimmed.trim_start_matches('#');
+        let line = if line.len() < trimmed.len() {
+            line.trim_start()
+        } else {
+            line
+        };
+        let line = line.strip_prefix("> ").unwrap_or(line);
+        let line = match line.strip_prefix("* ").or_else(|| line.strip_prefix("+ ")) {
+            Some(rest) => format!("- {}", rest),
+            None => line.to_string(),
+        };
+        lines.push(format!("{}{}", indent, strip_inline(&line)));
+    }
+    lines.join("\n")
+}
+
+/// Strip the emphasis, code spans and links of a line; `[text](url)` becomes `text (url)` and
+/// `![alt](url)` becomes `alt`.
+fn strip_inline(line: &str) -> String {
+    let line = line.replace("**", "").replace("__", "").replace('`', "");
+    let chars: Vec<char> = line.chars().collect();
+    let mut ret = String::with_capacity(line.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '[' || (c == '!' && chars.get(i + 1) == Some(&'[')) {
+            if let Some((label, url, end)) = parse_link(&chars, i + (c == '!') as usize) {
+                match c {
+                    '!' => ret.push_str(&label),
+                    _ => ret.push_str(&format!("{} ({})", label, url)),
+                }
+                i = end;
+                continue;
+            }
+        }
+        // an emphasis `*` touches a word on one side only, unlike `2 * 3`
+        let before = i.checked_sub(1).is_some_and(|j| chars[j].is_alphanumeric());
+        let after = chars.get(i + 1).is_some_and(|c| c.is_alphanumeric());
+        if c == '*' && before != after {
+            i += 1;
+            continue;
+        }
+        ret.push(c);
+        i += 1;
+    }
+    ret
+}
+
+/// Parse `[label](url)` at `start`, returning the label, the url and the index after the link.
+fn parse_link(chars: &[char], start: usize) -> Option<(String, String, usize)> {
+    let close = start + chars[start..].iter().position(|c| *c == ']')?;
+    if chars.get(close + 1) != Some(&'(') {
+        return None;
+    }
+    let end = close + 2 + chars[close + 2..].iter().position(|c| *c == ')')?;
+    let label = chars[start + 1..close].iter().collect();
+    let url = chars[close + 2..end].iter().collect();
+    Some((label, url, end + 1))
+}
+
+fn localize_numbers(text: &str, locale: Locale) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut ret = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        if !chars[i].is_ascii_digit() {
+            ret.push(chars[i]);
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.' || chars[i] == ',') {
+            i += 1;
+        }
+        // a separator at the end is punctuation
+        while !chars[i - 1].is_ascii_digit() {
+            i -= 1;
+        }
+        let token: String = chars[start..i].iter().collect();
+        // part of a word or a version, e.g. `v1.2` or `1.2.3-beta`
+        let attached = |c: Option<&char>| c.is_some_and(|c| c.is_alphabetic() || *c == '-');
+        if attached(start.checked_sub(1).and_then(|j| chars.get(j))) || attached(chars.get(i)) {
+            ret.push_str(&token);
+            continue;
+        }
+        match parse_number(&token) {
+            Some((int, frac, grouped)) => ret.push_str(&format_number(&int, frac, grouped, locale)),
+            None => ret.push_str(&token),
+        }
+    }
+    ret
+}
+
+/// Parse a number written with either convention: the integer digits, the fraction digits and
+/// whether the integer was grouped. `None` if the convention is ambiguous (`1,234`) or if it is
+/// not a number (`1.2.3`).
+fn parse_number(token: &str) -> Option<(String, Option<String>, bool)> {
+    let seps: Vec<char> = token.chars().filter(|c| !c.is_ascii_digit()).collect();
+    let (thousands, decimal) = match seps.as_slice() {
+        [] => return None,
+        [sep] => {
+            let frac = token.rsplit(*sep).next()?;
+            if frac.len() == 3 {
+                return None;
+            }
+            (None, Some(*sep))
+        }
+        [.., last] if seps.iter().all(|c| c == last) => (Some(*last), None),
+        [first, .., last] if seps[..seps.len() - 1].iter().all(|c| c == first) => {
+            (Some(*first), Some(*last))
+        }
+        _ => return None,
+    };
+    let (int, frac) = match decimal {
+        Some(sep) => {
+            let (int, frac) = token.rsplit_once(sep)?;
+            (int, Some(frac.to_string()))
+        }
+        None => (token, None),
+    };
+    if let Some(sep) = thousands {
+        let groups: Vec<&str> = int.split(sep).collect();
+        let valid = (1..=3).contains(&groups[0].len()) && groups[1..].iter().all(|g| g.len() == 3);
+        if !valid {
+            return None;
+        }
+    }
+    let int = int.chars().filter(|c| c.is_ascii_digit()).collect();
+    Some((int, frac, thousands.is_some()))
+}
+
+fn format_number(int: &str, frac: Option<String>, grouped: bool, locale: Locale) -> String {
+    let mut ret = String::new();
+    for (i, c) in int.chars().enumerate() {
+        if grouped && i > 0 && (int.len() - i) % 3 == 0 {
+            ret.push(locale.thousands_separator());
+        }
+        ret.push(c);
+    }
+    if let Some(frac) = frac {
+        ret.push(locale.decimal_separator());
+        ret.push_str(&frac);
+    }
+    ret
+}
+
+/// Rewrite the ISO dates (`2024-01-31`).
+fn localize_dates(text: &str, locale: Locale) -> String {
+    let bytes = text.as_bytes();
+    let mut ret = String::with_capacity(text.len());
+    let mut last = 0;
+    let mut i = 0;
+    while i + 10 <= bytes.len() {
+        let candidate = &bytes[i..i + 10];
+        let is_date = candidate.iter().enumerate().all(|(j, b)| match j {
+            4 | 7 => *b == b'-',
+            _ => b.is_ascii_digit(),
+        });
+        let bounded = (i == 0 || !bytes[i - 1].is_ascii_alphanumeric())
+            && bytes
+                .get(i + 10)
+                .map_or(true, |b| !b.is_ascii_alphanumeric() && *b != b'-');
+        if is_date && bounded {
+            let date = &text[i..i + 10];
+            ret.push_str(&text[last..i]);
+            ret.push_str(&locale.format_date(&date[..4], &date[5..7], &date[8..]));
+            i += 10;
+            last = i;
+        } else {
+            i += 1;
+        }
+    }
+    ret.push_str(&text[last..]);
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn post_process_should_normalize_punctuation() {
+        let pp = PostProcess::default().normalize_punctuation();
+        assert_eq!(
+            pp.apply("\u{201c}It\u{2019}s fine\u{201d} \u{2014} wait\u{2026}"),
+            "\"It's fine\" - wait..."
+        );
+    }
+
+    #[test]
+    fn post_process_should_strip_markdown() {
+        let pp = PostProcess::default().plain_text();
+        let text = "## Steps\n\n* **Install** the `cli`\n* Read the [docs](https://docs.rs)\n> *Note*: 2 * 3 = 6\n```\nrun\n```";
+        assert_eq!(
+            pp.apply(text),
+            "Steps\n\n- Install the cli\n- Read the docs (https://docs.rs)\nNote: 2 * 3 = 6\nrun"
+        );
+    }
+
+    #[test]
+    fn post_process_should_localize_numbers_and_dates() {
+        let pp = PostProcess::default().locale(Locale::DeDe);
+        assert_eq!(
+            pp.apply("It costs 1,234.50 or 3.5, paid 1,234 on 2024-01-31 with v1.2."),
+            "It costs 1.234,50 or 3,5, paid 1,234 on 31.01.2024 with v1.2."
+        );
+        let pp = PostProcess::default().locale(Locale::EnUs);
+        assert_eq!(
+            pp.apply("1.234.567,8 and 0,25 since 2024-01-31"),
+            "1,234,567.8 and 0.25 since 01/31/2024"
+        );
+        assert_eq!("fr-FR".parse::<Locale>().unwrap(), Locale::FrFr);
+    }
+}
@@ -0,0 +1,214 @@
+//! Admission checks before a call: `LlmSdk::preflight` estimates the tokens (or audio minutes)
+//! and the cost of an embedding or transcription request, and whether the budget of the
+//! `UsageTracker` and the remaining rate limits allow it, so a scheduler can hold the call back
+//! instead of having it rejected.
+
+use crate::{
+    api::estimate_tokens, usage::model_name, AudioFormat, EmbeddingInput, EmbeddingRequest,
+    PricingTable, WhisperFile, WhisperRequest,
+};
+
+/// The audio bytes per second when the duration can't be read from the file, by format.
+const AUDIO_BYTE_RATES: [(AudioFormat, f64); 7] = [
+    (AudioFormat::Flac, 88_200.0),
+    (AudioFormat::M4a, 16_000.0),
+    (AudioFormat::Mp3, 16_000.0),
+    (AudioFormat::Mp4, 16_000.0),
+    (AudioFormat::Ogg, 8_000.0),
+    (AudioFormat::Wav, 32_000.0),
+    (AudioFormat::Webm, 8_000.0),
+];
+
+/// What a request is expected to consume.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PreflightEstimate {
+    pub model: String,
+    /// The estimated input tokens, 0 for the audio models.
+    pub tokens: usize,
+    /// The estimated audio minutes, for the transcriptions and translations.
+    pub minutes: Option<f64>,
+    /// The estimated cost in USD, 0 if the model has no known price.
+    pub cost: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreflightReport {
+    pub estimate: PreflightEstimate,
+    /// The requests and tokens the call takes from the rate limits.
+    pub rate_limit_requests: u64,
+    pub rate_limit_tokens: u64,
+    /// The remaining requests and tokens as last reported by the API, `None` if unknown.
+    pub remaining_requests: Option<u64>,
+    pub remaining_tokens: Option<u64>,
+    /// `None` if the call is allowed, else why not.
+    pub rejection: Option<String>,
+}
+
+/// A request `LlmSdk::preflight` can estimate.
+pub trait Preflight {
+    fn estimate(&self, pricing: &PricingTable) -> PreflightEstimate;
+}
+
+impl PreflightReport {
+    pub fn allowed(&self) -> bool {
+        self.rejection.is_none()
+    }
+}
+
+impl Preflight for EmbeddingRequest {
+    fn estimate(&self, pricing: &PricingTable) -> PreflightEstimate {
+        let tokens = match &self.input {
+            EmbeddingInput::String(s) => estimate_tokens(s),
+            EmbeddingInput::StringArray(v) => v.iter().map(|s| estimate_tokens(s)).sum(),
+            EmbeddingInput::Tokens(t) => t.len(),
+            EmbeddingInput::TokensArray(v) => v.iter().map(|t| t.len()).sum(),
+        };
+        let model = model_name(&self.model);
+        PreflightEstimate {
+            cost: pricing.cost(&model, tokens, 0),
+            model,
+            tokens,
+            minutes: None,
+        }
+    }
+}
+
+impl Preflight for WhisperRequest {
+    fn estimate(&self, _pricing: &PricingTable) -> PreflightEstimate {
+        let minutes = audio_seconds(&self.file, self.format) / 60.0;
+        PreflightEstimate {
+            model: self.model.to_string(),
+            tokens: 0,
+            minutes: Some(minutes),
+            cost: minutes * self.model.price_per_minute(),
+        }
+    }
+}
+
+/// The duration of the audio: read from the header of an in-memory wav file, else estimated
+/// from its size with the typical bitrate of the format.
+fn audio_seconds(file: &WhisperFile, format: Option<AudioFormat>) -> f64 {
+    let (len, format) = match file {
+        WhisperFile::Bytes(data) => {
+            let format = format.or_else(|| AudioFormat::from_bytes(data));
+            if format == Some(AudioFormat::Wav) {
+                if let Some(secs) = wav_seconds(data) {
+                    return secs;
+                }
+            }
+            (data.len() as f64, format)
+        }
+        WhisperFile::Path { len, .. } => (*len as f64, format),
+    };
+    let format = format.unwrap_or(AudioFormat::Mp3);
+    let rate = AUDIO_BYTE_RATES
+        .iter()
+        .find(|(f, _)| *f == format)
+        .map_or(16_000.0, |(_, rate)| *rate);
+    len / rate
+}
+
+/// The duration of a canonical wav file, from the byte rate of its header.
+fn wav_seconds(data: &[u8]) -> Option<f64> {
+    let byte_rate = u32::from_le_bytes(data.get(28..32)?.try_into().ok()?);
+    (byte_rate > 0).then(|| data.len().saturating_sub(44) as f64 / byte_rate as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        EmbeddingModel, EmbeddingRequestBuilder, LlmSdk, LlmSdkBuilder, SpendLimit, UsageTracker,
+    };
+    use std::sync::Arc;
+
+    fn wav(secs: usize) -> Vec<u8> {
+        let byte_rate: u32 = 16_000 * 2;
+        let mut data = b"RIFF\0\0\0\0WAVEfmt ".to_vec();
+        data.resize(28, 0);
+        data.extend_from_slice(&byte_rate.to_le_bytes());
+        data.resize(44 + secs * byte_rate as usize, 0);
+        data
+    }
+
+    #[test]
+    fn embedding_preflight_should_estimate_tokens_and_cost() {
+        let req = EmbeddingRequestBuilder::default()
+            .input(EmbeddingInput::StringArray(vec!["a".repeat(4000); 250]))
+            .model(EmbeddingModel::TextEmbedding3Small)
+            .build()
+            .unwrap();
+        let estimate = req.estimate(&PricingTable::default());
+        assert_eq!(estimate.model, "text-embedding-3-small");
+        assert_eq!(estimate.tokens, 250_000);
+        assert!((estimate.cost - 0.005).abs() < 1e-9);
+    }
+
+    #[test]
+    fn preflight_should_check_budget() {
+        let tracker = UsageTracker::default().limit(SpendLimit::tokens_per_hour(1000));
+        let sdk = LlmSdkBuilder::default()
+            .base_url("http://localhost:11434/v1")
+            .token("")
+            .usage_tracker(Arc::new(tracker))
+            .build()
+            .unwrap();
+        let req = EmbeddingRequest::new("a".repeat(4000));
+        let report = sdk.preflight(&req);
+        assert!(report.allowed());
+        assert_eq!(report.rate_limit_tokens, 1000);
+        assert_eq!(report.remaining_tokens, None);
+
+        let req = EmbeddingRequest::new("a".repeat(4004));
+        let report = sdk.preflight(&req);
+        assert!(!report.allowed());
+        assert_eq!(
+            report.rejection.as_deref(),
+            Some("spend limit of 1000 tokens per hour exceeded, 1001 tokens spent")
+        );
+    }
+
+    #[test]
+    fn preflight_should_report_rate_limit_shortfall() {
+        let sdk = LlmSdk::ollama("http://localhost:11434/v1");
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert("x-ratelimit-remaining-tokens", "600".parse().unwrap());
+        sdk.rate_limits.update(std::time::Instant::now(), &headers);
+        let report = sdk.preflight(&EmbeddingRequest::new("a".repeat(4000)));
+        assert_eq!(report.remaining_tokens, Some(600));
+        assert_eq!(
+            report.rejection.as_deref(),
+            Some("needs 1000 tokens, 600 remaining in the rate limit")
+        );
+    }
+
+    #[test]
+    fn whisper_preflight_should_estimate_minutes() {
+        let req = WhisperRequest::transcription(wav(90));
+        let estimate = req.estimate(&PricingTable::default());
+        assert_eq!(estimate.minutes, Some(1.5));
+        assert!((estimate.cost - 0.009).abs() < 1e-9);
+
+        let file = WhisperFile::Path {
+            path: "talk.mp3".into(),
+            len: 960_000,
+        };
+        assert_eq!(audio_seconds(&file, None), 60.0);
+    }
+
+    #[test]
+    fn per_call_costs_should_be_estimated() {
+        let req = WhisperRequest::transcription(wav(90));
+        let (model, endpoint, cost) = crate::whisper_cost(&req);
+        assert_eq!(
+            (model.as_str(), endpoint),
+            ("whisper-1", "audio/transcriptions")
+        );
+        assert!((cost - 0.009).abs() < 1e-9);
+
+        let req = crate::SpeechRequest::new("a".repeat(1000));
+        let (model, cost) = crate::speech_cost(&req);
+        assert_eq!(model, "tts-1");
+        assert!((cost - 0.015).abs() < 1e-9);
+    }
+}
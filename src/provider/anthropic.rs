@@ -0,0 +1,433 @@
+use super::{ParamProfile, Provider, ProviderExt};
+use crate::{
+    AssistantMessage, ChatCompleteModel, ChatCompleteUsage, ChatCompletionChoice,
+    ChatCompletionMessage, ChatCompletionRequest, ChatCompletionResponse, ContentPart,
+    FinishReason, FunctionCall, ToolCall, ToolChoice, ToolType, UserContent,
+};
+use anyhow::Result;
+use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+/// The Messages API requires max_tokens, used when the request doesn't set it.
+const DEFAULT_MAX_TOKENS: usize = 4096;
+
+/// The Anthropic Messages API. Use it with `AuthMethod::XApiKey`, see `LlmSdk::new_anthropic`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Anthropic;
+
+#[derive(Debug, Serialize)]
+struct MessagesRequest {
+    model: ChatCompleteModel,
+    max_tokens: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<ToolDefinition>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<AnthropicToolChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<Metadata>,
+}
+
+#[derive(Debug, Serialize)]
+struct Message {
+    role: Role,
+    content: Vec<ContentBlock>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Role {
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum ContentBlock {
+    Text {
+        text: String,
+    },
+    Image {
+        source: ImageSource,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum ImageSource {
+    Base64 { media_type: String, data: String },
+    Url { url: String },
+}
+
+#[derive(Debug, Serialize)]
+struct ToolDefinition {
+    name: String,
+    description: String,
+    input_schema: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum AnthropicToolChoice {
+    None,
+    Auto,
+    Tool { name: String },
+}
+
+#[derive(Debug, Serialize)]
+struct Metadata {
+    user_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessagesResponse {
+    id: String,
+    content: Vec<ContentBlock>,
+    #[serde(default)]
+    stop_reason: Option<String>,
+    #[serde(default)]
+    stop_sequence: Option<String>,
+    usage: Usage,
+}
+
+#[derive(Debug, Deserialize)]
+struct Usage {
+    input_tokens: usize,
+    output_tokens: usize,
+}
+
+impl Provider for Anthropic {
+    fn chat_completion_request(
+        &self,
+        req: ChatCompletionRequest,
+        base_url: &str,
+        client: ClientWithMiddleware,
+    ) -> RequestBuilder {
+        let url = format!("{}/messages", base_url);
+        client
+            .post(url)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .json(&MessagesRequest::from(req))
+    }
+
+    fn parse_chat_completion(
+        &self,
+        model: ChatCompleteModel,
+        body: &[u8],
+    ) -> Result<ChatCompletionResponse> {
+        let res: MessagesResponse = serde_json::from_slice(body)?;
+        Ok(res.into_response(model))
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    fn param_profile(&self, _model: &ChatCompleteModel) -> ParamProfile {
+        ParamProfile::anthropic()
+    }
+}
+
+impl From<ChatCompletionRequest> for MessagesRequest {
+    fn from(req: ChatCompletionRequest) -> Self {
+        let mut system = Vec::new();
+        let mut messages: Vec<Message> = Vec::new();
+        for message in req.messages {
+            let (role, blocks) = match message {
+                ChatCompletionMessage::System(m) => {
+                    system.push(m.content);
+                    continue;
+                }
+                ChatCompletionMessage::User(m) => (Role::User, user_blocks(m.content)),
+                ChatCompletionMessage::Assistant(m) => (Role::Assistant, assistant_blocks(m)),
+                // tool results are sent back as a user message
+                ChatCompletionMessage::Tool(m) => (
+                    Role::User,
+                    vec![ContentBlock::ToolResult {
+                        tool_use_id: m.tool_call_id,
+                        content: m.content,
+                    }],
+                ),
+            };
+            // the roles must alternate, so merge consecutive messages of the same role
+            match messages.last_mut() {
+                Some(last) if last.role == role => last.content.extend(blocks),
+                _ => messages.push(Message {
+                    role,
+                    content: blocks,
+                }),
+            }
+        }
+
+        let tools = req
+            .tools
+            .into_iter()
+            .map(|tool| ToolDefinition {
+                name: tool.function.name,
+                description: tool.function.description,
+                input_schema: tool.function.parameters,
+            })
+            .collect();
+        let tool_choice = req.tool_choice.map(|choice| match choice {
+            ToolChoice::None => AnthropicToolChoice::None,
+            ToolChoice::Auto => AnthropicToolChoice::Auto,
+            ToolChoice::Function { name } => AnthropicToolChoice::Tool { name },
+        });
+
+        Self {
+            model: req.model,
+            max_tokens: req.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            system: (!system.is_empty()).then(|| system.join("\n\n")),
+            messages,
+            stop_sequences: req.stop.into_iter().collect(),
+            temperature: req.temperature,
+            top_p: req.top_p,
+            tools,
+            tool_choice,
+            metadata: req.user.map(|user_id| Metadata { user_id }),
+        }
+    }
+}
+
+fn user_blocks(content: UserContent) -> Vec<ContentBlock> {
+    let parts = match content {
+        UserContent::Text(text) => return vec![ContentBlock::Text { text }],
+        UserContent::Parts(parts) => parts,
+    };
+    parts
+        .into_iter()
+        .filter_map(|part| match part {
+            ContentPart::Text { text } => Some(ContentBlock::Text { text }),
+            ContentPart::ImageUrl { image_url } => {
+                // data:{media_type};base64,{data}
+                let source = image_url
+                    .url
+                    .strip_prefix("data:")
+                    .and_then(|rest| rest.split_once(";base64,"))
+                    .map(|(media_type, data)| ImageSource::Base64 {
+                        media_type: media_type.to_string(),
+                        data: data.to_string(),
+                    })
+                    .unwrap_or(ImageSource::Url { url: image_url.url });
+                Some(ContentBlock::Image { source })
+            }
+            // the Messages API doesn't accept audio
+            ContentPart::InputAudio { .. } => None,
+        })
+        .collect()
+}
+
+fn assistant_blocks(m: AssistantMessage) -> Vec<ContentBlock> {
+    let text = m
+        .content
+        .filter(|c| !c.is_empty())
+        .map(|text| ContentBlock::Text { text });
+    let tool_uses = m.tool_calls.into_iter().map(|call| ContentBlock::ToolUse {
+        id: call.id,
+        name: call.function.name,
+        input: serde_json::from_str(&call.function.arguments).unwrap_or_default(),
+    });
+    text.into_iter().chain(tool_uses).collect()
+}
+
+impl MessagesResponse {
+    fn into_response(self, model: ChatCompleteModel) -> ChatCompletionResponse {
+        let mut texts = Vec::new();
+        let mut tool_calls = Vec::new();
+        for block in self.content {
+            match block {
+                ContentBlock::Text { text } => texts.push(text),
+                ContentBlock::ToolUse { id, name, input } => tool_calls.push(ToolCall {
+                    id,
+                    r#type: ToolType::Function,
+                    function: FunctionCall {
+                        name,
+                        arguments: input.to_string(),
+                    },
+                }),
+                ContentBlock::Image { .. } | ContentBlock::ToolResult { .. } => {}
+            }
+        }
+        let finish_reason = match self.stop_reason.as_deref() {
+            Some("max_tokens") => FinishReason::Length,
+            Some("tool_use") => FinishReason::ToolCalls,
+            _ => FinishReason::Stop,
+        };
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as usize)
+            .unwrap_or_default();
+        ChatCompletionResponse {
+            id: self.id,
+            choices: vec![ChatCompletionChoice {
+                finish_reason,
+                index: 0,
+                message: AssistantMessage {
+                    content: (!texts.is_empty()).then(|| texts.join("")),
+                    name: None,
+                    tool_calls,
+                    audio: None,
+                },
+                logprobs: None,
+            }],
+            created,
+            model,
+            system_fingerprint: None,
+            object: "chat.completion".into(),
+            usage: ChatCompleteUsage {
+                completion_tokens: self.usage.output_tokens,
+                prompt_tokens: self.usage.input_tokens,
+                total_tokens: self.usage.input_tokens + self.usage.output_tokens,
+            },
+            provider_ext: Some(ProviderExt::Anthropic {
+                stop_reason: self.stop_reason,
+                stop_sequence: self.stop_sequence,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChatCompletionRequestBuilder, Tool, ToolMessage};
+    use schemars::JsonSchema;
+    use serde_json::json;
+
+    #[allow(dead_code)]
+    #[derive(Debug, Deserialize, JsonSchema)]
+    struct GetWeatherArgs {
+        /// The city to get the weather for.
+        city: String,
+    }
+
+    #[test]
+    fn messages_request_should_map_chat_completion_request() -> Result<()> {
+        let messages = vec![
+            ChatCompletionMessage::new_system("You are a weather bot.", ""),
+            ChatCompletionMessage::new_user("What's the weather in Boston?", ""),
+            ChatCompletionMessage::Assistant(AssistantMessage {
+                content: None,
+                name: None,
+                tool_calls: vec![ToolCall {
+                    id: "toolu_01".into(),
+                    r#type: ToolType::Function,
+                    function: FunctionCall {
+                        name: "get_weather".into(),
+                        arguments: r#"{"city":"Boston"}"#.into(),
+                    },
+                }],
+                audio: None,
+            }),
+            ChatCompletionMessage::Tool(ToolMessage {
+                content: "22 degrees".into(),
+                tool_call_id: "toolu_01".into(),
+            }),
+            ChatCompletionMessage::new_user("And in celsius?", ""),
+        ];
+        let req = ChatCompletionRequestBuilder::default()
+            .model(ChatCompleteModel::Claude35Sonnet)
+            .messages(messages)
+            .tools(vec![Tool::new_function::<GetWeatherArgs>(
+                "get_weather",
+                "Get the weather of a city",
+            )])
+            .build()?;
+        let value = serde_json::to_value(MessagesRequest::from(req))?;
+        assert_eq!(value["model"], "claude-3-5-sonnet-20240620");
+        assert_eq!(value["max_tokens"], DEFAULT_MAX_TOKENS);
+        assert_eq!(value["system"], "You are a weather bot.");
+        assert_eq!(value["tools"][0]["name"], "get_weather");
+        assert_eq!(
+            value["messages"],
+            json!([
+              { "role": "user", "content": [{ "type": "text", "text": "What's the weather in Boston?" }] },
+              { "role": "assistant", "content": [{
+                "type": "tool_use", "id": "toolu_01", "name": "get_weather", "input": { "city": "Boston" }
+              }] },
+              { "role": "user", "content": [
+                { "type": "tool_result", "tool_use_id": "toolu_01", "content": "22 degrees" },
+                { "type": "text", "text": "And in celsius?" }
+              ] }
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn messages_request_should_map_images() -> Result<()> {
+        let message = ChatCompletionMessage::new_user_with_parts(
+            vec![
+                ContentPart::image_bytes(b"hello", "image/png", None),
+                ContentPart::text("What's in this image?"),
+            ],
+            "",
+        );
+        let req = ChatCompletionRequest::new(ChatCompleteModel::Claude3Haiku, vec![message]);
+        let value = serde_json::to_value(MessagesRequest::from(req))?;
+        assert_eq!(
+            value["messages"][0]["content"][0],
+            json!({
+              "type": "image",
+              "source": { "type": "base64", "media_type": "image/png", "data": "aGVsbG8=" }
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn messages_response_should_map_to_chat_completion_response() -> Result<()> {
+        let body = json!({
+          "id": "msg_01",
+          "type": "message",
+          "role": "assistant",
+          "model": "claude-3-5-sonnet-20240620",
+          "content": [
+            { "type": "text", "text": "Let me check." },
+            { "type": "tool_use", "id": "toolu_01", "name": "get_weather", "input": { "city": "Boston" } }
+          ],
+          "stop_reason": "tool_use",
+          "stop_sequence": null,
+          "usage": { "input_tokens": 10, "output_tokens": 5 }
+        });
+        let res = Anthropic.parse_chat_completion(
+            ChatCompleteModel::Claude35Sonnet,
+            body.to_string().as_bytes(),
+        )?;
+        let choice = &res.choices[0];
+        assert_eq!(choice.finish_reason, FinishReason::ToolCalls);
+        assert_eq!(choice.message.content.as_deref(), Some("Let me check."));
+        assert_eq!(
+            choice.message.tool_calls[0].function.arguments,
+            r#"{"city":"Boston"}"#
+        );
+        assert_eq!(res.usage.total_tokens, 15);
+        assert_eq!(
+            res.provider_ext,
+            Some(ProviderExt::Anthropic {
+                stop_reason: Some("tool_use".into()),
+                stop_sequence: None
+            })
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use serde::Deserialize;
+
+/// Provider specific extras of a chat completion response, which have no OpenAI counterpart.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProviderExt {
+    Anthropic {
+        /// The raw stop reason, e.g. `end_turn` or `stop_sequence`.
+        stop_reason: Option<String>,
+        /// The stop sequence that was generated, if any.
+        stop_sequence: Option<String>,
+    },
+    Groq {
+        /// The Groq request id (`x_groq.id`).
+        request_id: Option<String>,
+        timing: GroqTiming,
+    },
+    OpenRouter {
+        /// The upstream provider which served the request.
+        provider: Option<String>,
+        /// The cost of the request in credits, if usage accounting is enabled.
+        cost: Option<f64>,
+    },
+}
+
+/// The timing of a Groq request in seconds, reported in its usage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct GroqTiming {
+    pub queue_time: f64,
+    pub prompt_time: f64,
+    pub completion_time: f64,
+    pub total_time: f64,
+}
+
+/// The extra fields of the OpenAI compatible providers.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct CompatibleExtras {
+    x_groq: Option<GroqExtras>,
+    provider: Option<String>,
+    usage: Option<UsageExtras>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct GroqExtras {
+    id: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct UsageExtras {
+    cost: Option<f64>,
+    #[serde(flatten)]
+    timing: GroqTiming,
+}
+
+impl ProviderExt {
+    /// Detect the extras of an OpenAI compatible response body, None for OpenAI itself.
+    pub(crate) fn from_compatible(body: &[u8]) -> Option<Self> {
+        let extras: CompatibleExtras = serde_json::from_slice(body).ok()?;
+        let usage = extras.usage.unwrap_or_default();
+        if let Some(groq) = extras.x_groq {
+            return Some(Self::Groq {
+                request_id: groq.id,
+                timing: usage.timing,
+            });
+        }
+        if extras.provider.is_some() || usage.cost.is_some() {
+            return Some(Self::OpenRouter {
+                provider: extras.provider,
+                cost: usage.cost,
+            });
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OpenAi, Provider};
+    use anyhow::Result;
+    use serde_json::json;
+
+    fn body(usage: serde_json::Value, extras: serde_json::Value) -> Vec<u8> {
+        let mut body = json!({
+          "id": "chatcmpl-1",
+          "object": "chat.completion",
+          "created": 1715000000,
+          "model": "llama-3.1-8b-instant",
+          "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": "Hi!" },
+            "finish_reason": "stop"
+          }],
+          "usage": { "prompt_tokens": 10, "completion_tokens": 2, "total_tokens": 12 }
+        });
+        let usage = usage.as_object().unwrap().clone();
+        body["usage"].as_object_mut().unwrap().extend(usage);
+        body.as_object_mut()
+            .unwrap()
+            .extend(extras.as_object().unwrap().clone());
+        body.to_string().into_bytes()
+    }
+
+    #[test]
+    fn provider_ext_should_detect_compatible_providers() -> Result<()> {
+        let model = crate::ChatCompleteModel::Other("llama-3.1-8b-instant".into());
+        let res = OpenAi.parse_chat_completion(
+            model.clone(),
+            &body(
+                json!({ "queue_time": 0.01, "prompt_time": 0.02, "completion_time": 0.03, "total_time": 0.05 }),
+                json!({ "x_groq": { "id": "req_01" } }),
+            ),
+        )?;
+        assert_eq!(
+            res.provider_ext,
+            Some(ProviderExt::Groq {
+                request_id: Some("req_01".into()),
+                timing: GroqTiming {
+                    queue_time: 0.01,
+                    prompt_time: 0.02,
+                    completion_time: 0.03,
+                    total_time: 0.05
+                }
+            })
+        );
+        assert_eq!(res.usage.total_tokens, 12);
+
+        let res = OpenAi.parse_chat_completion(
+            model.clone(),
+            &body(json!({ "cost": 0.0002 }), json!({ "provider": "Together" })),
+        )?;
+        assert_eq!(
+            res.provider_ext,
+            Some(ProviderExt::OpenRouter {
+                provider: Some("Together".into()),
+                cost: Some(0.0002)
+            })
+        );
+
+        let res = OpenAi.parse_chat_completion(model, &body(json!({}), json!({})))?;
+        assert_eq!(res.provider_ext, None);
+        Ok(())
+    }
+}
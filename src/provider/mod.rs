@@ -0,0 +1,192 @@
+mod anthropic;
+mod ext;
+mod profile;
+
+pub use anthropic::Anthropic;
+pub use ext::{GroqTiming, ProviderExt};
+pub use profile::ParamProfile;
+
+use crate::{ChatCompleteModel, ChatCompletionRequest, ChatCompletionResponse, IntoRequest};
+use anyhow::Result;
+use futures::{future::BoxFuture, FutureExt};
+use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
+use std::{fmt, future::Future, time::Duration};
+
+/// Maps the SDK's chat completion types onto the wire format of a provider, so the same
+/// `ChatCompletionRequest` can be sent to OpenAI (and compatible servers) or e.g. Anthropic.
+pub trait Provider: fmt::Debug + Send + Sync {
+    /// Build the HTTP request for a chat completion, without the credentials.
+    fn chat_completion_request(
+        &self,
+        req: ChatCompletionRequest,
+        base_url: &str,
+        client: ClientWithMiddleware,
+    ) -> RequestBuilder;
+
+    /// Parse the response body of a chat completion. `model` is the one of the request.
+    fn parse_chat_completion(
+        &self,
+        model: ChatCompleteModel,
+        body: &[u8],
+    ) -> Result<ChatCompletionResponse>;
+
+    /// Whether `LlmSdk::chat_completion_stream` works with this provider.
+    fn supports_stream(&self) -> bool {
+        false
+    }
+
+    /// Whether the `response_format` of the request (JSON mode and Structured Outputs) is
+    /// supported, see `LlmSdk::extract`.
+    fn supports_response_format(&self) -> bool {
+        false
+    }
+
+    /// Whether the tools (and `tool_choice`) of the request are supported.
+    fn supports_tools(&self) -> bool {
+        false
+    }
+
+    /// The parameter ranges of `model` on this provider, OpenAI's by default.
+    fn param_profile(&self, _model: &ChatCompleteModel) -> ParamProfile {
+        ParamProfile::openai()
+    }
+}
+
+/// A provider completing the chats itself instead of through the HTTP client of the SDK, e.g. an
+/// in-process model, or an API needing more than one request per call. Its future is not boxed,
+/// so the trait isn't object safe: `LlmSdkBuilder::async_provider` takes its boxed counterpart,
+/// `DynAsyncProvider`, implemented for every `AsyncProvider`.
+pub trait AsyncProvider: fmt::Debug + Send + Sync {
+    /// Complete `req`, once the SDK clamped its parameters and added the system prompt.
+    fn chat_completion(
+        &self,
+        req: ChatCompletionRequest,
+    ) -> impl Future<Output = Result<ChatCompletionResponse>> + Send;
+}
+
+/// The object safe `AsyncProvider`, returning a boxed future.
+pub trait DynAsyncProvider: fmt::Debug + Send + Sync {
+    fn chat_completion(
+        &self,
+        req: ChatCompletionRequest,
+    ) -> BoxFuture<'_, Result<ChatCompletionResponse>>;
+}
+
+impl<T: AsyncProvider> DynAsyncProvider for T {
+    fn chat_completion(
+        &self,
+        req: ChatCompletionRequest,
+    ) -> BoxFuture<'_, Result<ChatCompletionResponse>> {
+        AsyncProvider::chat_completion(self, req).boxed()
+    }
+}
+
+/// OpenAI and the OpenAI compatible servers. This is the default provider.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OpenAi;
+
+impl Provider for OpenAi {
+    fn chat_completion_request(
+        &self,
+        req: ChatCompletionRequest,
+        base_url: &str,
+        client: ClientWithMiddleware,
+    ) -> RequestBuilder {
+        req.into_request(base_url, client)
+    }
+
+    fn parse_chat_completion(
+        &self,
+        _model: ChatCompleteModel,
+        body: &[u8],
+    ) -> Result<ChatCompletionResponse> {
+        let mut res: ChatCompletionResponse = serde_json::from_slice(body)?;
+        res.provider_ext = ProviderExt::from_compatible(body);
+        Ok(res)
+    }
+
+    fn supports_stream(&self) -> bool {
+        true
+    }
+
+    fn supports_response_format(&self) -> bool {
+        true
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+}
+
+/// A chat completion request routed through a provider.
+pub(crate) struct ProviderRequest<'a> {
+    provider: &'a dyn Provider,
+    req: ChatCompletionRequest,
+}
+
+impl<'a> ProviderRequest<'a> {
+    pub(crate) fn new(provider: &'a dyn Provider, req: ChatCompletionRequest) -> Self {
+        Self { provider, req }
+    }
+}
+
+impl IntoRequest for ProviderRequest<'_> {
+    fn timeout(&self) -> Option<Duration> {
+        self.req.timeout
+    }
+
+    fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+        self.provider
+            .chat_completion_request(self.req, base_url, client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChatCompletionMessage, LlmSdkBuilder};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    /// Answers with the system prompt of the request.
+    #[derive(Debug)]
+    struct Echo;
+
+    impl AsyncProvider for Echo {
+        async fn chat_completion(
+            &self,
+            req: ChatCompletionRequest,
+        ) -> Result<ChatCompletionResponse> {
+            let prompt = serde_json::to_value(&req.messages)?[0]["content"].clone();
+            Ok(serde_json::from_value(json!({
+              "id": "chatcmpl-echo",
+              "object": "chat.completion",
+              "created": 1_700_000_000,
+              "model": "gpt-4o",
+              "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": prompt },
+                "finish_reason": "stop"
+              }]
+            }))?)
+        }
+    }
+
+    #[tokio::test]
+    async fn async_provider_should_complete_chats() -> Result<()> {
+        let sdk = LlmSdkBuilder::default()
+            .base_url("http://localhost:11434/v1")
+            .token("")
+            .system_prompt("Be brief.")
+            .async_provider(Arc::new(Echo))
+            .build()?;
+        let req = ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt4o,
+            vec![ChatCompletionMessage::new_user("Hi", "")],
+        );
+        let res = sdk.chat_completion(req.clone()).await?;
+        assert_eq!(res.choices[0].message.content.as_deref(), Some("Be brief."));
+        assert!(sdk.chat_completion_stream(req).await.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+use crate::ChatCompletionRequest;
+use tracing::info;
+
+/// The parameter ranges a backend accepts. Before a chat completion is sent, the parameters of
+/// the request are clamped into range, or stripped when unsupported, so one request template
+/// can be reused across backends. See `Provider::param_profile` and `LlmSdkBuilder::param_profile`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamProfile {
+    /// The valid `temperature` range, `None` if the backend has none.
+    pub temperature: Option<(f32, f32)>,
+    /// The valid `top_p` range, `None` if the backend has none.
+    pub top_p: Option<(f32, f32)>,
+    /// Whether `frequency_penalty` and `presence_penalty` (between -2 and 2) are supported.
+    pub penalties: bool,
+    /// Whether `seed` is supported.
+    pub seed: bool,
+    /// The max number of choices `n`, `None` for no limit.
+    pub max_n: Option<usize>,
+}
+
+impl Default for ParamProfile {
+    fn default() -> Self {
+        Self::openai()
+    }
+}
+
+impl ParamProfile {
+    pub fn openai() -> Self {
+        Self {
+            temperature: Some((0.0, 2.0)),
+            top_p: Some((0.0, 1.0)),
+            penalties: true,
+            seed: true,
+            max_n: None,
+        }
+    }
+
+    pub fn anthropic() -> Self {
+        Self {
+            temperature: Some((0.0, 1.0)),
+            top_p: Some((0.0, 1.0)),
+            penalties: false,
+            seed: false,
+            max_n: Some(1),
+        }
+    }
+
+    /// Gemini through its OpenAI compatible endpoint.
+    pub fn gemini() -> Self {
+        Self {
+            temperature: Some((0.0, 2.0)),
+            top_p: Some((0.0, 1.0)),
+            penalties: false,
+            seed: true,
+            max_n: Some(8),
+        }
+    }
+
+    /// Clamp or strip the out-of-range parameters of `req`, with a note for each change.
+    pub fn apply(&self, req: &mut ChatCompletionRequest) {
+        clamp("temperature", &mut req.temperature, self.temperature);
+        clamp("top_p", &mut req.top_p, self.top_p);
+        let penalties = self.penalties.then_some((-2.0, 2.0));
+        clamp("frequency_penalty", &mut req.frequency_penalty, penalties);
+        clamp("presence_penalty", &mut req.presence_penalty, penalties);
+        if !self.seed && req.seed.take().is_some() {
+            info!("seed is not supported by the backend, stripped");
+        }
+        match (req.n, self.max_n) {
+            (Some(n), Some(max)) if n > max => {
+                info!(
+                    "n {} is above the max of the backend, clamped to {}",
+                    n, max
+                );
+                req.n = (max > 1).then_some(max);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn clamp(name: &str, value: &mut Option<f32>, range: Option<(f32, f32)>) {
+    let Some(v) = *value else {
+        return;
+    };
+    match range {
+        None => {
+            info!("{} is not supported by the backend, stripped", name);
+            *value = None;
+        }
+        Some((min, max)) if !(min..=max).contains(&v) => {
+            let clamped = v.clamp(min, max);
+            info!("{} {} is out of range, clamped to {}", name, v, clamped);
+            *value = Some(clamped);
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChatCompleteModel, ChatCompletionMessage, ChatCompletionRequestBuilder};
+
+    #[test]
+    fn param_profile_should_clamp_and_strip() {
+        let mut req = ChatCompletionRequestBuilder::default()
+            .model(ChatCompleteModel::Claude3Haiku)
+            .messages(vec![ChatCompletionMessage::new_user("hi", "")])
+            .temperature(1.5)
+            .frequency_penalty(0.5)
+            .seed(42)
+            .n(3)
+            .build()
+            .unwrap();
+        ParamProfile::anthropic().apply(&mut req);
+        assert_eq!(req.temperature, Some(1.0));
+        assert_eq!(req.frequency_penalty, None);
+        assert_eq!(req.seed, None);
+        assert_eq!(req.n, None);
+
+        let mut req = ChatCompletionRequestBuilder::default()
+            .model(ChatCompleteModel::Gpt4o)
+            .messages(vec![ChatCompletionMessage::new_user("hi", "")])
+            .temperature(1.5)
+            .presence_penalty(3.0)
+            .build()
+            .unwrap();
+        ParamProfile::openai().apply(&mut req);
+        assert_eq!(req.temperature, Some(1.5));
+        assert_eq!(req.presence_penalty, Some(2.0));
+    }
+}
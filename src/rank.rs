@@ -0,0 +1,198 @@
+//! Pick the best of the choices of a response (requested with `n > 1`), by the logprobs of the
+//! tokens, by a `Judge`, or by any scoring function. The alternatives are returned with their
+//! scores, e.g. to log why a choice won.
+
+use crate::{AssistantMessage, ChatCompletionChoice, ChatCompletionResponse, Judge, LlmSdk};
+use anyhow::{anyhow, Result};
+use futures::future::try_join_all;
+use std::{fmt, sync::Arc};
+
+/// How to score a choice, the higher the better.
+#[derive(Clone)]
+pub enum Scorer {
+    /// The mean logprob of the tokens of the choice. The request needs `logprobs(true)`.
+    MeanLogprob,
+    /// The mean score of the judge over the criteria, one judge call per choice.
+    Judge { judge: Judge, criteria: Vec<String> },
+    /// Any function of the message.
+    Custom(Arc<dyn Fn(&AssistantMessage) -> f64 + Send + Sync>),
+}
+
+#[derive(Debug, Clone)]
+pub struct ScoredChoice {
+    pub choice: ChatCompletionChoice,
+    pub score: f64,
+}
+
+/// The best choice, and the others from the best to the worst.
+#[derive(Debug, Clone)]
+pub struct Ranking {
+    pub winner: ScoredChoice,
+    pub alternatives: Vec<ScoredChoice>,
+}
+
+impl fmt::Debug for Scorer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MeanLogprob => write!(f, "MeanLogprob"),
+            Self::Judge { criteria, .. } => write!(f, "Judge({:?})", criteria),
+            Self::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+impl Scorer {
+    pub fn judge(judge: Judge, criteria: &[&str]) -> Self {
+        Self::Judge {
+            judge,
+            criteria: criteria.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    pub fn custom(f: impl Fn(&AssistantMessage) -> f64 + Send + Sync + 'static) -> Self {
+        Self::Custom(Arc::new(f))
+    }
+
+    pub async fn score(&self, sdk: &LlmSdk, choice: &ChatCompletionChoice) -> Result<f64> {
+        match self {
+            Self::MeanLogprob => {
+                let tokens = choice
+                    .logprobs
+                    .as_ref()
+                    .map(|logprobs| &logprobs.content)
+                    .filter(|tokens| !tokens.is_empty())
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "choice {} has no logprobs, set `logprobs` on the request",
+                            choice.index
+                        )
+                    })?;
+                let sum: f64 = tokens.iter().map(|t| t.logprob as f64).sum();
+                Ok(sum / tokens.len() as f64)
+            }
+            Self::Judge { judge, criteria } => {
+                let answer = match &choice.message.content {
+                    Some(content) => content.clone(),
+                    None => serde_json::to_string(&choice.message.tool_calls)?,
+                };
+                let criteria: Vec<&str> = criteria.iter().map(|c| c.as_str()).collect();
+                let evaluation = judge.evaluate(sdk, &answer, &criteria).await?;
+                Ok(evaluation.mean() as f64)
+            }
+            Self::Custom(f) => Ok(f(&choice.message)),
+        }
+    }
+
+    /// Score the choices of `response` (concurrently for the judge) and rank them. Ties keep the
+    /// order of the choices.
+    pub async fn rank(&self, sdk: &LlmSdk, response: ChatCompletionResponse) -> Result<Ranking> {
+        let scores = try_join_all(
+            response
+                .choices
+                .iter()
+                .map(|choice| self.score(sdk, choice)),
+        )
+        .await?;
+        let mut scored: Vec<ScoredChoice> = response
+            .choices
+            .into_iter()
+            .zip(scores)
+            .map(|(choice, score)| ScoredChoice { choice, score })
+            .collect();
+        scored.sort_by(|a, b| b.score.total_cmp(&a.score));
+        let mut scored = scored.into_iter();
+        let winner = scored
+            .next()
+            .ok_or_else(|| anyhow!("the response has no choice"))?;
+        Ok(Ranking {
+            winner,
+            alternatives: scored.collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChatCompleteModel, ChatCompletionMessage, ChatCompletionRequestBuilder, SDK};
+    use serde_json::json;
+
+    fn response(choices: serde_json::Value) -> ChatCompletionResponse {
+        serde_json::from_value(json!({
+            "id": "chatcmpl-1",
+            "choices": choices,
+            "created": 1_700_000_000,
+            "model": "gpt-4o",
+            "object": "chat.completion",
+        }))
+        .unwrap()
+    }
+
+    fn choice(index: usize, content: &str, logprobs: &[f32]) -> serde_json::Value {
+        let tokens: Vec<_> = logprobs
+            .iter()
+            .map(|logprob| json!({ "token": "t", "logprob": logprob }))
+            .collect();
+        json!({
+            "finish_reason": "stop",
+            "index": index,
+            "message": { "role": "assistant", "content": content },
+            "logprobs": { "content": tokens },
+        })
+    }
+
+    #[tokio::test]
+    async fn rank_should_order_choices_by_mean_logprob() -> Result<()> {
+        let sdk = LlmSdk::ollama("http://localhost:11434/v1");
+        let res = response(json!([
+            choice(0, "maybe", &[-1.0, -2.0]),
+            choice(1, "yes", &[-0.1, -0.3]),
+            choice(2, "no", &[-0.5]),
+        ]));
+        let ranking = Scorer::MeanLogprob.rank(&sdk, res).await?;
+        assert_eq!(ranking.winner.choice.index, 1);
+        assert!((ranking.winner.score + 0.2).abs() < 1e-6);
+        let rest: Vec<_> = ranking
+            .alternatives
+            .iter()
+            .map(|s| s.choice.index)
+            .collect();
+        assert_eq!(rest, [2, 0]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rank_should_use_custom_scorer() -> Result<()> {
+        let sdk = LlmSdk::ollama("http://localhost:11434/v1");
+        let res = response(json!([choice(0, "short", &[]), choice(1, "longer", &[])]));
+        let scorer = Scorer::custom(|m| m.content.as_deref().map_or(0, str::len) as f64);
+        let ranking = scorer.rank(&sdk, res.clone()).await?;
+        assert_eq!(ranking.winner.choice.index, 1);
+        assert_eq!(ranking.alternatives[0].score, 5.0);
+
+        let err = Scorer::MeanLogprob.rank(&sdk, res).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "choice 0 has no logprobs, set `logprobs` on the request"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rank_should_work_with_logprobs() -> Result<()> {
+        let req = ChatCompletionRequestBuilder::default()
+            .model(ChatCompleteModel::Gpt3Turbo)
+            .messages(vec![ChatCompletionMessage::new_user(
+                "Name a color of the rainbow, one word.",
+                "",
+            )])
+            .n(3)
+            .logprobs(true)
+            .build()?;
+        let res = SDK.chat_completion(req).await?;
+        let ranking = Scorer::MeanLogprob.rank(&SDK, res).await?;
+        assert_eq!(ranking.alternatives.len(), 2);
+        assert!(ranking.winner.score >= ranking.alternatives[0].score);
+        Ok(())
+    }
+}
@@ -0,0 +1,288 @@
+//! The rate limits reported by OpenAI in the `x-ratelimit-*` response headers. They're returned
+//! with the response by `LlmSdk::chat_completion_with_meta`, and `RateLimitThrottle` (a
+//! middleware, see `LlmSdkBuilder::middlewares`) uses them to delay the requests once the
+//! remaining capacity is close to zero, instead of running into 429s.
+
+use reqwest::{header::HeaderMap, Request, Response};
+use reqwest_middleware::{Middleware, Next};
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use task_local_extensions::Extensions;
+use tracing::info;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RateLimits {
+    /// `x-ratelimit-limit-requests`
+    pub limit_requests: Option<u64>,
+    /// `x-ratelimit-limit-tokens`
+    pub limit_tokens: Option<u64>,
+    /// `x-ratelimit-remaining-requests`
+    pub remaining_requests: Option<u64>,
+    /// `x-ratelimit-remaining-tokens`
+    pub remaining_tokens: Option<u64>,
+    /// `x-ratelimit-reset-requests`, the time until the request limit is reset.
+    pub reset_requests: Option<Duration>,
+    /// `x-ratelimit-reset-tokens`, the time until the token limit is reset.
+    pub reset_tokens: Option<Duration>,
+}
+
+/// The metadata of an API response.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResponseMeta {
+    /// `x-request-id`, to reference the request when contacting the support.
+    pub request_id: Option<String>,
+    pub rate_limits: Option<RateLimits>,
+    /// The `Idempotency-Key` sent with the request, to match a retried request with its response.
+    pub idempotency_key: Option<String>,
+}
+
+/// A response with its metadata.
+#[derive(Debug, Clone)]
+pub struct WithMeta<T> {
+    pub data: T,
+    pub meta: ResponseMeta,
+}
+
+/// Delays the requests while the remaining requests or tokens are at or below the thresholds,
+/// until the limit is reset.
+#[derive(Debug, Default)]
+pub struct RateLimitThrottle {
+    min_requests: u64,
+    min_tokens: u64,
+    last: RateLimitState,
+}
+
+/// The last rate limits seen, and when.
+#[derive(Debug, Default)]
+pub(crate) struct RateLimitState(Mutex<Option<(Instant, RateLimits)>>);
+
+impl RateLimits {
+    /// `None` if the response has none of the headers.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let get = |name: &str| headers.get(name)?.to_str().ok();
+        let number = |name: &str| get(name)?.trim().parse().ok();
+        let duration = |name: &str| parse_duration(get(name)?);
+        let limits = Self {
+            limit_requests: number("x-ratelimit-limit-requests"),
+            limit_tokens: number("x-ratelimit-limit-tokens"),
+            remaining_requests: number("x-ratelimit-remaining-requests"),
+            remaining_tokens: number("x-ratelimit-remaining-tokens"),
+            reset_requests: duration("x-ratelimit-reset-requests"),
+            reset_tokens: duration("x-ratelimit-reset-tokens"),
+        };
+        (limits != Self::default()).then_some(limits)
+    }
+}
+
+impl ResponseMeta {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        Self {
+            request_id: headers
+                .get("x-request-id")
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string()),
+            rate_limits: RateLimits::from_headers(headers),
+            idempotency_key: None,
+        }
+    }
+}
+
+impl RateLimitState {
+    pub(crate) fn update(&self, now: Instant, headers: &HeaderMap) {
+        if let Some(limits) = RateLimits::from_headers(headers) {
+            *self.0.lock().unwrap_or_else(|e| e.into_inner()) = Some((now, limits));
+        }
+    }
+
+    /// The remaining requests and tokens at `now`, unknown (`None`) once the limit is reset.
+    pub(crate) fn remaining(&self, now: Instant) -> (Option<u64>, Option<u64>) {
+        let Some((seen, limits)) = self.get() else {
+            return (None, None);
+        };
+        // unknown once reset, or if the reset is too far to be represented
+        let current = |remaining: Option<u64>, reset: Option<Duration>| match reset {
+            Some(reset) => remaining.filter(|_| seen.checked_add(reset).is_some_and(|t| now < t)),
+            None => remaining,
+        };
+        (
+            current(limits.remaining_requests, limits.reset_requests),
+            current(limits.remaining_tokens, limits.reset_tokens),
+        )
+    }
+
+    pub(crate) fn get(&self) -> Option<(Instant, RateLimits)> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+impl RateLimitThrottle {
+    /// Wait once at most `min_requests` requests or `min_tokens` tokens remain.
+    pub fn new(min_requests: u64, min_tokens: u64) -> Self {
+        Self {
+            min_requests,
+            min_tokens,
+            last: RateLimitState::default(),
+        }
+    }
+
+    /// How long to wait before the next request.
+    fn wait(&self, now: Instant) -> Option<Duration> {
+        let (seen, limits) = self.last.get()?;
+        let exhausted = |remaining: Option<u64>, min: u64, reset: Option<Duration>| {
+            remaining.filter(|n| *n <= min).and(reset)
+        };
+        let reset = [
+            exhausted(
+                limits.remaining_requests,
+                self.min_requests,
+                limits.reset_requests,
+            ),
+            exhausted(
+                limits.remaining_tokens,
+                self.min_tokens,
+                limits.reset_tokens,
+            ),
+        ]
+        .into_iter()
+        .flatten()
+        .max()?;
+        seen.checked_add(reset)?
+            .checked_duration_since(now)
+            .filter(|d| !d.is_zero())
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for RateLimitThrottle {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        if let Some(wait) = self.wait(Instant::now()) {
+            info!("rate limit almost exhausted, waiting {:?}", wait);
+            tokio::time::sleep(wait).await;
+        }
+        let res = next.run(req, extensions).await?;
+        self.last.update(Instant::now(), res.headers());
+        Ok(res)
+    }
+}
+
+/// Parse the durations of the reset headers, e.g. `1s`, `6m0s`, `20ms` or `1h2m3.5s`.
+fn parse_duration(s: &str) -> Option<Duration> {
+    let mut rest = s.trim();
+    let mut secs = 0.0;
+    while !rest.is_empty() {
+        let end = rest.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+        let value: f64 = rest[..end].parse().ok()?;
+        rest = &rest[end..];
+        let (unit, len) = if rest.starts_with("ms") {
+            (0.001, 2)
+        } else {
+            match rest.chars().next()? {
+                'h' => (3600.0, 1),
+                'm' => (60.0, 1),
+                's' => (1.0, 1),
+                _ => return None,
+            }
+        };
+        secs += value * unit;
+        rest = &rest[len..];
+    }
+    Duration::try_from_secs_f64(secs).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn parse_duration_should_work() {
+        assert_eq!(parse_duration("1s"), Some(Duration::from_secs(1)));
+        assert_eq!(parse_duration("6m0s"), Some(Duration::from_secs(360)));
+        assert_eq!(parse_duration("20ms"), Some(Duration::from_millis(20)));
+        assert_eq!(
+            parse_duration("1h2m3.5s"),
+            Some(Duration::from_millis(3_723_500))
+        );
+        assert_eq!(parse_duration("soon"), None);
+        assert_eq!(parse_duration(&format!("{}s", "9".repeat(400))), None);
+        assert_eq!(parse_duration(&format!("{}h", "9".repeat(30))), None);
+    }
+
+    #[test]
+    fn response_meta_should_parse_headers() {
+        let headers = headers(&[
+            ("x-request-id", "req_123"),
+            ("x-ratelimit-limit-requests", "60"),
+            ("x-ratelimit-remaining-requests", "59"),
+            ("x-ratelimit-remaining-tokens", "149984"),
+            ("x-ratelimit-reset-requests", "1s"),
+            ("x-ratelimit-reset-tokens", "6m0s"),
+        ]);
+        let meta = ResponseMeta::from_headers(&headers);
+        assert_eq!(meta.request_id.as_deref(), Some("req_123"));
+        let limits = meta.rate_limits.unwrap();
+        assert_eq!(limits.limit_requests, Some(60));
+        assert_eq!(limits.limit_tokens, None);
+        assert_eq!(limits.remaining_tokens, Some(149984));
+        assert_eq!(limits.reset_tokens, Some(Duration::from_secs(360)));
+        assert!(RateLimits::from_headers(&HeaderMap::new()).is_none());
+    }
+
+    #[test]
+    fn throttle_should_wait_until_reset_when_exhausted() {
+        let throttle = RateLimitThrottle::new(1, 100);
+        let now = Instant::now();
+        assert_eq!(throttle.wait(now), None);
+
+        throttle.last.update(
+            now,
+            &headers(&[
+                ("x-ratelimit-remaining-requests", "10"),
+                ("x-ratelimit-remaining-tokens", "50"),
+                ("x-ratelimit-reset-requests", "1s"),
+                ("x-ratelimit-reset-tokens", "20s"),
+            ]),
+        );
+        assert_eq!(throttle.last.remaining(now), (Some(10), Some(50)));
+        assert_eq!(
+            throttle.last.remaining(now + Duration::from_secs(2)),
+            (None, Some(50))
+        );
+        assert_eq!(throttle.wait(now), Some(Duration::from_secs(20)));
+        assert_eq!(
+            throttle.wait(now + Duration::from_secs(5)),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(throttle.wait(now + Duration::from_secs(20)), None);
+    }
+
+    #[test]
+    fn throttle_should_ignore_unrepresentable_reset() {
+        let throttle = RateLimitThrottle::new(1, 100);
+        let now = Instant::now();
+        throttle.last.update(
+            now,
+            &headers(&[
+                ("x-ratelimit-remaining-requests", "0"),
+                ("x-ratelimit-reset-requests", "10000000000000000000s"),
+            ]),
+        );
+        assert_eq!(throttle.last.remaining(now), (None, None));
+        assert_eq!(throttle.wait(now), None);
+    }
+}
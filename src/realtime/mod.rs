@@ -0,0 +1,459 @@
+//! The realtime API over WebSocket (feature `realtime`), for low-latency voice agents.
+//! `LlmSdk::realtime` opens a session to `{base_url}/realtime`; the client and server events are
+//! typed, and the `RealtimeSession` can be split into a sender and a receiver, e.g. to stream
+//! the microphone from one task while playing the responses in another (see `JitterBuffer`).
+
+mod reconnect;
+mod record;
+
+pub use reconnect::ReconnectPolicy;
+pub use record::{RealtimeDirection, RealtimeRecorder, RealtimeReplay, RecordedEvent};
+
+use crate::SpeechVoice;
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::{
+    future::BoxFuture,
+    ready,
+    stream::{SplitSink, SplitStream},
+    FutureExt, SinkExt, Stream, StreamExt,
+};
+use reconnect::Resume;
+use serde::{Deserialize, Serialize};
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{client::IntoClientRequest, http::HeaderValue, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+use tracing::warn;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+pub const REALTIME_MODEL: &str = "gpt-4o-realtime-preview";
+
+/// A realtime session, sending `RealtimeClientEvent`s and yielding `RealtimeServerEvent`s.
+pub struct RealtimeSession {
+    sender: RealtimeSender,
+    receiver: RealtimeReceiver,
+    url: String,
+    token: String,
+    /// Set by `reconnect`.
+    resume: Option<Resume>,
+    reconnecting: Option<BoxFuture<'static, Result<(RealtimeSession, u32)>>>,
+}
+
+/// The sending half of a `RealtimeSession`.
+pub struct RealtimeSender {
+    sink: SplitSink<Socket, Message>,
+    recorder: Option<RealtimeRecorder>,
+}
+
+/// The receiving half of a `RealtimeSession`, a stream of the server events.
+pub struct RealtimeReceiver {
+    stream: SplitStream<Socket>,
+    recorder: Option<RealtimeRecorder>,
+    /// Whether the server closed the connection, rather than it dropped.
+    closed: bool,
+}
+
+/// The configuration of a session, sent with `session.update`. Unset fields are left as they are.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RealtimeSessionConfig {
+    /// `["text"]` or `["text", "audio"]`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub modalities: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub voice: Option<SpeechVoice>,
+    /// `pcm16`, `g711_ulaw` or `g711_alaw`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_audio_format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_audio_format: Option<String>,
+    /// The voice activity detection, e.g. `{"type": "server_vad"}`, or null to commit the audio
+    /// buffer manually.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub turn_detection: Option<serde_json::Value>,
+    /// The functions, flattened: `{"type": "function", "name": ..., "parameters": ...}`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum RealtimeClientEvent {
+    #[serde(rename = "session.update")]
+    SessionUpdate { session: RealtimeSessionConfig },
+    /// Base64 encoded audio in the `input_audio_format` of the session.
+    #[serde(rename = "input_audio_buffer.append")]
+    InputAudioBufferAppend { audio: String },
+    #[serde(rename = "input_audio_buffer.commit")]
+    InputAudioBufferCommit,
+    #[serde(rename = "input_audio_buffer.clear")]
+    InputAudioBufferClear,
+    /// Add a message or a function call output to the conversation.
+    #[serde(rename = "conversation.item.create")]
+    ConversationItemCreate { item: serde_json::Value },
+    /// Ask for a response, with optional overrides of the session (e.g. `instructions`).
+    #[serde(rename = "response.create")]
+    ResponseCreate {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        response: Option<serde_json::Value>,
+    },
+    #[serde(rename = "response.cancel")]
+    ResponseCancel,
+}
+
+/// The server events used by most voice agents; the others are kept as `Other` with their type.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type")]
+pub enum RealtimeServerEvent {
+    #[serde(rename = "error")]
+    Error { error: RealtimeError },
+    #[serde(rename = "session.created")]
+    SessionCreated { session: serde_json::Value },
+    #[serde(rename = "session.updated")]
+    SessionUpdated { session: serde_json::Value },
+    #[serde(rename = "input_audio_buffer.speech_started")]
+    SpeechStarted {
+        audio_start_ms: u64,
+        item_id: String,
+    },
+    #[serde(rename = "input_audio_buffer.speech_stopped")]
+    SpeechStopped { audio_end_ms: u64, item_id: String },
+    #[serde(rename = "conversation.item.input_audio_transcription.completed")]
+    InputTranscriptionCompleted { item_id: String, transcript: String },
+    /// Base64 encoded audio in the `output_audio_format` of the session.
+    #[serde(rename = "response.audio.delta")]
+    AudioDelta {
+        response_id: String,
+        item_id: String,
+        delta: String,
+    },
+    #[serde(rename = "response.audio_transcript.delta")]
+    AudioTranscriptDelta {
+        response_id: String,
+        item_id: String,
+        delta: String,
+    },
+    #[serde(rename = "response.text.delta")]
+    TextDelta {
+        response_id: String,
+        item_id: String,
+        delta: String,
+    },
+    #[serde(rename = "response.function_call_arguments.done")]
+    FunctionCallArgumentsDone {
+        response_id: String,
+        call_id: String,
+        name: String,
+        arguments: String,
+    },
+    #[serde(rename = "response.done")]
+    ResponseDone { response: serde_json::Value },
+    /// Not sent by the server: the connection dropped and `RealtimeSession` reconnected after
+    /// `attempts` attempts, restoring the configuration and the uncommitted input audio.
+    #[serde(skip)]
+    Reconnected { attempts: u32 },
+    #[serde(skip)]
+    Other(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct RealtimeError {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub code: Option<String>,
+    pub message: String,
+}
+
+impl RealtimeSessionConfig {
+    /// Apply an update: its set fields replace the ones of `self`.
+    pub(crate) fn merge(&mut self, update: &Self) {
+        fn set<T: Clone>(field: &mut Option<T>, update: &Option<T>) {
+            if update.is_some() {
+                field.clone_from(update);
+            }
+        }
+        if !update.modalities.is_empty() {
+            self.modalities.clone_from(&update.modalities);
+        }
+        set(&mut self.instructions, &update.instructions);
+        set(&mut self.voice, &update.voice);
+        set(&mut self.input_audio_format, &update.input_audio_format);
+        set(&mut self.output_audio_format, &update.output_audio_format);
+        set(&mut self.turn_detection, &update.turn_detection);
+        if !update.tools.is_empty() {
+            self.tools.clone_from(&update.tools);
+        }
+        set(&mut self.temperature, &update.temperature);
+    }
+}
+
+impl RealtimeSession {
+    /// Connect to `url` (a `wss://` url with the model in the query) with a bearer token.
+    pub(crate) async fn connect(url: &str, token: &str) -> Result<Self> {
+        let mut req = url.into_client_request()?;
+        let headers = req.headers_mut();
+        if !token.is_empty() {
+            headers.insert(
+                "Authorization",
+                HeaderValue::from_str(&format!("Bearer {}", token))?,
+            );
+        }
+        headers.insert("OpenAI-Beta", HeaderValue::from_static("realtime=v1"));
+        let (socket, _) = connect_async(req).await?;
+        let (sink, stream) = socket.split();
+        Ok(Self {
+            sender: RealtimeSender {
+                sink,
+                recorder: None,
+            },
+            receiver: RealtimeReceiver {
+                stream,
+                recorder: None,
+                closed: false,
+            },
+            url: url.to_string(),
+            token: token.to_string(),
+            resume: None,
+            reconnecting: None,
+        })
+    }
+
+    /// Reconnect when the connection drops, see `ReconnectPolicy`. The session configuration
+    /// and the input audio not committed yet are sent again, so a failed `send` doesn't need to
+    /// be retried. Only the unsplit session reconnects.
+    pub fn reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        self.resume = Some(Resume::new(policy));
+        self
+    }
+
+    /// Record the events sent and received from now on, see `RealtimeReplay`.
+    pub fn record(mut self, recorder: RealtimeRecorder) -> Self {
+        self.sender.recorder = Some(recorder.clone());
+        self.receiver.recorder = Some(recorder);
+        self
+    }
+
+    pub async fn send(&mut self, event: RealtimeClientEvent) -> Result<()> {
+        if let Some(resume) = &mut self.resume {
+            resume.sent(&event);
+        }
+        self.sender.send(event).await
+    }
+
+    pub async fn append_audio(&mut self, audio: &[u8]) -> Result<()> {
+        self.send(RealtimeClientEvent::InputAudioBufferAppend {
+            audio: STANDARD.encode(audio),
+        })
+        .await
+    }
+
+    /// Split the session into halves which can be used from different tasks.
+    pub fn split(self) -> (RealtimeSender, RealtimeReceiver) {
+        (self.sender, self.receiver)
+    }
+}
+
+impl RealtimeSender {
+    pub async fn send(&mut self, event: RealtimeClientEvent) -> Result<()> {
+        let text = serde_json::to_string(&event)?;
+        if let Some(recorder) = &self.recorder {
+            recorder.record(RealtimeDirection::Client, &text);
+        }
+        self.sink.send(Message::Text(text)).await?;
+        Ok(())
+    }
+
+    /// Append raw audio to the input buffer, base64 encoding it.
+    pub async fn append_audio(&mut self, audio: &[u8]) -> Result<()> {
+        self.send(RealtimeClientEvent::InputAudioBufferAppend {
+            audio: STANDARD.encode(audio),
+        })
+        .await
+    }
+
+    pub async fn close(mut self) -> Result<()> {
+        self.sink.close().await?;
+        Ok(())
+    }
+}
+
+impl Stream for RealtimeReceiver {
+    type Item = Result<RealtimeServerEvent>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let message = match self.stream.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(message))) => message,
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e.into()))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+            match message {
+                Message::Text(text) => {
+                    if let Some(recorder) = &self.recorder {
+                        recorder.record(RealtimeDirection::Server, &text);
+                    }
+                    return Poll::Ready(Some(RealtimeServerEvent::parse(&text)));
+                }
+                Message::Close(_) => {
+                    self.closed = true;
+                    return Poll::Ready(None);
+                }
+                // pings are answered by tungstenite
+                _ => continue,
+            }
+        }
+    }
+}
+
+impl Stream for RealtimeSession {
+    type Item = Result<RealtimeServerEvent>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if let Some(reconnecting) = &mut this.reconnecting {
+            let result = ready!(reconnecting.poll_unpin(cx));
+            this.reconnecting = None;
+            return Poll::Ready(Some(match result {
+                Ok((session, attempts)) => {
+                    this.sender.sink = session.sender.sink;
+                    this.receiver.stream = session.receiver.stream;
+                    Ok(RealtimeServerEvent::Reconnected { attempts })
+                }
+                Err(e) => {
+                    this.resume = None;
+                    Err(e)
+                }
+            }));
+        }
+        let event = ready!(this.receiver.poll_next_unpin(cx));
+        let Some(resume) = &mut this.resume else {
+            return Poll::Ready(event);
+        };
+        match event {
+            Some(Ok(event)) => {
+                resume.received(&event);
+                Poll::Ready(Some(Ok(event)))
+            }
+            None if this.receiver.closed => Poll::Ready(None),
+            dropped => {
+                if let Some(Err(e)) = dropped {
+                    warn!("realtime connection dropped: {}", e);
+                }
+                this.reconnecting = Some(
+                    reconnect::reconnect(
+                        this.url.clone(),
+                        this.token.clone(),
+                        resume.policy.clone(),
+                        resume.events(),
+                        this.receiver.recorder.clone(),
+                    )
+                    .boxed(),
+                );
+                self.poll_next(cx)
+            }
+        }
+    }
+}
+
+impl RealtimeServerEvent {
+    pub(crate) fn parse(text: &str) -> Result<Self> {
+        Self::from_value(serde_json::from_str(text)?)
+    }
+
+    pub(crate) fn from_value(value: serde_json::Value) -> Result<Self> {
+        let kind = value["type"]
+            .as_str()
+            .ok_or_else(|| anyhow!("realtime event without a type: {}", value))?
+            .to_string();
+        match serde_json::from_value(value) {
+            Ok(event) => Ok(event),
+            Err(_) => Ok(Self::Other(kind)),
+        }
+    }
+}
+
+/// The realtime url of an http(s) base url, e.g. `wss://api.openai.com/v1/realtime?model=...`.
+pub(crate) fn realtime_url(base_url: &str, model: &str) -> String {
+    let base_url = match base_url.split_once("://") {
+        Some(("http", rest)) => format!("ws://{}", rest),
+        Some(("https", rest)) => format!("wss://{}", rest),
+        _ => base_url.to_string(),
+    };
+    format!("{}/realtime?model={}", base_url, model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn realtime_client_event_should_serialize() -> Result<()> {
+        let event = RealtimeClientEvent::SessionUpdate {
+            session: RealtimeSessionConfig {
+                instructions: Some("Be brief.".into()),
+                voice: Some(SpeechVoice::Alloy),
+                ..Default::default()
+            },
+        };
+        assert_eq!(
+            serde_json::to_value(event)?,
+            json!({
+              "type": "session.update",
+              "session": { "instructions": "Be brief.", "voice": "alloy" }
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(RealtimeClientEvent::ResponseCreate { response: None })?,
+            json!({ "type": "response.create" })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn realtime_server_event_should_parse() -> Result<()> {
+        let event = RealtimeServerEvent::parse(
+            r#"{"type":"response.audio.delta","event_id":"e1","response_id":"r1","item_id":"i1","output_index":0,"content_index":0,"delta":"AAA="}"#,
+        )?;
+        assert_eq!(
+            event,
+            RealtimeServerEvent::AudioDelta {
+                response_id: "r1".into(),
+                item_id: "i1".into(),
+                delta: "AAA=".into(),
+            }
+        );
+        let event =
+            RealtimeServerEvent::parse(r#"{"type":"rate_limits.updated","rate_limits":[]}"#)?;
+        assert_eq!(
+            event,
+            RealtimeServerEvent::Other("rate_limits.updated".into())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn realtime_url_should_use_websocket_scheme() {
+        assert_eq!(
+            realtime_url("https://api.openai.com/v1", REALTIME_MODEL),
+            "wss://api.openai.com/v1/realtime?model=gpt-4o-realtime-preview"
+        );
+        assert_eq!(
+            realtime_url("http://localhost:8080/v1", "m"),
+            "ws://localhost:8080/v1/realtime?model=m"
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! Reconnection of a dropped realtime session: `RealtimeSession::reconnect` keeps what's needed
+//! to resume (the session configuration, and the input audio not committed yet), and when the
+//! connection drops, reconnects with a backoff, sends them again and yields
+//! `RealtimeServerEvent::Reconnected`.
+
+use super::{
+    RealtimeClientEvent, RealtimeRecorder, RealtimeServerEvent, RealtimeSession,
+    RealtimeSessionConfig,
+};
+use anyhow::Result;
+use std::time::Duration;
+use tracing::warn;
+
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// The attempts before the session fails, 5 by default.
+    pub max_attempts: u32,
+    /// The backoff before the first attempt, doubled after each failure. 500ms by default.
+    pub min_backoff: Duration,
+    /// 30s by default.
+    pub max_backoff: Duration,
+}
+
+/// The state of a session to restore on reconnection.
+#[derive(Debug)]
+pub(super) struct Resume {
+    pub(super) policy: ReconnectPolicy,
+    /// The configuration sent with `session.update`, merged.
+    config: Option<RealtimeSessionConfig>,
+    /// The base64 audio appended since the last commit or clear.
+    audio: Vec<String>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            min_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// The backoff before the attempt `n` (from 0).
+    fn backoff(&self, n: u32) -> Duration {
+        self.min_backoff
+            .checked_mul(2u32.saturating_pow(n))
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+}
+
+impl Resume {
+    pub(super) fn new(policy: ReconnectPolicy) -> Self {
+        Self {
+            policy,
+            config: None,
+            audio: Vec::new(),
+        }
+    }
+
+    pub(super) fn sent(&mut self, event: &RealtimeClientEvent) {
+        match event {
+            RealtimeClientEvent::SessionUpdate { session } => {
+                self.config
+                    .get_or_insert_with(Default::default)
+                    .merge(session);
+            }
+            RealtimeClientEvent::InputAudioBufferAppend { audio } => self.audio.push(audio.clone()),
+            RealtimeClientEvent::InputAudioBufferCommit
+            | RealtimeClientEvent::InputAudioBufferClear => self.audio.clear(),
+            _ => {}
+        }
+    }
+
+    /// With the server voice activity detection, the server commits the audio itself.
+    pub(super) fn received(&mut self, event: &RealtimeServerEvent) {
+        if let RealtimeServerEvent::Other(kind) = event {
+            if kind == "input_audio_buffer.committed" || kind == "input_audio_buffer.cleared" {
+                self.audio.clear();
+            }
+        }
+    }
+
+    /// The events restoring the session on a new connection.
+    pub(super) fn events(&self) -> Vec<RealtimeClientEvent> {
+        let config = self
+            .config
+            .clone()
+            .map(|session| RealtimeClientEvent::SessionUpdate { session });
+        let audio = self
+            .audio
+            .iter()
+            .map(|audio| RealtimeClientEvent::InputAudioBufferAppend {
+                audio: audio.clone(),
+            });
+        config.into_iter().chain(audio).collect()
+    }
+}
+
+/// Connect again and send `events`, retrying with the backoff of `policy`. Returns the new
+/// session and the number of attempts.
+pub(super) async fn reconnect(
+    url: String,
+    token: String,
+    policy: ReconnectPolicy,
+    events: Vec<RealtimeClientEvent>,
+    recorder: Option<RealtimeRecorder>,
+) -> Result<(RealtimeSession, u32)> {
+    let mut attempts = 0;
+    loop {
+        tokio::time::sleep(policy.backoff(attempts)).await;
+        attempts += 1;
+        match resume(&url, &token, &events, recorder.clone()).await {
+            Ok(session) => return Ok((session, attempts)),
+            Err(e) if attempts >= policy.max_attempts => {
+                return Err(e.context(format!(
+                    "failed to reconnect the realtime session after {} attempts",
+                    attempts
+                )))
+            }
+            Err(e) => warn!("realtime reconnection attempt #{} failed: {}", attempts, e),
+        }
+    }
+}
+
+async fn resume(
+    url: &str,
+    token: &str,
+    events: &[RealtimeClientEvent],
+    recorder: Option<RealtimeRecorder>,
+) -> Result<RealtimeSession> {
+    let mut session = RealtimeSession::connect(url, token).await?;
+    if let Some(recorder) = recorder {
+        session = session.record(recorder);
+    }
+    for event in events {
+        session.sender.send(event.clone()).await?;
+    }
+    Ok(session)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resume_should_keep_config_and_uncommitted_audio() {
+        let mut resume = Resume::new(ReconnectPolicy::default());
+        resume.sent(&RealtimeClientEvent::SessionUpdate {
+            session: RealtimeSessionConfig {
+                instructions: Some("Be brief.".into()),
+                ..Default::default()
+            },
+        });
+        resume.sent(&RealtimeClientEvent::SessionUpdate {
+            session: RealtimeSessionConfig {
+                temperature: Some(0.5),
+                ..Default::default()
+            },
+        });
+        resume.sent(&RealtimeClientEvent::InputAudioBufferAppend { audio: "AA".into() });
+        resume.sent(&RealtimeClientEvent::InputAudioBufferCommit);
+        resume.sent(&RealtimeClientEvent::InputAudioBufferAppend { audio: "BB".into() });
+        let events = serde_json::to_value(resume.events()).unwrap();
+        assert_eq!(
+            events,
+            serde_json::json!([
+                {
+                    "type": "session.update",
+                    "session": { "instructions": "Be brief.", "temperature": 0.5 }
+                },
+                { "type": "input_audio_buffer.append", "audio": "BB" },
+            ])
+        );
+
+        resume.received(&RealtimeServerEvent::Other(
+            "input_audio_buffer.committed".into(),
+        ));
+        assert_eq!(resume.events().len(), 1);
+    }
+
+    #[test]
+    fn reconnect_policy_should_double_the_backoff() {
+        let policy = ReconnectPolicy::default();
+        assert_eq!(policy.backoff(0), Duration::from_millis(500));
+        assert_eq!(policy.backoff(3), Duration::from_secs(4));
+        assert_eq!(policy.backoff(10), Duration::from_secs(30));
+        assert_eq!(policy.backoff(40), Duration::from_secs(30));
+    }
+}
@@ -0,0 +1,164 @@
+//! Recording of the realtime events, to test a voice agent offline. A session with a
+//! `RealtimeRecorder` writes every client and server event as a JSON line with its time, and
+//! `RealtimeReplay` plays the server events of the file back as a stream, so the code handling
+//! them runs without a connection.
+
+use super::RealtimeServerEvent;
+use anyhow::Result;
+use futures::{stream::BoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, LineWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RealtimeDirection {
+    Client,
+    Server,
+}
+
+/// A line of a recording.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    /// The time since the start of the recording, in milliseconds.
+    pub at_ms: u64,
+    pub direction: RealtimeDirection,
+    /// The event as sent on the wire.
+    pub event: serde_json::Value,
+}
+
+/// Writes the events of a session, see `RealtimeSession::record`. A failed write is logged and
+/// doesn't interrupt the session.
+#[derive(Debug, Clone)]
+pub struct RealtimeRecorder {
+    start: Instant,
+    writer: Arc<Mutex<LineWriter<File>>>,
+}
+
+/// The server events of a recording, to replay them.
+#[derive(Debug, Clone, Default)]
+pub struct RealtimeReplay {
+    events: Vec<RecordedEvent>,
+}
+
+impl RealtimeRecorder {
+    /// Record into `path`, truncating it.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self {
+            start: Instant::now(),
+            writer: Arc::new(Mutex::new(LineWriter::new(File::create(path)?))),
+        })
+    }
+
+    pub(crate) fn record(&self, direction: RealtimeDirection, text: &str) {
+        let event = serde_json::from_str(text).unwrap_or_else(|_| text.into());
+        let line = RecordedEvent {
+            at_ms: self.start.elapsed().as_millis() as u64,
+            direction,
+            event,
+        };
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        let written = serde_json::to_writer(&mut *writer, &line)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| Ok(writer.write_all(b"\n")?));
+        if let Err(e) = written {
+            warn!("failed to record a realtime event: {}", e);
+        }
+    }
+}
+
+impl RealtimeReplay {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let mut events = Vec::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                events.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(Self { events })
+    }
+
+    pub fn events(&self) -> &[RecordedEvent] {
+        &self.events
+    }
+
+    /// The events the client sent during the recording, e.g. to compare them with the ones of
+    /// the agent under test.
+    pub fn client_events(&self) -> impl Iterator<Item = &serde_json::Value> {
+        self.events
+            .iter()
+            .filter(|e| e.direction == RealtimeDirection::Client)
+            .map(|e| &e.event)
+    }
+
+    /// The server events, at the pace of the recording if `timed`, else all at once.
+    pub fn server_events(&self, timed: bool) -> BoxStream<'static, Result<RealtimeServerEvent>> {
+        let events: Vec<_> = self
+            .events
+            .iter()
+            .filter(|e| e.direction == RealtimeDirection::Server)
+            .map(|e| (e.at_ms, e.event.clone()))
+            .collect();
+        let start = tokio::time::Instant::now();
+        futures::stream::iter(events)
+            .then(move |(at_ms, event)| async move {
+                if timed {
+                    tokio::time::sleep_until(start + Duration::from_millis(at_ms)).await;
+                }
+                RealtimeServerEvent::from_value(event)
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn realtime_replay_should_stream_recorded_server_events() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("realtime-{}.jsonl", std::process::id()));
+        let recorder = RealtimeRecorder::create(&path)?;
+        recorder.record(
+            RealtimeDirection::Client,
+            r#"{"type":"input_audio_buffer.commit"}"#,
+        );
+        recorder.record(
+            RealtimeDirection::Server,
+            r#"{"type":"response.text.delta","response_id":"r1","item_id":"i1","delta":"Hi"}"#,
+        );
+        recorder.record(
+            RealtimeDirection::Server,
+            r#"{"type":"rate_limits.updated"}"#,
+        );
+
+        let replay = RealtimeReplay::open(&path)?;
+        std::fs::remove_file(&path)?;
+        assert_eq!(replay.events().len(), 3);
+        assert_eq!(
+            replay.client_events().collect::<Vec<_>>(),
+            [&serde_json::json!({ "type": "input_audio_buffer.commit" })]
+        );
+        let events: Vec<_> = replay.server_events(true).collect().await;
+        assert_eq!(
+            events[0].as_ref().unwrap(),
+            &RealtimeServerEvent::TextDelta {
+                response_id: "r1".into(),
+                item_id: "i1".into(),
+                delta: "Hi".into(),
+            }
+        );
+        assert_eq!(
+            events[1].as_ref().unwrap(),
+            &RealtimeServerEvent::Other("rate_limits.updated".into())
+        );
+        Ok(())
+    }
+}
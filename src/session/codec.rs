@@ -0,0 +1,181 @@
+//! Chat session snapshots at rest. A `SnapshotCodec` stores the JSON of a `ChatSessionSnapshot`
+//! compressed with zstd and, once given a `SnapshotCipher` (e.g. `ChaChaCipher` with a key of the
+//! app), sealed with an AEAD so the stored chat logs can't be read or altered without the key. A
+//! two-byte header (version, flags) tells `decode` how the snapshot was stored.
+
+use super::ChatSessionSnapshot;
+use anyhow::{anyhow, Result};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Nonce,
+};
+use std::{fmt, sync::Arc};
+
+const VERSION: u8 = 1;
+const COMPRESSED: u8 = 1;
+const ENCRYPTED: u8 = 2;
+const NONCE_LEN: usize = 12;
+
+/// The encryption hook of a `SnapshotCodec`: an AEAD authenticating what it encrypts.
+pub trait SnapshotCipher: fmt::Debug + Send + Sync {
+    fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>>;
+    /// Fails if `sealed` was not sealed with the same key or was tampered with.
+    fn open(&self, sealed: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// ChaCha20-Poly1305 with a random nonce per snapshot, stored before the ciphertext.
+#[derive(Clone)]
+pub struct ChaChaCipher(ChaCha20Poly1305);
+
+/// How the snapshots are stored: zstd compressed (level 3) by default, and encrypted once a
+/// cipher is set.
+#[derive(Debug, Clone)]
+pub struct SnapshotCodec {
+    /// `None` to store the JSON uncompressed.
+    compression_level: Option<i32>,
+    cipher: Option<Arc<dyn SnapshotCipher>>,
+}
+
+impl ChaChaCipher {
+    pub fn new(key: [u8; 32]) -> Self {
+        Self(ChaCha20Poly1305::new(&key.into()))
+    }
+}
+
+impl fmt::Debug for ChaChaCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // never print the key
+        f.write_str("ChaChaCipher")
+    }
+}
+
+impl SnapshotCipher for ChaChaCipher {
+    fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .0
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| anyhow!("failed to encrypt the snapshot"))?;
+        Ok([nonce.as_slice(), &ciphertext].concat())
+    }
+
+    fn open(&self, sealed: &[u8]) -> Result<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            return Err(anyhow!("the encrypted snapshot is truncated"));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.0
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("failed to decrypt the snapshot: wrong key or corrupted data"))
+    }
+}
+
+impl Default for SnapshotCodec {
+    fn default() -> Self {
+        Self {
+            compression_level: Some(3),
+            cipher: None,
+        }
+    }
+}
+
+impl SnapshotCodec {
+    pub fn with_compression_level(mut self, level: Option<i32>) -> Self {
+        self.compression_level = level;
+        self
+    }
+
+    pub fn with_cipher(mut self, cipher: impl SnapshotCipher + 'static) -> Self {
+        self.cipher = Some(Arc::new(cipher));
+        self
+    }
+
+    pub fn encode(&self, snapshot: &ChatSessionSnapshot) -> Result<Vec<u8>> {
+        let mut data = serde_json::to_vec(snapshot)?;
+        let mut flags = 0;
+        if let Some(level) = self.compression_level {
+            data = zstd::encode_all(data.as_slice(), level)?;
+            flags |= COMPRESSED;
+        }
+        if let Some(cipher) = &self.cipher {
+            data = cipher.seal(&data)?;
+            flags |= ENCRYPTED;
+        }
+        Ok([&[VERSION, flags][..], &data].concat())
+    }
+
+    /// Decode a snapshot stored by `encode`, whatever its compression level. An encrypted
+    /// snapshot needs the cipher it was sealed with.
+    pub fn decode(&self, data: &[u8]) -> Result<ChatSessionSnapshot> {
+        let (flags, data) = match data {
+            [VERSION, flags, data @ ..] => (*flags, data),
+            _ => return Err(anyhow!("not a chat session snapshot of a known version")),
+        };
+        let mut data = data.to_vec();
+        if flags & ENCRYPTED != 0 {
+            let cipher = self
+                .cipher
+                .as_ref()
+                .ok_or_else(|| anyhow!("the snapshot is encrypted but the codec has no cipher"))?;
+            data = cipher.open(&data)?;
+        }
+        if flags & COMPRESSED != 0 {
+            data = zstd::decode_all(data.as_slice())?;
+        }
+        Ok(serde_json::from_slice(&data)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChatCompleteModel, ChatCompletionMessage};
+
+    fn snapshot() -> ChatSessionSnapshot {
+        let answer = "Hello! How can I help you today? ".repeat(20);
+        ChatSessionSnapshot {
+            model: ChatCompleteModel::Gpt4o,
+            messages: vec![
+                ChatCompletionMessage::new_system("You are a helpful bot.", ""),
+                ChatCompletionMessage::new_user("Hi", ""),
+                ChatCompletionMessage::new_assistant(answer),
+            ],
+        }
+    }
+
+    #[test]
+    fn snapshot_codec_should_compress_and_encrypt() -> Result<()> {
+        let snapshot = snapshot();
+        let json = serde_json::to_vec(&snapshot)?;
+        let codec = SnapshotCodec::default().with_cipher(ChaChaCipher::new([7; 32]));
+        let data = codec.encode(&snapshot)?;
+        assert!(data.len() < json.len() / 2);
+        assert!(!data.windows(2).any(|w| w == b"Hi"));
+
+        let decoded = codec.decode(&data)?;
+        assert_eq!(serde_json::to_vec(&decoded)?, json);
+
+        // a plain codec still reads its own snapshots, not the encrypted ones
+        let plain = SnapshotCodec::default().with_compression_level(None);
+        let decoded = plain.decode(&plain.encode(&snapshot)?)?;
+        assert_eq!(serde_json::to_vec(&decoded)?, json);
+        assert!(plain.decode(&data).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn snapshot_codec_should_reject_wrong_key_or_tampering() -> Result<()> {
+        let data = SnapshotCodec::default()
+            .with_cipher(ChaChaCipher::new([7; 32]))
+            .encode(&snapshot())?;
+        let other = SnapshotCodec::default().with_cipher(ChaChaCipher::new([8; 32]));
+        assert!(other.decode(&data).is_err());
+
+        let mut tampered = data;
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        let codec = SnapshotCodec::default().with_cipher(ChaChaCipher::new([7; 32]));
+        assert!(codec.decode(&tampered).is_err());
+        Ok(())
+    }
+}
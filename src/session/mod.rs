@@ -0,0 +1,375 @@
+//! A multi-turn chat session. `ChatSession` owns the message history: every turn appends the
+//! user message, the tool calls and their results (with a `ToolRegistry`) and the answer, so the
+//! caller only deals with texts. The transcript can be snapshotted to persist the session and
+//! restored later, compressed and encrypted by a `SnapshotCodec` (`session-codec` feature). With a `Truncation`, the oldest messages are dropped from the history
+//! before a turn would exceed the context window of the model. A session can be forked at any
+//! message into an independent branch, e.g. to edit a message and regenerate the answer: the
+//! common prefix of the history is shared, not copied.
+
+use crate::{
+    ChatCompleteModel, ChatCompletionMessage, ChatCompletionRequest, LlmSdk, ToolRegistry,
+    Truncation,
+};
+use anyhow::{anyhow, Result};
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+#[cfg(feature = "session-codec")]
+mod codec;
+
+#[cfg(feature = "session-codec")]
+pub use codec::{ChaChaCipher, SnapshotCipher, SnapshotCodec};
+
+static NEXT_BRANCH_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, Clone)]
+pub struct ChatSession {
+    sdk: LlmSdk,
+    model: ChatCompleteModel,
+    /// The start of the history shared with the forks: the first messages of a segment.
+    prefix: Option<(Arc<Segment>, usize)>,
+    /// The rest of the history, owned by this session.
+    messages: Vec<ChatCompletionMessage>,
+    tools: Option<Arc<ToolRegistry>>,
+    truncation: Option<Truncation>,
+    branch: Branch,
+}
+
+/// The lineage of a session, see `ChatSession::fork`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Branch {
+    /// Unique in the process.
+    pub id: u64,
+    /// The id of the session it was forked from, and the number of messages it took.
+    pub parent: Option<(u64, usize)>,
+}
+
+/// Immutable messages shared by the forks, following the first messages of the parent.
+#[derive(Debug)]
+struct Segment {
+    parent: Option<(Arc<Segment>, usize)>,
+    messages: Vec<ChatCompletionMessage>,
+}
+
+/// The persisted state of a `ChatSession`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatSessionSnapshot {
+    pub model: ChatCompleteModel,
+    pub messages: Vec<ChatCompletionMessage>,
+}
+
+impl ChatSession {
+    pub fn new(sdk: LlmSdk, model: ChatCompleteModel) -> Self {
+        Self {
+            sdk,
+            model,
+            prefix: None,
+            messages: Vec::new(),
+            tools: None,
+            truncation: None,
+            branch: Branch::new(None),
+        }
+    }
+
+    /// Start the history with a system message.
+    pub fn with_system(mut self, prompt: impl Into<String>) -> Self {
+        let mut messages = self.history();
+        messages.insert(0, ChatCompletionMessage::new_system(prompt, ""));
+        self.set_history(messages, true);
+        self
+    }
+
+    /// Run the tool calls of the model with `tools` (see `LlmSdk::chat_with_tools`). Tools are
+    /// not available to `send_stream`.
+    pub fn with_tools(mut self, tools: Arc<ToolRegistry>) -> Self {
+        self.tools = Some(tools);
+        self
+    }
+
+    /// Truncate the history to fit the context window before every turn. The dropped messages
+    /// are gone from the history once the turn succeeds.
+    pub fn with_truncation(mut self, truncation: Truncation) -> Self {
+        self.truncation = Some(truncation);
+        self
+    }
+
+    /// Continue a persisted session.
+    pub fn restore(sdk: LlmSdk, snapshot: ChatSessionSnapshot) -> Self {
+        Self {
+            sdk,
+            model: snapshot.model,
+            prefix: None,
+            messages: snapshot.messages,
+            tools: None,
+            truncation: None,
+            branch: Branch::new(None),
+        }
+    }
+
+    pub fn snapshot(&self) -> ChatSessionSnapshot {
+        ChatSessionSnapshot {
+            model: self.model.clone(),
+            messages: self.history(),
+        }
+    }
+
+    pub fn messages(&self) -> Vec<&ChatCompletionMessage> {
+        let mut ret: Vec<_> = self.prefix_slices().into_iter().flatten().collect();
+        ret.extend(&self.messages);
+        ret
+    }
+
+    pub fn branch(&self) -> Branch {
+        self.branch
+    }
+
+    /// A new branch with the first `at` messages of the history, e.g. `fork(n - 1)` to edit the
+    /// last user message and `send` it again. The branches are independent, but share the
+    /// messages they have in common.
+    pub fn fork(&mut self, at: usize) -> Result<Self> {
+        let len = self.messages.len() + self.prefix_len();
+        if at > len {
+            return Err(anyhow!(
+                "can't fork at message {}, the session has {} messages",
+                at,
+                len
+            ));
+        }
+        // freeze the messages of this session so the fork can share them
+        if !self.messages.is_empty() {
+            let segment = Segment {
+                parent: self.prefix.take(),
+                messages: std::mem::take(&mut self.messages),
+            };
+            let n = segment.messages.len();
+            self.prefix = Some((Arc::new(segment), n));
+        }
+        let mut prefix = self.prefix.clone();
+        let mut end = len;
+        while let Some((segment, n)) = prefix {
+            let start = end - n;
+            if at > start {
+                prefix = Some((segment, at - start));
+                break;
+            }
+            prefix = segment.parent.clone();
+            end = start;
+        }
+        Ok(Self {
+            prefix,
+            messages: Vec::new(),
+            branch: Branch::new(Some((self.branch.id, at))),
+            ..self.clone()
+        })
+    }
+
+    /// Forget the history, except the system message.
+    pub fn clear(&mut self) {
+        let mut messages = self.history();
+        messages.retain(|message| matches!(message, ChatCompletionMessage::System(_)));
+        self.set_history(messages, true);
+    }
+
+    /// Send a user message and return the answer. The history is only updated when the turn
+    /// succeeds, so a failed turn can be retried.
+    pub async fn send(&mut self, text: impl Into<String>) -> Result<String> {
+        let (req, truncated) = self.request(text.into());
+        let (res, mut messages) = match &self.tools {
+            Some(tools) => {
+                let run = self.sdk.chat_with_tools(req, tools).await?;
+                (run.response, run.messages)
+            }
+            None => {
+                let messages = req.messages().to_vec();
+                (self.sdk.chat_completion(req).await?, messages)
+            }
+        };
+        let message = res
+            .choices
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("the response has no choice"))?
+            .message;
+        let content = message.content.clone().unwrap_or_default();
+        messages.push(ChatCompletionMessage::Assistant(message));
+        self.set_history(messages, truncated);
+        Ok(content)
+    }
+
+    /// Send a user message and stream the deltas of the answer. The turn is added to the history
+    /// once the stream is consumed to the end; a stream dropped (or failing) before leaves the
+    /// history as it was.
+    pub async fn send_stream(
+        &mut self,
+        text: impl Into<String>,
+    ) -> Result<BoxStream<'_, Result<String>>> {
+        let (req, truncated) = self.request(text.into());
+        let messages = req.messages().to_vec();
+        let stream = self.sdk.chat_completion_stream(req).await?;
+        let state = Some((stream, String::new(), messages, self));
+        Ok(stream::unfold(state, move |state| async move {
+            let (mut stream, mut text, mut messages, session) = state?;
+            loop {
+                match stream.next().await {
+                    Some(Ok(chunk)) => {
+                        let delta = chunk
+                            .choices
+                            .into_iter()
+                            .next()
+                            .and_then(|choice| choice.delta.content)
+                            .unwrap_or_default();
+                        if delta.is_empty() {
+                            continue;
+                        }
+                        text.push_str(&delta);
+                        return Some((Ok(delta), Some((stream, text, messages, session))));
+                    }
+                    Some(Err(e)) => return Some((Err(e), None)),
+                    None => {
+                        messages.push(ChatCompletionMessage::new_assistant(text));
+                        session.set_history(messages, truncated);
+                        return None;
+                    }
+                }
+            }
+        })
+        .boxed())
+    }
+
+    /// The request of a turn, and whether the history was truncated.
+    fn request(&self, text: String) -> (ChatCompletionRequest, bool) {
+        let mut messages = self.history();
+        messages.push(ChatCompletionMessage::new_user(text, ""));
+        let mut truncated = false;
+        if let Some(truncation) = &self.truncation {
+            let len = messages.len();
+            messages = truncation.apply(&self.model, messages);
+            truncated = messages.len() != len;
+        }
+        (
+            ChatCompletionRequest::new(self.model.clone(), messages),
+            truncated,
+        )
+    }
+
+    fn history(&self) -> Vec<ChatCompletionMessage> {
+        self.messages().into_iter().cloned().collect()
+    }
+
+    /// Replace the history. Unless `changed`, it starts with the current history, and the shared
+    /// prefix is kept.
+    fn set_history(&mut self, mut messages: Vec<ChatCompletionMessage>, changed: bool) {
+        if changed {
+            self.prefix = None;
+            self.messages = messages;
+        } else {
+            self.messages = messages.split_off(self.prefix_len());
+        }
+    }
+
+    fn prefix_len(&self) -> usize {
+        self.prefix_slices().iter().map(|s| s.len()).sum()
+    }
+
+    /// The slices of the shared prefix, in order.
+    fn prefix_slices(&self) -> Vec<&[ChatCompletionMessage]> {
+        let mut ret = Vec::new();
+        let mut prefix = self.prefix.as_ref();
+        while let Some((segment, n)) = prefix {
+            ret.push(&segment.messages[..*n]);
+            prefix = segment.parent.as_ref();
+        }
+        ret.reverse();
+        ret
+    }
+}
+
+impl Branch {
+    fn new(parent: Option<(u64, usize)>) -> Self {
+        Self {
+            id: NEXT_BRANCH_ID.fetch_add(1, Ordering::Relaxed),
+            parent,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SDK;
+
+    #[test]
+    fn chat_session_snapshot_should_restore() -> Result<()> {
+        let sdk = LlmSdk::ollama("http://localhost:11434/v1");
+        let mut session = ChatSession::new(sdk.clone(), ChatCompleteModel::Gpt4o)
+            .with_system("You are a helpful bot.");
+        session
+            .messages
+            .push(ChatCompletionMessage::new_user("Hi", ""));
+        session
+            .messages
+            .push(ChatCompletionMessage::new_assistant("Hello!"));
+        let data = serde_json::to_string(&session.snapshot())?;
+
+        let mut session = ChatSession::restore(sdk, serde_json::from_str(&data)?);
+        assert_eq!(session.messages().len(), 3);
+        assert_eq!(serde_json::to_string(&session.snapshot())?, data);
+        session.clear();
+        assert_eq!(session.messages().len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn chat_session_fork_should_share_prefix() -> Result<()> {
+        let sdk = LlmSdk::ollama("http://localhost:11434/v1");
+        let mut session =
+            ChatSession::new(sdk, ChatCompleteModel::Gpt4o).with_system("You are a helpful bot.");
+        for text in ["Hi", "Hello!", "Tell me a joke", "No."] {
+            session
+                .messages
+                .push(ChatCompletionMessage::new_user(text, ""));
+        }
+        assert!(session.fork(6).is_err());
+
+        let mut fork = session.fork(3)?;
+        assert_eq!(fork.messages().len(), 3);
+        assert!(fork.messages.is_empty());
+        assert_eq!(fork.branch().parent, Some((session.branch().id, 3)));
+        let (Some((a, _)), Some((b, _))) = (&session.prefix, &fork.prefix) else {
+            panic!("no shared prefix");
+        };
+        assert!(Arc::ptr_eq(a, b));
+
+        fork.messages
+            .push(ChatCompletionMessage::new_user("Tell me a story", ""));
+        assert_eq!(session.messages().len(), 5);
+        assert_eq!(fork.messages().len(), 4);
+        let nested = fork.fork(4)?;
+        assert_eq!(nested.prefix_len(), 4);
+        assert_eq!(nested.branch().parent, Some((fork.branch().id, 4)));
+        assert_eq!(session.fork(0)?.messages().len(), 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn chat_session_should_keep_history() -> Result<()> {
+        let mut session = ChatSession::new(SDK.clone(), ChatCompleteModel::Gpt4o);
+        session.send("My name is Tyr. Just say hi.").await?;
+        let mut stream = session.send_stream("What's my name?").await?;
+        let mut answer = String::new();
+        while let Some(delta) = stream.next().await {
+            answer.push_str(&delta?);
+        }
+        drop(stream);
+        assert!(answer.contains("Tyr"));
+        assert_eq!(session.messages().len(), 4);
+        Ok(())
+    }
+}
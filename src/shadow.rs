@@ -0,0 +1,82 @@
+//! Shadow traffic: mirror a sampled fraction of the chat completions to a secondary provider or
+//! model, e.g. to evaluate a migration target under real traffic. The mirrored requests run in
+//! the background after the primary one is sent; their responses are only logged (latency and
+//! usage as tracing events) and never returned to the caller.
+
+use crate::{ChatCompleteModel, ChatCompletionRequest, LlmSdk};
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
+use tracing::{info, warn};
+
+#[derive(Debug)]
+pub struct ShadowTraffic {
+    sdk: LlmSdk,
+    model: Option<ChatCompleteModel>,
+    sample_rate: f64,
+    count: AtomicU64,
+}
+
+impl ShadowTraffic {
+    /// Mirror `sample_rate` (0.0 - 1.0) of the requests to `sdk`, which should not have shadow
+    /// traffic configured itself.
+    pub fn new(sdk: LlmSdk, sample_rate: f64) -> Self {
+        Self {
+            sdk,
+            model: None,
+            sample_rate: sample_rate.clamp(0.0, 1.0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    /// Send the mirrored requests with this model instead of the one of the request.
+    pub fn with_model(mut self, model: ChatCompleteModel) -> Self {
+        self.model = Some(model);
+        self
+    }
+
+    /// Whether the next request is mirrored. The sampling is evenly spaced rather than random,
+    /// e.g. every 10th request for 0.1.
+    pub(crate) fn sample(&self) -> bool {
+        let n = self.count.fetch_add(1, Ordering::Relaxed) as f64;
+        ((n + 1.0) * self.sample_rate).floor() > (n * self.sample_rate).floor()
+    }
+
+    /// Send the request to the secondary in the background. Must be called within a tokio runtime.
+    pub(crate) fn mirror(&self, mut req: ChatCompletionRequest) {
+        if let Some(model) = &self.model {
+            req.model = model.clone();
+        }
+        let sdk = self.sdk.clone();
+        tokio::spawn(async move {
+            let model = req.model.clone();
+            let start = Instant::now();
+            match sdk.send_chat_completion(req).await {
+                Ok(res) => info!(
+                    model = %model,
+                    elapsed_ms = start.elapsed().as_millis() as u64,
+                    prompt_tokens = res.usage.prompt_tokens,
+                    completion_tokens = res.usage.completion_tokens,
+                    "shadow completion"
+                ),
+                Err(e) => warn!(model = %model, "shadow completion failed: {}", e),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shadow_traffic_should_sample_evenly() {
+        let shadow = ShadowTraffic::new(LlmSdk::ollama("http://localhost:11434/v1"), 0.1);
+        let sampled = (0..100).filter(|_| shadow.sample()).count();
+        assert_eq!(sampled, 10);
+
+        let shadow = ShadowTraffic::new(LlmSdk::ollama("http://localhost:11434/v1"), 0.0);
+        assert!(!(0..100).any(|_| shadow.sample()));
+    }
+}
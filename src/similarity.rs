@@ -0,0 +1,137 @@
+//! Vector similarity helpers for embeddings. With the `simd` feature the inner loops use
+//! 8-lane f32 vectors, which keeps brute-force top-k search over a few hundred thousand
+//! embeddings in the millisecond range.
+
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+/// Dot product of two vectors of the same dimension.
+pub fn dot_product(a: &[f32], b: &[f32]) -> f32 {
+    debug_assert_eq!(a.len(), b.len(), "vectors must have the same dimension");
+    #[cfg(feature = "simd")]
+    {
+        simd::dot(a, b)
+    }
+    #[cfg(not(feature = "simd"))]
+    {
+        a.iter().zip(b).map(|(x, y)| x * y).sum()
+    }
+}
+
+/// Cosine similarity of two vectors, 0 if either of them is a zero vector. OpenAI embeddings are
+/// normalized to length 1, so for them this is the same as `dot_product`.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let norm = (dot_product(a, a) * dot_product(b, b)).sqrt();
+    if norm == 0.0 {
+        0.0
+    } else {
+        dot_product(a, b) / norm
+    }
+}
+
+/// Return the `k` candidates most similar to `query` by cosine similarity, as (index, score)
+/// pairs sorted by descending score.
+pub fn top_k<'a>(
+    query: &[f32],
+    candidates: impl IntoIterator<Item = &'a [f32]>,
+    k: usize,
+) -> Vec<(usize, f32)> {
+    if k == 0 {
+        return Vec::new();
+    }
+    let query_norm = dot_product(query, query).sqrt();
+    // min-heap on score, so the worst of the current top k is on top
+    let mut heap = BinaryHeap::with_capacity(k + 1);
+    for (index, candidate) in candidates.into_iter().enumerate() {
+        let norm = query_norm * dot_product(candidate, candidate).sqrt();
+        let score = if norm == 0.0 {
+            0.0
+        } else {
+            dot_product(query, candidate) / norm
+        };
+        heap.push(Scored { index, score });
+        if heap.len() > k {
+            heap.pop();
+        }
+    }
+    let mut ret: Vec<_> = heap.into_iter().map(|s| (s.index, s.score)).collect();
+    ret.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ret
+}
+
+struct Scored {
+    index: usize,
+    score: f32,
+}
+
+impl PartialEq for Scored {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // reversed to make BinaryHeap a min-heap
+        other.score.total_cmp(&self.score)
+    }
+}
+
+#[cfg(feature = "simd")]
+mod simd {
+    use wide::f32x8;
+
+    pub(super) fn dot(a: &[f32], b: &[f32]) -> f32 {
+        let (ca, cb) = (a.chunks_exact(8), b.chunks_exact(8));
+        let tail: f32 = ca
+            .remainder()
+            .iter()
+            .zip(cb.remainder())
+            .map(|(x, y)| x * y)
+            .sum();
+        let mut acc = f32x8::ZERO;
+        for (x, y) in ca.zip(cb) {
+            acc = lanes(x).mul_add(lanes(y), acc);
+        }
+        acc.reduce_add() + tail
+    }
+
+    fn lanes(v: &[f32]) -> f32x8 {
+        // chunks_exact guarantees 8 elements
+        f32x8::from(<[f32; 8]>::try_from(v).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dot_product_should_work() {
+        let a: Vec<f32> = (0..19).map(|i| i as f32).collect();
+        let b = [2.0; 19];
+        assert_eq!(dot_product(&a, &b), 342.0);
+    }
+
+    #[test]
+    fn cosine_similarity_should_work() {
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]), 1.0);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 3.0]), 0.0);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 1.0]), 0.0);
+    }
+
+    #[test]
+    fn top_k_should_return_most_similar_first() {
+        let candidates = [[0.0, 1.0], [1.0, 0.1], [-1.0, 0.0], [1.0, 0.0]];
+        let ret = top_k(&[1.0, 0.0], candidates.iter().map(|v| v.as_slice()), 2);
+        assert_eq!(ret.iter().map(|(i, _)| *i).collect::<Vec<_>>(), [3, 1]);
+        assert_eq!(ret[0].1, 1.0);
+    }
+}
@@ -0,0 +1,263 @@
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use futures::{stream::BoxStream, Stream, StreamExt};
+use std::{collections::VecDeque, time::Duration};
+
+/// Event names some gateways / providers inject to keep idle connections open.
+pub(crate) const HEARTBEAT_EVENTS: [&str; 4] = ["ping", "heartbeat", "keep-alive", "keepalive"];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct SseEvent {
+    pub(crate) event: Option<String>,
+    pub(crate) data: String,
+}
+
+/// Incremental server-sent events decoder. Comment lines (`: keep-alive`) and the configured
+/// heartbeat events are swallowed, so consumers only ever see events that carry a payload.
+#[derive(Debug, Default)]
+pub(crate) struct SseDecoder {
+    buf: Vec<u8>,
+    event: Option<String>,
+    data: Vec<String>,
+    heartbeats: Vec<String>,
+}
+
+impl SseDecoder {
+    pub(crate) fn new(heartbeats: Vec<String>) -> Self {
+        Self {
+            heartbeats,
+            ..Default::default()
+        }
+    }
+
+    /// Feed a chunk of the body, returning all the events it completed.
+    pub(crate) fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buf.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(pos) = self.buf.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if let Some(event) = self.process_line(line) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// Flush whatever is left once the body is finished (a server may omit the final blank line).
+    pub(crate) fn finish(&mut self) -> Option<SseEvent> {
+        let rest = std::mem::take(&mut self.buf);
+        let line = String::from_utf8_lossy(&rest);
+        let line = line.trim_end_matches(['\n', '\r']);
+        if !line.is_empty() {
+            // a pending line can't complete an event by itself
+            let _ = self.process_line(line);
+        }
+        self.dispatch()
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            // comment line, commonly used as keep-alive
+            return None;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => self.data.push(value.to_string()),
+            // id, retry and unknown fields are not used by any of the APIs
+            _ => {}
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        let data = std::mem::take(&mut self.data);
+        if data.is_empty() {
+            return None;
+        }
+        if let Some(name) = &event {
+            if self.heartbeats.iter().any(|h| h == name) {
+                return None;
+            }
+        }
+        Some(SseEvent {
+            event,
+            data: data.join("\n"),
+        })
+    }
+}
+
+struct EventStreamState {
+    body: BoxStream<'static, reqwest::Result<Bytes>>,
+    decoder: SseDecoder,
+    pending: VecDeque<SseEvent>,
+    idle_timeout: Duration,
+    done: bool,
+}
+
+/// Turn a response body into a stream of SSE events. The idle timeout is measured against raw
+/// bytes, so comments and heartbeats keep the stream alive even though they are never yielded.
+pub(crate) fn event_stream(
+    body: impl Stream<Item = reqwest::Result<Bytes>> + Send + 'static,
+    idle_timeout: Duration,
+    heartbeats: Vec<String>,
+) -> impl Stream<Item = Result<SseEvent>> + Send + 'static {
+    let state = EventStreamState {
+        body: body.boxed(),
+        decoder: SseDecoder::new(heartbeats),
+        pending: VecDeque::new(),
+        idle_timeout,
+        done: false,
+    };
+
+    futures::stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(event) = state.pending.pop_front() {
+                return Some((Ok(event), state));
+            }
+            if state.done {
+                return None;
+            }
+            match tokio::time::timeout(state.idle_timeout, state.body.next()).await {
+                Err(_) => {
+                    state.done = true;
+                    let err = anyhow!("stream idle for more than {:?}", state.idle_timeout);
+                    return Some((Err(err), state));
+                }
+                Ok(None) => {
+                    state.done = true;
+                    state.pending.extend(state.decoder.finish());
+                }
+                Ok(Some(Err(e))) => {
+                    state.done = true;
+                    return Some((Err(anyhow::Error::from(e)), state));
+                }
+                Ok(Some(Ok(chunk))) => {
+                    let events = state.decoder.feed(&chunk);
+                    state.pending.extend(events);
+                }
+            }
+        }
+    })
+}
+
+/// Cancels the job of a stream on the server (a response or a run) when the stream is dropped
+/// while the job is running, so the provider stops generating (and billing) tokens. Dropping the
+/// stream closes the connection, which is enough for the chat completions.
+pub(crate) struct CancelOnDrop {
+    id: Option<String>,
+    cancel: Box<dyn Fn(String) + Send>,
+}
+
+impl CancelOnDrop {
+    pub(crate) fn new(cancel: impl Fn(String) + Send + 'static) -> Self {
+        Self {
+            id: None,
+            cancel: Box::new(cancel),
+        }
+    }
+
+    /// The job `id` is running.
+    pub(crate) fn arm(&mut self, id: impl Into<String>) {
+        self.id = Some(id.into());
+    }
+
+    /// The job is done, nothing to cancel.
+    pub(crate) fn disarm(&mut self) {
+        self.id = None;
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(id) = self.id.take() {
+            (self.cancel)(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+    use std::sync::{Arc, Mutex};
+
+    fn decoder() -> SseDecoder {
+        SseDecoder::new(HEARTBEAT_EVENTS.iter().map(|s| s.to_string()).collect())
+    }
+
+    #[test]
+    fn sse_decoder_should_skip_comments_and_heartbeats() {
+        let mut decoder = decoder();
+        let events = decoder.feed(
+            b": keep-alive\n\nevent: ping\ndata: {}\n\n: OPENROUTER PROCESSING\n\ndata: {\"a\":1}\n\n",
+        );
+        assert_eq!(
+            events,
+            vec![SseEvent {
+                event: None,
+                data: "{\"a\":1}".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn sse_decoder_should_handle_split_chunks_and_multiline_data() {
+        let mut decoder = decoder();
+        assert!(decoder.feed(b"event: message\r\ndata: hel").is_empty());
+        assert!(decoder.feed(b"lo\r\ndata: world\r\n").is_empty());
+        let events = decoder.feed(b"\r\ndata: [DONE]");
+        assert_eq!(
+            events,
+            vec![SseEvent {
+                event: Some("message".into()),
+                data: "hello\nworld".into()
+            }]
+        );
+        assert_eq!(
+            decoder.finish(),
+            Some(SseEvent {
+                event: None,
+                data: "[DONE]".into()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn event_stream_should_time_out_only_without_bytes() {
+        let body = stream::iter(vec![
+            Ok(Bytes::from(": keep-alive\n\n")),
+            Ok(Bytes::from("data: 1\n\n")),
+        ])
+        .chain(stream::pending());
+        let mut events = Box::pin(event_stream(
+            body,
+            Duration::from_millis(50),
+            Default::default(),
+        ));
+        assert_eq!(events.next().await.unwrap().unwrap().data, "1");
+        assert!(events.next().await.unwrap().is_err());
+        assert!(events.next().await.is_none());
+    }
+
+    #[test]
+    fn cancel_on_drop_should_cancel_armed_job() {
+        let cancelled = Arc::new(Mutex::new(Vec::new()));
+        let c = cancelled.clone();
+        let mut guard = CancelOnDrop::new(move |id| c.lock().unwrap().push(id));
+        guard.arm("resp_1");
+        guard.disarm();
+        guard.arm("resp_2");
+        drop(guard);
+        assert_eq!(*cancelled.lock().unwrap(), vec!["resp_2".to_string()]);
+    }
+}
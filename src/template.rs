@@ -0,0 +1,305 @@
+//! Request templates: a `ChatCompletionRequest` whose messages contain `{name}` placeholders.
+//! The request (model, tools, schema) is validated and the placeholders are parsed once, then
+//! `ChatTemplate::render` only substitutes the values, e.g. for a RAG prompt in a hot path.
+//! Use `{{` and `}}` for literal braces.
+
+use crate::{
+    models::warn_if_deprecated, BuildError, ChatCompleteModel, ChatCompletionMessage,
+    ChatCompletionRequest, ContentPart, ResponseFormat, ToolChoice, UserContent,
+};
+use std::collections::{BTreeSet, HashSet};
+
+/// The maximum length of the tool and schema names.
+const MAX_NAME_LEN: usize = 64;
+
+/// A validated request with placeholders.
+#[derive(Debug, Clone)]
+pub struct ChatTemplate {
+    request: ChatCompletionRequest,
+    /// The parsed texts of the messages, in the order of `texts_mut`.
+    texts: Vec<Vec<Segment>>,
+    placeholders: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Placeholder(String),
+}
+
+impl ChatTemplate {
+    pub fn new(request: ChatCompletionRequest) -> Result<Self, BuildError> {
+        validate(&request)?;
+        let mut request = request;
+        let mut texts = Vec::new();
+        for text in texts_mut(&mut request.messages) {
+            texts.push(parse(text)?);
+        }
+        let placeholders = texts
+            .iter()
+            .flatten()
+            .filter_map(|segment| match segment {
+                Segment::Placeholder(name) => Some(name.clone()),
+                Segment::Literal(_) => None,
+            })
+            .collect();
+        Ok(Self {
+            request,
+            texts,
+            placeholders,
+        })
+    }
+
+    /// The names of the placeholders, sorted.
+    pub fn placeholders(&self) -> impl Iterator<Item = &str> {
+        self.placeholders.iter().map(|s| s.as_str())
+    }
+
+    /// The request with the placeholders replaced by `values`. Every placeholder needs a value,
+    /// and every value a placeholder.
+    pub fn render(&self, values: &[(&str, &str)]) -> Result<ChatCompletionRequest, BuildError> {
+        if let Some((name, _)) = values
+            .iter()
+            .find(|(name, _)| !self.placeholders.contains(*name))
+        {
+            return Err(format!("the template has no placeholder {{{}}}", name).into());
+        }
+        let value = |name: &str| {
+            values
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, v)| *v)
+                .ok_or_else(|| {
+                    BuildError::from(format!("no value for the placeholder {{{}}}", name))
+                })
+        };
+        let mut request = self.request.clone();
+        for (text, segments) in texts_mut(&mut request.messages).zip(&self.texts) {
+            let mut rendered = String::with_capacity(text.len());
+            for segment in segments {
+                match segment {
+                    Segment::Literal(s) => rendered.push_str(s),
+                    Segment::Placeholder(name) => rendered.push_str(value(name)?),
+                }
+            }
+            *text = rendered;
+        }
+        Ok(request)
+    }
+}
+
+impl TryFrom<ChatCompletionRequest> for ChatTemplate {
+    type Error = BuildError;
+
+    fn try_from(request: ChatCompletionRequest) -> Result<Self, Self::Error> {
+        Self::new(request)
+    }
+}
+
+fn validate(request: &ChatCompletionRequest) -> Result<(), BuildError> {
+    if request.messages.is_empty() {
+        return Err("the template has no messages".to_string().into());
+    }
+    if matches!(&request.model, ChatCompleteModel::Other(name) if name.trim().is_empty()) {
+        return Err("the model name is empty".to_string().into());
+    }
+    warn_if_deprecated(&request.model);
+
+    let mut names = HashSet::new();
+    for tool in &request.tools {
+        let name = &tool.function.name;
+        if !is_valid_name(name) {
+            return Err(format!("invalid tool name {:?}", name).into());
+        }
+        if !names.insert(name.as_str()) {
+            return Err(format!("duplicate tool {:?}", name).into());
+        }
+        if !tool.function.parameters.is_object() {
+            return Err(format!("the parameters of tool {:?} are not a JSON schema", name).into());
+        }
+    }
+    match &request.tool_choice {
+        Some(ToolChoice::Function { name }) if !names.contains(name.as_str()) => {
+            return Err(format!("the tool choice {:?} is not a tool", name).into());
+        }
+        Some(ToolChoice::Auto) if request.tools.is_empty() => {
+            return Err("the tool choice is auto but there are no tools"
+                .to_string()
+                .into());
+        }
+        _ => {}
+    }
+
+    if let Some(ResponseFormat::JsonSchema { name, schema, .. }) = &request.response_format {
+        if !is_valid_name(name) {
+            return Err(format!("invalid response format name {:?}", name).into());
+        }
+        if !schema.is_object() {
+            return Err("the response format schema is not a JSON schema"
+                .to_string()
+                .into());
+        }
+    }
+    Ok(())
+}
+
+/// a-z, A-Z, 0-9, underscores and dashes, at most 64 characters.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// The texts of the messages which may contain placeholders.
+fn texts_mut(messages: &mut [ChatCompletionMessage]) -> impl Iterator<Item = &mut String> {
+    messages.iter_mut().flat_map(|message| -> Vec<&mut String> {
+        match message {
+            ChatCompletionMessage::System(m) => vec![&mut m.content],
+            ChatCompletionMessage::User(m) => match &mut m.content {
+                UserContent::Text(text) => vec![text],
+                UserContent::Parts(parts) => parts
+                    .iter_mut()
+                    .filter_map(|part| match part {
+                        ContentPart::Text { text } => Some(text),
+                        _ => None,
+                    })
+                    .collect(),
+            },
+            ChatCompletionMessage::Assistant(m) => m.content.iter_mut().collect(),
+            ChatCompletionMessage::Tool(m) => vec![&mut m.content],
+        }
+    })
+}
+
+fn parse(text: &str) -> Result<Vec<Segment>, BuildError> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                let closed = chars.by_ref().any(|c| {
+                    c == '}' || {
+                        name.push(c);
+                        false
+                    }
+                });
+                if !closed
+                    || name.is_empty()
+                    || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                {
+                    return Err(format!("invalid placeholder {{{}}} in {:?}", name, text).into());
+                }
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(Segment::Placeholder(name));
+            }
+            '}' => return Err(format!("unmatched '}}' in {:?}", text).into()),
+            c => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChatCompletionRequestBuilder, Tool};
+    use schemars::JsonSchema;
+
+    #[allow(dead_code)]
+    #[derive(Debug, JsonSchema)]
+    struct SearchArgs {
+        /// the search query
+        query: String,
+    }
+
+    fn template() -> ChatTemplate {
+        let messages = vec![
+            ChatCompletionMessage::new_system("Answer from the context: {context}", ""),
+            ChatCompletionMessage::new_user("{question} {{verbatim}}", ""),
+        ];
+        ChatTemplate::new(ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt4o,
+            messages,
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn template_should_render_placeholders() {
+        let template = template();
+        assert_eq!(
+            template.placeholders().collect::<Vec<_>>(),
+            ["context", "question"]
+        );
+        let req = template
+            .render(&[("question", "Where?"), ("context", "Paris")])
+            .unwrap();
+        let json = serde_json::to_value(&req).unwrap();
+        assert_eq!(
+            json["messages"][0]["content"],
+            "Answer from the context: Paris"
+        );
+        assert_eq!(json["messages"][1]["content"], "Where? {verbatim}");
+    }
+
+    #[test]
+    fn template_should_reject_missing_or_unknown_values() {
+        let template = template();
+        let err = template.render(&[("question", "Where?")]).unwrap_err();
+        assert_eq!(err.to_string(), "no value for the placeholder {context}");
+        let err = template
+            .render(&[("question", "Where?"), ("context", ""), ("lang", "fr")])
+            .unwrap_err();
+        assert_eq!(err.to_string(), "the template has no placeholder {lang}");
+    }
+
+    #[test]
+    fn template_should_validate_the_request() {
+        let messages = vec![ChatCompletionMessage::new_user("{question", "")];
+        let err = ChatTemplate::new(ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt4o,
+            messages,
+        ))
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid placeholder {question} in \"{question\""
+        );
+
+        let tool = Tool::new_function::<SearchArgs>("search", "Search the web");
+        let req = ChatCompletionRequestBuilder::default()
+            .messages(vec![ChatCompletionMessage::new_user("{question}", "")])
+            .tools(vec![tool.clone(), tool])
+            .build()
+            .unwrap();
+        let err = ChatTemplate::new(req).unwrap_err();
+        assert_eq!(err.to_string(), "duplicate tool \"search\"");
+
+        let req = ChatCompletionRequestBuilder::default()
+            .messages(vec![ChatCompletionMessage::new_user("{question}", "")])
+            .tool_choice(ToolChoice::Function {
+                name: "lookup".to_string(),
+            })
+            .build()
+            .unwrap();
+        let err = ChatTemplate::new(req).unwrap_err();
+        assert_eq!(err.to_string(), "the tool choice \"lookup\" is not a tool");
+    }
+}
@@ -0,0 +1,117 @@
+//! Valid response objects, built without a server or hand-written JSON. The usage is estimated
+//! from the texts, the ids are fixed so the fixtures compare equal across runs.
+
+use crate::{
+    api::estimate_tokens, AssistantMessage, ChatCompleteModel, ChatCompleteUsage,
+    ChatCompletionChoice, ChatCompletionResponse, Embedding, EmbeddingData, EmbeddingResponse,
+    EmbeddingUsage, FinishReason, FunctionCall, ToolCall, ToolType,
+};
+use serde::Serialize;
+
+/// A chat completion answering `text`.
+pub fn chat_response(text: impl Into<String>) -> ChatCompletionResponse {
+    let text = text.into();
+    let completion_tokens = estimate_tokens(&text);
+    response(
+        FinishReason::Stop,
+        AssistantMessage {
+            content: Some(text),
+            name: None,
+            tool_calls: vec![],
+            audio: None,
+        },
+        completion_tokens,
+    )
+}
+
+/// A chat completion calling the tool `name` with `args`, serialized as JSON.
+pub fn tool_call(name: impl Into<String>, args: impl Serialize) -> ChatCompletionResponse {
+    let arguments = serde_json::to_string(&args).expect("the arguments should serialize");
+    let completion_tokens = estimate_tokens(&arguments);
+    let call = ToolCall {
+        id: "call_fixture".to_string(),
+        r#type: ToolType::Function,
+        function: FunctionCall {
+            name: name.into(),
+            arguments,
+        },
+    };
+    response(
+        FinishReason::ToolCalls,
+        AssistantMessage {
+            content: None,
+            name: None,
+            tool_calls: vec![call],
+            audio: None,
+        },
+        completion_tokens,
+    )
+}
+
+/// An embedding of `dim` dimensions, normalized like the ones of OpenAI.
+pub fn embedding(dim: usize) -> EmbeddingResponse {
+    let value = 1.0 / (dim.max(1) as f32).sqrt();
+    EmbeddingResponse {
+        object: "list".to_string(),
+        data: vec![EmbeddingData {
+            index: 0,
+            embedding: Embedding::Float(vec![value; dim]),
+            object: "embedding".to_string(),
+        }],
+        model: "text-embedding-3-small".to_string(),
+        usage: EmbeddingUsage {
+            prompt_tokens: 1,
+            total_tokens: 1,
+        },
+    }
+}
+
+fn response(
+    finish_reason: FinishReason,
+    message: AssistantMessage,
+    completion_tokens: usize,
+) -> ChatCompletionResponse {
+    let prompt_tokens = 10;
+    ChatCompletionResponse {
+        id: "chatcmpl-fixture".to_string(),
+        choices: vec![ChatCompletionChoice {
+            finish_reason,
+            index: 0,
+            message,
+            logprobs: None,
+        }],
+        created: 1_700_000_000,
+        model: ChatCompleteModel::Gpt4o,
+        system_fingerprint: None,
+        object: "chat.completion".to_string(),
+        usage: ChatCompleteUsage {
+            completion_tokens,
+            prompt_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+        },
+        provider_ext: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn fixtures_should_be_valid_responses() {
+        let res = chat_response("hello");
+        assert_eq!(res.choices[0].message.content.as_deref(), Some("hello"));
+        assert_eq!(res.usage.total_tokens, 12);
+
+        let res = tool_call("get_weather", json!({ "city": "Paris" }));
+        let call = &res.choices[0].message.tool_calls[0];
+        assert_eq!(res.choices[0].finish_reason, FinishReason::ToolCalls);
+        assert_eq!(call.function.arguments, r#"{"city":"Paris"}"#);
+
+        let res = embedding(4);
+        let floats = res.data[0].embedding.as_floats().unwrap();
+        assert_eq!(floats.len(), 4);
+        assert!((floats.iter().map(|v| v * v).sum::<f32>() - 1.0).abs() < 1e-6);
+    }
+}
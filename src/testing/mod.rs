@@ -0,0 +1,3 @@
+//! Helpers for the unit tests of the apps built on the SDK (the `testing` feature).
+
+pub mod fixtures;
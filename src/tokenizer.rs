@@ -0,0 +1,134 @@
+//! Token counting with tiktoken (the `tokenizer` feature), to estimate the cost and validate the
+//! size of a prompt before calling the API. The Claude models and `Other` are counted with the
+//! cl100k encoding, which is only an approximation for them.
+
+use crate::{ChatCompleteModel, ChatCompletionMessage, ContentPart, Tokenizer, UserContent};
+use std::sync::OnceLock;
+use tiktoken_rs::CoreBPE;
+
+/// The tokens of every message for the role and the separators.
+const TOKENS_PER_MESSAGE: usize = 3;
+/// The extra tokens of the `name` of a message, on top of the name itself.
+const TOKENS_PER_NAME: usize = 1;
+/// Every reply is primed with `<|start|>assistant<|message|>`.
+const REPLY_PRIMING: usize = 3;
+
+/// A `Tokenizer` for `Truncation` with the encoding of a model.
+#[derive(Debug, Clone)]
+pub struct Tiktoken {
+    model: ChatCompleteModel,
+}
+
+impl Tiktoken {
+    pub fn new(model: ChatCompleteModel) -> Self {
+        Self { model }
+    }
+}
+
+impl Tokenizer for Tiktoken {
+    fn count(&self, text: &str) -> usize {
+        count_tokens(&self.model, text)
+    }
+}
+
+/// The number of tokens of `text` for `model`.
+pub fn count_tokens(model: &ChatCompleteModel, text: &str) -> usize {
+    bpe(model).encode_with_special_tokens(text).len()
+}
+
+/// The number of prompt tokens of `messages` for `model`, including the per-message overhead and
+/// the priming of the reply, as reported by the `usage` of the response. Images and audio are
+/// not counted.
+pub fn count_chat_tokens(model: &ChatCompleteModel, messages: &[ChatCompletionMessage]) -> usize {
+    let bpe = bpe(model);
+    let count = |text: &str| bpe.encode_with_special_tokens(text).len();
+    let tokens: usize = messages
+        .iter()
+        .map(|message| {
+            let mut tokens = TOKENS_PER_MESSAGE + count(role(message));
+            match message {
+                ChatCompletionMessage::System(m) => {
+                    tokens += count(&m.content);
+                    if let Some(name) = &m.name {
+                        tokens += TOKENS_PER_NAME + count(name);
+                    }
+                }
+                ChatCompletionMessage::User(m) => {
+                    tokens += match &m.content {
+                        UserContent::Text(text) => count(text),
+                        UserContent::Parts(parts) => parts
+                            .iter()
+                            .filter_map(|part| match part {
+                                ContentPart::Text { text } => Some(count(text)),
+                                _ => None,
+                            })
+                            .sum(),
+                    };
+                    if let Some(name) = &m.name {
+                        tokens += TOKENS_PER_NAME + count(name);
+                    }
+                }
+                ChatCompletionMessage::Assistant(m) => {
+                    tokens += m.content.as_deref().map_or(0, count);
+                    for call in &m.tool_calls {
+                        tokens += count(&call.function.name) + count(&call.function.arguments);
+                    }
+                    if let Some(name) = &m.name {
+                        tokens += TOKENS_PER_NAME + count(name);
+                    }
+                }
+                ChatCompletionMessage::Tool(m) => tokens += count(&m.content),
+            }
+            tokens
+        })
+        .sum();
+    tokens + REPLY_PRIMING
+}
+
+fn role(message: &ChatCompletionMessage) -> &'static str {
+    match message {
+        ChatCompletionMessage::System(_) => "system",
+        ChatCompletionMessage::User(_) => "user",
+        ChatCompletionMessage::Assistant(_) => "assistant",
+        ChatCompletionMessage::Tool(_) => "tool",
+    }
+}
+
+fn bpe(model: &ChatCompleteModel) -> &'static CoreBPE {
+    static O200K: OnceLock<CoreBPE> = OnceLock::new();
+    static CL100K: OnceLock<CoreBPE> = OnceLock::new();
+    match model {
+        ChatCompleteModel::Gpt4o | ChatCompleteModel::Gpt4oAudioPreview => {
+            O200K.get_or_init(|| tiktoken_rs::o200k_base().expect("the o200k encoding is embedded"))
+        }
+        _ => CL100K
+            .get_or_init(|| tiktoken_rs::cl100k_base().expect("the cl100k encoding is embedded")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_tokens_should_work() {
+        assert_eq!(count_tokens(&ChatCompleteModel::Gpt4o, "hello world"), 2);
+        assert_eq!(
+            count_tokens(&ChatCompleteModel::Gpt4Turbo, "hello world"),
+            2
+        );
+    }
+
+    #[test]
+    fn count_chat_tokens_should_add_message_overhead() {
+        let model = ChatCompleteModel::Gpt4o;
+        let messages = vec![
+            ChatCompletionMessage::new_system("hello world", ""),
+            ChatCompletionMessage::new_user("hello world", "tyr"),
+        ];
+        // "system" and "user" are a token each
+        let name = TOKENS_PER_NAME + count_tokens(&model, "tyr");
+        let expected = 2 * (TOKENS_PER_MESSAGE + 1 + 2) + name + REPLY_PRIMING;
+        assert_eq!(count_chat_tokens(&model, &messages), expected);
+    }
+}
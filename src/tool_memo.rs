@@ -0,0 +1,117 @@
+//! Memoization of tool results within one agent run. Models (especially the flaky ones) often
+//! repeat an identical tool call; `ToolMemo` answers the repeats from memory, which saves the
+//! latency and avoids running side effects twice. Use one memo per run, the results are never
+//! invalidated.
+
+use crate::ToolCall;
+use anyhow::Result;
+use std::{collections::HashMap, future::Future};
+
+#[derive(Debug, Clone, Default)]
+pub struct ToolMemo {
+    /// Keyed by the tool name and the canonical form of the arguments.
+    results: HashMap<(String, String), String>,
+    stats: ToolMemoStats,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ToolMemoStats {
+    /// Calls answered from memory.
+    pub hits: usize,
+    /// Calls that ran the tool.
+    pub misses: usize,
+}
+
+impl ToolMemo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The output of `call`, running `f` only if an identical call (same tool and arguments, in
+    /// any key order) has not succeeded before in this run. Failures are not memoized.
+    pub async fn call<F, Fut>(&mut self, call: &ToolCall, f: F) -> Result<String>
+    where
+        F: FnOnce(&ToolCall) -> Fut,
+        Fut: Future<Output = Result<String>>,
+    {
+        let key = key(call);
+        if let Some(output) = self.results.get(&key) {
+            self.stats.hits += 1;
+            return Ok(output.clone());
+        }
+        self.stats.misses += 1;
+        let output = f(call).await?;
+        self.results.insert(key, output.clone());
+        Ok(output)
+    }
+
+    /// The memoized output of `call`, without touching the stats.
+    pub fn get(&self, call: &ToolCall) -> Option<&str> {
+        self.results.get(&key(call)).map(|s| s.as_str())
+    }
+
+    pub fn stats(&self) -> ToolMemoStats {
+        self.stats
+    }
+}
+
+/// The arguments are re-serialized (serde_json sorts the object keys), so that the same arguments
+/// generated in a different order or with different whitespace share the entry. Invalid JSON is
+/// used as is.
+fn key(call: &ToolCall) -> (String, String) {
+    let arguments = serde_json::from_str::<serde_json::Value>(&call.function.arguments)
+        .map(|v| v.to_string())
+        .unwrap_or_else(|_| call.function.arguments.clone());
+    (call.function.name.clone(), arguments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    fn tool_call(id: &str, name: &str, arguments: &str) -> ToolCall {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "type": "function",
+            "function": { "name": name, "arguments": arguments }
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn tool_memo_should_answer_repeated_calls() -> Result<()> {
+        let mut memo = ToolMemo::new();
+        let mut runs = 0;
+        let calls = [
+            tool_call("call_1", "get_weather", r#"{"city":"Paris","unit":"c"}"#),
+            tool_call(
+                "call_2",
+                "get_weather",
+                r#"{ "unit": "c", "city": "Paris" }"#,
+            ),
+            tool_call("call_3", "get_weather", r#"{"city":"Rome","unit":"c"}"#),
+        ];
+        for call in &calls {
+            let output = memo
+                .call(call, |call| {
+                    runs += 1;
+                    let rome = call.function.arguments.contains("Rome");
+                    async move { Ok(if rome { "sunny" } else { "rainy" }.to_string()) }
+                })
+                .await?;
+            assert!(output == "rainy" || output == "sunny");
+        }
+        assert_eq!(runs, 2);
+        assert_eq!(memo.stats(), ToolMemoStats { hits: 1, misses: 2 });
+        assert_eq!(memo.get(&calls[1]), Some("rainy"));
+
+        let failing = tool_call("call_4", "search", "{}");
+        let res = memo
+            .call(&failing, |_| async { Err(anyhow!("timeout")) })
+            .await;
+        assert!(res.is_err());
+        assert_eq!(memo.get(&failing), None);
+        Ok(())
+    }
+}
@@ -0,0 +1,217 @@
+//! Local tool execution. A `ToolRegistry` maps the function names to async Rust closures, and
+//! `LlmSdk::chat_with_tools` drives the loop: send the request, run the tool calls of the
+//! response, append their results and resend, until the model answers. Identical tool calls
+//! within a run are answered by a `ToolMemo`.
+
+use crate::{
+    ChatCompletionMessage, ChatCompletionResponse, ToSchema, Tool, ToolCall, ToolMemoStats,
+};
+use anyhow::{anyhow, Result};
+use futures::{future::BoxFuture, FutureExt};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{collections::HashMap, fmt, future::Future};
+
+type ToolFn = Box<dyn Fn(String) -> BoxFuture<'static, Result<String>> + Send + Sync>;
+
+/// The future of an `LlmTool` call.
+pub type ToolFuture<O> = BoxFuture<'static, Result<O>>;
+
+/// A tool whose definition and dispatch come from an async fn, usually generated by
+/// `#[llm_tool]` (feature `macros`).
+pub trait LlmTool {
+    type Args: ToSchema + DeserializeOwned + Send + 'static;
+    type Output: Serialize + 'static;
+    const NAME: &'static str;
+    const DESCRIPTION: &'static str;
+
+    fn call(args: Self::Args) -> ToolFuture<Self::Output>;
+}
+
+pub struct ToolRegistry {
+    tools: HashMap<String, (Tool, ToolFn)>,
+    max_rounds: usize,
+}
+
+/// The result of `LlmSdk::chat_with_tools`.
+#[derive(Debug, Clone)]
+pub struct ToolRun {
+    /// The response with the final answer.
+    pub response: ChatCompletionResponse,
+    /// The messages of the request followed by the tool calls and their results, e.g. to
+    /// continue the conversation.
+    pub messages: Vec<ChatCompletionMessage>,
+    /// Number of requests sent.
+    pub rounds: usize,
+    pub memo: ToolMemoStats,
+}
+
+impl Default for ToolRegistry {
+    fn default() -> Self {
+        Self {
+            tools: HashMap::new(),
+            max_rounds: 10,
+        }
+    }
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `f` as the function `name`; its parameters are the JSON schema of `T`, and its
+    /// output is sent back to the model as JSON (or as is for a `String`).
+    pub fn register<T, O, F, Fut>(
+        mut self,
+        name: impl Into<String>,
+        description: impl Into<String>,
+        f: F,
+    ) -> Self
+    where
+        T: ToSchema + DeserializeOwned + Send + 'static,
+        O: Serialize + 'static,
+        F: Fn(T) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<O>> + Send + 'static,
+    {
+        let name = name.into();
+        let tool = Tool::new_function::<T>(name.clone(), description);
+        let f: ToolFn =
+            Box::new(
+                move |arguments: String| match serde_json::from_str::<T>(&arguments) {
+                    Ok(args) => f(args).map(|output| output.and_then(to_output)).boxed(),
+                    Err(e) => {
+                        futures::future::ready(Err(anyhow!("invalid arguments: {}", e))).boxed()
+                    }
+                },
+            );
+        self.tools.insert(name, (tool, f));
+        self
+    }
+
+    /// Register a tool generated by `#[llm_tool]`.
+    pub fn tool<T: LlmTool + 'static>(self, _tool: T) -> Self {
+        self.register(T::NAME, T::DESCRIPTION, T::call)
+    }
+
+    /// The max number of requests of a run, 10 by default.
+    pub fn max_rounds(mut self, max_rounds: usize) -> Self {
+        self.max_rounds = max_rounds.max(1);
+        self
+    }
+
+    /// The definitions of the registered tools, sorted by name.
+    pub fn tools(&self) -> Vec<Tool> {
+        let mut tools: Vec<_> = self.tools.values().map(|(tool, _)| tool.clone()).collect();
+        tools.sort_by(|a, b| a.function.name.cmp(&b.function.name));
+        tools
+    }
+
+    pub(crate) fn rounds(&self) -> usize {
+        self.max_rounds
+    }
+
+    /// Run the tool of `call`.
+    pub async fn call(&self, call: &ToolCall) -> Result<String> {
+        let (_, f) = self
+            .tools
+            .get(&call.function.name)
+            .ok_or_else(|| anyhow!("unknown tool {}", call.function.name))?;
+        f(call.function.arguments.clone()).await
+    }
+}
+
+impl fmt::Debug for ToolRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<_> = self.tools.keys().collect();
+        names.sort();
+        f.debug_struct("ToolRegistry")
+            .field("tools", &names)
+            .field("max_rounds", &self.max_rounds)
+            .finish()
+    }
+}
+
+fn to_output<O: Serialize>(output: O) -> Result<String> {
+    Ok(match serde_json::to_value(output)? {
+        serde_json::Value::String(s) => s,
+        value => value.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChatCompleteModel, ChatCompletionRequest, FunctionCall, ToolType, SDK};
+    use schemars::JsonSchema;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, JsonSchema)]
+    struct AddArgs {
+        a: i64,
+        b: i64,
+    }
+
+    fn registry() -> ToolRegistry {
+        ToolRegistry::new().register("add", "Add two numbers", |args: AddArgs| async move {
+            Ok(args.a + args.b)
+        })
+    }
+
+    fn call(name: &str, arguments: &str) -> ToolCall {
+        ToolCall {
+            id: "call_1".into(),
+            r#type: ToolType::Function,
+            function: FunctionCall {
+                name: name.into(),
+                arguments: arguments.into(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn tool_registry_should_dispatch_by_name() -> Result<()> {
+        let registry = registry();
+        assert_eq!(registry.tools().len(), 1);
+        assert_eq!(registry.call(&call("add", r#"{"a":1,"b":2}"#)).await?, "3");
+        assert!(registry.call(&call("add", r#"{"a":1}"#)).await.is_err());
+        assert!(registry
+            .call(&call("sub", r#"{"a":1,"b":2}"#))
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "macros")]
+    #[tokio::test]
+    async fn llm_tool_should_register() -> Result<()> {
+        /// Multiply two numbers.
+        #[crate::llm_tool(name = "mul")]
+        async fn multiply(args: AddArgs) -> Result<i64> {
+            Ok(args.a * args.b)
+        }
+
+        let registry = ToolRegistry::new().tool(MultiplyTool);
+        assert_eq!(
+            registry.tools()[0].function.description,
+            "Multiply two numbers."
+        );
+        assert_eq!(registry.call(&call("mul", r#"{"a":3,"b":4}"#)).await?, "12");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn chat_with_tools_should_work() -> Result<()> {
+        let req = ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt4o,
+            vec![ChatCompletionMessage::new_user(
+                "What's 123456 + 654321? Use the add tool.",
+                "",
+            )],
+        );
+        let run = SDK.chat_with_tools(req, &registry()).await?;
+        let content = run.response.choices[0].message.content.clone().unwrap();
+        assert!(content.replace(',', "").contains("777777"));
+        assert!(run.rounds >= 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,204 @@
+//! Keep a chat history within the context window of the model. A `Truncation` drops the oldest
+//! messages until the prompt (plus the tokens reserved for the answer) fits, counting tokens with
+//! a `Tokenizer`. The SDK has no tokenizer of its own: the default `EstimateTokenizer` assumes ~4
+//! characters per token, plug in a real one (e.g. tiktoken) for an exact count.
+
+use crate::{api::estimate_tokens, ChatCompleteModel, ChatCompletionMessage};
+use std::sync::Arc;
+
+/// Tokens added to every message for the role and the separators.
+const MESSAGE_OVERHEAD: usize = 4;
+/// The default number of tokens left for the answer.
+const DEFAULT_RESERVE: usize = 1024;
+
+pub trait Tokenizer: Send + Sync {
+    /// The number of tokens of `text`.
+    fn count(&self, text: &str) -> usize;
+}
+
+/// Estimates ~4 characters per token, which is close for English but undercounts most other
+/// languages.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EstimateTokenizer;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TruncationStrategy {
+    /// Drop the oldest messages, the system message included.
+    DropOldest,
+    /// Drop the oldest messages but keep the system messages.
+    KeepSystem,
+    /// Like `KeepSystem`, but also keep the rest of the history within `max_tokens`, however large
+    /// the context window of the model is.
+    SlidingWindow { max_tokens: usize },
+}
+
+#[derive(Clone)]
+pub struct Truncation {
+    strategy: TruncationStrategy,
+    tokenizer: Arc<dyn Tokenizer>,
+    reserve: usize,
+}
+
+impl Tokenizer for EstimateTokenizer {
+    fn count(&self, text: &str) -> usize {
+        estimate_tokens(text)
+    }
+}
+
+impl Truncation {
+    pub fn new(strategy: TruncationStrategy) -> Self {
+        Self {
+            strategy,
+            tokenizer: Arc::new(EstimateTokenizer),
+            reserve: DEFAULT_RESERVE,
+        }
+    }
+
+    pub fn with_tokenizer(mut self, tokenizer: impl Tokenizer + 'static) -> Self {
+        self.tokenizer = Arc::new(tokenizer);
+        self
+    }
+
+    /// The tokens of the context window left for the answer, 1024 by default.
+    pub fn reserve(mut self, tokens: usize) -> Self {
+        self.reserve = tokens;
+        self
+    }
+
+    /// The number of tokens of a message. Its JSON is counted, so the tool calls and the image
+    /// urls are accounted for.
+    pub fn count(&self, message: &ChatCompletionMessage) -> usize {
+        let text = serde_json::to_string(message).unwrap_or_default();
+        self.tokenizer.count(&text) + MESSAGE_OVERHEAD
+    }
+
+    /// The tokens available to the prompt, `None` for no limit (the context window of the model
+    /// is unknown and there is no sliding window).
+    pub fn budget(&self, model: &ChatCompleteModel) -> Option<usize> {
+        let window = model
+            .context_window()
+            .map(|n| n.saturating_sub(self.reserve));
+        match self.strategy {
+            TruncationStrategy::SlidingWindow { max_tokens } => {
+                Some(window.map_or(max_tokens, |n| n.min(max_tokens)))
+            }
+            _ => window,
+        }
+    }
+
+    /// Drop the oldest messages of `messages` until they fit the budget for `model`. The last
+    /// message is always kept, and so are the system messages unless the strategy is
+    /// `DropOldest`. Tool results whose tool call was dropped are dropped as well, the API
+    /// rejects them.
+    pub fn apply(
+        &self,
+        model: &ChatCompleteModel,
+        messages: Vec<ChatCompletionMessage>,
+    ) -> Vec<ChatCompletionMessage> {
+        let Some(budget) = self.budget(model) else {
+            return messages;
+        };
+        let keep_system = self.strategy != TruncationStrategy::DropOldest;
+        let pinned = |message: &ChatCompletionMessage| {
+            keep_system && matches!(message, ChatCompletionMessage::System(_))
+        };
+        let counts: Vec<usize> = messages.iter().map(|m| self.count(m)).collect();
+        let mut total: usize = counts.iter().sum();
+        let mut keep = vec![true; messages.len()];
+        let last = messages.len().saturating_sub(1);
+        let mut i = 0;
+        while i < last && (total > budget || orphan_tool(&messages, &keep, i)) {
+            if !pinned(&messages[i]) {
+                keep[i] = false;
+                total -= counts[i];
+            }
+            i += 1;
+        }
+        messages
+            .into_iter()
+            .zip(keep)
+            .filter_map(|(message, keep)| keep.then_some(message))
+            .collect()
+    }
+}
+
+/// Whether `messages[i]` is a tool result following only dropped or pinned messages, i.e. its
+/// tool call may have been dropped.
+fn orphan_tool(messages: &[ChatCompletionMessage], keep: &[bool], i: usize) -> bool {
+    matches!(messages[i], ChatCompletionMessage::Tool(_))
+        && (0..i)
+            .rev()
+            .find(|&j| !matches!(messages[j], ChatCompletionMessage::Tool(_)))
+            .map_or(true, |j| !keep[j])
+}
+
+impl std::fmt::Debug for Truncation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Truncation")
+            .field("strategy", &self.strategy)
+            .field("reserve", &self.reserve)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct WordTokenizer;
+
+    impl Tokenizer for WordTokenizer {
+        fn count(&self, text: &str) -> usize {
+            text.split_whitespace().count()
+        }
+    }
+
+    fn history() -> Vec<ChatCompletionMessage> {
+        vec![
+            ChatCompletionMessage::new_system("be brief", ""),
+            ChatCompletionMessage::new_user("one two three", ""),
+            ChatCompletionMessage::new_assistant("four five six"),
+            ChatCompletionMessage::new_user("seven eight", ""),
+        ]
+    }
+
+    #[test]
+    fn sliding_window_should_keep_system_and_recent_messages() {
+        let truncation = Truncation::new(TruncationStrategy::SlidingWindow { max_tokens: 20 })
+            .with_tokenizer(WordTokenizer);
+        let messages = truncation.apply(&ChatCompleteModel::Gpt4o, history());
+        assert_eq!(messages.len(), 3);
+        assert!(matches!(messages[0], ChatCompletionMessage::System(_)));
+        assert!(matches!(messages[1], ChatCompletionMessage::Assistant(_)));
+    }
+
+    #[test]
+    fn drop_oldest_should_drop_system_message() {
+        let truncation = Truncation::new(TruncationStrategy::DropOldest)
+            .with_tokenizer(WordTokenizer)
+            .reserve(4_096 - 15);
+        let messages = truncation.apply(&ChatCompleteModel::Gpt3TurboInstruct, history());
+        assert_eq!(messages.len(), 2);
+        assert!(matches!(messages[0], ChatCompletionMessage::Assistant(_)));
+    }
+
+    #[test]
+    fn truncation_should_keep_last_message_and_skip_unknown_models() {
+        let truncation = Truncation::new(TruncationStrategy::KeepSystem).reserve(usize::MAX);
+        let messages = truncation.apply(&ChatCompleteModel::Gpt4o, history());
+        assert_eq!(messages.len(), 2);
+        let model = ChatCompleteModel::Other("llama3".into());
+        assert_eq!(truncation.apply(&model, history()).len(), 4);
+    }
+
+    #[test]
+    fn truncation_should_drop_orphan_tool_results() {
+        let mut messages = history();
+        messages.insert(3, ChatCompletionMessage::new_tool("42", "call_1"));
+        let truncation = Truncation::new(TruncationStrategy::SlidingWindow { max_tokens: 18 })
+            .with_tokenizer(WordTokenizer);
+        let messages = truncation.apply(&ChatCompleteModel::Gpt4o, messages);
+        assert_eq!(messages.len(), 2);
+        assert!(matches!(messages[1], ChatCompletionMessage::User(_)));
+    }
+}
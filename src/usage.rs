@@ -0,0 +1,498 @@
+//! Cost tracking: a `UsageTracker` set on the SDK (see `LlmSdkBuilder::usage_tracker`) is fed the
+//! token usage of every chat completion, completion, embedding and response (streamed or not),
+//! and prices it with a `PricingTable`. The images, speeches and transcriptions are billed per
+//! image, character or audio minute: their cost is recorded per call, without tokens. With spend
+//! limits (tokens or dollars, in total or per hour / day), the calls fail with
+//! `LlmError::BudgetExceeded` before they are sent once a limit is reached.
+
+use crate::LlmError;
+use anyhow::Result;
+use serde::Serialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+const HOUR: Duration = Duration::from_secs(3600);
+const DAY: Duration = Duration::from_secs(24 * 3600);
+
+/// The price of a model in USD per 1M tokens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelPrice {
+    pub prompt: f64,
+    pub completion: f64,
+}
+
+/// The prices by model name. A model is priced by the longest name it starts with, so the dated
+/// snapshots (e.g. `gpt-4o-2024-08-06`) get the price of their model.
+#[derive(Debug, Clone)]
+pub struct PricingTable {
+    prices: HashMap<String, ModelPrice>,
+}
+
+/// The aggregated usage of a model or of all of them.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ModelUsage {
+    pub requests: usize,
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    /// In USD, the models missing from the pricing table cost nothing.
+    pub cost: f64,
+}
+
+/// An amount of tokens (prompt and completion) or dollars.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Spend {
+    Tokens(usize),
+    Usd(f64),
+}
+
+/// A hard limit of the spend over a sliding `period`, or in total if `None`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpendLimit {
+    pub max: Spend,
+    pub period: Option<Duration>,
+}
+
+#[derive(Debug, Default)]
+pub struct UsageTracker {
+    pricing: PricingTable,
+    limits: Vec<SpendLimit>,
+    state: Mutex<UsageState>,
+}
+
+#[derive(Debug, Default)]
+struct UsageState {
+    usage: HashMap<(String, &'static str), ModelUsage>,
+    /// The time, tokens and cost of the calls within the longest period of the limits.
+    recent: VecDeque<(Instant, usize, f64)>,
+}
+
+const PRICES: &[(&str, f64, f64)] = &[
+    ("gpt-3.5-turbo", 0.5, 1.5),
+    ("gpt-3.5-turbo-1106", 1.0, 2.0),
+    ("gpt-3.5-turbo-instruct", 1.5, 2.0),
+    ("gpt-4-1106-preview", 10.0, 30.0),
+    ("gpt-4-1106-vision-preview", 10.0, 30.0),
+    ("gpt-4-turbo", 10.0, 30.0),
+    ("gpt-4o", 2.5, 10.0),
+    ("gpt-4o-mini", 0.15, 0.6),
+    ("claude-3-5-sonnet", 3.0, 15.0),
+    ("claude-3-opus", 15.0, 75.0),
+    ("claude-3-haiku", 0.25, 1.25),
+    ("gpt-image-1", 5.0, 40.0),
+    ("text-embedding-3-small", 0.02, 0.0),
+    ("text-embedding-3-large", 0.13, 0.0),
+    ("text-embedding-ada-002", 0.1, 0.0),
+];
+
+impl Default for PricingTable {
+    fn default() -> Self {
+        let prices = PRICES
+            .iter()
+            .map(|&(name, prompt, completion)| {
+                (name.to_string(), ModelPrice { prompt, completion })
+            })
+            .collect();
+        Self { prices }
+    }
+}
+
+impl PricingTable {
+    /// A table without any price.
+    pub fn empty() -> Self {
+        Self {
+            prices: HashMap::new(),
+        }
+    }
+
+    /// Add or replace the price of `model`.
+    pub fn set(mut self, model: impl Into<String>, price: ModelPrice) -> Self {
+        self.prices.insert(model.into(), price);
+        self
+    }
+
+    pub fn price(&self, model: &str) -> Option<ModelPrice> {
+        self.prices
+            .iter()
+            .filter(|(name, _)| model.starts_with(name.as_str()))
+            .max_by_key(|(name, _)| name.len())
+            .map(|(_, price)| *price)
+    }
+
+    /// The cost in USD, 0 for an unknown model.
+    pub fn cost(&self, model: &str, prompt_tokens: usize, completion_tokens: usize) -> f64 {
+        self.price(model).map_or(0.0, |price| {
+            (prompt_tokens as f64 * price.prompt + completion_tokens as f64 * price.completion)
+                / 1_000_000.0
+        })
+    }
+}
+
+impl fmt::Display for Spend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tokens(n) => write!(f, "{} tokens", n),
+            Self::Usd(usd) => write!(f, "${:.2}", usd),
+        }
+    }
+}
+
+impl SpendLimit {
+    pub fn usd(max: f64) -> Self {
+        Self::new(Spend::Usd(max), None)
+    }
+
+    pub fn usd_per_hour(max: f64) -> Self {
+        Self::new(Spend::Usd(max), Some(HOUR))
+    }
+
+    pub fn usd_per_day(max: f64) -> Self {
+        Self::new(Spend::Usd(max), Some(DAY))
+    }
+
+    pub fn tokens(max: usize) -> Self {
+        Self::new(Spend::Tokens(max), None)
+    }
+
+    pub fn tokens_per_hour(max: usize) -> Self {
+        Self::new(Spend::Tokens(max), Some(HOUR))
+    }
+
+    pub fn tokens_per_day(max: usize) -> Self {
+        Self::new(Spend::Tokens(max), Some(DAY))
+    }
+
+    fn new(max: Spend, period: Option<Duration>) -> Self {
+        Self { max, period }
+    }
+
+    /// The spend if it reaches the limit.
+    fn exceeded(&self, tokens: usize, cost: f64) -> Option<Spend> {
+        match self.max {
+            Spend::Tokens(max) if tokens >= max => Some(Spend::Tokens(tokens)),
+            Spend::Usd(max) if cost >= max => Some(Spend::Usd(cost)),
+            _ => None,
+        }
+    }
+
+    /// The projected spend if it goes over the limit.
+    fn overrun(&self, tokens: usize, cost: f64) -> Option<Spend> {
+        match self.max {
+            Spend::Tokens(max) if tokens > max => Some(Spend::Tokens(tokens)),
+            Spend::Usd(max) if cost > max => Some(Spend::Usd(cost)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SpendLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.period {
+            Some(HOUR) => write!(f, "{} per hour", self.max),
+            Some(DAY) => write!(f, "{} per day", self.max),
+            Some(period) => write!(f, "{} per {:?}", self.max, period),
+            None => write!(f, "{}", self.max),
+        }
+    }
+}
+
+impl ModelUsage {
+    fn add(&mut self, other: &ModelUsage) {
+        self.requests += other.requests;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.cost += other.cost;
+    }
+}
+
+impl UsageTracker {
+    pub fn new(pricing: PricingTable) -> Self {
+        Self {
+            pricing,
+            ..Default::default()
+        }
+    }
+
+    /// Reject the calls once the total cost reaches `usd`, same as `limit(SpendLimit::usd(usd))`.
+    pub fn budget(self, usd: f64) -> Self {
+        self.limit(SpendLimit::usd(usd))
+    }
+
+    /// Reject the calls once `limit` is reached. The call that crosses a limit still succeeds,
+    /// its usage is only known afterwards.
+    pub fn limit(mut self, limit: SpendLimit) -> Self {
+        self.limits.push(limit);
+        self
+    }
+
+    /// Record the usage of a call to `endpoint` (e.g. "chat/completions").
+    pub fn record(
+        &self,
+        model: &str,
+        endpoint: &'static str,
+        prompt_tokens: usize,
+        completion_tokens: usize,
+    ) {
+        self.record_at(
+            Instant::now(),
+            model,
+            endpoint,
+            prompt_tokens,
+            completion_tokens,
+        );
+    }
+
+    /// Record a call to `endpoint` billed per unit rather than per token (an image, a character
+    /// of speech, a minute of audio), which cost `cost` USD.
+    pub fn record_cost(&self, model: &str, endpoint: &'static str, cost: f64) {
+        self.add(
+            Instant::now(),
+            model,
+            endpoint,
+            ModelUsage {
+                requests: 1,
+                cost,
+                ..Default::default()
+            },
+        );
+    }
+
+    fn record_at(
+        &self,
+        at: Instant,
+        model: &str,
+        endpoint: &'static str,
+        prompt_tokens: usize,
+        completion_tokens: usize,
+    ) {
+        let usage = ModelUsage {
+            requests: 1,
+            prompt_tokens,
+            completion_tokens,
+            cost: self.pricing.cost(model, prompt_tokens, completion_tokens),
+        };
+        self.add(at, model, endpoint, usage);
+    }
+
+    fn add(&self, at: Instant, model: &str, endpoint: &'static str, usage: ModelUsage) {
+        let mut state = self.lock();
+        if self.limits.iter().any(|limit| limit.period.is_some()) {
+            let tokens = usage.prompt_tokens + usage.completion_tokens;
+            state.recent.push_back((at, tokens, usage.cost));
+        }
+        state
+            .usage
+            .entry((model.to_string(), endpoint))
+            .or_default()
+            .add(&usage);
+    }
+
+    pub fn total(&self) -> ModelUsage {
+        self.lock()
+            .usage
+            .values()
+            .fold(ModelUsage::default(), |mut total, u| {
+                total.add(u);
+                total
+            })
+    }
+
+    pub fn total_cost(&self) -> f64 {
+        self.total().cost
+    }
+
+    /// The usage by model name.
+    pub fn by_model(&self) -> HashMap<String, ModelUsage> {
+        self.breakdown(|model, _| model.to_string())
+    }
+
+    /// The usage by endpoint.
+    pub fn by_endpoint(&self) -> HashMap<String, ModelUsage> {
+        self.breakdown(|_, endpoint| endpoint.to_string())
+    }
+
+    /// Fails with `LlmError::BudgetExceeded` if a limit is reached.
+    pub fn check(&self) -> Result<()> {
+        self.check_at(Instant::now(), 0, 0.0)
+    }
+
+    /// Like `check`, but also fails if a call spending `tokens` and `cost` would go over a limit.
+    pub fn check_spend(&self, tokens: usize, cost: f64) -> Result<()> {
+        self.check_at(Instant::now(), tokens, cost)
+    }
+
+    pub(crate) fn pricing(&self) -> &PricingTable {
+        &self.pricing
+    }
+
+    fn check_at(&self, now: Instant, extra_tokens: usize, extra_cost: f64) -> Result<()> {
+        let total = self.total();
+        let mut state = self.lock();
+        if let Some(max) = self.limits.iter().filter_map(|limit| limit.period).max() {
+            while let Some(&(at, _, _)) = state.recent.front() {
+                if now.saturating_duration_since(at) < max {
+                    break;
+                }
+                state.recent.pop_front();
+            }
+        }
+        for limit in &self.limits {
+            let (tokens, cost) = match limit.period {
+                Some(period) => state
+                    .recent
+                    .iter()
+                    .filter(|(at, _, _)| now.saturating_duration_since(*at) < period)
+                    .fold((0, 0.0), |(tokens, cost), (_, t, c)| (tokens + t, cost + c)),
+                None => (total.prompt_tokens + total.completion_tokens, total.cost),
+            };
+            let spent = limit
+                .exceeded(tokens, cost)
+                .or_else(|| limit.overrun(tokens + extra_tokens, cost + extra_cost));
+            if let Some(spent) = spent {
+                return Err(LlmError::BudgetExceeded {
+                    limit: *limit,
+                    spent,
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn reset(&self) {
+        let mut state = self.lock();
+        state.usage.clear();
+        state.recent.clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, UsageState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn breakdown(&self, key: impl Fn(&str, &str) -> String) -> HashMap<String, ModelUsage> {
+        let mut ret: HashMap<String, ModelUsage> = HashMap::new();
+        for ((model, endpoint), u) in self.lock().usage.iter() {
+            ret.entry(key(model, endpoint)).or_default().add(u);
+        }
+        ret
+    }
+}
+
+/// The API name of a model, e.g. `gpt-4o` for `ChatCompleteModel::Gpt4o`.
+pub(crate) fn model_name(model: &impl Serialize) -> String {
+    match serde_json::to_value(model) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pricing_table_should_match_longest_prefix() {
+        let pricing = PricingTable::default();
+        assert_eq!(pricing.price("gpt-4o-2024-08-06").unwrap().prompt, 2.5);
+        assert_eq!(
+            pricing.price("gpt-4o-mini-2024-07-18").unwrap().prompt,
+            0.15
+        );
+        assert!(pricing.price("llama3").is_none());
+        assert_eq!(pricing.cost("gpt-4o", 1_000_000, 100_000), 3.5);
+    }
+
+    #[test]
+    fn usage_tracker_should_aggregate_and_enforce_budget() {
+        let tracker = UsageTracker::new(PricingTable::default()).budget(3.0);
+        tracker.record("gpt-4o", "chat/completions", 1_000_000, 0);
+        tracker.record("text-embedding-3-small", "embeddings", 1_000_000, 0);
+        tracker.record("llama3", "chat/completions", 1000, 10);
+        assert!(tracker.check().is_ok());
+
+        let total = tracker.total();
+        assert_eq!(total.requests, 3);
+        assert_eq!(total.prompt_tokens, 2_001_000);
+        assert!((total.cost - 2.52).abs() < 1e-9);
+        assert_eq!(tracker.by_model()["llama3"].cost, 0.0);
+        assert_eq!(tracker.by_endpoint()["chat/completions"].requests, 2);
+
+        tracker.record_cost("dall-e-3", "images/generations", 0.4);
+        assert_eq!(tracker.by_endpoint()["images/generations"].requests, 1);
+        assert!((tracker.total_cost() - 2.92).abs() < 1e-9);
+        assert_eq!(tracker.total().prompt_tokens, 2_001_000);
+
+        tracker.record("gpt-4o", "chat/completions", 0, 100_000);
+        assert!(tracker.check().is_err());
+        tracker.reset();
+        assert!(tracker.check().is_ok());
+    }
+
+    #[tokio::test]
+    async fn exhausted_budget_should_reject_every_call() -> Result<()> {
+        use crate::{
+            CompletionModel, CompletionRequest, CreateImageRequest, LlmSdkBuilder, SpeechRequest,
+            WhisperRequest,
+        };
+        use std::sync::Arc;
+
+        fn rejected<T>(res: Result<T>) -> bool {
+            res.is_err_and(|e| {
+                matches!(
+                    e.downcast_ref::<LlmError>(),
+                    Some(LlmError::BudgetExceeded { .. })
+                )
+            })
+        }
+
+        let tracker = UsageTracker::default().limit(SpendLimit::tokens(1));
+        tracker.record("gpt-4o", "chat/completions", 1, 0);
+        let sdk = LlmSdkBuilder::default()
+            .base_url("http://localhost:11434/v1")
+            .token("")
+            .usage_tracker(Arc::new(tracker))
+            .build()?;
+        let completion = CompletionRequest::new(CompletionModel::default(), "Hi");
+        assert!(rejected(sdk.completion(completion.clone()).await));
+        assert!(rejected(sdk.completion_stream(completion).await));
+        let image = CreateImageRequest::new("a cat")?;
+        assert!(rejected(sdk.create_image(image).await));
+        assert!(rejected(sdk.speech(SpeechRequest::new("Hi")).await));
+        assert!(rejected(sdk.speech_stream(SpeechRequest::new("Hi")).await));
+        let audio = WhisperRequest::transcription(vec![0; 16]);
+        assert!(rejected(sdk.whisper(audio.clone()).await));
+        assert!(rejected(sdk.whisper_verbose(audio).await));
+        Ok(())
+    }
+
+    #[test]
+    fn spend_limit_should_slide_over_period() {
+        let tracker = UsageTracker::default()
+            .limit(SpendLimit::tokens_per_hour(1000))
+            .limit(SpendLimit::usd_per_day(1.0));
+        let now = Instant::now();
+        tracker.record_at(now, "gpt-4o", "chat/completions", 800, 200);
+        let err = tracker.check_at(now, 0, 0.0).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<LlmError>(),
+            Some(&LlmError::BudgetExceeded {
+                limit: SpendLimit::tokens_per_hour(1000),
+                spent: Spend::Tokens(1000),
+            })
+        );
+        assert_eq!(
+            err.to_string(),
+            "spend limit of 1000 tokens per hour exceeded, 1000 tokens spent"
+        );
+        assert!(tracker.check_at(now + HOUR, 0, 0.0).is_ok());
+        assert!(tracker.check_at(now + HOUR, 1000, 0.0).is_ok());
+        assert!(tracker.check_at(now + HOUR, 1001, 0.0).is_err());
+
+        tracker.record_at(now + HOUR, "gpt-4o", "chat/completions", 400_000, 0);
+        assert!(tracker.check_at(now + 2 * HOUR, 0, 0.0).is_err());
+        assert!(tracker.check_at(now + HOUR + DAY, 0, 0.0).is_ok());
+    }
+}